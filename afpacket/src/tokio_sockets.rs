@@ -1,6 +1,8 @@
 use crate::sockets;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use std::{ffi::CStr, io};
-use tokio::io::{AsyncReadExt, AsyncWriteExt, PollEvented};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, PollEvented};
 
 pub struct AsyncBoundSocket {
     sock: PollEvented<sockets::BoundSocket>,
@@ -28,3 +30,33 @@ impl AsyncBoundSocket {
         self.sock.read(frame).await
     }
 }
+
+/// Each read receives a single frame.
+impl AsyncRead for AsyncBoundSocket {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.sock).poll_read(cx, buf)
+    }
+}
+
+/// Each write sends a single frame.
+impl AsyncWrite for AsyncBoundSocket {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.sock).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.sock).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.sock).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,4 @@
+#[derive(Debug, Clone)]
+pub struct IntegerPacket {
+    pub id: u32,
+}
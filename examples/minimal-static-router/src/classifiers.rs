@@ -4,13 +4,23 @@ use route_rs_runtime::classifier::Classifier;
 use std::net::{Ipv4Addr, Ipv6Addr};
 use treebitmap::IpLookupTable;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Interface {
     Interface0,
     Interface1,
     Interface2,
 }
 
+/// Why a subnet router sends a packet where it does, as reported by `explain`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassificationExplanation<A> {
+    /// The longest prefix that matched the destination, as its address and length, or None if no
+    /// prefix matched and the packet went to the default interface.
+    pub prefix: Option<(A, u32)>,
+    /// The interface the packet is sent to.
+    pub interface: Interface,
+}
+
 pub struct Ipv4SubnetRouter {
     pub default_if: Interface,
     pub lookup_table: IpLookupTable<Ipv4Addr, Interface>,
@@ -28,6 +38,21 @@ impl Ipv4SubnetRouter {
             lookup_table,
         }
     }
+
+    /// Reports which prefix the destination of `packet` matches, and the interface it is sent to
+    /// as a result, without sending it anywhere.
+    pub fn explain(&self, packet: &Ipv4Packet) -> ClassificationExplanation<Ipv4Addr> {
+        match self.lookup_table.longest_match(packet.dest_addr()) {
+            Some((addr, len, interface)) => ClassificationExplanation {
+                prefix: Some((addr, len)),
+                interface: *interface,
+            },
+            None => ClassificationExplanation {
+                prefix: None,
+                interface: self.default_if,
+            },
+        }
+    }
 }
 
 impl Classifier for Ipv4SubnetRouter {
@@ -35,11 +60,7 @@ impl Classifier for Ipv4SubnetRouter {
     type Class = Interface;
 
     fn classify(&self, packet: &Self::Packet) -> Self::Class {
-        if let Some(entry) = self.lookup_table.longest_match(packet.dest_addr()) {
-            *entry.2
-        } else {
-            self.default_if
-        }
+        self.explain(packet).interface
     }
 }
 
@@ -72,6 +93,21 @@ impl Ipv6SubnetRouter {
             lookup_table,
         }
     }
+
+    /// Reports which prefix the destination of `packet` matches, and the interface it is sent to
+    /// as a result, without sending it anywhere.
+    pub fn explain(&self, packet: &Ipv6Packet) -> ClassificationExplanation<Ipv6Addr> {
+        match self.lookup_table.longest_match(packet.dest_addr()) {
+            Some((addr, len, interface)) => ClassificationExplanation {
+                prefix: Some((addr, len)),
+                interface: *interface,
+            },
+            None => ClassificationExplanation {
+                prefix: None,
+                interface: self.default_if,
+            },
+        }
+    }
 }
 
 impl Classifier for Ipv6SubnetRouter {
@@ -79,11 +115,7 @@ impl Classifier for Ipv6SubnetRouter {
     type Class = Interface;
 
     fn classify(&self, packet: &Self::Packet) -> Self::Class {
-        if let Some(entry) = self.lookup_table.longest_match(packet.dest_addr()) {
-            *entry.2
-        } else {
-            self.default_if
-        }
+        self.explain(packet).interface
     }
 }
 
@@ -211,6 +243,60 @@ mod tests {
         assert_eq!(results[2][0], packet_interface2);
     }
 
+    #[test]
+    fn explain_ipv4() {
+        let mut packet = Ipv4Packet::empty();
+        let mut router = Ipv4SubnetRouter::new(Interface0);
+
+        packet.set_dest_addr(Ipv4Addr::new(192, 168, 10, 5));
+        assert_eq!(
+            router.explain(&packet),
+            ClassificationExplanation {
+                prefix: Some((Ipv4Addr::new(192, 168, 0, 0), 16)),
+                interface: Interface2,
+            }
+        );
+
+        // The longest of the matching prefixes is reported.
+        packet.set_dest_addr(Ipv4Addr::new(10, 10, 10, 7));
+        assert_eq!(
+            router.explain(&packet).prefix,
+            Some((Ipv4Addr::new(10, 10, 10, 0), 24))
+        );
+        assert_eq!(router.explain(&packet).interface, router.classify(&packet));
+
+        router.lookup_table.remove(Ipv4Addr::new(0, 0, 0, 0), 0);
+        router.default_if = Interface1;
+        packet.set_dest_addr(Ipv4Addr::new(8, 8, 8, 8));
+        assert_eq!(
+            router.explain(&packet),
+            ClassificationExplanation {
+                prefix: None,
+                interface: Interface1,
+            }
+        );
+    }
+
+    #[test]
+    fn explain_ipv6() {
+        let data_v6: Vec<u8> = vec![
+            0x60, 0, 0, 0, 0, 0, 17, 64, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let mut packet = Ipv6Packet::from_buffer(data_v6, None, 0).unwrap();
+        let router = Ipv6SubnetRouter::new(Interface0);
+
+        packet.set_dest_addr(Ipv6Addr::new(0x2001, 0xdb8, 0xdead, 0xbeef, 0, 0, 0, 1));
+
+        assert_eq!(
+            router.explain(&packet),
+            ClassificationExplanation {
+                prefix: Some((Ipv6Addr::new(0x2001, 0xdb8, 0xdead, 0xbeef, 0, 0, 0, 0), 64)),
+                interface: Interface2,
+            }
+        );
+    }
+
     #[test]
     fn route_ipv6() {
         let data_v6: Vec<u8> = vec![
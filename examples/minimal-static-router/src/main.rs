@@ -1,12 +1,12 @@
 use route_rs_packets::EthernetFrame;
 use route_rs_runtime::link::{
-    primitive::{ClassifyLink, JoinLink, ProcessLink},
-    Link, LinkBuilder, PacketStream, ProcessLinkBuilder,
+    primitive::ClassifyLink, Link, LinkBuildError, LinkBuilder, PacketStream,
 };
 use route_rs_runtime::utils::{runner::runner, test::packet_generators::immediate_stream};
 
 mod classifiers;
 mod processors;
+mod templates;
 
 fn main() {
     let data_v4: Vec<u8> = vec![
@@ -90,9 +90,9 @@ impl LinkBuilder<EthernetFrame, EthernetFrame> for Router {
         }
     }
 
-    fn build_link(self) -> Link<EthernetFrame> {
+    fn try_build_link(self) -> Result<Link<EthernetFrame>, LinkBuildError> {
         if self.in_streams.is_none() {
-            panic!("Can not build link, missing input stream");
+            Err(LinkBuildError::MissingIngressors)
         } else {
             // TODO: Build the router here
 
@@ -103,14 +103,8 @@ impl LinkBuilder<EthernetFrame, EthernetFrame> for Router {
             //               \--Ipv6Dencap--Ipv6SubnetRouter(Classifier)<--encap
             //                                                          \--encap
 
-            //return an empty thing for now so it compiles.
             let mut all_runnables = vec![];
 
-            let ipv4_router =
-                classifiers::Ipv4SubnetRouter::new(classifiers::Interface::Interface0);
-            let ipv6_router =
-                classifiers::Ipv6SubnetRouter::new(classifiers::Interface::Interface0);
-
             let (mut classify_runables, mut classify_egressors) = ClassifyLink::new()
                 .ingressors(self.in_streams.unwrap())
                 .num_egressors(2)
@@ -120,106 +114,46 @@ impl LinkBuilder<EthernetFrame, EthernetFrame> for Router {
                     classifiers::ClassifyIPType::IPv6 => 1,
                     classifiers::ClassifyIPType::None => 1, // we can't drop packets in a classify. Maybe we do need
                 })) // the DropLink back?
-                .build_link();
+                .try_build_link()?;
             all_runnables.append(&mut classify_runables);
 
             //------------Ipv4 Subnet router--------------//
-
-            // Reminder that process links don't have any runnables, so we can ignore that half of the tuple
-            let (_, ipv4_dencap_egressors) = ProcessLink::new()
-                .ingressor(classify_egressors.remove(0))
-                .processor(processors::Ipv4Decap)
-                .build_link();
-
-            let (mut ipv4_subnet_router_runnables, mut ipv4_subnet_router_egressors) =
-                ClassifyLink::new()
-                    .ingressors(ipv4_dencap_egressors)
-                    .num_egressors(3)
-                    .classifier(ipv4_router)
-                    .dispatcher(Box::new(|c| match c {
-                        classifiers::Interface::Interface0 => 0,
-                        classifiers::Interface::Interface1 => 1,
-                        classifiers::Interface::Interface2 => 2,
-                    }))
-                    .build_link();
-            all_runnables.append(&mut ipv4_subnet_router_runnables);
-
-            let (_, mut ipv4_encap_interface0_egressors) = ProcessLink::new()
-                .ingressor(ipv4_subnet_router_egressors.remove(0))
-                .processor(processors::Ipv4Encap)
-                .build_link();
-
-            let (_, mut ipv4_encap_interface1_egressors) = ProcessLink::new()
-                .ingressor(ipv4_subnet_router_egressors.remove(0))
-                .processor(processors::Ipv4Encap)
-                .build_link();
-
-            let (_, mut ipv4_encap_interface2_egressors) = ProcessLink::new()
-                .ingressor(ipv4_subnet_router_egressors.remove(0))
-                .processor(processors::Ipv4Encap)
-                .build_link();
+            let ipv4 = templates::decap_route_encap(
+                classify_egressors.remove(0),
+                processors::Ipv4Decap,
+                classifiers::Ipv4SubnetRouter::new(classifiers::Interface::Interface0),
+                || processors::Ipv4Encap,
+            )?;
 
             //----------IPv6 Subnet Router--------------//
-
-            let (_, ipv6_dencap_egressors) = ProcessLink::new()
-                .ingressor(classify_egressors.remove(0))
-                .processor(processors::Ipv6Decap)
-                .build_link();
-
-            let (mut ipv6_subnet_router_runnables, mut ipv6_subnet_router_egressors) =
-                ClassifyLink::new()
-                    .ingressors(ipv6_dencap_egressors)
-                    .num_egressors(3)
-                    .classifier(ipv6_router)
-                    .dispatcher(Box::new(|c| match c {
-                        classifiers::Interface::Interface0 => 0,
-                        classifiers::Interface::Interface1 => 1,
-                        classifiers::Interface::Interface2 => 2,
-                    }))
-                    .build_link();
-            all_runnables.append(&mut ipv6_subnet_router_runnables);
-
-            let (_, mut ipv6_encap_interface0_egressors) = ProcessLink::new()
-                .ingressor(ipv6_subnet_router_egressors.remove(0))
-                .processor(processors::Ipv6Encap)
-                .build_link();
-
-            let (_, mut ipv6_encap_interface1_egressors) = ProcessLink::new()
-                .ingressor(ipv6_subnet_router_egressors.remove(0))
-                .processor(processors::Ipv6Encap)
-                .build_link();
-
-            let (_, mut ipv6_encap_interface2_egressors) = ProcessLink::new()
-                .ingressor(ipv6_subnet_router_egressors.remove(0))
-                .processor(processors::Ipv6Encap)
-                .build_link();
+            let ipv6 = templates::decap_route_encap(
+                classify_egressors.remove(0),
+                processors::Ipv6Decap,
+                classifiers::Ipv6SubnetRouter::new(classifiers::Interface::Interface0),
+                || processors::Ipv6Encap,
+            )?;
 
             //---------Join to interfaces--------------//
-            let mut interfaces = vec![];
-
-            let (mut join0_runnables, mut interface0) = JoinLink::new()
-                .ingressor(ipv4_encap_interface0_egressors.remove(0))
-                .ingressor(ipv6_encap_interface0_egressors.remove(0))
-                .build_link();
-            all_runnables.append(&mut join0_runnables);
-            interfaces.append(&mut interface0);
-
-            let (mut join1_runnables, mut interface1) = JoinLink::new()
-                .ingressor(ipv4_encap_interface1_egressors.remove(0))
-                .ingressor(ipv6_encap_interface1_egressors.remove(0))
-                .build_link();
-            all_runnables.append(&mut join1_runnables);
-            interfaces.append(&mut interface1);
-
-            let (mut join2_runnables, mut interface2) = JoinLink::new()
-                .ingressor(ipv4_encap_interface2_egressors.remove(0))
-                .ingressor(ipv6_encap_interface2_egressors.remove(0))
-                .build_link();
-            all_runnables.append(&mut join2_runnables);
-            interfaces.append(&mut interface2);
+            let (mut join_runnables, interfaces) = templates::join_interfaces(vec![ipv4, ipv6])?;
+            all_runnables.append(&mut join_runnables);
 
             //---------Return built Link!--------------//
-            (all_runnables, interfaces)
+            Ok((all_runnables, interfaces))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_each_ip_version_to_its_interface() {
+        let results = runner(router_runner);
+
+        assert_eq!(results.len(), templates::NUM_INTERFACES);
+        assert!(results[0].is_empty());
+        assert_eq!(results[1][0].ether_type(), 0x0800);
+        assert_eq!(results[2][0].ether_type(), 0x86DD);
+    }
+}
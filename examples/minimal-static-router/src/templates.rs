@@ -0,0 +1,149 @@
+//! Sub-pipelines the router is assembled from. Each IP version takes the same path through the
+//! router, decapsulated, routed to an interface, and encapsulated again, and the interfaces each
+//! gather the traffic of every IP version; these helpers wire up those paths once, so that adding
+//! a direction or an interface does not mean copying a chain of links.
+
+use crate::classifiers::Interface;
+use route_rs_packets::EthernetFrame;
+use route_rs_runtime::classifier::Classifier;
+use route_rs_runtime::link::{
+    primitive::{ClassifyLink, JoinLink, ProcessLink},
+    Link, LinkBuildError, LinkBuilder, PacketStream, ProcessLinkBuilder,
+};
+use route_rs_runtime::processor::Processor;
+
+/// Number of interfaces of the router, one per variant of `Interface`.
+pub const NUM_INTERFACES: usize = 3;
+
+/// Decapsulates each frame of `stream` with `decap`, routes the packet inside to an interface with
+/// `router`, and encapsulates it again with a processor made by `encap`. The link has one egressor
+/// per interface, in the order of `Interface`. Frames `decap` cannot decapsulate are dropped.
+pub fn decap_route_encap<D, R, E>(
+    stream: PacketStream<EthernetFrame>,
+    decap: D,
+    router: R,
+    encap: impl Fn() -> E,
+) -> Result<Link<EthernetFrame>, LinkBuildError>
+where
+    D: Processor<Input = EthernetFrame> + Send + 'static,
+    R: Classifier<Packet = D::Output, Class = Interface> + Send + 'static,
+    E: Processor<Input = D::Output, Output = EthernetFrame> + Send + 'static,
+{
+    // Process links don't have any runnables, so we can ignore that half of the tuple
+    let (_, decap_egressors) = ProcessLink::new()
+        .ingressor(stream)
+        .processor(decap)
+        .try_build_link()?;
+
+    let (runnables, route_egressors) = ClassifyLink::new()
+        .ingressors(decap_egressors)
+        .num_egressors(NUM_INTERFACES)
+        .classifier(router)
+        .dispatcher(Box::new(|interface| interface as usize))
+        .try_build_link()?;
+
+    let mut egressors = vec![];
+    for route_egressor in route_egressors {
+        let (_, mut encap_egressors) = ProcessLink::new()
+            .ingressor(route_egressor)
+            .processor(encap())
+            .try_build_link()?;
+        egressors.append(&mut encap_egressors);
+    }
+    Ok((runnables, egressors))
+}
+
+/// Joins the links of several paths through the router, each with one egressor per interface,
+/// into a link with one egressor per interface carrying the traffic of every path.
+pub fn join_interfaces(
+    paths: Vec<Link<EthernetFrame>>,
+) -> Result<Link<EthernetFrame>, LinkBuildError> {
+    let mut all_runnables = vec![];
+    let mut per_interface: Vec<Vec<PacketStream<EthernetFrame>>> =
+        (0..NUM_INTERFACES).map(|_| vec![]).collect();
+    for (mut runnables, egressors) in paths {
+        all_runnables.append(&mut runnables);
+        for (interface, egressor) in egressors.into_iter().enumerate() {
+            per_interface[interface].push(egressor);
+        }
+    }
+
+    let mut interfaces = vec![];
+    for streams in per_interface {
+        let (mut join_runnables, mut interface) =
+            JoinLink::new().ingressors(streams).try_build_link()?;
+        all_runnables.append(&mut join_runnables);
+        interfaces.append(&mut interface);
+    }
+    Ok((all_runnables, interfaces))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifiers::Ipv4SubnetRouter;
+    use crate::processors::{Ipv4Decap, Ipv4Encap};
+    use route_rs_runtime::utils::test::harness::{initialize_runtime, run_link};
+    use route_rs_runtime::utils::test::packet_generators::immediate_stream;
+
+    fn ipv4_frame(dest_addr: [u8; 4]) -> EthernetFrame {
+        let mut data: Vec<u8> = vec![
+            0xde, 0xad, 0xbe, 0xef, 0xff, 0xff, 1, 2, 3, 4, 5, 6, 8, 00, 0x45, 0, 0, 20, 0, 0, 0,
+            0, 64, 17, 0, 0, 192, 178, 128, 0,
+        ];
+        data.extend_from_slice(&dest_addr);
+        EthernetFrame::from_buffer(data, 0).unwrap()
+    }
+
+    #[test]
+    fn routes_to_interface_egressors() {
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = decap_route_encap(
+                immediate_stream(vec![ipv4_frame([10, 0, 0, 1]), ipv4_frame([8, 8, 8, 8])]),
+                Ipv4Decap,
+                Ipv4SubnetRouter::new(Interface::Interface0),
+                || Ipv4Encap,
+            )
+            .unwrap();
+
+            run_link(link).await
+        });
+
+        assert_eq!(results.len(), NUM_INTERFACES);
+        assert_eq!(results[0], vec![ipv4_frame([8, 8, 8, 8])]);
+        assert_eq!(results[1], vec![ipv4_frame([10, 0, 0, 1])]);
+        assert!(results[2].is_empty());
+    }
+
+    #[test]
+    fn joins_paths_per_interface() {
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let paths = vec![
+                (
+                    vec![],
+                    vec![
+                        immediate_stream(vec![ipv4_frame([0, 0, 0, 0])]),
+                        immediate_stream(vec![]),
+                        immediate_stream(vec![ipv4_frame([2, 2, 2, 2])]),
+                    ],
+                ),
+                (
+                    vec![],
+                    vec![
+                        immediate_stream(vec![ipv4_frame([0, 0, 0, 1])]),
+                        immediate_stream(vec![ipv4_frame([1, 1, 1, 1])]),
+                        immediate_stream(vec![]),
+                    ],
+                ),
+            ];
+
+            run_link(join_interfaces(paths).unwrap()).await
+        });
+
+        assert_eq!(results[0].len(), 2);
+        assert_eq!(results[1], vec![ipv4_frame([1, 1, 1, 1])]);
+        assert_eq!(results[2], vec![ipv4_frame([2, 2, 2, 2])]);
+    }
+}
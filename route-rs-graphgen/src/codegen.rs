@@ -396,6 +396,13 @@ pub fn expr_path_ident(id: &str) -> syn::Expr {
     })
 }
 
+pub fn expr_usize(n: usize) -> syn::Expr {
+    syn::Expr::Lit(syn::ExprLit {
+        attrs: vec![],
+        lit: syn::Lit::Int(syn::LitInt::new(n.to_string().as_str(), fake_span())),
+    })
+}
+
 pub fn builder(base: syn::Ident, setters: Vec<(syn::Ident, Vec<syn::Expr>)>) -> syn::Expr {
     let mut expr_accum = syn::Expr::Call(syn::ExprCall {
         attrs: vec![],
@@ -0,0 +1,126 @@
+use crate::pipeline_graph::{NodeKind, PipelineGraph};
+
+/// Escapes the characters of `s` that would end a quoted DOT ID early.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Quotes `s` as a DOT ID.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", escape(s))
+}
+
+/// Renders `graph` as a Graphviz DOT digraph, for eyeballing the topology of a pipeline. Each node
+/// is labeled with its class and kind, and drawn as a diamond for IO, a trapezium for classifiers,
+/// a box for processors and a double box for composites. Edges are labeled with their outlet, if
+/// they have one.
+pub fn to_dot(graph: &PipelineGraph) -> String {
+    let mut lines = vec![
+        String::from("digraph pipeline {"),
+        String::from("    rankdir=LR;"),
+    ];
+
+    for node in graph.ordered_nodes() {
+        let (kind, shape) = match &node.node_kind {
+            NodeKind::IO => (String::from("IO"), "diamond"),
+            NodeKind::Classifier => (String::from("Classifier"), "trapezium"),
+            NodeKind::Processor => (String::from("Processor"), "box"),
+            NodeKind::Composite(link_type) => (link_type.to_owned(), "box3d"),
+        };
+        // The class and kind go on separate lines, so the newline between them must not be escaped.
+        let label = if node.node_class.is_empty() {
+            quote(&kind)
+        } else {
+            format!("\"{}\\n{}\"", escape(&node.node_class), escape(&kind))
+        };
+        lines.push(format!(
+            "    {} [label={}, shape={}];",
+            quote(&node.xml_node_id),
+            label,
+            shape
+        ));
+    }
+
+    for edge in graph.edges() {
+        let attrs = match &edge.label {
+            Some(label) => format!(" [label={}]", quote(label)),
+            None => String::new(),
+        };
+        lines.push(format!(
+            "    {} -> {}{};",
+            quote(&edge.source),
+            quote(&edge.target),
+            attrs
+        ));
+    }
+
+    lines.push(String::from("}"));
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod to_dot {
+    use super::*;
+    use std::io::Cursor;
+    use xml::reader::EventReader;
+
+    fn dot(xml: &str) -> String {
+        super::to_dot(&PipelineGraph::merge(vec![(
+            String::from("graph.xml"),
+            EventReader::new(Cursor::new(xml)),
+        )]))
+    }
+
+    fn count_nodes(dot: &str) -> usize {
+        dot.lines()
+            .filter(|l| l.contains("[label=") && !l.contains("->"))
+            .count()
+    }
+
+    fn count_edges(dot: &str) -> usize {
+        dot.lines().filter(|l| l.contains("->")).count()
+    }
+
+    #[test]
+    fn example_pipeline() {
+        let dot = dot(include_str!(
+            "../../examples/dns-interceptor/src/pipeline.xml"
+        ));
+
+        assert!(dot.starts_with("digraph pipeline {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert_eq!(count_nodes(&dot), 5);
+        assert_eq!(count_edges(&dot), 5);
+        assert!(
+            dot.contains(r#""processor-2" [label="ClassifyDNS\nClassifier", shape=trapezium];"#)
+        );
+        assert!(dot.contains(r#""processor-2" -> "processor-3" [label="ClassifyDNSOutput::DNS"];"#));
+        assert!(dot.contains(r#""input-1" -> "processor-1";"#));
+    }
+
+    #[test]
+    fn composite_pipeline() {
+        let dot = dot(include_str!(
+            "../../examples/composite-fanout/src/pipeline.xml"
+        ));
+
+        assert_eq!(count_nodes(&dot), 3);
+        assert_eq!(count_edges(&dot), 3);
+        assert!(dot.contains("shape=box3d"));
+    }
+
+    #[test]
+    fn escapes_quotes() {
+        let dot = dot(r#"<?xml version="1.0" encoding="UTF-8"?>
+            <mxGraphModel>
+                <root>
+                    <mxCell id="input-1" style="rhombus" vertex="1" value="IntegerPacket"/>
+                    <mxCell id="output-1" style="rhombus" vertex="1" value="IntegerPacket"/>
+                    <mxCell id="link-1" edge="1" value="&quot;odd&quot;" source="input-1" target="output-1"/>
+                </root>
+            </mxGraphModel>
+        "#);
+
+        assert!(dot.contains(r#"[label="\"odd\""]"#));
+    }
+}
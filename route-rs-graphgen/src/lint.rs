@@ -0,0 +1,128 @@
+use crate::pipeline_graph::{NodeData, NodeKind, PipelineGraph};
+use std::fmt;
+
+/// A mistake in a pipeline graph that would not stop code from being generated, but would make
+/// the pipeline misbehave.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lint {
+    /// A node that nothing feeds, so it will never see a packet. Inputs are not fed by design.
+    Unreachable(NodeData),
+    /// A node whose egressors nothing consumes, so every packet it emits is leaked. Outputs are
+    /// not consumed by design.
+    Dangling(NodeData),
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Lint::Unreachable(node) => write!(
+                f,
+                "{} ({}) is unreachable: it has no inbound edges",
+                node.xml_node_id, node.node_class
+            ),
+            Lint::Dangling(node) => write!(
+                f,
+                "{} ({}) is dangling: its egressors have no consumers",
+                node.xml_node_id, node.node_class
+            ),
+        }
+    }
+}
+
+/// Reports the nodes of `graph` that have no inbound edges or no outbound edges. IO nodes are
+/// the sources and sinks of the pipeline, so they are expected to lack one or the other.
+pub fn lint(graph: &PipelineGraph) -> Vec<Lint> {
+    let edges = graph.edges();
+    let mut lints = vec![];
+
+    for node in graph.ordered_nodes() {
+        if node.node_kind == NodeKind::IO {
+            continue;
+        }
+        if !edges.iter().any(|e| e.target == node.xml_node_id) {
+            lints.push(Lint::Unreachable(node.to_owned()));
+        }
+        if !edges.iter().any(|e| e.source == node.xml_node_id) {
+            lints.push(Lint::Dangling(node.to_owned()));
+        }
+    }
+
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use xml::reader::EventReader;
+
+    fn lint_xml(cells: &str) -> Vec<String> {
+        let xml = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <mxGraphModel>
+                <root>
+                    <mxCell id="input-1" style="rhombus" vertex="1" value="IntegerPacket"/>
+                    <mxCell id="output-1" style="rhombus" vertex="1" value="IntegerPacket"/>
+                    {}
+                </root>
+            </mxGraphModel>
+        "#,
+            cells
+        );
+        let graph = PipelineGraph::merge(vec![(
+            String::from("graph.xml"),
+            EventReader::new(Cursor::new(xml)),
+        )]);
+
+        lint(&graph).iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn clean_graph() {
+        let lints = lint_xml(
+            r#"
+            <mxCell id="processor-1" style="" vertex="1" value="Identity"/>
+            <mxCell id="link-1" edge="1" source="input-1" target="processor-1"/>
+            <mxCell id="link-2" edge="1" source="processor-1" target="output-1"/>
+            "#,
+        );
+
+        assert!(lints.is_empty(), "{:?}", lints);
+    }
+
+    #[test]
+    fn dangling_egressor() {
+        let lints = lint_xml(
+            r#"
+            <mxCell id="processor-1" style="" vertex="1" value="Identity"/>
+            <mxCell id="processor-2" style="" vertex="1" value="CountPackets"/>
+            <mxCell id="link-1" edge="1" source="input-1" target="processor-1"/>
+            <mxCell id="link-2" edge="1" source="processor-1" target="output-1"/>
+            <mxCell id="link-3" edge="1" source="processor-1" target="processor-2"/>
+            "#,
+        );
+
+        assert_eq!(
+            lints,
+            vec!["processor-2 (CountPackets) is dangling: its egressors have no consumers"]
+        );
+    }
+
+    #[test]
+    fn unreachable_node() {
+        let lints = lint_xml(
+            r#"
+            <mxCell id="processor-1" style="" vertex="1" value="Identity"/>
+            <mxCell id="processor-2" style="" vertex="1" value="Orphan"/>
+            <mxCell id="link-1" edge="1" source="input-1" target="processor-1"/>
+            <mxCell id="link-2" edge="1" source="processor-1" target="output-1"/>
+            <mxCell id="link-3" edge="1" source="processor-2" target="output-1"/>
+            "#,
+        );
+
+        assert_eq!(
+            lints,
+            vec!["processor-2 (Orphan) is unreachable: it has no inbound edges"]
+        );
+    }
+}
@@ -11,24 +11,42 @@ use xml::reader::EventReader;
 
 use crate::codegen::magic_newline_stmt;
 use crate::pipeline_graph::{EdgeData, NodeData, NodeKind, PipelineGraph, XmlNodeId};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::iter::FromIterator;
 use syn::export::ToTokens;
 
 mod codegen;
+mod dot;
+mod lint;
 mod pipeline_graph;
 
 enum Link {
     Input,
     Output((XmlNodeId, Option<String>)),
     Sync((XmlNodeId, Option<String>), XmlNodeId),
-    Classify((XmlNodeId, Option<String>), XmlNodeId, Vec<String>),
-    Join(Vec<(XmlNodeId, Option<String>)>),
+    Classify(
+        (XmlNodeId, Option<String>),
+        XmlNodeId,
+        Vec<String>,
+        Option<usize>,
+    ),
+    Join(Vec<(XmlNodeId, Option<String>)>, Option<usize>),
+    Composite {
+        feeders: Vec<(XmlNodeId, Option<String>)>,
+        link_type: String,
+        processor: Option<XmlNodeId>,
+        outlets: Vec<Option<String>>,
+        queue_capacities: BTreeMap<String, usize>,
+    },
 }
 
-fn gen_source_imports(local_modules: Vec<&str>, runtime_modules: Vec<&str>) -> String {
+fn gen_source_imports(
+    local_modules: Vec<&str>,
+    runtime_modules: Vec<&str>,
+    uses_composites: bool,
+) -> String {
     let mut imports = vec![];
     for lm in local_modules {
         imports.push(syn::UseTree::Path(codegen::use_path(
@@ -56,6 +74,18 @@ fn gen_source_imports(local_modules: Vec<&str>, runtime_modules: Vec<&str>) -> S
             )),
         )),
     )));
+    if uses_composites {
+        imports.push(syn::UseTree::Path(codegen::use_path(
+            "route_rs_runtime",
+            syn::UseTree::Path(codegen::use_path(
+                "link",
+                syn::UseTree::Path(codegen::use_path(
+                    "composite",
+                    syn::UseTree::Glob(codegen::use_glob()),
+                )),
+            )),
+        )));
+    }
     for rm in runtime_modules {
         imports.push(syn::UseTree::Path(codegen::use_path(
             "route_rs_runtime",
@@ -195,7 +225,7 @@ fn gen_link_decls(
                         1
                     )
                 }
-                Link::Classify(feeder, processor, branches) => {
+                Link::Classify(feeder, processor, branches, queue_capacity) => {
                     let mut match_branches = vec![];
                     for branch_index in 0..(branches.len()) {
                         match_branches.push((
@@ -210,10 +240,7 @@ fn gen_link_decls(
                             format!("link_{}_egress_{}", decl_idx, branch_index),
                         );
                     }
-                    codegen::build_link(
-                        decl_idx,
-                        "ClassifyLink",
-                        vec![
+                    let mut setters = vec![
                             (codegen::ident("ingressor"), vec![codegen::expr_path_ident(map_get_with_panic(&link_decls_map, &feeder).as_str())]),
                             (codegen::ident("classifier"), vec![codegen::expr_path_ident(processor_decls.get(processor.as_str()).unwrap())]),
                             (codegen::ident("dispatcher"), vec![syn::Expr::Call(syn::ExprCall {
@@ -258,11 +285,18 @@ fn gen_link_decls(
                                 )].into_iter()),
                             })]),
                             (codegen::ident("num_egressors"), vec![syn::Expr::Lit(syn::ExprLit { attrs: vec![], lit: syn::Lit::Int(syn::LitInt::new(branches.len().to_string().as_str(), proc_macro2::Span::call_site())) })]),
-                        ],
+                        ];
+                    if let Some(queue_capacity) = queue_capacity {
+                        setters.push((codegen::ident("queue_capacity"), vec![codegen::expr_usize(*queue_capacity)]));
+                    }
+                    codegen::build_link(
+                        decl_idx,
+                        "ClassifyLink",
+                        setters,
                         branches.len()
                     )
                 }
-                Link::Join(feeders) => {
+                Link::Join(feeders, queue_capacity) => {
                     let egressor_symbol = format!("link_{}_egress_{}", decl_idx, 0);
                     link_decls_map.insert((id.to_owned(), None), egressor_symbol);
                     let mut feeders_decls = vec![];
@@ -272,15 +306,43 @@ fn gen_link_decls(
                             &feeders.get(feeder_index).unwrap(),
                         ));
                     }
+                    let mut setters = vec![
+                        (codegen::ident("ingressors"), vec![codegen::vec(feeders_decls.into_iter().map(|d| codegen::expr_path_ident(d)).collect::<Vec<syn::Expr>>())])
+                    ];
+                    if let Some(queue_capacity) = queue_capacity {
+                        setters.push((codegen::ident("queue_capacity"), vec![codegen::expr_usize(*queue_capacity)]));
+                    }
                     codegen::build_link(
                         decl_idx,
                         "JoinLink",
-                        vec![
-                            (codegen::ident("ingressors"), vec![codegen::vec(feeders_decls.into_iter().map(|d| codegen::expr_path_ident(d)).collect::<Vec<syn::Expr>>())])
-                        ],
+                        setters,
                         1
                     )
                 }
+                Link::Composite { feeders, link_type, processor, outlets, queue_capacities } => {
+                    let feeders_decls = feeders
+                        .iter()
+                        .map(|f| codegen::expr_path_ident(map_get_with_panic(&link_decls_map, f).as_str()))
+                        .collect::<Vec<syn::Expr>>();
+                    for (egress_index, outlet) in outlets.iter().enumerate() {
+                        let key = (id.to_owned(), outlet.to_owned());
+                        if link_decls_map.contains_key(&key) {
+                            panic!("{} has more than one egressor labeled {:?}, label each one uniquely", id, outlet);
+                        }
+                        link_decls_map.insert(key, format!("link_{}_egress_{}", decl_idx, egress_index));
+                    }
+                    let mut setters = vec![
+                        (codegen::ident("ingressors"), vec![codegen::vec(feeders_decls)]),
+                    ];
+                    if let Some(processor) = processor {
+                        setters.push((codegen::ident("processor"), vec![codegen::expr_path_ident(processor_decls.get(processor.as_str()).unwrap())]));
+                    }
+                    for (setter, capacity) in queue_capacities {
+                        setters.push((codegen::ident(setter), vec![codegen::expr_usize(*capacity)]));
+                    }
+                    setters.push((codegen::ident("num_egressors"), vec![codegen::expr_usize(outlets.len())]));
+                    codegen::build_link(decl_idx, link_type, setters, outlets.len())
+                }
             }
         })
         .collect();
@@ -405,10 +467,23 @@ fn gen_tokio_run() -> Vec<syn::Stmt> {
     ]
 }
 
+/// Panics if `node` sets a queue capacity that the links generated for it do not have.
+fn check_queue_capacities(node: &NodeData, supported: &[&str]) {
+    for name in node.queue_capacities.keys() {
+        if !supported.contains(&name.as_str()) {
+            panic!(
+                "{} of {} is not supported, expected one of {:?}",
+                name, node.xml_node_id, supported
+            );
+        }
+    }
+}
+
 fn expand_join_link<'a>(
     feeders: &[&&EdgeData],
     links: &mut Vec<(String, Link)>,
     orig_xml_node_id: &str,
+    join_queue_capacity: Option<usize>,
     link_builder: Box<dyn Fn(XmlNodeId, Option<String>) -> Link + 'a>,
 ) {
     if feeders.len() == 1 {
@@ -422,7 +497,10 @@ fn expand_join_link<'a>(
             .iter()
             .map(|f| (f.source.to_owned(), f.label.to_owned()))
             .collect::<Vec<(XmlNodeId, Option<String>)>>();
-        links.push((join_xml_node_id.to_owned(), Link::Join(join_feeders)));
+        links.push((
+            join_xml_node_id.to_owned(),
+            Link::Join(join_feeders, join_queue_capacity),
+        ));
         links.push((
             orig_xml_node_id.to_owned(),
             link_builder(join_xml_node_id, None),
@@ -444,15 +522,19 @@ fn gen_run_body(
             .iter()
             .filter(|e| e.target == nd.xml_node_id)
             .collect();
+        let join_queue_capacity = nd.queue_capacities.get("join_queue_capacity").cloned();
         match &nd.node_kind {
             NodeKind::IO => {
                 if nd.xml_node_id == input_node.xml_node_id {
+                    check_queue_capacities(nd, &[]);
                     links.push((nd.xml_node_id.to_owned(), Link::Input));
                 } else if nd.xml_node_id == output_node.xml_node_id {
+                    check_queue_capacities(nd, &["join_queue_capacity"]);
                     expand_join_link(
                         &feeders,
                         &mut links,
                         &nd.xml_node_id,
+                        join_queue_capacity,
                         Box::new(|xni, label| Link::Output((xni, label))),
                     );
                 } else {
@@ -460,11 +542,13 @@ fn gen_run_body(
                 }
             }
             NodeKind::Processor => {
+                check_queue_capacities(nd, &["join_queue_capacity"]);
                 processors.push(nd);
                 expand_join_link(
                     &feeders,
                     &mut links,
                     &nd.xml_node_id,
+                    join_queue_capacity,
                     Box::new(|xni, label| Link::Sync((xni, label), nd.xml_node_id.to_owned())),
                 );
             }
@@ -474,16 +558,52 @@ fn gen_run_body(
                     .filter(|e| e.source == nd.xml_node_id)
                     .map(|e| e.label.clone().unwrap())
                     .collect();
+                check_queue_capacities(nd, &["join_queue_capacity", "queue_capacity"]);
+                let queue_capacity = nd.queue_capacities.get("queue_capacity").cloned();
                 processors.push(nd);
                 expand_join_link(
                     &feeders,
                     &mut links,
                     &nd.xml_node_id,
+                    join_queue_capacity,
                     Box::new(|xni, label| {
-                        Link::Classify((xni, label), nd.xml_node_id.to_owned(), outlets.to_owned())
+                        Link::Classify(
+                            (xni, label),
+                            nd.xml_node_id.to_owned(),
+                            outlets.to_owned(),
+                            queue_capacity,
+                        )
                     }),
                 );
             }
+            NodeKind::Composite(link_type) => {
+                // Composites take any number of ingressors, so they are fed directly rather than
+                // through a JoinLink.
+                let outlets: Vec<Option<String>> = edges
+                    .iter()
+                    .filter(|e| e.source == nd.xml_node_id)
+                    .map(|e| e.label.clone())
+                    .collect();
+                let processor = if nd.node_class.is_empty() {
+                    None
+                } else {
+                    processors.push(nd);
+                    Some(nd.xml_node_id.to_owned())
+                };
+                links.push((
+                    nd.xml_node_id.to_owned(),
+                    Link::Composite {
+                        feeders: feeders
+                            .iter()
+                            .map(|f| (f.source.to_owned(), f.label.to_owned()))
+                            .collect(),
+                        link_type: link_type.to_owned(),
+                        processor,
+                        outlets,
+                        queue_capacities: nd.queue_capacities.to_owned(),
+                    },
+                ));
+            }
         }
     }
 
@@ -610,7 +730,7 @@ fn gen_source_pipeline(nodes: Vec<&NodeData>, edges: Vec<&EdgeData>) -> String {
 }
 
 fn generate_pipeline_source(
-    source_graph_path: PathBuf,
+    source_graph_paths: Vec<PathBuf>,
     local_modules: Vec<&str>,
     runtime_modules: Vec<&str>,
     nodes: Vec<&NodeData>,
@@ -620,9 +740,19 @@ fn generate_pipeline_source(
         codegen::comment(format!(
             "Generated by route-rs-graphgen\n\
              Source graph: {}",
-            source_graph_path.as_path().display()
+            source_graph_paths
+                .iter()
+                .map(|p| p.as_path().display().to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
         )),
-        gen_source_imports(local_modules, runtime_modules),
+        gen_source_imports(
+            local_modules,
+            runtime_modules,
+            nodes
+                .iter()
+                .any(|n| matches!(n.node_kind, NodeKind::Composite(_))),
+        ),
         gen_source_pipeline(nodes, edges),
     ]
     .join("\n\n")
@@ -642,6 +772,27 @@ fn get_pathbuf_arg(arg_matches: &ArgMatches, name: &str) -> PathBuf {
     Path::new(arg_matches.value_of(name).unwrap()).to_path_buf()
 }
 
+/// Prints the pipeline source to stdout, passing it through rustfmt first if asked to.
+fn print_pipeline_source(pipeline_source: String, rustfmt: bool) {
+    let source = codegen::unmagic_newlines(pipeline_source);
+    if rustfmt {
+        let mut rustfmt = std::process::Command::new("rustfmt")
+            .args(&["--edition", "2018"])
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        rustfmt
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(source.as_bytes())
+            .unwrap();
+        assert!(rustfmt.wait().unwrap().success())
+    } else {
+        print!("{}", source);
+    }
+}
+
 fn main() {
     let app = App::new("route-rs graphgen")
         .version("0.1.0")
@@ -661,7 +812,10 @@ fn main() {
                 .short("g")
                 .long("graph")
                 .value_name("GRAPH_FILE")
+                .help("Graph to generate from, may be repeated to merge several graphs")
                 .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
                 .required(true)
                 .validator(|g| {
                     if Path::new(&g).is_file() {
@@ -677,7 +831,7 @@ fn main() {
                 .long("output")
                 .value_name("OUTPUT_FILE")
                 .takes_value(true)
-                .required(true)
+                .required_unless_one(&["lint", "dry-run", "dot"])
                 .validator(|g| {
                     if Path::new(&g).parent().unwrap().is_dir() {
                         Ok(())
@@ -686,6 +840,35 @@ fn main() {
                     }
                 }),
         )
+        .arg(
+            Arg::with_name("dot")
+                .long("dot")
+                .value_name("DOT_FILE")
+                .help("Write the parsed graph as Graphviz DOT, for debugging its topology")
+                .takes_value(true)
+                .validator(|d| {
+                    if Path::new(&d).parent().unwrap().is_dir() {
+                        Ok(())
+                    } else {
+                        Err(format!("Path {} is not in a directory", d))
+                    }
+                }),
+        )
+        .arg(
+            Arg::with_name("lint")
+                .long("lint")
+                .help("Only check the graph for unreachable and dangling nodes"),
+        )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .help("Fail if the graph has unreachable or dangling nodes"),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Print the generated pipeline to stdout instead of writing the output file"),
+        )
         .arg(
             Arg::with_name("rustfmt")
                 .long("rustfmt")
@@ -709,10 +892,43 @@ fn main() {
         )
         .get_matches();
 
-    let graph_file_path = get_pathbuf_arg(&app, "graph");
-    let graph_file = File::open(&graph_file_path).unwrap();
-    let graph_xml = EventReader::new(BufReader::new(graph_file));
-    let graph = PipelineGraph::new(graph_xml);
+    let graph_file_paths: Vec<PathBuf> = app
+        .values_of("graph")
+        .unwrap()
+        .map(|g| Path::new(g).to_path_buf())
+        .collect();
+    let graph_xmls = graph_file_paths
+        .iter()
+        .map(|path| {
+            let graph_file = File::open(path).unwrap();
+            (
+                path.as_path().display().to_string(),
+                EventReader::new(BufReader::new(graph_file)),
+            )
+        })
+        .collect();
+    let graph = PipelineGraph::merge(graph_xmls);
+
+    let lints = lint::lint(&graph);
+    for l in lints.iter() {
+        eprintln!("warning: {}", l);
+    }
+    if app.is_present("strict") && !lints.is_empty() {
+        eprintln!("error: {} lint(s) found in strict mode", lints.len());
+        std::process::exit(1);
+    }
+    if app.is_present("lint") {
+        return;
+    }
+
+    if app.is_present("dot") {
+        let dot_file_path = get_pathbuf_arg(&app, "dot");
+        let mut dot_file = File::create(&dot_file_path).unwrap();
+        dot_file.write_all(dot::to_dot(&graph).as_bytes()).unwrap();
+        if !app.is_present("output") && !app.is_present("dry-run") {
+            return;
+        }
+    }
 
     let local_modules: Vec<&str> = get_array_arg(&app, "local-modules");
     let runtime_modules: Vec<&str> = get_array_arg(&app, "runtime-modules");
@@ -720,14 +936,19 @@ fn main() {
     let ordered_nodes = graph.ordered_nodes();
     let edges = graph.edges();
 
-    let output_file_path = get_pathbuf_arg(&app, "output");
     let pipeline_source = generate_pipeline_source(
-        graph_file_path,
+        graph_file_paths,
         local_modules,
         runtime_modules,
         ordered_nodes,
         edges,
     );
+    if app.is_present("dry-run") {
+        print_pipeline_source(pipeline_source, app.is_present("rustfmt"));
+        return;
+    }
+
+    let output_file_path = get_pathbuf_arg(&app, "output");
     let mut output_file = File::create(&output_file_path).unwrap();
     output_file
         .write_all(codegen::unmagic_newlines(pipeline_source).as_bytes())
@@ -740,3 +961,110 @@ fn main() {
         assert!(rustfmt.unwrap().success())
     }
 }
+
+#[cfg(test)]
+mod generate_pipeline_source {
+    use super::*;
+    use std::io::Cursor;
+
+    fn generate(styles: (&str, &str)) -> String {
+        let (classifier_style, output_style) = styles;
+        let xml = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <mxGraphModel>
+                <root>
+                    <mxCell id="input-1" style="rhombus" vertex="1" value="IntegerPacket"/>
+                    <mxCell id="output-1" style="rhombus;{}" vertex="1" value="IntegerPacket"/>
+                    <mxCell id="classifier-1" style="{}" vertex="1" value="ClassifyEven"/>
+                    <mxCell id="link-1" edge="1" source="input-1" target="classifier-1"/>
+                    <mxCell id="link-2" edge="1" value="true" source="classifier-1" target="output-1"/>
+                    <mxCell id="link-3" edge="1" value="false" source="classifier-1" target="output-1"/>
+                </root>
+            </mxGraphModel>
+        "#,
+            output_style, classifier_style
+        );
+        let graph = PipelineGraph::merge(vec![(
+            String::from("pipeline.xml"),
+            EventReader::new(Cursor::new(xml)),
+        )]);
+
+        let source = super::generate_pipeline_source(
+            vec![PathBuf::from("pipeline.xml")],
+            vec![],
+            vec![],
+            graph.ordered_nodes(),
+            graph.edges(),
+        );
+        source.split_whitespace().collect()
+    }
+
+    #[test]
+    fn default_queue_capacities() {
+        let source = generate(("", ""));
+
+        assert!(!source.contains("queue_capacity"));
+    }
+
+    #[test]
+    fn custom_queue_capacities() {
+        let source = generate(("queue_capacity=25", "join_queue_capacity=40"));
+
+        assert!(source.contains("ClassifyLink::new()"));
+        assert!(source.contains(".num_egressors(2).queue_capacity(25).build_link()"));
+        assert!(source.contains("JoinLink::new()"));
+        assert!(source.contains("]).queue_capacity(40).build_link()"));
+    }
+
+    #[test]
+    fn merged_graphs() {
+        let ipv4_xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <mxGraphModel>
+                <root>
+                    <mxCell id="input-1" style="rhombus" vertex="1" value="IntegerPacket"/>
+                    <mxCell id="ipv4-1" style="" vertex="1" value="Ipv4Check"/>
+                    <mxCell id="link-1" edge="1" source="input-1" target="ipv4-1"/>
+                </root>
+            </mxGraphModel>
+        "#;
+        let output_xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <mxGraphModel>
+                <root>
+                    <mxCell id="output-1" style="rhombus" vertex="1" value="IntegerPacket"/>
+                    <mxCell id="link-2" edge="1" source="ipv4-1" target="output-1"/>
+                </root>
+            </mxGraphModel>
+        "#;
+        let graph = PipelineGraph::merge(vec![
+            (
+                String::from("ipv4.xml"),
+                EventReader::new(Cursor::new(ipv4_xml)),
+            ),
+            (
+                String::from("output.xml"),
+                EventReader::new(Cursor::new(output_xml)),
+            ),
+        ]);
+
+        let source: String = super::generate_pipeline_source(
+            vec![PathBuf::from("ipv4.xml"), PathBuf::from("output.xml")],
+            vec![],
+            vec![],
+            graph.ordered_nodes(),
+            graph.edges(),
+        )
+        .split_whitespace()
+        .collect();
+
+        assert!(source.contains("//Sourcegraph:ipv4.xml,output.xml"));
+        assert!(source
+            .contains("ProcessLink::new().ingressor(link_1_egress_0).processor(elem_1_ipv4check)"));
+        assert!(source.contains("OutputChannelLink::new().ingressor(link_2_egress_0)"));
+    }
+
+    #[test]
+    #[should_panic(expected = "queue_capacity of output-1 is not supported")]
+    fn unsupported_queue_capacity() {
+        generate(("", "queue_capacity=40"));
+    }
+}
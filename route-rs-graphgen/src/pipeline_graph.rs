@@ -1,6 +1,6 @@
 use petgraph::graph::NodeIndex;
 use petgraph::{Directed, Graph};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io::Read;
 use xml::attribute::OwnedAttribute;
 use xml::name::OwnedName;
@@ -13,6 +13,8 @@ pub enum NodeKind {
     Classifier,
     Processor,
     IO,
+    /// A composite link, such as `MtransformNLink`, named by its type.
+    Composite(String),
 }
 
 impl Default for NodeKind {
@@ -26,6 +28,8 @@ pub struct NodeData {
     pub xml_node_id: XmlNodeId,
     pub node_class: String,
     pub node_kind: NodeKind,
+    /// Builder settings such as `join_queue_capacity`, keyed by setter name.
+    pub queue_capacities: BTreeMap<String, usize>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
@@ -41,22 +45,38 @@ pub struct PipelineGraph {
 }
 
 impl PipelineGraph {
-    pub fn new<R: Read>(xml_source: EventReader<R>) -> Self {
-        let (nodes, edges) = nodes_edges_from_xml(xml_source);
-
+    /// Builds one graph from several named XML sources. Edges may connect nodes declared in
+    /// different sources, since they refer to nodes by id. A node id declared more than once is
+    /// an error.
+    pub fn merge<R: Read>(xml_sources: Vec<(String, EventReader<R>)>) -> Self {
         let mut graph = Graph::<NodeData, EdgeData, Directed>::new();
 
-        let mut node_map = HashMap::<XmlNodeId, NodeIndex>::new();
-
-        for n in nodes {
-            let node_name = n.xml_node_id.clone();
-            let index = graph.add_node(n);
-            node_map.insert(node_name, index);
+        let mut node_map = HashMap::<XmlNodeId, (NodeIndex, String)>::new();
+        let mut all_edges = vec![];
+
+        for (source_name, xml_source) in xml_sources {
+            let (nodes, edges) = nodes_edges_from_xml(xml_source);
+            for n in nodes {
+                if let Some((_, other_source_name)) = node_map.get(&n.xml_node_id) {
+                    panic!(
+                        "Node id {} is declared in both {} and {}",
+                        n.xml_node_id, other_source_name, source_name
+                    );
+                }
+                let node_name = n.xml_node_id.clone();
+                let index = graph.add_node(n);
+                node_map.insert(node_name, (index, source_name.clone()));
+            }
+            all_edges.extend(edges);
         }
 
-        for e in edges {
-            let source_index = node_map[&e.source];
-            let target_index = node_map[&e.target];
+        let node_index = |edge: &EdgeData, id: &XmlNodeId| match node_map.get(id) {
+            Some((index, _)) => *index,
+            None => panic!("Edge {} refers to unknown node {}", edge.xml_node_id, id),
+        };
+        for e in all_edges {
+            let source_index = node_index(&e, &e.source);
+            let target_index = node_index(&e, &e.target);
             graph.extend_with_edges(&[(source_index, target_index, e)]);
         }
 
@@ -67,10 +87,11 @@ impl PipelineGraph {
 
     /// Converts processors that have multiple output edges into Classifiers. In the future we'll
     /// want to distinguish between Classifiers and Tees based on whether they have labels, but for
-    /// now we only have a Classifier example.
+    /// now we only have a Classifier example. Composites fan out on their own, so they are left
+    /// alone.
     pub fn mark_classifiers(&mut self) {
         self.graph.node_indices().for_each(|ni| {
-            if self.graph.edges(ni).count() > 1 {
+            if self.graph.edges(ni).count() > 1 && self.graph[ni].node_kind == NodeKind::Processor {
                 let mut weight = self.graph.node_weight_mut(ni).unwrap();
                 weight.node_kind = NodeKind::Classifier;
             }
@@ -139,7 +160,10 @@ mod PipelineGraph_ordered_nodes {
             </mxGraphModel>
         "#;
 
-        let pg = PipelineGraph::new(EventReader::new(Cursor::new(xml)));
+        let pg = PipelineGraph::merge(vec![(
+            String::from("graph.xml"),
+            EventReader::new(Cursor::new(xml)),
+        )]);
         let nodes = pg.nodes();
         let nodes_set: HashSet<&&NodeData> = HashSet::from_iter(nodes.iter());
         let ordered_nodes = pg.ordered_nodes();
@@ -149,11 +173,85 @@ mod PipelineGraph_ordered_nodes {
     }
 }
 
+#[cfg(test)]
+#[allow(non_snake_case)]
+mod PipelineGraph_merge {
+    use super::*;
+    use std::io::Cursor;
+
+    const IPV4_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <mxGraphModel>
+            <root>
+                <mxCell id="input-1" style="rhombus" vertex="1" value="Packet"/>
+                <mxCell id="ipv4-1" style="" vertex="1" value="Ipv4Check"/>
+                <mxCell id="link-1" edge="1" source="input-1" target="ipv4-1"/>
+            </root>
+        </mxGraphModel>
+    "#;
+
+    fn source(name: &str, xml: &'static str) -> (String, EventReader<Cursor<&'static str>>) {
+        (String::from(name), EventReader::new(Cursor::new(xml)))
+    }
+
+    #[test]
+    fn cross_file_edges() {
+        let output_xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <mxGraphModel>
+                <root>
+                    <mxCell id="output-1" style="rhombus" vertex="1" value="Packet"/>
+                    <mxCell id="link-2" edge="1" source="ipv4-1" target="output-1"/>
+                </root>
+            </mxGraphModel>
+        "#;
+
+        let pg = PipelineGraph::merge(vec![
+            source("ipv4.xml", IPV4_XML),
+            source("output.xml", output_xml),
+        ]);
+
+        let ordered_ids: Vec<&str> = pg
+            .ordered_nodes()
+            .iter()
+            .map(|n| n.xml_node_id.as_str())
+            .collect();
+        assert_eq!(ordered_ids, vec!["input-1", "ipv4-1", "output-1"]);
+        assert_eq!(pg.edges().len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Node id input-1 is declared in both ipv4.xml and copy.xml")]
+    fn duplicate_node_ids() {
+        PipelineGraph::merge(vec![
+            source("ipv4.xml", IPV4_XML),
+            source("copy.xml", IPV4_XML),
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Edge link-1 refers to unknown node ipv4-1")]
+    fn unknown_node() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <mxGraphModel>
+                <root>
+                    <mxCell id="input-1" style="rhombus" vertex="1" value="Packet"/>
+                    <mxCell id="link-1" edge="1" source="input-1" target="ipv4-1"/>
+                </root>
+            </mxGraphModel>
+        "#;
+
+        PipelineGraph::merge(vec![source("input.xml", xml)]);
+    }
+}
+
 /// Given an EventReader of XML source code, returns a vector of nodes and a vector of edges
 /// extracted from that source.
 ///
-/// Nodes with the rhombus shape are considered IO types. Nodes with the default shape are
-/// considered Processor types.
+/// Nodes with the rhombus shape are considered IO types. Nodes with a `composite=LinkType` style
+/// are considered Composite types, whose value is the processor they are built with, if any. Nodes
+/// with the default shape are considered Processor types.
+///
+/// Styles ending in `queue_capacity`, such as `join_queue_capacity=20`, are collected as the
+/// queue capacities of the node. Each must be within `QUEUE_CAPACITY_RANGE`.
 fn nodes_edges_from_xml<R: Read>(xml_source: EventReader<R>) -> (Vec<NodeData>, Vec<EdgeData>) {
     let mut nodes = vec![];
     let mut edges = vec![];
@@ -172,14 +270,23 @@ fn nodes_edges_from_xml<R: Read>(xml_source: EventReader<R>) -> (Vec<NodeData>,
             if xml_node_name == "mxCell" {
                 if has_attr(&attrs, "vertex") {
                     let styles = get_styles(&attrs);
+                    let xml_node_id = get_attr(&attrs, "id").unwrap();
+                    let queue_capacities = styles
+                        .iter()
+                        .filter(|(k, _)| k.ends_with("queue_capacity"))
+                        .map(|(k, v)| (k.to_owned(), parse_queue_capacity(&xml_node_id, k, v)))
+                        .collect();
                     nodes.push(NodeData {
-                        xml_node_id: get_attr(&attrs, "id").unwrap(),
                         node_class: get_attr(&attrs, "value").unwrap(),
                         node_kind: if styles.contains_key("rhombus") {
                             NodeKind::IO
+                        } else if let Some(link_type) = styles.get("composite") {
+                            NodeKind::Composite(link_type.to_owned())
                         } else {
                             NodeKind::Processor
                         },
+                        xml_node_id,
+                        queue_capacities,
                     });
                 } else if has_attr(&attrs, "edge") {
                     edges.push(EdgeData {
@@ -239,6 +346,92 @@ mod nodes_edges_from_xml {
         assert_eq!(nodes.len(), 1);
         assert_eq!(nodes[0].node_kind, NodeKind::Processor);
     }
+
+    #[test]
+    fn composite_xml() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <mxGraphModel>
+                <root>
+                    <mxCell id="fooasdfbar-1" style="composite=MtransformNLink;fork_queue_capacity=20" vertex="1" value="FooAsdfBar">
+                        <mxGeometry width="100" height="100" as="geometry"/>
+                    </mxCell>
+                </root>
+            </mxGraphModel>
+        "#;
+
+        let (nodes, _) = nodes_edges_from_xml(EventReader::new(Cursor::new(xml)));
+
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(
+            nodes[0].node_kind,
+            NodeKind::Composite(String::from("MtransformNLink"))
+        );
+        assert_eq!(nodes[0].node_class, "FooAsdfBar");
+        assert_eq!(nodes[0].queue_capacities.len(), 1);
+        assert_eq!(nodes[0].queue_capacities["fork_queue_capacity"], 20);
+    }
+
+    fn queue_capacity_xml(capacity: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <mxGraphModel>
+                <root>
+                    <mxCell id="fooasdfbar-1" style="join_queue_capacity={}" vertex="1" value="FooAsdfBar">
+                        <mxGeometry width="100" height="100" as="geometry"/>
+                    </mxCell>
+                </root>
+            </mxGraphModel>
+        "#,
+            capacity
+        )
+    }
+
+    #[test]
+    fn queue_capacity_bounds() {
+        for capacity in &["1", "1000"] {
+            let xml = queue_capacity_xml(capacity);
+            let (nodes, _) = nodes_edges_from_xml(EventReader::new(Cursor::new(xml)));
+
+            assert_eq!(
+                nodes[0].queue_capacities["join_queue_capacity"].to_string(),
+                *capacity
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "join_queue_capacity of fooasdfbar-1 must be a number in 1..=1000")]
+    fn queue_capacity_zero() {
+        nodes_edges_from_xml(EventReader::new(Cursor::new(queue_capacity_xml("0"))));
+    }
+
+    #[test]
+    #[should_panic(expected = "join_queue_capacity of fooasdfbar-1 must be a number in 1..=1000")]
+    fn queue_capacity_too_large() {
+        nodes_edges_from_xml(EventReader::new(Cursor::new(queue_capacity_xml("1001"))));
+    }
+
+    #[test]
+    #[should_panic(expected = "join_queue_capacity of fooasdfbar-1 must be a number in 1..=1000")]
+    fn queue_capacity_not_a_number() {
+        nodes_edges_from_xml(EventReader::new(Cursor::new(queue_capacity_xml("ten"))));
+    }
+}
+
+/// The queue capacities that may be set from a graph. Links only require a capacity > 0, but
+/// anything past the upper bound is more likely a typo than a deliberate choice.
+pub const QUEUE_CAPACITY_RANGE: std::ops::RangeInclusive<usize> = 1..=1000;
+
+/// Parses the queue capacity `name` of a node, panicking if it is not a number within
+/// `QUEUE_CAPACITY_RANGE`.
+fn parse_queue_capacity(xml_node_id: &str, name: &str, value: &str) -> usize {
+    match value.parse() {
+        Ok(capacity) if QUEUE_CAPACITY_RANGE.contains(&capacity) => capacity,
+        _ => panic!(
+            "{} of {} must be a number in {:?}, found {:?}",
+            name, xml_node_id, QUEUE_CAPACITY_RANGE, value
+        ),
+    }
 }
 
 /// Helper method to extract an attribute from the attributes vector.
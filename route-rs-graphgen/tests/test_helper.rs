@@ -14,6 +14,22 @@ impl TestHelper {
     where
         S: Into<String>,
         T: Into<String>,
+    {
+        let example_crate_string = example_crate.into();
+        Self::named(
+            example_crate_string.clone(),
+            example_crate_string,
+            extra_args,
+        )
+    }
+
+    /// Like `new`, but with a test name of its own, so that several tests can use the same
+    /// example crate without sharing a tmpdir.
+    pub fn named<R, S, T>(test_name: R, example_crate: S, extra_args: Vec<T>) -> Self
+    where
+        R: Into<String>,
+        S: Into<String>,
+        T: Into<String>,
     {
         let root = Path::new(env!("CARGO_MANIFEST_DIR"))
             .join(Path::new(".."))
@@ -21,7 +37,7 @@ impl TestHelper {
             .unwrap();
         let global_tmpdir = Path::new(&std::env::temp_dir()).canonicalize().unwrap();
         let example_crate_string = example_crate.into();
-        let mut test_name = example_crate_string.clone();
+        let mut test_name = test_name.into();
         test_name.retain(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
         test_name = test_name.replace('-', "_");
 
@@ -97,6 +113,37 @@ impl TestHelper {
         );
     }
 
+    /// Runs graphgen with `--dry-run`, returning what it printed.
+    pub fn run_graphgen_dry_run(&self) -> String {
+        let graphgen_cmd = Command::new(self.graphgen_binary())
+            .args(&["--graph", self.graph_file().to_str().unwrap()])
+            .args(&["--output", self.output_file().to_str().unwrap()])
+            .arg("--dry-run")
+            .args(&self.extra_args)
+            .output()
+            .expect("Failed to execute graphgen");
+        assert!(
+            graphgen_cmd.status.success(),
+            "Error:\n{}",
+            String::from_utf8(graphgen_cmd.stderr).unwrap(),
+        );
+        String::from_utf8(graphgen_cmd.stdout).unwrap()
+    }
+
+    pub fn run_lint(&self) {
+        let lint_cmd = Command::new(self.graphgen_binary())
+            .args(&["--graph", self.graph_file().to_str().unwrap()])
+            .arg("--lint")
+            .arg("--strict")
+            .output()
+            .expect("Failed to execute graphgen");
+        assert!(
+            lint_cmd.status.success(),
+            "Lint failed:\n{}",
+            String::from_utf8(lint_cmd.stderr).unwrap(),
+        );
+    }
+
     pub fn run_diff(&self) {
         let diff_cmd = Command::new("diff")
             .arg("-u")
@@ -14,6 +14,7 @@ fn trivial_identity() {
         ],
     );
 
+    test_helper.run_lint();
     test_helper.run_graphgen();
     test_helper.run_diff();
 }
@@ -22,6 +23,55 @@ fn trivial_identity() {
 fn dns_interceptor() {
     let test_helper = test_helper::TestHelper::new("dns-interceptor", vec!["--rustfmt"]);
 
+    test_helper.run_lint();
     test_helper.run_graphgen();
     test_helper.run_diff();
 }
+
+#[test]
+fn composite_fanout() {
+    let test_helper = test_helper::TestHelper::new(
+        "composite-fanout",
+        vec![
+            "--rustfmt",
+            "--local-modules",
+            "packets",
+            "--runtime-modules",
+            "processor",
+        ],
+    );
+
+    test_helper.run_lint();
+    test_helper.run_graphgen();
+    test_helper.run_diff();
+}
+
+#[test]
+fn dry_run() {
+    let test_helper = test_helper::TestHelper::named(
+        "dry-run",
+        "trivial-identity",
+        vec![
+            "--rustfmt",
+            "--local-modules",
+            "packets",
+            "--runtime-modules",
+            "processor",
+        ],
+    );
+
+    let stdout = test_helper.run_graphgen_dry_run();
+    let expected = std::fs::read_to_string(test_helper.pipeline_file()).unwrap();
+
+    assert!(stdout.contains("InputChannelLink::new().channel(input_channel).build_link();"));
+    assert!(stdout.contains(".processor(elem_1_identity)\n            .build_link();"));
+    // Only the source graph path, which is relative to where graphgen ran, may differ.
+    let without_source = |s: &str| {
+        s.lines()
+            .filter(|l| !l.starts_with("// Source graph:"))
+            .collect::<Vec<&str>>()
+            .join("\n")
+    };
+    assert_eq!(without_source(&stdout), without_source(&expected));
+    assert!(!test_helper.output_file().exists());
+}
@@ -129,9 +129,9 @@ impl TryFrom<UdpSegment> for EthernetFrame {
 impl TryFrom<Ipv4Packet> for EthernetFrame {
     type Error = &'static str;
 
-    fn try_from(packet: Ipv4Packet) -> Result<Self, Self::Error> {
+    fn try_from(mut packet: Ipv4Packet) -> Result<Self, Self::Error> {
         if let Some(layer2_offset) = packet.layer2_offset {
-            EthernetFrame::from_buffer(packet.data, layer2_offset)
+            EthernetFrame::from_buffer(packet.take_data(), layer2_offset)
         } else {
             Err("IPv4 Packet does not contain an Ethernet Frame")
         }
@@ -0,0 +1,256 @@
+use crate::*;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+pub const ICMP_ECHO_REPLY: u8 = 0;
+pub const ICMP_DEST_UNREACHABLE: u8 = 3;
+pub const ICMP_REDIRECT: u8 = 5;
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+
+pub const ICMPV6_DEST_UNREACHABLE: u8 = 1;
+pub const ICMPV6_PACKET_TOO_BIG: u8 = 2;
+pub const ICMPV6_TIME_EXCEEDED: u8 = 3;
+pub const ICMPV6_ECHO_REQUEST: u8 = 128;
+pub const ICMPV6_ECHO_REPLY: u8 = 129;
+pub const ICMPV6_ROUTER_SOLICITATION: u8 = 133;
+pub const ICMPV6_ROUTER_ADVERTISEMENT: u8 = 134;
+pub const ICMPV6_NEIGHBOR_SOLICITATION: u8 = 135;
+pub const ICMPV6_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+/// The next header number of ICMPv6.
+pub const IPV6_NEXT_HEADER_ICMPV6: u8 = 58;
+
+/// The smallest MTU an IPv6 link may have, which ICMPv6 error messages must fit in.
+pub const IPV6_MIN_MTU: usize = 1280;
+
+/// Destination unreachable code for "fragmentation needed and DF set".
+pub const ICMP_CODE_FRAGMENTATION_NEEDED: u8 = 4;
+
+/// Redirect code for "redirect datagrams for the host".
+pub const ICMP_CODE_REDIRECT_HOST: u8 = 1;
+
+/// Computes the Internet checksum (RFC 1071) of `data`. An odd trailing byte is padded with zero.
+pub fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum = data.chunks(2).fold(0u32, |acc, chunk| {
+        let word = match *chunk {
+            [high, low] => u16::from_be_bytes([high, low]),
+            [high] => u16::from_be_bytes([high, 0]),
+            _ => 0,
+        };
+        acc + u32::from(word)
+    });
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Updates the Internet `checksum` of some data for one 16 bit word of it changing from `old` to
+/// `new`, without summing the data again (RFC 1624, equation 3).
+pub fn incremental_checksum(checksum: u16, old: u16, new: u16) -> u16 {
+    let mut sum = u32::from(!checksum) + u32::from(!old) + u32::from(new);
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+impl Ipv4Packet {
+    /// Builds an ICMP error message about `original`, sent from `src_addr` back to the source of
+    /// `original`. `rest_of_header` fills the four bytes after the checksum, such as the next-hop
+    /// MTU or the gateway of a redirect. As RFC 792 requires, the message quotes the IP header of
+    /// `original` and the first 8 bytes of its payload. Both checksums are set.
+    pub fn icmp_error(
+        original: &Ipv4Packet,
+        src_addr: Ipv4Addr,
+        icmp_type: u8,
+        code: u8,
+        rest_of_header: [u8; 4],
+    ) -> Ipv4Packet {
+        let quoted_len = (original.payload_offset + 8).min(original.data.len());
+        let quoted = &original.data[original.layer3_offset..quoted_len];
+
+        let mut message = Vec::with_capacity(8 + quoted.len());
+        message.extend_from_slice(&[icmp_type, code, 0, 0]);
+        message.extend_from_slice(&rest_of_header);
+        message.extend_from_slice(quoted);
+        let checksum = internet_checksum(&message);
+        message[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+        let mut packet = Ipv4Packet::empty();
+        packet.set_payload(&message);
+        packet.set_protocol(1);
+        packet.set_ttl(64);
+        packet.set_src_addr(src_addr);
+        packet.set_dest_addr(original.src_addr());
+        packet.set_checksum();
+        packet
+    }
+}
+
+impl Ipv6Packet {
+    /// The Internet checksum of the ICMPv6 message in the payload, including the pseudo-header of
+    /// RFC 8200 section 8.1. The payload is assumed to follow the fixed header directly, with no
+    /// extension headers in between.
+    fn icmpv6_sum(&self) -> u16 {
+        let payload = &self.data[self.payload_offset..];
+        let mut data = Vec::with_capacity(40 + payload.len());
+        data.extend_from_slice(&self.src_addr().octets());
+        data.extend_from_slice(&self.dest_addr().octets());
+        data.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        data.extend_from_slice(&[0, 0, 0, IPV6_NEXT_HEADER_ICMPV6]);
+        data.extend_from_slice(payload);
+        internet_checksum(&data)
+    }
+
+    /// Computes and sets the checksum of the ICMPv6 message in the payload. Set the addresses and
+    /// payload first, since the checksum covers them.
+    pub fn set_icmpv6_checksum(&mut self) {
+        let offset = self.payload_offset;
+        self.data[offset + 2..offset + 4].copy_from_slice(&[0, 0]);
+        let checksum = self.icmpv6_sum();
+        self.data[offset + 2..offset + 4].copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    /// Whether the ICMPv6 message in the payload has a valid checksum.
+    pub fn validate_icmpv6_checksum(&self) -> bool {
+        self.payload().len() >= 4 && self.icmpv6_sum() == 0
+    }
+
+    /// Builds an ICMPv6 message of `icmp_type` and `code`, from `src_addr` to `dest_addr`, whose
+    /// body follows the checksum. The hop limit is 255, as Neighbor Discovery requires, and the
+    /// checksum is set.
+    pub fn icmpv6(
+        src_addr: Ipv6Addr,
+        dest_addr: Ipv6Addr,
+        icmp_type: u8,
+        code: u8,
+        body: &[u8],
+    ) -> Ipv6Packet {
+        let mut message = Vec::with_capacity(4 + body.len());
+        message.extend_from_slice(&[icmp_type, code, 0, 0]);
+        message.extend_from_slice(body);
+
+        let mut packet = Ipv6Packet::empty();
+        packet.set_next_header(IPV6_NEXT_HEADER_ICMPV6);
+        packet.set_hop_limit(255);
+        packet.set_src_addr(src_addr);
+        packet.set_dest_addr(dest_addr);
+        packet.set_payload(&message);
+        packet.set_icmpv6_checksum();
+        packet
+    }
+
+    /// Builds an ICMPv6 error message about `original`, sent from `src_addr` back to the source of
+    /// `original`. `rest_of_header` fills the four bytes after the checksum, such as the MTU of a
+    /// Packet Too Big. As RFC 4443 requires, the message quotes as much of `original` as fits
+    /// without the message exceeding the minimum IPv6 MTU. The checksum is set.
+    pub fn icmpv6_error(
+        original: &Ipv6Packet,
+        src_addr: Ipv6Addr,
+        icmp_type: u8,
+        code: u8,
+        rest_of_header: [u8; 4],
+    ) -> Ipv6Packet {
+        let quoted = &original.data[original.layer3_offset..];
+        let quoted = &quoted[..quoted.len().min(IPV6_MIN_MTU - 48)];
+
+        let mut body = Vec::with_capacity(4 + quoted.len());
+        body.extend_from_slice(&rest_of_header);
+        body.extend_from_slice(quoted);
+        let mut packet = Ipv6Packet::icmpv6(src_addr, original.src_addr(), icmp_type, code, &body);
+        packet.set_hop_limit(64);
+        packet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_matches_rfc_1071_example() {
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(internet_checksum(&data), !0xddf2);
+    }
+
+    #[test]
+    fn checksum_pads_odd_length() {
+        assert_eq!(internet_checksum(&[0x12]), !0x1200);
+        assert_eq!(internet_checksum(&[]), 0xFFFF);
+    }
+
+    #[test]
+    fn icmp_error_quotes_original() {
+        let mut original = Ipv4Packet::empty();
+        original.set_src_addr(Ipv4Addr::new(192, 168, 1, 10));
+        original.set_dest_addr(Ipv4Addr::new(8, 8, 8, 8));
+        original.set_protocol(17);
+        original.set_payload(&[0xAA; 100]);
+
+        let mut error = Ipv4Packet::icmp_error(
+            &original,
+            Ipv4Addr::new(192, 168, 1, 1),
+            ICMP_TIME_EXCEEDED,
+            0,
+            [0; 4],
+        );
+
+        assert_eq!(error.protocol(), IpProtocol::ICMP);
+        assert_eq!(error.src_addr(), Ipv4Addr::new(192, 168, 1, 1));
+        assert_eq!(error.dest_addr(), Ipv4Addr::new(192, 168, 1, 10));
+        assert!(error.validate_checksum());
+
+        let message = error.payload();
+        assert_eq!(message[0], ICMP_TIME_EXCEEDED);
+        assert_eq!(internet_checksum(&message), 0);
+        assert_eq!(message[8..], original.data[..28]);
+    }
+
+    #[test]
+    fn icmp_error_quotes_short_payload() {
+        let original = Ipv4Packet::empty();
+        let error = Ipv4Packet::icmp_error(
+            &original,
+            Ipv4Addr::new(10, 0, 0, 1),
+            ICMP_DEST_UNREACHABLE,
+            0,
+            [0; 4],
+        );
+        assert_eq!(error.payload().len(), 8 + 20);
+    }
+
+    #[test]
+    fn icmpv6_checksum_covers_pseudo_header() {
+        let src_addr = "fe80::1".parse().unwrap();
+        let dest_addr = "fe80::2".parse().unwrap();
+        let mut packet = Ipv6Packet::icmpv6(src_addr, dest_addr, 128, 0, &[0, 1, 0, 1]);
+
+        assert_eq!(packet.next_header(), IpProtocol::IPv6_ICMP);
+        assert_eq!(packet.hop_limit(), 255);
+        assert!(packet.validate_icmpv6_checksum());
+
+        packet.set_dest_addr("fe80::3".parse().unwrap());
+        assert!(!packet.validate_icmpv6_checksum());
+    }
+
+    #[test]
+    fn icmpv6_error_fits_minimum_mtu() {
+        let mut original = Ipv6Packet::empty();
+        original.set_src_addr("2001:db8::10".parse().unwrap());
+        original.set_payload(&[0xAA; 1500]);
+
+        let error = Ipv6Packet::icmpv6_error(
+            &original,
+            "2001:db8::1".parse().unwrap(),
+            ICMPV6_TIME_EXCEEDED,
+            0,
+            [0; 4],
+        );
+
+        assert_eq!(error.data.len(), IPV6_MIN_MTU);
+        assert_eq!(error.dest_addr(), original.src_addr());
+        assert!(error.validate_icmpv6_checksum());
+        assert_eq!(error.payload()[8..48], original.data[..40]);
+    }
+}
@@ -9,6 +9,7 @@ pub struct Ipv4Packet {
     pub layer2_offset: Option<usize>,
     pub layer3_offset: usize,
     pub payload_offset: usize,
+    pool: Option<PacketPool>,
 }
 
 impl Ipv4Packet {
@@ -17,40 +18,61 @@ impl Ipv4Packet {
         layer2_offset: Option<usize>,
         layer3_offset: usize,
     ) -> Result<Ipv4Packet, &'static str> {
-        // Header of Ethernet Frame: 14 bytes
-        // Header of IPv4 Frame: 20 bytes
-        if data.len() < layer3_offset + 20 {
-            return Err("Data is too short to be an IPv4 Packet");
-        }
-
-        // Check version number
-        let version: u8 = (data[layer3_offset] & 0xF0) >> 4;
-        if version != 4 {
-            return Err("Packet has incorrect version, is not Ipv4Packet");
-        }
-
-        // TotalLen is the 3rd and 4th byte of the IP Header
-        let total_len = u16::from_be_bytes(
-            data[layer3_offset + 2..=layer3_offset + 3]
-                .try_into()
-                .unwrap(),
-        ) as usize;
-        if data.len() != total_len + layer3_offset {
-            return Err("Packet has invalid total length field");
-        }
-
-        // This is the header length in 32bit words
-        let ihl = (data[layer3_offset] & 0x0F) as usize;
-        let payload_offset = layer3_offset + (ihl * 4);
+        let payload_offset = match validate_ipv4_header(&data, layer3_offset) {
+            Ok((total_len, payload_offset)) => {
+                if data.len() != total_len + layer3_offset {
+                    return Err("Packet has invalid total length field");
+                }
+                payload_offset
+            }
+            Err(err) => return Err(err.as_str()),
+        };
 
         Ok(Ipv4Packet {
             data,
             layer2_offset,
             layer3_offset,
             payload_offset,
+            pool: None,
         })
     }
 
+    /// Checked constructor for untrusted input. Copies an IPv4 packet out of `bytes`, validating
+    /// the version, that the header length lies within the packet, and that the total length
+    /// fits in the buffer. Bytes past the total length, such as Ethernet padding, are ignored.
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Ipv4Packet, ParseError> {
+        let (total_len, payload_offset) = validate_ipv4_header(bytes, 0)?;
+        Ok(Ipv4Packet {
+            data: bytes[..total_len].to_vec(),
+            layer2_offset: None,
+            layer3_offset: 0,
+            payload_offset,
+            pool: None,
+        })
+    }
+
+    /// Create an Ipv4Packet whose backing storage is drawn from `pool`. The bytes are copied into
+    /// a recycled buffer, and the buffer is returned to the pool when the packet is dropped.
+    /// Converting the packet into another packet type moves the buffer out of the pool.
+    pub fn from_pool(
+        pool: &PacketPool,
+        bytes: &[u8],
+        layer2_offset: Option<usize>,
+        layer3_offset: usize,
+    ) -> Result<Ipv4Packet, &'static str> {
+        let mut data = pool.take();
+        data.extend_from_slice(bytes);
+        let mut packet = Ipv4Packet::from_buffer(data, layer2_offset, layer3_offset)?;
+        packet.pool = Some(pool.clone());
+        Ok(packet)
+    }
+
+    /// Moves the backing buffer out of the packet, detaching it from any pool.
+    pub(crate) fn take_data(&mut self) -> PacketData {
+        self.pool = None;
+        std::mem::take(&mut self.data)
+    }
+
     /// Create an empty Ipv4Packet with no layer 2 header. All possible values are set to 0
     pub fn empty() -> Ipv4Packet {
         let mut data = vec![0x45];
@@ -60,6 +82,26 @@ impl Ipv4Packet {
         Ipv4Packet::from_buffer(data, None, 0).unwrap()
     }
 
+    /// Create an Ipv4Packet with no layer 2 header from the fields that usually matter, and a
+    /// valid header: the header length, total length and checksum are computed, and every other
+    /// field is 0. The payload is taken as is, so a TCP or UDP payload should carry its checksum
+    /// already, or be given one with `recompute_transport_checksum`.
+    pub fn from_parts(
+        src_addr: Ipv4Addr,
+        dest_addr: Ipv4Addr,
+        protocol: u8,
+        ttl: u8,
+        payload: &[u8],
+    ) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(src_addr);
+        packet.set_dest_addr(dest_addr);
+        packet.set_protocol(protocol);
+        packet.set_ttl(ttl);
+        packet.set_payload(payload);
+        packet
+    }
+
     pub fn src_addr(&self) -> Ipv4Addr {
         let data: [u8; 4] = self.data[self.layer3_offset + 12..self.layer3_offset + 16]
             .try_into()
@@ -91,23 +133,29 @@ impl Ipv4Packet {
     fn set_ihl(&mut self, header_length: usize) {
         self.data[self.layer3_offset] &= 0xF0;
         self.data[self.layer3_offset] |= 0x0F & ((header_length / 4) as u8);
-        self.payload_offset = header_length;
+        self.payload_offset = self.layer3_offset + header_length;
     }
 
     pub fn payload(&self) -> Cow<[u8]> {
         Cow::from(&self.data[self.payload_offset..])
     }
 
-    pub fn set_payload(&mut self, payload: &[u8]) {
-        let payload_len = payload.len();
+    /// A mutable view of the payload, starting past the header and its options, for rewriting the
+    /// transport header in place. The payload offset is checked against the buffer when the packet
+    /// is built, so the view is always in bounds. Unlike `set_payload`, the length of the payload
+    /// cannot change, and the checksum of the header is left alone, since it does not cover it.
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        &mut self.data[self.payload_offset..]
+    }
 
+    /// Replaces the payload, and updates the total length field and the checksum to match. Panics
+    /// if the packet would not fit the total length field, rather than truncating the field.
+    pub fn set_payload(&mut self, payload: &[u8]) {
+        assert_fits_total_len(self.payload_offset - self.layer3_offset + payload.len());
         self.data.truncate(self.payload_offset);
-
-        let total_len = (payload_len as u16 + u16::from(self.ihl() * 4)).to_be_bytes();
-        self.data[self.layer3_offset + 2..=self.layer3_offset + 3].copy_from_slice(&total_len);
-
-        self.data.reserve_exact(payload_len);
+        self.data.reserve_exact(payload.len());
         self.data.extend(payload);
+        self.sync_total_len();
     }
 
     pub fn options(&self) -> Option<Cow<[u8]>> {
@@ -123,13 +171,16 @@ impl Ipv4Packet {
     /// sets the IHL field of the packet, and the internal payload_offset
     /// field.
     /// Note: The user should provide options that are padded to a 32bit length.
+    /// Panics if the packet would not fit the total length field, rather than truncating the field.
     pub fn set_options(&mut self, options: &[u8]) {
+        assert_fits_total_len(20 + options.len() + self.data.len() - self.payload_offset);
         let payload = self.data.split_off(self.payload_offset);
         self.data.truncate(self.layer3_offset + 20);
         self.data.reserve_exact(payload.len() + options.len());
         self.data.extend(options);
         self.data.extend(payload);
         self.set_ihl(options.len() + 20);
+        self.sync_total_len();
     }
 
     pub fn protocol(&self) -> IpProtocol {
@@ -140,6 +191,27 @@ impl Ipv4Packet {
         self.data[self.layer3_offset + 9] = protocol;
     }
 
+    /// The length of the packet in bytes, from the start of the IPv4 header to the end of the
+    /// buffer. Every method that changes the length keeps the total length field equal to it.
+    pub fn len(&self) -> usize {
+        self.data.len() - self.layer3_offset
+    }
+
+    /// Always false, since a packet has at least a header.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sets the total length field to the current length, and recomputes the checksum to match.
+    fn sync_total_len(&mut self) {
+        let total_len = u16::try_from(self.len())
+            .expect("length checked against the total length field")
+            .to_be_bytes();
+        self.data[self.layer3_offset + 2..=self.layer3_offset + 3].copy_from_slice(&total_len);
+        self.set_checksum();
+    }
+
+    /// The total length field of the header.
     pub fn total_len(&self) -> u16 {
         u16::from_be_bytes(
             self.data[self.layer3_offset + 2..=self.layer3_offset + 3]
@@ -218,6 +290,20 @@ impl Ipv4Packet {
         (df, mf)
     }
 
+    /// The reserved flag, which RFC 791 requires to be 0, and RFC 3514 jokingly calls the evil
+    /// bit.
+    pub fn reserved_flag(&self) -> bool {
+        (self.data[self.layer3_offset + 6] & 0x80) != 0
+    }
+
+    pub fn set_reserved_flag(&mut self, reserved: bool) {
+        if reserved {
+            self.data[self.layer3_offset + 6] |= 0x80;
+        } else {
+            self.data[self.layer3_offset + 6] &= 0x7F;
+        }
+    }
+
     pub fn set_flags(&mut self, df: bool, mf: bool) {
         let bits: u8;
         match (df, mf) {
@@ -268,6 +354,88 @@ impl Ipv4Packet {
         self.data[self.layer3_offset + 11] = (new_checksum & 0x00FF) as u8;
     }
 
+    /// The offset into `data` of the checksum of the TCP or UDP segment the packet carries. None
+    /// for other protocols, and for fragments, whose checksum covers the whole datagram.
+    fn transport_checksum_offset(&self) -> Option<usize> {
+        let (_, more_fragments) = self.flags();
+        if more_fragments || self.fragment_offset() != 0 {
+            return None;
+        }
+        let offset = match self.protocol() {
+            IpProtocol::TCP => self.payload_offset + 16,
+            IpProtocol::UDP => self.payload_offset + 6,
+            _ => return None,
+        };
+        if offset + 2 > self.data.len() {
+            return None;
+        }
+        Some(offset)
+    }
+
+    /// The sum over the pseudo header and the segment of a TCP or UDP packet, checksum field
+    /// included, folded to 16 bits and complemented.
+    fn transport_sum(&self) -> u16 {
+        let segment = &self.data[self.payload_offset..];
+        let mut data = Vec::with_capacity(12 + segment.len());
+        data.extend_from_slice(&self.data[self.layer3_offset + 12..self.layer3_offset + 20]);
+        data.extend_from_slice(&[0, self.data[self.layer3_offset + 9]]);
+        data.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+        data.extend_from_slice(segment);
+        internet_checksum(&data)
+    }
+
+    /// Recomputes the header checksum, and the checksum of the TCP or UDP segment over the pseudo
+    /// header and the segment, after addresses or ports have been rewritten. The segment checksum
+    /// of other protocols and of fragments is left alone.
+    pub fn recompute_transport_checksum(&mut self) {
+        self.set_checksum();
+        let offset = match self.transport_checksum_offset() {
+            Some(offset) => offset,
+            None => return,
+        };
+        self.data[offset..offset + 2].copy_from_slice(&[0, 0]);
+        let mut checksum = self.transport_sum();
+        // A UDP checksum of zero means there is none, so a computed zero is sent as all ones.
+        if checksum == 0 && self.protocol() == IpProtocol::UDP {
+            checksum = 0xFFFF;
+        }
+        self.data[offset..offset + 2].copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    /// Updates the checksum of the TCP or UDP segment for one 16 bit word of the segment or the
+    /// pseudo header changing from `old` to `new`, such as a port or half of an address, without
+    /// summing the segment again. An address is in both headers, so the header checksum must be
+    /// updated for it too. UDP segments sent without a checksum are left without one.
+    pub fn update_transport_checksum(&mut self, old: u16, new: u16) {
+        let offset = match self.transport_checksum_offset() {
+            Some(offset) => offset,
+            None => return,
+        };
+        let checksum = u16::from_be_bytes([self.data[offset], self.data[offset + 1]]);
+        let is_udp = self.protocol() == IpProtocol::UDP;
+        if checksum == 0 && is_udp {
+            return;
+        }
+        let mut checksum = incremental_checksum(checksum, old, new);
+        if checksum == 0 && is_udp {
+            checksum = 0xFFFF;
+        }
+        self.data[offset..offset + 2].copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    /// Verifies the checksum of the TCP or UDP segment. True for other protocols, for fragments,
+    /// and for UDP segments sent without a checksum, since there is nothing to verify.
+    pub fn validate_transport_checksum(&self) -> bool {
+        let offset = match self.transport_checksum_offset() {
+            Some(offset) => offset,
+            None => return true,
+        };
+        if self.protocol() == IpProtocol::UDP && self.data[offset..offset + 2] == [0, 0] {
+            return true;
+        }
+        self.transport_sum() == 0
+    }
+
     /// Takes a UdpSegment, and returns an Ipv6Packet with the
     /// segment as payload. Does not set checksums
     pub fn encap_udp(udp: UdpSegment) -> Ipv4Packet {
@@ -298,6 +466,58 @@ impl PartialEq for Ipv4Packet {
 
 impl Eq for Ipv4Packet {}
 
+impl Drop for Ipv4Packet {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.recycle(std::mem::take(&mut self.data));
+        }
+    }
+}
+
+/// Validates the IPv4 header that starts at `layer3_offset`, returning the total length of the
+/// packet and the offset of its payload.
+/// Panics unless a packet of `len` bytes fits the 16 bit total length field.
+fn assert_fits_total_len(len: usize) {
+    assert!(
+        u16::try_from(len).is_ok(),
+        "packet length: {}, must be <= {}",
+        len,
+        u16::MAX
+    );
+}
+
+fn validate_ipv4_header(data: &[u8], layer3_offset: usize) -> Result<(usize, usize), ParseError> {
+    // Header of IPv4 Frame: 20 bytes
+    if data.len() < layer3_offset + 20 {
+        return Err(ParseError::TooShort {
+            len: data.len().saturating_sub(layer3_offset),
+            min_len: 20,
+        });
+    }
+
+    let version: u8 = (data[layer3_offset] & 0xF0) >> 4;
+    if version != 4 {
+        return Err(ParseError::InvalidVersion(version));
+    }
+
+    // TotalLen is the 3rd and 4th byte of the IP Header
+    let total_len = u16::from_be_bytes([data[layer3_offset + 2], data[layer3_offset + 3]]) as usize;
+    if total_len > data.len() - layer3_offset {
+        return Err(ParseError::InvalidTotalLength {
+            total_len,
+            buffer_len: data.len() - layer3_offset,
+        });
+    }
+
+    // This is the header length in 32bit words
+    let ihl = data[layer3_offset] & 0x0F;
+    if ihl < 5 || usize::from(ihl) * 4 > total_len {
+        return Err(ParseError::InvalidHeaderLength(ihl));
+    }
+
+    Ok((total_len, layer3_offset + usize::from(ihl) * 4))
+}
+
 /// Returns Ipv4 payload type, reads the header information to get the type
 /// of IpProtocol payload is included. Upon error, returns IpProtocol::Reserved.
 pub fn get_ipv4_payload_type(
@@ -347,6 +567,7 @@ impl TryFrom<UdpSegment> for Ipv4Packet {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::assert_roundtrip;
     use std::vec::Vec;
 
     #[test]
@@ -427,6 +648,183 @@ mod tests {
         assert_eq!(packet.ihl(), 6);
     }
 
+    #[test]
+    fn try_from_bytes() {
+        let data: Vec<u8> = vec![
+            0x45, 0, 0, 24, 0, 0, 0, 0, 64, 17, 0, 0, 192, 178, 128, 0, 10, 0, 0, 1, 1, 2, 3, 4, 0,
+            0,
+        ];
+        let packet = Ipv4Packet::try_from_bytes(&data).unwrap();
+        assert_eq!(packet.total_len(), 24);
+        assert_eq!(packet.data, &data[..24]);
+        assert_eq!(packet.payload(), &[1, 2, 3, 4][..]);
+        assert_eq!(
+            packet,
+            Ipv4Packet::from_buffer(data[..24].to_vec(), None, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn payload_mut() {
+        let data: Vec<u8> = vec![
+            0x46, 0, 0, 28, 0, 0, 0, 0, 64, 17, 0, 0, 192, 178, 128, 0, 10, 0, 0, 1, 0x94, 0x04, 0,
+            0, 1, 2, 3, 4,
+        ];
+        let mut packet = Ipv4Packet::try_from_bytes(&data).unwrap();
+        assert_eq!(packet.payload_mut(), &mut [1, 2, 3, 4][..]);
+
+        packet.payload_mut()[1..3].copy_from_slice(&[0xAA, 0xBB]);
+
+        assert_eq!(packet.payload(), &[1, 0xAA, 0xBB, 4][..]);
+        assert_eq!(packet.options().unwrap(), &[0x94, 0x04, 0, 0][..]);
+        assert_eq!(packet.data[..24], data[..24]);
+        let reparsed = Ipv4Packet::try_from_bytes(&packet.data).unwrap();
+        assert_eq!(reparsed.payload(), &[1, 0xAA, 0xBB, 4][..]);
+    }
+
+    #[test]
+    fn payload_mut_empty() {
+        let mut packet = Ipv4Packet::empty();
+
+        assert!(packet.payload_mut().is_empty());
+    }
+
+    /// A packet carrying a segment of `protocol`, with `len` bytes of header before the ports.
+    fn transport_packet(protocol: u8, len: usize) -> Ipv4Packet {
+        let mut segment = vec![0x5A; len];
+        segment[0..2].copy_from_slice(&5353u16.to_be_bytes());
+        segment[2..4].copy_from_slice(&53u16.to_be_bytes());
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(Ipv4Addr::new(192, 168, 1, 10));
+        packet.set_dest_addr(Ipv4Addr::new(10, 0, 0, 1));
+        packet.set_ttl(64);
+        packet.set_protocol(protocol);
+        packet.set_payload(&segment);
+        packet.recompute_transport_checksum();
+        packet
+    }
+
+    fn rewrite_dest_port(packet: &mut Ipv4Packet, port: u16) -> u16 {
+        let old = u16::from_be_bytes([packet.payload()[2], packet.payload()[3]]);
+        packet.payload_mut()[2..4].copy_from_slice(&port.to_be_bytes());
+        old
+    }
+
+    #[test]
+    fn recompute_transport_checksum() {
+        for (protocol, len) in [(6, 27), (17, 20)].iter() {
+            let mut packet = transport_packet(*protocol, *len);
+            assert!(packet.validate_checksum());
+            assert!(packet.validate_transport_checksum());
+
+            rewrite_dest_port(&mut packet, 5300);
+            packet.set_dest_addr(Ipv4Addr::new(10, 0, 0, 2));
+            assert!(!packet.validate_checksum());
+            assert!(!packet.validate_transport_checksum());
+
+            packet.recompute_transport_checksum();
+            assert!(packet.validate_checksum(), "protocol {}", protocol);
+            assert!(
+                packet.validate_transport_checksum(),
+                "protocol {}",
+                protocol
+            );
+        }
+    }
+
+    #[test]
+    fn update_transport_checksum() {
+        for (protocol, len) in [(6, 27), (17, 20)].iter() {
+            let mut packet = transport_packet(*protocol, *len);
+
+            let old = rewrite_dest_port(&mut packet, 5300);
+            packet.update_transport_checksum(old, 5300);
+
+            assert!(packet.validate_checksum(), "protocol {}", protocol);
+            assert!(
+                packet.validate_transport_checksum(),
+                "protocol {}",
+                protocol
+            );
+            let mut recomputed = packet.clone();
+            recomputed.recompute_transport_checksum();
+            assert_eq!(packet, recomputed);
+        }
+    }
+
+    #[test]
+    fn transport_checksum_skips_udp_without_checksum() {
+        let mut packet = transport_packet(17, 20);
+        packet.payload_mut()[6..8].copy_from_slice(&[0, 0]);
+
+        let old = rewrite_dest_port(&mut packet, 5300);
+        packet.update_transport_checksum(old, 5300);
+
+        assert_eq!(packet.payload()[6..8], [0, 0]);
+        assert!(packet.validate_transport_checksum());
+    }
+
+    #[test]
+    fn transport_checksum_skips_fragments() {
+        let mut packet = transport_packet(17, 20);
+        packet.set_flags(false, true);
+        let segment = packet.payload().into_owned();
+
+        rewrite_dest_port(&mut packet, 5300);
+        packet.recompute_transport_checksum();
+
+        assert!(packet.validate_checksum());
+        assert_eq!(packet.payload()[6..8], segment[6..8]);
+    }
+
+    #[test]
+    fn try_from_bytes_too_short() {
+        assert_eq!(
+            Ipv4Packet::try_from_bytes(&[0x45, 0, 0]),
+            Err(ParseError::TooShort {
+                len: 3,
+                min_len: 20
+            })
+        );
+    }
+
+    #[test]
+    fn try_from_bytes_ihl_overruns_buffer() {
+        let data: Vec<u8> = vec![
+            0x4F, 0, 0, 20, 0, 0, 0, 0, 64, 17, 0, 0, 192, 178, 128, 0, 10, 0, 0, 1,
+        ];
+        assert_eq!(
+            Ipv4Packet::try_from_bytes(&data),
+            Err(ParseError::InvalidHeaderLength(15))
+        );
+        assert!(Ipv4Packet::from_buffer(data, None, 0).is_err());
+    }
+
+    #[test]
+    fn try_from_bytes_invalid_version() {
+        let data: Vec<u8> = vec![
+            0x65, 0, 0, 20, 0, 0, 0, 0, 64, 17, 0, 0, 192, 178, 128, 0, 10, 0, 0, 1,
+        ];
+        assert_eq!(
+            Ipv4Packet::try_from_bytes(&data),
+            Err(ParseError::InvalidVersion(6))
+        );
+    }
+
+    #[test]
+    fn try_from_bytes_total_len_overruns_buffer() {
+        let data: Vec<u8> = vec![
+            0x45, 0, 0, 40, 0, 0, 0, 0, 64, 17, 0, 0, 192, 178, 128, 0, 10, 0, 0, 1,
+        ];
+        assert_eq!(
+            Ipv4Packet::try_from_bytes(&data),
+            Err(ParseError::InvalidTotalLength {
+                total_len: 40,
+                buffer_len: 20
+            })
+        );
+    }
+
     #[test]
     fn empty() {
         let empty_packet = Ipv4Packet::empty();
@@ -435,6 +833,102 @@ mod tests {
         assert_eq!(empty_packet.payload_offset, 20);
     }
 
+    #[test]
+    fn reserved_flag() {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_flags(true, false);
+        packet.set_fragment_offset(0x1234);
+
+        packet.set_reserved_flag(true);
+        assert!(packet.reserved_flag());
+        assert_eq!(packet.data[6], 0xD2);
+        assert_eq!(packet.flags(), (true, false));
+        assert_eq!(packet.fragment_offset(), 0x1234);
+
+        packet.set_reserved_flag(false);
+        assert!(!packet.reserved_flag());
+        assert_eq!(packet.data[6], 0x52);
+    }
+
+    #[test]
+    fn from_parts() {
+        let payload = [0xde, 0xad, 0xbe, 0xef, 0x01];
+        let mut packet = Ipv4Packet::from_parts(
+            Ipv4Addr::new(192, 168, 1, 10),
+            Ipv4Addr::new(8, 8, 4, 4),
+            1,
+            64,
+            &payload,
+        );
+
+        assert_eq!(packet.layer2_offset, None);
+        assert_eq!(packet.src_addr(), Ipv4Addr::new(192, 168, 1, 10));
+        assert_eq!(packet.dest_addr(), Ipv4Addr::new(8, 8, 4, 4));
+        assert_eq!(packet.ihl(), 5);
+        assert_eq!(packet.options(), None);
+        assert_eq!(packet.protocol(), IpProtocol::ICMP);
+        assert_eq!(packet.ttl(), 64);
+        assert_eq!(packet.total_len(), 25);
+        assert_eq!(packet.len(), 25);
+        assert_eq!(packet.dscp(), 0);
+        assert_eq!(packet.ecn(), 0);
+        assert_eq!(packet.indentification(), 0);
+        assert_eq!(packet.fragment_offset(), 0);
+        assert_eq!(packet.flags(), (false, false));
+        assert_eq!(packet.payload(), &payload[..]);
+        assert_eq!(packet.checksum(), packet.caclulate_checksum());
+        assert!(packet.validate_checksum());
+        assert_roundtrip::<Ipv4Packet>(&packet.data);
+    }
+
+    #[test]
+    fn pooled_packet_matches_unpooled() {
+        let data: Vec<u8> = vec![
+            0x45, 0, 0, 24, 0, 0, 0, 0, 64, 17, 0, 0, 192, 178, 128, 0, 10, 0, 0, 1, 1, 2, 3, 4,
+        ];
+        let pool = PacketPool::new(8);
+
+        let mut unpooled = Ipv4Packet::from_buffer(data.clone(), None, 0).unwrap();
+        let mut pooled = Ipv4Packet::from_pool(&pool, &data, None, 0).unwrap();
+        assert_eq!(pooled, unpooled);
+        assert_eq!(pooled.src_addr(), unpooled.src_addr());
+        assert_eq!(pooled.payload(), unpooled.payload());
+
+        pooled.set_ttl(12);
+        pooled.set_payload(&[0, 53, 0, 53, 0, 8, 0, 0]);
+        pooled.set_checksum();
+        unpooled.set_ttl(12);
+        unpooled.set_payload(&[0, 53, 0, 53, 0, 8, 0, 0]);
+        unpooled.set_checksum();
+        assert_eq!(pooled, unpooled);
+        assert!(pooled.validate_checksum());
+
+        let segment = UdpSegment::try_from(pooled).unwrap();
+        assert_eq!(segment.data, unpooled.data);
+        assert_eq!(pool.available(), 0);
+    }
+
+    #[test]
+    fn pooled_packets_reuse_buffers() {
+        let data: Vec<u8> = vec![
+            0x45, 0, 0, 20, 0, 0, 0, 0, 64, 17, 0, 0, 192, 178, 128, 0, 10, 0, 0, 1,
+        ];
+        let pool = PacketPool::new(8);
+
+        for _ in 0..1000 {
+            let packet = Ipv4Packet::from_pool(&pool, &data, None, 0).unwrap();
+            assert_eq!(packet.dest_addr(), Ipv4Addr::new(10, 0, 0, 1));
+        }
+        assert_eq!(pool.allocations(), 1);
+        assert_eq!(pool.available(), 1);
+
+        let unpooled: Vec<Ipv4Packet> = (0..1000)
+            .map(|_| Ipv4Packet::from_buffer(data.clone(), None, 0).unwrap())
+            .collect();
+        assert_eq!(unpooled.len(), 1000);
+        assert_eq!(pool.allocations(), 1);
+    }
+
     #[test]
     fn encap_udp() {
         let udp = UdpSegment::empty();
@@ -460,4 +954,106 @@ mod tests {
         assert_eq!(new_segment.layer3_offset, Some(0));
         assert_eq!(new_segment.layer4_offset, 20);
     }
+
+    /// ICMP echo request, 192.168.1.10 -> 8.8.8.8, Don't Fragment set.
+    const ICMP_ECHO: [u8; 68] = [
+        0x45, 0x00, 0x00, 0x44, 0x1c, 0x46, 0x40, 0x00, 0x40, 0x01, 0x4c, 0xb1, 0xc0, 0xa8, 0x01,
+        0x0a, 0x08, 0x08, 0x08, 0x08, 0x08, 0x00, 0x26, 0xf8, 0x12, 0x34, 0x00, 0x01, 0x10, 0x11,
+        0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f, 0x20,
+        0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x2b, 0x2c, 0x2d, 0x2e, 0x2f,
+        0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37,
+    ];
+
+    /// DNS query for example.com over UDP, 10.0.0.2:53000 -> 1.1.1.1:53.
+    const DNS_QUERY: [u8; 57] = [
+        0x45, 0x00, 0x00, 0x39, 0xbe, 0xef, 0x00, 0x00, 0x80, 0x11, 0x6f, 0xc1, 0x0a, 0x00, 0x00,
+        0x02, 0x01, 0x01, 0x01, 0x01, 0xcf, 0x08, 0x00, 0x35, 0x00, 0x25, 0x00, 0x00, 0xab, 0xcd,
+        0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x65, 0x78, 0x61, 0x6d,
+        0x70, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x01, 0x00, 0x01,
+    ];
+
+    /// IGMPv2 report with the Router Alert option, so the header is 24 bytes.
+    const IGMP_REPORT: [u8; 32] = [
+        0x46, 0xc0, 0x00, 0x20, 0x00, 0x00, 0x40, 0x00, 0x01, 0x02, 0x42, 0x45, 0xc0, 0xa8, 0x01,
+        0x14, 0xe0, 0x00, 0x00, 0x16, 0x94, 0x04, 0x00, 0x00, 0x22, 0x00, 0x0a, 0xfa, 0x00, 0x00,
+        0x00, 0x00,
+    ];
+
+    #[test]
+    fn roundtrips_captured_packets() {
+        let packet: Ipv4Packet = assert_roundtrip(&ICMP_ECHO);
+        assert_eq!(packet.protocol(), IpProtocol::ICMP);
+        assert_eq!(packet.flags(), (true, false));
+
+        let packet: Ipv4Packet = assert_roundtrip(&DNS_QUERY);
+        assert_eq!(packet.protocol(), IpProtocol::UDP);
+        assert_eq!(packet.dest_addr(), Ipv4Addr::new(1, 1, 1, 1));
+
+        let packet: Ipv4Packet = assert_roundtrip(&IGMP_REPORT);
+        assert_eq!(packet.ihl(), 6);
+        assert_eq!(
+            packet.options().unwrap().as_ref(),
+            &[0x94, 0x04, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Checksum invalid")]
+    fn roundtrip_catches_bad_checksum() {
+        let mut corrupted = DNS_QUERY;
+        corrupted[8] -= 1;
+        assert_roundtrip::<Ipv4Packet>(&corrupted);
+    }
+
+    #[test]
+    fn len_tracks_payload_changes() {
+        let mut packet: Ipv4Packet = assert_roundtrip(&DNS_QUERY);
+        assert_eq!(packet.len(), DNS_QUERY.len());
+        assert_eq!(usize::from(packet.total_len()), packet.len());
+
+        packet.set_payload(&[0xAB; 300]);
+        assert_eq!(packet.len(), 320);
+        assert_eq!(usize::from(packet.total_len()), packet.len());
+        assert!(packet.validate_checksum());
+
+        packet.set_payload(&[]);
+        assert_eq!(packet.len(), 20);
+        assert_eq!(usize::from(packet.total_len()), packet.len());
+        assert!(packet.validate_checksum());
+    }
+
+    #[test]
+    fn len_tracks_option_changes() {
+        let mac_data: Vec<u8> = vec![0xde, 0xad, 0xbe, 0xef, 0xff, 0xff, 1, 2, 3, 4, 5, 6, 0, 0];
+        let mut frame = EthernetFrame::from_buffer(mac_data, 0).unwrap();
+        frame.set_payload(&ICMP_ECHO);
+        let mut packet = Ipv4Packet::try_from(frame).unwrap();
+        let payload = packet.payload().into_owned();
+
+        packet.set_options(&[0x94, 0x04, 0x00, 0x00]);
+        assert_eq!(packet.len(), ICMP_ECHO.len() + 4);
+        assert_eq!(usize::from(packet.total_len()), packet.len());
+        assert_eq!(packet.payload().as_ref(), payload.as_slice());
+        assert!(packet.validate_checksum());
+
+        // The packet still parses from its frame, so its header fields are consistent.
+        let frame = EthernetFrame::try_from(packet).unwrap();
+        let packet = Ipv4Packet::try_from(frame).unwrap();
+        assert_eq!(packet.ihl(), 6);
+        assert_eq!(packet.payload().as_ref(), payload.as_slice());
+    }
+
+    #[test]
+    #[should_panic(expected = "packet length: 65536, must be <= 65535")]
+    fn set_payload_refuses_oversized_payload() {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_payload(&vec![0; 65536 - 20]);
+    }
+
+    #[test]
+    fn set_payload_takes_largest_payload() {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_payload(&vec![0; 65535 - 20]);
+        assert_eq!(packet.total_len(), 65535);
+    }
 }
@@ -1,6 +1,9 @@
 mod types;
 pub use self::types::*;
 
+mod pool;
+pub use self::pool::*;
+
 mod ethernet;
 pub use self::ethernet::*;
 
@@ -10,8 +13,14 @@ pub use self::ipv4::*;
 mod ipv6;
 pub use self::ipv6::*;
 
+mod icmp;
+pub use self::icmp::*;
+
 mod udp;
 pub use self::udp::*;
 
 mod tcp;
 pub use self::tcp::*;
+
+#[cfg(test)]
+mod test_utils;
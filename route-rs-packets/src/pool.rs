@@ -0,0 +1,93 @@
+use crate::*;
+use std::sync::{Arc, Mutex};
+
+/// A shared pool of packet buffers.
+///
+/// Building a packet normally allocates a fresh `PacketData` for every packet that moves through
+/// the router. A `PacketPool` keeps buffers that are no longer needed around, so that the next
+/// packet can reuse the allocation instead of asking the allocator for a new one. Cloning a
+/// `PacketPool` produces another handle to the same pool, so a handle can be handed to every
+/// processor that creates packets.
+///
+/// The pool holds at most `max_buffers` idle buffers; buffers recycled past that are freed.
+#[derive(Clone, Debug)]
+pub struct PacketPool {
+    inner: Arc<Mutex<PoolState>>,
+    max_buffers: usize,
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    buffers: Vec<PacketData>,
+    allocations: usize,
+}
+
+impl PacketPool {
+    pub fn new(max_buffers: usize) -> PacketPool {
+        PacketPool {
+            inner: Arc::new(Mutex::new(PoolState::default())),
+            max_buffers,
+        }
+    }
+
+    /// Returns an empty buffer. The buffer is drawn from the pool if one is available, otherwise
+    /// a new buffer is allocated.
+    pub fn take(&self) -> PacketData {
+        let mut state = self.inner.lock().unwrap();
+        match state.buffers.pop() {
+            Some(buffer) => buffer,
+            None => {
+                state.allocations += 1;
+                PacketData::new()
+            }
+        }
+    }
+
+    /// Returns a buffer to the pool so that it may be reused.
+    pub fn recycle(&self, mut buffer: PacketData) {
+        buffer.clear();
+        let mut state = self.inner.lock().unwrap();
+        if state.buffers.len() < self.max_buffers {
+            state.buffers.push(buffer);
+        }
+    }
+
+    /// Number of idle buffers currently held by the pool.
+    pub fn available(&self) -> usize {
+        self.inner.lock().unwrap().buffers.len()
+    }
+
+    /// Number of times `take` had to allocate a new buffer because the pool was empty.
+    pub fn allocations(&self) -> usize {
+        self.inner.lock().unwrap().allocations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_recycled_buffers() {
+        let pool = PacketPool::new(4);
+        let mut buffer = pool.take();
+        buffer.extend_from_slice(&[1, 2, 3]);
+        let address = buffer.as_ptr();
+        pool.recycle(buffer);
+        assert_eq!(pool.available(), 1);
+
+        let buffer = pool.take();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.as_ptr(), address);
+        assert_eq!(pool.allocations(), 1);
+    }
+
+    #[test]
+    fn holds_at_most_max_buffers() {
+        let pool = PacketPool::new(2);
+        for _ in 0..5 {
+            pool.recycle(vec![0; 20]);
+        }
+        assert_eq!(pool.available(), 2);
+    }
+}
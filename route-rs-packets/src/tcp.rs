@@ -235,9 +235,10 @@ impl Eq for TcpSegment {}
 impl TryFrom<Ipv4Packet> for TcpSegment {
     type Error = &'static str;
 
-    fn try_from(packet: Ipv4Packet) -> Result<Self, Self::Error> {
+    fn try_from(mut packet: Ipv4Packet) -> Result<Self, Self::Error> {
+        let data = packet.take_data();
         TcpSegment::from_buffer(
-            packet.data,
+            data,
             packet.layer2_offset,
             Some(packet.layer3_offset),
             packet.payload_offset,
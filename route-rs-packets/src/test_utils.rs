@@ -0,0 +1,52 @@
+//! Shared helpers for testing packet types. Every packet type should be able to parse a buffer
+//! and serialize it back to the same bytes, and should carry valid checksums when the buffer did.
+//! Implement `Roundtrip` for a packet type to check it with `assert_roundtrip`.
+
+use crate::*;
+use std::fmt::Debug;
+
+pub(crate) trait Roundtrip: Sized {
+    type Error: Debug;
+
+    fn parse(bytes: &[u8]) -> Result<Self, Self::Error>;
+
+    fn serialize(&self) -> Vec<u8>;
+
+    /// Whether the checksums the packet carries are correct for its contents.
+    fn checksums_valid(&mut self) -> bool;
+}
+
+/// Parses `bytes` as a `P`, and asserts that it serializes back to exactly `bytes` and that its
+/// checksums are valid. Returns the parsed packet for further checks.
+pub(crate) fn assert_roundtrip<P: Roundtrip>(bytes: &[u8]) -> P {
+    let mut packet = P::parse(bytes).unwrap_or_else(|err| {
+        panic!("Failed to parse {:02x?}: {:?}", bytes, err);
+    });
+    assert_eq!(
+        packet.serialize(),
+        bytes,
+        "Serialized bytes differ from parsed"
+    );
+    assert!(
+        packet.checksums_valid(),
+        "Checksum invalid for {:02x?}",
+        bytes
+    );
+    packet
+}
+
+impl Roundtrip for Ipv4Packet {
+    type Error = ParseError;
+
+    fn parse(bytes: &[u8]) -> Result<Self, Self::Error> {
+        Ipv4Packet::try_from_bytes(bytes)
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        self.data[self.layer3_offset..].to_vec()
+    }
+
+    fn checksums_valid(&mut self) -> bool {
+        self.validate_checksum() && self.caclulate_checksum() == self.checksum()
+    }
+}
@@ -4,6 +4,59 @@ use std::fmt;
 /// The common datatype that all packet structures share to repreasent their data
 pub type PacketData = Vec<u8>;
 
+/// Describes why a buffer could not be parsed as a packet.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum ParseError {
+    /// The buffer is shorter than the smallest valid header.
+    TooShort { len: usize, min_len: usize },
+    /// The version field does not match the packet type.
+    InvalidVersion(u8),
+    /// The header length field is smaller than the minimum header, or runs past the end of the packet.
+    InvalidHeaderLength(u8),
+    /// The length field disagrees with the size of the buffer.
+    InvalidTotalLength { total_len: usize, buffer_len: usize },
+}
+
+impl ParseError {
+    /// A short, static description of the error, for APIs that report errors as `&'static str`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParseError::TooShort { .. } => "Data is too short to be an IPv4 Packet",
+            ParseError::InvalidVersion(_) => "Packet has incorrect version, is not Ipv4Packet",
+            ParseError::InvalidHeaderLength(_) => "Packet has invalid header length field",
+            ParseError::InvalidTotalLength { .. } => "Packet has invalid total length field",
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::TooShort { len, min_len } => write!(
+                f,
+                "buffer of {} bytes is shorter than the minimum of {} bytes",
+                len, min_len
+            ),
+            ParseError::InvalidVersion(version) => write!(f, "unexpected version {}", version),
+            ParseError::InvalidHeaderLength(ihl) => write!(
+                f,
+                "header length of {} words is invalid for this packet",
+                ihl
+            ),
+            ParseError::InvalidTotalLength {
+                total_len,
+                buffer_len,
+            } => write!(
+                f,
+                "total length of {} bytes does not fit a buffer of {} bytes",
+                total_len, buffer_len
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 // Most significant byte is 0th
 #[derive(Eq, Clone, Copy, Hash, PartialEq, Debug)]
 pub struct MacAddr {
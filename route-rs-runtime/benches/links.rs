@@ -0,0 +1,189 @@
+//! Throughput of the primitive links, in packets per second, over a fixed workload.
+//!
+//! Run with `cargo bench -p route-rs-runtime --bench links`. Each link is built and run to
+//! completion `ITERATIONS` times on `PACKETS` packets, and the median run is reported, which keeps
+//! the numbers steady from one run to the next. Every egressor is drained without collecting its
+//! packets, so only the link itself is measured.
+//!
+//! Baseline, on a single core x86_64 Linux VM. The numbers vary by about 20% between runs, so
+//! only a drop well beyond that is a regression worth looking into:
+//!
+//! ```text
+//! ProcessLink     ~300M packets/sec
+//! ClassifyLink     ~12M packets/sec
+//! JoinLink          ~6M packets/sec
+//! ForkLink          ~6M packets/sec
+//! ```
+//!
+//! The allocations made while a `ProcessLink` carries freshly parsed `Ipv4Packet`s are counted
+//! too, once with every packet allocating its own buffer and once with buffers drawn from a
+//! `PacketPool`. Pooled packets should make close to no allocations per packet:
+//!
+//! ```text
+//! Ipv4Packet unpooled     1.00 allocations/packet
+//! Ipv4Packet pooled       0.00 allocations/packet
+//! ```
+
+use futures::stream;
+use route_rs_packets::{Ipv4Packet, PacketData, PacketPool};
+use route_rs_runtime::classifier::Even;
+use route_rs_runtime::link::primitive::{ClassifyLink, ForkLink, JoinLink, ProcessLink};
+use route_rs_runtime::link::{Link, LinkBuilder, ProcessLinkBuilder, TokioRunnable};
+use route_rs_runtime::processor::Identity;
+use route_rs_runtime::utils::test::harness::initialize_runtime;
+use route_rs_runtime::utils::test::packet_collectors::ExhaustiveDrain;
+use route_rs_runtime::utils::test::packet_generators::{
+    immediate_stream, pooled_ipv4_stream, Ipv4PacketGenerator,
+};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+
+const PACKETS: usize = 100_000;
+const ITERATIONS: usize = 15;
+
+/// The system allocator, counting every allocation made through it.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Runs a link to completion, draining every egressor.
+async fn run<T: Debug + Send + 'static>(link: Link<T>) {
+    let (mut runnables, egressors) = link;
+    for (id, egressor) in egressors.into_iter().enumerate() {
+        let drain: TokioRunnable = Box::new(ExhaustiveDrain::new(id, egressor));
+        runnables.push(drain);
+    }
+
+    let handles: Vec<_> = runnables.into_iter().map(tokio::spawn).collect();
+    for handle in handles {
+        handle.await.unwrap();
+    }
+}
+
+/// Reports the median throughput of the links built by `build`, which must take `PACKETS`
+/// packets of input.
+fn bench(runtime: &mut Runtime, name: &str, build: impl Fn() -> Link<i32>) {
+    let mut runs: Vec<Duration> = (0..ITERATIONS)
+        .map(|_| {
+            let link = build();
+            let start = Instant::now();
+            runtime.block_on(run(link));
+            start.elapsed()
+        })
+        .collect();
+    runs.sort();
+
+    let median = runs[ITERATIONS / 2];
+    println!(
+        "{:<15} {:>6.2}M packets/sec  (median of {} runs, {:?} per run)",
+        name,
+        PACKETS as f64 / median.as_secs_f64() / 1_000_000.0,
+        ITERATIONS,
+        median
+    );
+}
+
+/// Reports how many allocations running the link built by `build` makes per packet, beyond those
+/// made to build it.
+fn bench_allocations<T: Debug + Send + 'static>(
+    runtime: &mut Runtime,
+    name: &str,
+    build: impl Fn() -> Link<T>,
+) {
+    let link = build();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    runtime.block_on(run(link));
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+    println!(
+        "{:<23} {:>4.2} allocations/packet",
+        name,
+        allocations as f64 / PACKETS as f64
+    );
+}
+
+fn packets() -> Vec<i32> {
+    (0..PACKETS as i32).collect()
+}
+
+/// The same valid IPv4 packet, as bytes, `PACKETS` times over.
+fn frames() -> Vec<PacketData> {
+    let frame = Ipv4PacketGenerator::new(0).valid_packet().data.clone();
+    vec![frame; PACKETS]
+}
+
+fn main() {
+    let mut runtime = initialize_runtime();
+
+    bench(&mut runtime, "ProcessLink", || {
+        ProcessLink::new()
+            .ingressor(immediate_stream(packets()))
+            .processor(Identity::new())
+            .build_link()
+    });
+
+    bench(&mut runtime, "ClassifyLink", || {
+        ClassifyLink::new()
+            .ingressor(immediate_stream(packets()))
+            .num_egressors(2)
+            .classifier(Even::new())
+            .dispatcher(Box::new(|is_even| if is_even { 0 } else { 1 }))
+            .build_link()
+    });
+
+    bench(&mut runtime, "JoinLink", || {
+        let per_ingressor = PACKETS as i32 / 4;
+        JoinLink::new()
+            .ingressors(
+                (0..4)
+                    .map(|i| immediate_stream(i * per_ingressor..(i + 1) * per_ingressor))
+                    .collect(),
+            )
+            .build_link()
+    });
+
+    bench(&mut runtime, "ForkLink", || {
+        ForkLink::new()
+            .ingressor(immediate_stream(packets()))
+            .num_egressors(2)
+            .build_link()
+    });
+
+    let frames = frames();
+    bench_allocations(&mut runtime, "Ipv4Packet unpooled", || {
+        let frames = frames.clone();
+        let parsed = stream::iter(
+            frames
+                .into_iter()
+                .filter_map(|frame| Ipv4Packet::try_from_bytes(&frame).ok()),
+        );
+        ProcessLink::new()
+            .ingressor(Box::new(parsed))
+            .processor(Identity::new())
+            .build_link()
+    });
+
+    bench_allocations(&mut runtime, "Ipv4Packet pooled", || {
+        ProcessLink::new()
+            .ingressor(pooled_ipv4_stream(PacketPool::new(64), frames.clone()))
+            .processor(Identity::new())
+            .build_link()
+    });
+}
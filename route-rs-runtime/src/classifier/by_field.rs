@@ -0,0 +1,103 @@
+use crate::classifier::Classifier;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+type Extractor<P, K> = Box<dyn Fn(&P) -> K + Send>;
+
+/// Classifies packets on any field that can be hashed, such as the protocol, a port, or the DSCP.
+/// An extractor pulls the field `K` out of each packet, and the class is looked up in a map from
+/// field to class `T`. Packets whose field is not in the map get the default class.
+pub struct ByField<P, K, T> {
+    extractor: Extractor<P, K>,
+    classes: HashMap<K, T>,
+    default: T,
+}
+
+impl<P, K: Eq + Hash, T> ByField<P, K, T> {
+    pub fn new<F>(extractor: F, classes: HashMap<K, T>, default: T) -> Self
+    where
+        F: Fn(&P) -> K + Send + 'static,
+    {
+        ByField {
+            extractor: Box::new(extractor),
+            classes,
+            default,
+        }
+    }
+}
+
+impl<P: Send + Clone, K: Eq + Hash, T: Clone> Classifier for ByField<P, K, T> {
+    type Packet = P;
+    type Class = T;
+
+    fn classify(&self, packet: &Self::Packet) -> Self::Class {
+        self.classes
+            .get(&(self.extractor)(packet))
+            .unwrap_or(&self.default)
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::primitive::ClassifyLink;
+    use crate::link::LinkBuilder;
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use route_rs_packets::Ipv4Packet;
+    use std::net::Ipv4Addr;
+
+    fn packet(last_octet: u8) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_dest_addr(Ipv4Addr::new(192, 168, 1, last_octet));
+        packet
+    }
+
+    fn by_last_octet() -> ByField<Ipv4Packet, u8, usize> {
+        let classes = vec![(1, 0), (2, 1), (3, 1)].into_iter().collect();
+        ByField::new(
+            |packet: &Ipv4Packet| packet.dest_addr().octets()[3],
+            classes,
+            2,
+        )
+    }
+
+    #[test]
+    fn classifies_by_extracted_field() {
+        let classifier = by_last_octet();
+
+        assert_eq!(classifier.classify(&packet(1)), 0);
+        assert_eq!(classifier.classify(&packet(2)), 1);
+        assert_eq!(classifier.classify(&packet(3)), 1);
+        assert_eq!(classifier.classify(&packet(4)), 2);
+    }
+
+    #[test]
+    fn sorts_into_buckets() {
+        let packets: Vec<Ipv4Packet> = (1..=6).map(packet).collect();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = ClassifyLink::new()
+                .ingressor(immediate_stream(packets))
+                .num_egressors(3)
+                .classifier(by_last_octet())
+                .dispatcher(Box::new(|bucket| bucket))
+                .build_link();
+
+            run_link(link).await
+        });
+
+        let last_octets: Vec<Vec<u8>> = results
+            .iter()
+            .map(|bucket| {
+                bucket
+                    .iter()
+                    .map(|packet| packet.dest_addr().octets()[3])
+                    .collect()
+            })
+            .collect();
+        assert_eq!(last_octets, vec![vec![1], vec![2, 3], vec![4, 5, 6]]);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::classifier::Classifier;
+use crate::processor::Mark;
+use std::marker::PhantomData;
+
+/// Classifies marked packets, as `SetMark` marks them, by their mark, so that a `ClassifyLink` can
+/// branch on a decision made by an earlier stage without looking at the packet again.
+pub struct ByMark<P> {
+    phantom: PhantomData<P>,
+}
+
+impl<P> ByMark<P> {
+    pub fn new() -> Self {
+        ByMark {
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<P> Default for ByMark<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Send + Clone> Classifier for ByMark<P> {
+    type Packet = (Mark, P);
+    type Class = Mark;
+
+    fn classify(&self, (mark, _): &Self::Packet) -> Self::Class {
+        *mark
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::primitive::ClassifyLink;
+    use crate::link::LinkBuilder;
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+
+    #[test]
+    fn classifies_by_mark() {
+        let classifier = ByMark::new();
+
+        assert_eq!(classifier.classify(&(0, 'a')), 0);
+        assert_eq!(classifier.classify(&(7, 'b')), 7);
+    }
+
+    #[test]
+    fn dispatches_by_mark() {
+        let packets = vec![(2, 'a'), (0, 'b'), (1, 'c'), (2, 'd'), (0, 'e')];
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = ClassifyLink::new()
+                .ingressor(immediate_stream(packets))
+                .num_egressors(3)
+                .classifier(ByMark::new())
+                .dispatcher(Box::new(|mark| mark as usize))
+                .build_link();
+
+            run_link(link).await
+        });
+
+        assert_eq!(
+            results,
+            vec![
+                vec![(0, 'b'), (0, 'e')],
+                vec![(1, 'c')],
+                vec![(2, 'a'), (2, 'd')],
+            ]
+        );
+    }
+}
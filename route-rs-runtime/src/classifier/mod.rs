@@ -10,6 +10,12 @@ pub use self::even::*;
 mod fizz_buzz;
 pub use self::fizz_buzz::*;
 
+mod by_field;
+pub use self::by_field::*;
+
+mod by_mark;
+pub use self::by_mark::*;
+
 /// Used by a ClassifyLink to determine the kind of packet we have. Classifier::Class is then
 /// consumed by the dispatcher on the ClassifyLink to send it down the appropriate path.
 pub trait Classifier {
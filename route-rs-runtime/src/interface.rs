@@ -0,0 +1,144 @@
+//! # What is it for?
+//!
+//! Processors that run on the egress side of a router often need to know something about the
+//! interface a packet is leaving through: its MTU, its addresses, or its MAC. Rather than each
+//! processor carrying its own copy, the router builds one `InterfaceConfig` and hands a clone to
+//! every processor that needs it. Interfaces are identified by the same index used to tag packets
+//! and to pick egressors.
+
+use route_rs_packets::MacAddr;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, RwLock};
+
+/// The settings of a single interface.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterfaceSettings {
+    pub mtu: u16,
+    pub mac: MacAddr,
+    /// The IPv4 addresses of the interface. The first one is used as the source of packets the
+    /// router originates on this interface, such as ICMP errors.
+    pub ipv4_addrs: Vec<Ipv4Addr>,
+}
+
+impl InterfaceSettings {
+    pub fn new(mtu: u16, mac: MacAddr, ipv4_addrs: Vec<Ipv4Addr>) -> Self {
+        InterfaceSettings {
+            mtu,
+            mac,
+            ipv4_addrs,
+        }
+    }
+
+    /// The address the router uses as the source of packets it originates on this interface.
+    pub fn primary_ipv4_addr(&self) -> Option<Ipv4Addr> {
+        self.ipv4_addrs.first().copied()
+    }
+}
+
+/// A map from interface to `InterfaceSettings`. Clones share the same map, so changes made
+/// through one handle are seen by every processor holding another.
+#[derive(Clone, Default)]
+pub struct InterfaceConfig {
+    interfaces: Arc<RwLock<HashMap<usize, InterfaceSettings>>>,
+}
+
+impl InterfaceConfig {
+    pub fn new() -> Self {
+        InterfaceConfig {
+            interfaces: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Adds or replaces the settings of `interface`.
+    pub fn set(&self, interface: usize, settings: InterfaceSettings) {
+        self.interfaces.write().unwrap().insert(interface, settings);
+    }
+
+    /// Returns a copy of the settings of `interface`, if it is configured.
+    pub fn get(&self, interface: usize) -> Option<InterfaceSettings> {
+        self.interfaces.read().unwrap().get(&interface).cloned()
+    }
+
+    pub fn mtu(&self, interface: usize) -> Option<u16> {
+        self.interfaces
+            .read()
+            .unwrap()
+            .get(&interface)
+            .map(|settings| settings.mtu)
+    }
+
+    pub fn mac(&self, interface: usize) -> Option<MacAddr> {
+        self.interfaces
+            .read()
+            .unwrap()
+            .get(&interface)
+            .map(|settings| settings.mac)
+    }
+
+    /// The configured interfaces, in ascending order.
+    pub fn interfaces(&self) -> Vec<usize> {
+        let mut interfaces: Vec<usize> = self.interfaces.read().unwrap().keys().copied().collect();
+        interfaces.sort();
+        interfaces
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAN: usize = 0;
+    const WAN: usize = 1;
+
+    fn home_router() -> InterfaceConfig {
+        let config = InterfaceConfig::new();
+        config.set(
+            LAN,
+            InterfaceSettings::new(
+                1500,
+                MacAddr::new([0x02, 0, 0, 0, 0, 0x01]),
+                vec![Ipv4Addr::new(192, 168, 1, 1)],
+            ),
+        );
+        config.set(
+            WAN,
+            InterfaceSettings::new(
+                1492,
+                MacAddr::new([0x02, 0, 0, 0, 0, 0x02]),
+                vec![Ipv4Addr::new(203, 0, 113, 7)],
+            ),
+        );
+        config
+    }
+
+    #[test]
+    fn reads_per_interface_settings() {
+        let config = home_router();
+
+        assert_eq!(config.mtu(LAN), Some(1500));
+        assert_eq!(config.mtu(WAN), Some(1492));
+        assert_eq!(
+            config.mac(WAN),
+            Some(MacAddr::new([0x02, 0, 0, 0, 0, 0x02]))
+        );
+        assert_eq!(
+            config.get(WAN).unwrap().primary_ipv4_addr(),
+            Some(Ipv4Addr::new(203, 0, 113, 7))
+        );
+        assert_eq!(config.mtu(7), None);
+        assert_eq!(config.interfaces(), vec![LAN, WAN]);
+    }
+
+    #[test]
+    fn clones_share_changes() {
+        let config = home_router();
+        let handle = config.clone();
+
+        let mut wan = config.get(WAN).unwrap();
+        wan.mtu = 1400;
+        config.set(WAN, wan);
+
+        assert_eq!(handle.mtu(WAN), Some(1400));
+    }
+}
@@ -14,6 +14,15 @@ pub mod classifier;
 /// Wrappers around Processors and Classfiers, and implement all the movement of Packets through the Router.
 pub mod link;
 
+/// Per-interface configuration, such as the MTU and addresses of each interface of the router.
+pub mod interface;
+
+/// The routing table, shared between the processors that look routes up and whatever updates them.
+pub mod routing;
+
+/// Serves the state of a running router, such as its routes and counters, over a Unix socket.
+pub mod management;
+
 /// Structure meant to encapsulate a router as and input and output channel. Used by graphgen.
 pub mod pipeline;
 
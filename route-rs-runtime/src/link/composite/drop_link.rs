@@ -1,5 +1,5 @@
 use crate::link::primitive::ProcessLink;
-use crate::link::{Link, LinkBuilder, PacketStream, ProcessLinkBuilder};
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream, ProcessLinkBuilder};
 use crate::processor::Drop;
 
 /// Link that drops packets.
@@ -23,17 +23,15 @@ impl<I> DropLink<I> {
 
     pub fn drop_chance(self, chance: f64) -> Self {
         DropLink {
-            in_stream: self.in_stream,
             drop_chance: Some(chance),
-            seed: self.seed,
+            ..self
         }
     }
 
     pub fn seed(self, int_seed: u64) -> Self {
         DropLink {
-            in_stream: self.in_stream,
-            drop_chance: self.drop_chance,
             seed: Some(int_seed),
+            ..self
         }
     }
 }
@@ -52,8 +50,7 @@ impl<I: Send + Clone + 'static> LinkBuilder<I, I> for DropLink<I> {
 
         DropLink {
             in_stream: Some(ingress_streams.remove(0)),
-            drop_chance: self.drop_chance,
-            seed: self.seed,
+            ..self
         }
     }
 
@@ -64,14 +61,17 @@ impl<I: Send + Clone + 'static> LinkBuilder<I, I> for DropLink<I> {
 
         DropLink {
             in_stream: Some(in_stream),
-            drop_chance: self.drop_chance,
-            seed: self.seed,
+            ..self
         }
     }
 
-    fn build_link(self) -> Link<I> {
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<I>, LinkBuildError> {
         if self.in_stream.is_none() {
-            panic!("Cannot build link! Missing input streams");
+            Err(LinkBuildError::MissingIngressors)
         } else {
             let mut dropper: Drop<I> = Drop::new();
 
@@ -86,7 +86,7 @@ impl<I: Send + Clone + 'static> LinkBuilder<I, I> for DropLink<I> {
             ProcessLink::new()
                 .ingressor(self.in_stream.unwrap())
                 .processor(dropper)
-                .build_link()
+                .try_build_link()
         }
     }
 }
@@ -1,7 +1,8 @@
 use crate::link::{
     primitive::{ForkLink, JoinLink, ProcessLink},
-    Link, LinkBuilder, PacketStream, ProcessLinkBuilder,
+    Link, LinkBuildError, LinkBuilder, PacketStream, ProcessLinkBuilder,
 };
+use crate::link_builder;
 use crate::processor::Processor;
 
 #[derive(Default)]
@@ -24,48 +25,13 @@ impl<P: Processor + Send> MtransformNLink<P> {
         }
     }
 
-    /// Changes join_queue_capcity, default value is 10.
-    pub fn join_queue_capacity(self, queue_capacity: usize) -> Self {
-        assert!(
-            queue_capacity > 0,
-            format!("join_queue_capacity: {} must be > 0", queue_capacity)
-        );
-
-        MtransformNLink {
-            in_streams: self.in_streams,
-            processor: self.processor,
-            join_queue_capacity: queue_capacity,
-            fork_queue_capacity: self.fork_queue_capacity,
-            num_egressors: self.num_egressors,
-        }
-    }
-
-    /// Changes tee_queue_capcity, default value is 10.
-    pub fn fork_queue_capacity(self, queue_capacity: usize) -> Self {
-        assert!(
-            queue_capacity > 0,
-            format!("fork_queue_capacity: {} must be > 0", queue_capacity)
-        );
-
-        MtransformNLink {
-            in_streams: self.in_streams,
-            processor: self.processor,
-            join_queue_capacity: self.join_queue_capacity,
-            fork_queue_capacity: queue_capacity,
-            num_egressors: self.num_egressors,
-        }
-    }
-
-    pub fn num_egressors(self, num_egressors: usize) -> Self {
-        assert_ne!(num_egressors, 0, "num_egressors must be > 0");
-
-        MtransformNLink {
-            in_streams: self.in_streams,
-            processor: self.processor,
-            join_queue_capacity: self.join_queue_capacity,
-            fork_queue_capacity: self.fork_queue_capacity,
-            num_egressors: Some(num_egressors),
-        }
+    link_builder! {
+        /// Changes join_queue_capacity, default value is 10.
+        join_queue_capacity: usize where join_queue_capacity > 0,
+        /// Changes fork_queue_capacity, default value is 10.
+        fork_queue_capacity: usize where fork_queue_capacity > 0,
+        /// Sets the number of egressors, which must be > 0.
+        num_egressors: Option<usize>,
     }
 }
 
@@ -82,10 +48,7 @@ impl<P: Processor + Send + 'static> LinkBuilder<P::Input, P::Output> for Mtransf
 
         MtransformNLink {
             in_streams: Some(in_streams),
-            processor: self.processor,
-            join_queue_capacity: self.join_queue_capacity,
-            fork_queue_capacity: self.fork_queue_capacity,
-            num_egressors: self.num_egressors,
+            ..self
         }
     }
 
@@ -93,50 +56,52 @@ impl<P: Processor + Send + 'static> LinkBuilder<P::Input, P::Output> for Mtransf
         match self.in_streams {
             None => MtransformNLink {
                 in_streams: Some(vec![in_stream]),
-                processor: self.processor,
-                join_queue_capacity: self.join_queue_capacity,
-                fork_queue_capacity: self.fork_queue_capacity,
-                num_egressors: self.num_egressors,
+                ..self
             },
             Some(mut existing_streams) => {
                 existing_streams.push(in_stream);
                 MtransformNLink {
                     in_streams: Some(existing_streams),
-                    processor: self.processor,
-                    join_queue_capacity: self.join_queue_capacity,
-                    fork_queue_capacity: self.fork_queue_capacity,
-                    num_egressors: self.num_egressors,
+                    ..self
                 }
             }
         }
     }
 
-    fn build_link(self) -> Link<P::Output> {
+    fn ingressor_capacity(&self) -> Option<usize> {
+        if self.in_streams.is_some() {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn try_build_link(self) -> Result<Link<P::Output>, LinkBuildError> {
         if self.in_streams.is_none() {
-            panic!("Cannot build link! Missing input stream");
+            Err(LinkBuildError::MissingIngressors)
         } else if self.num_egressors.is_none() {
-            panic!("Cannot build link! Missing number of num_egressors");
+            Err(LinkBuildError::Missing("num_egressors"))
         } else if self.processor.is_none() {
-            panic!("Cannot build link! Missing processor");
+            Err(LinkBuildError::Missing("processor"))
         } else {
             let (mut join_runnables, join_egressors) = JoinLink::new()
                 .ingressors(self.in_streams.unwrap())
                 .queue_capacity(self.join_queue_capacity)
-                .build_link();
+                .try_build_link()?;
 
             let (_, process_egressors) = ProcessLink::new()
                 .ingressors(join_egressors)
                 .processor(self.processor.unwrap())
-                .build_link();
+                .try_build_link()?;
 
             let (mut fork_link_runnables, fork_link_egressors) = ForkLink::new()
                 .ingressors(process_egressors)
                 .queue_capacity(self.fork_queue_capacity)
                 .num_egressors(self.num_egressors.unwrap())
-                .build_link();
+                .try_build_link()?;
             fork_link_runnables.append(&mut join_runnables);
 
-            (fork_link_runnables, fork_link_egressors)
+            Ok((fork_link_runnables, fork_link_egressors))
         }
     }
 }
@@ -144,11 +109,8 @@ impl<P: Processor + Send + 'static> LinkBuilder<P::Input, P::Output> for Mtransf
 impl<P: Processor + Send + 'static> ProcessLinkBuilder<P> for MtransformNLink<P> {
     fn processor(self, processor: P) -> Self {
         MtransformNLink {
-            in_streams: self.in_streams,
             processor: Some(processor),
-            join_queue_capacity: self.join_queue_capacity,
-            fork_queue_capacity: self.fork_queue_capacity,
-            num_egressors: self.num_egressors,
+            ..self
         }
     }
 }
@@ -11,3 +11,11 @@ pub use self::m_transform_n_link::*;
 /// Drops packets with weighted randomness.
 mod drop_link;
 pub use self::drop_link::*;
+
+/// Copies all input to each of its outputs by reference counting, rather than deep copying.
+mod rc_fork_link;
+pub use self::rc_fork_link::*;
+
+/// Emulates a WAN link by chaining loss, rate limiting, and delay.
+mod wan_emulator_link;
+pub use self::wan_emulator_link::*;
@@ -1,7 +1,8 @@
 use crate::link::{
     primitive::{ForkLink, JoinLink},
-    Link, LinkBuilder, PacketStream,
+    Link, LinkBuildError, LinkBuilder, PacketStream,
 };
+use crate::link_builder;
 
 #[derive(Default)]
 pub struct MtoNLink<Packet: Sized + Send + Clone> {
@@ -21,21 +22,6 @@ impl<Packet: Sized + Send + Clone> MtoNLink<Packet> {
         }
     }
 
-    /// Changes join_queue_capcity, default value is 10.
-    pub fn join_queue_capacity(self, queue_capacity: usize) -> Self {
-        assert!(
-            queue_capacity > 0,
-            format!("join_queue_capacity: {}, must be > 0", queue_capacity)
-        );
-
-        MtoNLink {
-            in_streams: self.in_streams,
-            join_queue_capacity: queue_capacity,
-            fork_queue_capacity: self.fork_queue_capacity,
-            num_egressors: self.num_egressors,
-        }
-    }
-
     /// Changes tee_queue_capcity, default value is 10.
     pub fn tee_queue_capacity(self, queue_capacity: usize) -> Self {
         assert!(
@@ -44,25 +30,16 @@ impl<Packet: Sized + Send + Clone> MtoNLink<Packet> {
         );
 
         MtoNLink {
-            in_streams: self.in_streams,
-            join_queue_capacity: self.join_queue_capacity,
             fork_queue_capacity: queue_capacity,
-            num_egressors: self.num_egressors,
+            ..self
         }
     }
 
-    pub fn num_egressors(self, num_egressors: usize) -> Self {
-        assert!(
-            num_egressors > 0,
-            format!("num_egressors: {}, must be > 0", num_egressors)
-        );
-
-        MtoNLink {
-            in_streams: self.in_streams,
-            join_queue_capacity: self.join_queue_capacity,
-            fork_queue_capacity: self.fork_queue_capacity,
-            num_egressors: Some(num_egressors),
-        }
+    link_builder! {
+        /// Changes join_queue_capacity, default value is 10.
+        join_queue_capacity: usize where join_queue_capacity > 0,
+        /// Sets the number of egressors, which must be > 0.
+        num_egressors: Option<usize>,
     }
 }
 
@@ -79,9 +56,7 @@ impl<Packet: Sized + Send + Clone + 'static> LinkBuilder<Packet, Packet> for Mto
 
         MtoNLink {
             in_streams: Some(in_streams),
-            join_queue_capacity: self.join_queue_capacity,
-            fork_queue_capacity: self.fork_queue_capacity,
-            num_egressors: self.num_egressors,
+            ..self
         }
     }
 
@@ -89,39 +64,43 @@ impl<Packet: Sized + Send + Clone + 'static> LinkBuilder<Packet, Packet> for Mto
         match self.in_streams {
             None => MtoNLink {
                 in_streams: Some(vec![in_stream]),
-                join_queue_capacity: self.join_queue_capacity,
-                fork_queue_capacity: self.fork_queue_capacity,
-                num_egressors: self.num_egressors,
+                ..self
             },
             Some(mut existing_streams) => {
                 existing_streams.push(in_stream);
                 MtoNLink {
                     in_streams: Some(existing_streams),
-                    join_queue_capacity: self.join_queue_capacity,
-                    fork_queue_capacity: self.fork_queue_capacity,
-                    num_egressors: self.num_egressors,
+                    ..self
                 }
             }
         }
     }
 
-    fn build_link(self) -> Link<Packet> {
+    fn ingressor_capacity(&self) -> Option<usize> {
+        if self.in_streams.is_some() {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn try_build_link(self) -> Result<Link<Packet>, LinkBuildError> {
         if self.in_streams.is_none() {
-            panic!("Cannot build link! Missing input stream");
+            Err(LinkBuildError::MissingIngressors)
         } else if self.num_egressors.is_none() {
-            panic!("Cannot build link! Missing number of num_egressors");
+            Err(LinkBuildError::Missing("num_egressors"))
         } else {
             let (mut join_runnables, join_egressors) = JoinLink::new()
                 .ingressors(self.in_streams.unwrap())
                 .queue_capacity(self.join_queue_capacity)
-                .build_link();
+                .try_build_link()?;
             let (mut fork_link_runnables, fork_link_egressors) = ForkLink::new()
                 .ingressors(join_egressors)
                 .queue_capacity(self.fork_queue_capacity)
                 .num_egressors(self.num_egressors.unwrap())
-                .build_link();
+                .try_build_link()?;
             fork_link_runnables.append(&mut join_runnables);
-            (fork_link_runnables, fork_link_egressors)
+            Ok((fork_link_runnables, fork_link_egressors))
         }
     }
 }
@@ -135,6 +114,16 @@ mod tests {
 
     use crate::utils::test::harness::{initialize_runtime, run_link};
 
+    #[test]
+    fn try_build_link_rejects_zero_egressors() {
+        let result = MtoNLink::<i32>::new()
+            .ingressor(immediate_stream(vec![]))
+            .ingressor(immediate_stream(vec![]))
+            .num_egressors(0)
+            .try_build_link();
+        assert_eq!(result.err(), Some(LinkBuildError::NoEgressors));
+    }
+
     #[test]
     fn clone_m_streams_on_to_n_egress_streams() {
         let packets = vec![0, 1, 2, 420, 1337, 3, 4, 5, 6, 7, 8, 9, 11];
@@ -0,0 +1,232 @@
+use crate::link::{
+    primitive::{ForkLink, ProcessLink},
+    Link, LinkBuildError, LinkBuilder, PacketStream, ProcessLinkBuilder,
+};
+use crate::link_builder;
+use crate::processor::TransformFrom;
+use std::sync::Arc;
+
+/// `RcForkLink` behaves like a `ForkLink`, but rather than deep copying each packet once per
+/// egressor, it wraps the packet in an `Arc` so that every egressor shares the same buffer.
+///
+/// Downstream links receive `Arc<Packet>`, which only gives read-only access. This makes it a good
+/// fit for taps, sampling, and logging paths that only inspect packets. A consumer that does need
+/// to modify its packet can call `Arc::make_mut`, which only copies the packet if it is still shared
+/// with another egressor.
+#[derive(Default)]
+pub struct RcForkLink<Packet: Send + Sync + Clone> {
+    in_stream: Option<PacketStream<Packet>>,
+    queue_capacity: usize,
+    num_egressors: Option<usize>,
+}
+
+impl<Packet: Send + Sync + Clone> RcForkLink<Packet> {
+    pub fn new() -> Self {
+        RcForkLink {
+            in_stream: None,
+            queue_capacity: 10,
+            num_egressors: None,
+        }
+    }
+
+    link_builder! {
+        /// Changes queue_capacity, default value is 10.
+        queue_capacity: usize where queue_capacity > 0,
+        /// Sets the number of egressors, which must be > 0.
+        num_egressors: Option<usize>,
+    }
+}
+
+impl<Packet: Send + Sync + Clone + 'static> LinkBuilder<Packet, Arc<Packet>>
+    for RcForkLink<Packet>
+{
+    fn ingressors(self, mut in_streams: Vec<PacketStream<Packet>>) -> Self {
+        assert_eq!(
+            in_streams.len(),
+            1,
+            "RcForkLink may only take 1 input stream"
+        );
+
+        if self.in_stream.is_some() {
+            panic!("RcForkLink may only take 1 input stream")
+        }
+
+        RcForkLink {
+            in_stream: Some(in_streams.remove(0)),
+            queue_capacity: self.queue_capacity,
+            num_egressors: self.num_egressors,
+        }
+    }
+
+    fn ingressor(self, in_stream: PacketStream<Packet>) -> Self {
+        if self.in_stream.is_some() {
+            panic!("RcForkLink may only take 1 input stream")
+        }
+
+        RcForkLink {
+            in_stream: Some(in_stream),
+            queue_capacity: self.queue_capacity,
+            num_egressors: self.num_egressors,
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<Arc<Packet>>, LinkBuildError> {
+        let in_stream = self.in_stream.ok_or(LinkBuildError::MissingIngressors)?;
+        let num_egressors = self
+            .num_egressors
+            .ok_or(LinkBuildError::Missing("num_egressors"))?;
+
+        let (_, share_egressors) = ProcessLink::new()
+            .ingressor(in_stream)
+            .processor(TransformFrom::<Packet, Arc<Packet>>::new())
+            .try_build_link()?;
+
+        ForkLink::new()
+            .ingressors(share_egressors)
+            .queue_capacity(self.queue_capacity)
+            .num_egressors(num_egressors)
+            .try_build_link()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A large packet that records every time it is deep copied.
+    #[derive(Debug)]
+    struct LargePacket {
+        payload: Vec<u8>,
+        copies: Arc<AtomicUsize>,
+    }
+
+    impl Clone for LargePacket {
+        fn clone(&self) -> Self {
+            self.copies.fetch_add(1, Ordering::SeqCst);
+            LargePacket {
+                payload: self.payload.clone(),
+                copies: Arc::clone(&self.copies),
+            }
+        }
+    }
+
+    fn large_packets(copies: &Arc<AtomicUsize>) -> Vec<LargePacket> {
+        (0..16)
+            .map(|_| LargePacket {
+                payload: vec![0xAB; 9000],
+                copies: Arc::clone(copies),
+            })
+            .collect()
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_when_built_without_input_streams() {
+        RcForkLink::<i32>::new().num_egressors(10).build_link();
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_when_built_without_num_egressors() {
+        RcForkLink::<i32>::new()
+            .ingressor(immediate_stream(vec![]))
+            .build_link();
+    }
+
+    #[test]
+    fn three_way() {
+        let packets = vec![0, 1, 2, 420, 1337, 3, 4, 5, 6, 7, 8, 9];
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = RcForkLink::new()
+                .ingressor(immediate_stream(packets.clone()))
+                .num_egressors(3)
+                .build_link();
+
+            run_link(link).await
+        });
+        for result in results.iter() {
+            let unwrapped: Vec<i32> = result.iter().map(|packet| **packet).collect();
+            assert_eq!(unwrapped, packets);
+        }
+    }
+
+    #[test]
+    fn egressors_share_one_buffer() {
+        let packets = vec![vec![0xAB; 9000], vec![0xCD; 9000]];
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = RcForkLink::new()
+                .ingressor(immediate_stream(packets))
+                .num_egressors(2)
+                .build_link();
+
+            run_link(link).await
+        });
+        assert_eq!(results[0].len(), 2);
+        for (left, right) in results[0].iter().zip(results[1].iter()) {
+            assert!(Arc::ptr_eq(left, right));
+        }
+    }
+
+    #[test]
+    fn copies_less_than_fork_link() {
+        let num_egressors = 4;
+        let fork_copies = Arc::new(AtomicUsize::new(0));
+        let rc_fork_copies = Arc::new(AtomicUsize::new(0));
+
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let link = ForkLink::new()
+                .ingressor(immediate_stream(large_packets(&fork_copies)))
+                .num_egressors(num_egressors)
+                .build_link();
+
+            run_link(link).await
+        });
+        runtime.block_on(async {
+            let link = RcForkLink::new()
+                .ingressor(immediate_stream(large_packets(&rc_fork_copies)))
+                .num_egressors(num_egressors)
+                .build_link();
+
+            run_link(link).await
+        });
+
+        assert_eq!(fork_copies.load(Ordering::SeqCst), 16 * num_egressors);
+        assert_eq!(rc_fork_copies.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn make_mut_copies_only_when_shared() {
+        let copies = Arc::new(AtomicUsize::new(0));
+
+        let mut runtime = initialize_runtime();
+        let mut results = runtime.block_on(async {
+            let link = RcForkLink::new()
+                .ingressor(immediate_stream(large_packets(&copies)))
+                .num_egressors(2)
+                .build_link();
+
+            run_link(link).await
+        });
+
+        let mut shared = results[0].remove(0);
+        Arc::make_mut(&mut shared).payload[0] = 0;
+        assert_eq!(copies.load(Ordering::SeqCst), 1);
+
+        let mut unique = results[1].remove(1);
+        results[0].remove(0);
+        Arc::make_mut(&mut unique).payload[0] = 0;
+        assert_eq!(copies.load(Ordering::SeqCst), 1);
+    }
+}
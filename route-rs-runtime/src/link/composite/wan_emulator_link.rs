@@ -0,0 +1,244 @@
+use crate::link::composite::DropLink;
+use crate::link::primitive::{DelayLink, HtbClass, HtbLink};
+use crate::link::utils::clock::{Clock, SystemClock};
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream};
+use crate::link_builder;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// `WanEmulatorLink` emulates one direction of a WAN link, for testing how traffic behaves through
+/// the router under realistic conditions. Packets are first dropped with probability `loss`, then
+/// shaped to `packets_per_sec`, and then delayed by half of `rtt`, so that a `WanEmulatorLink` in
+/// each direction adds up to the round trip time. Shaping counts packets, not bytes, as `HtbLink`
+/// does, so to emulate a bandwidth in bits per second, divide it by the bits in a typical packet.
+///
+/// Each stage is its own link, `DropLink`, `HtbLink`, and `DelayLink`, which can be used directly
+/// for finer control.
+pub struct WanEmulatorLink<Packet> {
+    in_stream: Option<PacketStream<Packet>>,
+    packets_per_sec: Option<u64>,
+    rtt: Duration,
+    loss: f64,
+    seed: Option<u64>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<Packet> WanEmulatorLink<Packet> {
+    pub fn new() -> Self {
+        WanEmulatorLink {
+            in_stream: None,
+            packets_per_sec: None,
+            rtt: Duration::from_secs(0),
+            loss: 0.0,
+            seed: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    link_builder! {
+        /// Sets how many packets per second the link carries at most.
+        packets_per_sec: Option<u64> where packets_per_sec > 0,
+        /// Changes rtt, the round trip time, default value is 0.
+        rtt: Duration,
+        /// Changes loss, the chance of each packet being dropped, default value is 0.
+        loss: f64 where (0.0..=1.0).contains(&loss),
+        /// Seeds the random loss, default is seeded from entropy.
+        seed: Option<u64>,
+    }
+
+    /// Changes the clock used for shaping and delay, default is `SystemClock`.
+    pub fn clock<C: Clock + 'static>(self, clock: C) -> Self {
+        WanEmulatorLink {
+            clock: Arc::new(clock),
+            ..self
+        }
+    }
+}
+
+impl<Packet> Default for WanEmulatorLink<Packet> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Packet: Send + Clone + 'static> LinkBuilder<Packet, Packet> for WanEmulatorLink<Packet> {
+    fn ingressors(self, mut in_streams: Vec<PacketStream<Packet>>) -> Self {
+        assert_eq!(
+            in_streams.len(),
+            1,
+            "WanEmulatorLink may only take 1 input stream"
+        );
+
+        if self.in_stream.is_some() {
+            panic!("WanEmulatorLink may only take 1 input stream")
+        }
+
+        WanEmulatorLink {
+            in_stream: Some(in_streams.remove(0)),
+            ..self
+        }
+    }
+
+    fn ingressor(self, in_stream: PacketStream<Packet>) -> Self {
+        if self.in_stream.is_some() {
+            panic!("WanEmulatorLink may only take 1 input stream")
+        }
+
+        WanEmulatorLink {
+            in_stream: Some(in_stream),
+            ..self
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<Packet>, LinkBuildError> {
+        let in_stream = self.in_stream.ok_or(LinkBuildError::MissingIngressors)?;
+        let packets_per_sec = self
+            .packets_per_sec
+            .ok_or(LinkBuildError::Missing("packets_per_sec"))?;
+
+        let mut lossy = DropLink::new().ingressor(in_stream).drop_chance(self.loss);
+        if let Some(seed) = self.seed {
+            lossy = lossy.seed(seed);
+        }
+        let (mut runnables, mut egressors) = lossy.try_build_link()?;
+
+        let (mut shaper_runnables, mut egressors) = HtbLink::new()
+            .ingressor(egressors.remove(0))
+            .rate(packets_per_sec)
+            .classes(vec![HtbClass::new(packets_per_sec, packets_per_sec)])
+            .clock(Arc::clone(&self.clock))
+            .try_build_link()?;
+        runnables.append(&mut shaper_runnables);
+
+        let (mut delay_runnables, egressors) = DelayLink::new()
+            .ingressor(egressors.remove(0))
+            .delay(self.rtt / 2)
+            .clock(self.clock)
+            .try_build_link()?;
+        runnables.append(&mut delay_runnables);
+
+        Ok((runnables, egressors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::clock::ManualClock;
+    use crate::utils::test::harness::{initialize_runtime, poll_once};
+    use futures::channel::mpsc;
+    use futures::task::Poll;
+
+    /// Polls the egressor until it has nothing ready, returning how many packets it yielded.
+    async fn drain(egressor: &mut PacketStream<i32>) -> usize {
+        let mut count = 0;
+        while let Poll::Ready(Some(_)) = poll_once(egressor).await {
+            count += 1;
+        }
+        count
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_when_built_without_packets_per_sec() {
+        let (_, receiver) = mpsc::unbounded::<i32>();
+        WanEmulatorLink::new()
+            .ingressor(Box::new(receiver))
+            .build_link();
+    }
+
+    #[test]
+    fn throughput_saturates_at_packets_per_sec() {
+        let clock = ManualClock::new();
+
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded::<i32>();
+            let (_, mut egressors) = WanEmulatorLink::new()
+                .ingressor(Box::new(receiver))
+                .packets_per_sec(200)
+                .clock(clock.clone())
+                .build_link();
+            let mut egressor = egressors.remove(0);
+
+            for packet in 0..1000 {
+                sender.unbounded_send(packet).unwrap();
+            }
+
+            let mut released = 0;
+            for _ in 0..100 {
+                clock.advance(Duration::from_millis(10));
+                released += drain(&mut egressor).await;
+            }
+            assert!((198..=200).contains(&released), "released: {}", released);
+        });
+    }
+
+    #[test]
+    fn round_trip_takes_rtt() {
+        let clock = ManualClock::new();
+
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded::<i32>();
+            let (_, mut there) = WanEmulatorLink::new()
+                .ingressor(Box::new(receiver))
+                .packets_per_sec(1_000_000)
+                .rtt(Duration::from_millis(40))
+                .clock(clock.clone())
+                .build_link();
+            let (_, mut back) = WanEmulatorLink::new()
+                .ingressor(there.remove(0))
+                .packets_per_sec(1_000_000)
+                .rtt(Duration::from_millis(40))
+                .clock(clock.clone())
+                .build_link();
+            let mut egressor = back.remove(0);
+
+            // Let the shapers fill with tokens, so only the delay is measured.
+            clock.advance(Duration::from_millis(1));
+            sender.unbounded_send(1).unwrap();
+
+            let mut elapsed = 0;
+            while drain(&mut egressor).await == 0 {
+                clock.advance(Duration::from_millis(1));
+                elapsed += 1;
+                assert!(elapsed <= 40, "packet held past the round trip time");
+            }
+            assert_eq!(elapsed, 40);
+        });
+    }
+
+    #[test]
+    fn drops_at_loss_rate() {
+        let clock = ManualClock::new();
+
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded::<i32>();
+            let (_, mut egressors) = WanEmulatorLink::new()
+                .ingressor(Box::new(receiver))
+                .packets_per_sec(1_000_000)
+                .loss(0.25)
+                .seed(0)
+                .clock(clock.clone())
+                .build_link();
+            let mut egressor = egressors.remove(0);
+
+            clock.advance(Duration::from_secs(1));
+            for packet in 0..10_000 {
+                sender.unbounded_send(packet).unwrap();
+            }
+            let survived = drain(&mut egressor).await;
+            assert!(
+                (7_300..=7_700).contains(&survived),
+                "survived: {}",
+                survived
+            );
+        });
+    }
+}
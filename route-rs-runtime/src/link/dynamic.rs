@@ -0,0 +1,274 @@
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream, TokioRunnable};
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Why a link could not be assembled through a `LinkRegistry`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DynLinkError {
+    /// No constructor is registered under this name.
+    UnknownLink(String),
+    /// A stream carries packets of a different type than the link it was handed to expects.
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// The link was handed streams of the right type, but could not be built from them.
+    Build(LinkBuildError),
+}
+
+impl fmt::Display for DynLinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DynLinkError::UnknownLink(name) => write!(f, "No link registered as {:?}", name),
+            DynLinkError::TypeMismatch { expected, found } => {
+                write!(f, "Stream of {}, expected a stream of {}", found, expected)
+            }
+            DynLinkError::Build(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for DynLinkError {}
+
+impl From<LinkBuildError> for DynLinkError {
+    fn from(err: LinkBuildError) -> Self {
+        DynLinkError::Build(err)
+    }
+}
+
+/// A `PacketStream` whose packet type has been erased, so that streams of different types can be
+/// stored and passed around together. The type is checked when the stream is turned back into a
+/// `PacketStream`.
+pub struct DynStream {
+    type_id: TypeId,
+    type_name: &'static str,
+    stream: Box<dyn Any + Send>,
+}
+
+impl DynStream {
+    pub fn new<T: Send + 'static>(stream: PacketStream<T>) -> Self {
+        DynStream {
+            type_id: TypeId::of::<T>(),
+            type_name: type_name::<T>(),
+            stream: Box::new(stream),
+        }
+    }
+
+    /// The name of the type of packets in the stream, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Whether the stream carries packets of type `T`.
+    pub fn is<T: 'static>(&self) -> bool {
+        self.type_id == TypeId::of::<T>()
+    }
+
+    /// Turns the stream back into a `PacketStream<T>`, failing if it carries another type.
+    pub fn downcast<T: Send + 'static>(self) -> Result<PacketStream<T>, DynLinkError> {
+        if !self.is::<T>() {
+            return Err(DynLinkError::TypeMismatch {
+                expected: type_name::<T>(),
+                found: self.type_name,
+            });
+        }
+        Ok(*self.stream.downcast::<PacketStream<T>>().unwrap())
+    }
+}
+
+/// A `Link` whose egressors have had their packet type erased, so that links of different types
+/// can be stored together, as when a pipeline is assembled at runtime from a configuration.
+pub struct DynLink {
+    pub runnables: Vec<TokioRunnable>,
+    pub egressors: Vec<DynStream>,
+}
+
+impl DynLink {
+    pub fn new<T: Send + 'static>(link: Link<T>) -> Self {
+        let (runnables, egressors) = link;
+        DynLink {
+            runnables,
+            egressors: egressors.into_iter().map(DynStream::new).collect(),
+        }
+    }
+
+    /// Turns the link back into a `Link<T>`, failing if its egressors carry another type.
+    pub fn downcast<T: Send + 'static>(self) -> Result<Link<T>, DynLinkError> {
+        let egressors = self
+            .egressors
+            .into_iter()
+            .map(DynStream::downcast)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((self.runnables, egressors))
+    }
+}
+
+type Constructor = Box<dyn Fn(Vec<DynStream>) -> Result<DynLink, DynLinkError> + Send + Sync>;
+
+/// Constructors of links, by name, for a control plane to assemble a pipeline at runtime. Each
+/// constructor returns a `LinkBuilder` with every setting but its ingressors; the registry hands
+/// it the ingressors, checking that they carry the packet type it takes, and builds it into a
+/// `DynLink` whose egressors can be handed on to the next link.
+#[derive(Default)]
+pub struct LinkRegistry {
+    constructors: HashMap<String, Constructor>,
+}
+
+impl LinkRegistry {
+    pub fn new() -> Self {
+        LinkRegistry {
+            constructors: HashMap::new(),
+        }
+    }
+
+    /// Registers `constructor` as `name`, replacing any constructor already registered as it.
+    pub fn register<Input, Output, B, F>(&mut self, name: &str, constructor: F)
+    where
+        Input: Send + 'static,
+        Output: Send + 'static,
+        B: LinkBuilder<Input, Output>,
+        F: Fn() -> B + Send + Sync + 'static,
+    {
+        let constructor = move |ingressors: Vec<DynStream>| {
+            let ingressors = ingressors
+                .into_iter()
+                .map(DynStream::downcast::<Input>)
+                .collect::<Result<Vec<_>, _>>()?;
+            let builder = constructor().try_ingressors(ingressors)?;
+            Ok(DynLink::new(builder.try_build_link()?))
+        };
+        self.constructors
+            .insert(name.to_string(), Box::new(constructor));
+    }
+
+    /// Whether a constructor is registered as `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.constructors.contains_key(name)
+    }
+
+    /// Builds the link registered as `name`, fed by `ingressors`.
+    pub fn build(&self, name: &str, ingressors: Vec<DynStream>) -> Result<DynLink, DynLinkError> {
+        match self.constructors.get(name) {
+            Some(constructor) => constructor(ingressors),
+            None => Err(DynLinkError::UnknownLink(name.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::Even;
+    use crate::link::primitive::{ClassifyLink, JoinLink, ProcessLink};
+    use crate::link::ProcessLinkBuilder;
+    use crate::processor::Identity;
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use route_rs_packets::Ipv4Packet;
+
+    fn registry() -> LinkRegistry {
+        let mut registry = LinkRegistry::new();
+        registry.register("even_odd", || {
+            ClassifyLink::new()
+                .num_egressors(2)
+                .classifier(Even::new())
+                .dispatcher(Box::new(|is_even| if is_even { 0 } else { 1 }))
+        });
+        registry.register("join", JoinLink::<i32>::new);
+        registry
+    }
+
+    #[test]
+    fn assembles_pipeline_by_name() {
+        let registry = registry();
+        let mut runtime = initialize_runtime();
+
+        let results = runtime.block_on(async {
+            let source = DynStream::new(immediate_stream(0..10));
+            let mut classify = registry.build("even_odd", vec![source]).unwrap();
+            let join = registry.build("join", classify.egressors).unwrap();
+
+            let mut runnables = vec![];
+            runnables.append(&mut classify.runnables);
+            let (mut join_runnables, egressors) = join.downcast::<i32>().unwrap();
+            runnables.append(&mut join_runnables);
+            run_link((runnables, egressors)).await
+        });
+
+        let mut output = results[0].clone();
+        output.sort();
+        assert_eq!(output, (0..10).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn rejects_mismatched_stream() {
+        let mut registry = registry();
+        registry.register("ipv4", || {
+            ProcessLink::new().processor(Identity::<Ipv4Packet>::new())
+        });
+
+        let source = DynStream::new(immediate_stream(0..10));
+        let err = registry.build("ipv4", vec![source]).err().unwrap();
+
+        assert_eq!(
+            err,
+            DynLinkError::TypeMismatch {
+                expected: type_name::<Ipv4Packet>(),
+                found: type_name::<i32>(),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_link() {
+        let registry = registry();
+
+        assert!(registry.contains("join"));
+        assert!(!registry.contains("fork"));
+        assert_eq!(
+            registry.build("fork", vec![]).err().unwrap(),
+            DynLinkError::UnknownLink(String::from("fork"))
+        );
+    }
+
+    #[test]
+    fn reports_build_errors() {
+        let registry = registry();
+
+        assert_eq!(
+            registry.build("join", vec![]).err().unwrap(),
+            DynLinkError::Build(LinkBuildError::MissingIngressors)
+        );
+    }
+
+    #[test]
+    fn rejects_too_many_ingressors() {
+        let registry = registry();
+        let sources = vec![
+            DynStream::new(immediate_stream(0..10)),
+            DynStream::new(immediate_stream(10..20)),
+        ];
+
+        assert_eq!(
+            registry.build("even_odd", sources).err().unwrap(),
+            DynLinkError::Build(LinkBuildError::TooManyIngressors {
+                ingressors: 2,
+                capacity: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn downcast_checks_egressor_type() {
+        let link = DynLink::new(
+            ProcessLink::new()
+                .ingressor(immediate_stream(0..10))
+                .processor(Identity::<i32>::new())
+                .build_link(),
+        );
+
+        assert!(link.egressors[0].is::<i32>());
+        assert!(link.downcast::<Ipv4Packet>().is_err());
+    }
+}
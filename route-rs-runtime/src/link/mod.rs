@@ -11,8 +11,13 @@
 //! to reduce the complexity of your router, and make its design more inspectable. Users of the library are encouraged to create their own Composite
 //! Links by composing `Primitives` and other `CompositeLinks` together. This prevents the user from having to worry about the complexities of generically
 //! chaining asynchronous computation together around Channels; freeing you to focus on the business logic you would like your router to implement.
+//!
+//! Primitive links log what they do with packets, such as the egressor a packet was classified to, a packet being dropped, or a queue
+//! filling up, through the `log` facade at trace level, so they show up in whatever logger the router binary installs. Trace records
+//! cost a level check when disabled, and nothing at all in builds that set one of the `release_max_level_*` features of `log`.
 
 use crate::processor::Processor;
+use std::fmt;
 
 /// Composites are groups of links pre-assmebled to provide higher level functionality. They are highly customizable and users of the
 /// library are encourged to make their own to encourage code reuse.
@@ -23,6 +28,14 @@ pub mod composite;
 /// their own custom composite links.
 pub mod primitive;
 
+/// Type-erased links and streams, and a registry of link constructors, for assembling pipelines
+/// at runtime from a configuration rather than in code.
+pub mod dynamic;
+
+/// Chains links one after another, wiring the egressors of each into the next.
+mod pipeline;
+pub use self::pipeline::*;
+
 /// Commmon utilities used by links, for instance the `task_park` utility used in primitive links to facilite sleeping and waking.
 pub mod utils;
 
@@ -33,6 +46,55 @@ pub type TokioRunnable = Box<dyn futures::Future<Output = ()> + Send + Unpin>;
 /// LinkBuilders build this.
 pub type Link<Output> = (Vec<TokioRunnable>, Vec<PacketStream<Output>>);
 
+/// Why a `LinkBuilder` could not build its `Link`. Returned by `try_build_link`, so that a
+/// router configured at runtime can reject a bad configuration instead of aborting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkBuildError {
+    /// The link was never given an ingressor.
+    MissingIngressors,
+    /// A required setting was never provided. Holds the name of its setter.
+    Missing(&'static str),
+    /// `num_egressors` was set to 0; links must have at least one egressor.
+    NoEgressors,
+    /// A setting that needs one entry per ingressor has the wrong number of entries.
+    Mismatch {
+        setting: &'static str,
+        entries: usize,
+        ingressors: usize,
+    },
+    /// The link was handed more ingressors than it can take.
+    TooManyIngressors { ingressors: usize, capacity: usize },
+}
+
+impl fmt::Display for LinkBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkBuildError::MissingIngressors => write!(f, "Missing input streams"),
+            LinkBuildError::Missing(setting) => write!(f, "Missing {}", setting),
+            LinkBuildError::NoEgressors => write!(f, "num_egressors: 0, must be > 0"),
+            LinkBuildError::Mismatch {
+                setting,
+                entries,
+                ingressors,
+            } => write!(
+                f,
+                "{}: {} entries, must have one per input stream: {}",
+                setting, entries, ingressors
+            ),
+            LinkBuildError::TooManyIngressors {
+                ingressors,
+                capacity,
+            } => write!(
+                f,
+                "{} input streams, can take at most {} more",
+                ingressors, capacity
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LinkBuildError {}
+
 /// `LinkBuilder` applies a builder pattern to create `Links`! `Links` should be created this way
 /// so they can be composed together
 ///
@@ -49,10 +111,50 @@ pub trait LinkBuilder<Input, Output> {
     /// If the link can not support the addition of another ingressor, it will panic.
     fn ingressor(self, in_stream: PacketStream<Input>) -> Self;
 
+    /// How many more ingressors `ingressors` can hand the Link, or `None` if there is no limit.
+    /// Links that panic when given too many ingressors report their limit here, so that
+    /// `try_ingressors` can refuse them instead.
+    fn ingressor_capacity(&self) -> Option<usize> {
+        None
+    }
+
+    /// Like `ingressors`, but returns an error rather than panicking if the Link can not take that
+    /// many ingressors, for links wired up from a configuration at runtime. An empty set of
+    /// ingressors leaves the Link as it was, so a Link that has none fails to build with
+    /// `MissingIngressors`.
+    fn try_ingressors(self, in_streams: Vec<PacketStream<Input>>) -> Result<Self, LinkBuildError>
+    where
+        Self: Sized,
+    {
+        if in_streams.is_empty() {
+            return Ok(self);
+        }
+        match self.ingressor_capacity() {
+            Some(capacity) if in_streams.len() > capacity => {
+                Err(LinkBuildError::TooManyIngressors {
+                    ingressors: in_streams.len(),
+                    capacity,
+                })
+            }
+            _ => Ok(self.ingressors(in_streams)),
+        }
+    }
+
     /// Provides any tokio-driven Futures needed to drive the Link, as well as handles for downstream
     /// `Link`s to use. This method consumes the `Link` since we want to move ownership of a `Link`'s
-    /// runnables and egressors to the caller.
-    fn build_link(self) -> Link<Output>;
+    /// runnables and egressors to the caller. Returns an error if the Link is missing required
+    /// settings, or they do not fit together.
+    fn try_build_link(self) -> Result<Link<Output>, LinkBuildError>;
+
+    /// Like `try_build_link`, but panics if the Link can not be built, since in a statically
+    /// configured router that is a programming error.
+    fn build_link(self) -> Link<Output>
+    where
+        Self: Sized,
+    {
+        self.try_build_link()
+            .unwrap_or_else(|err| panic!("Cannot build link! {}", err))
+    }
 }
 
 /// `ProcessLink` and `QueueLink` impl `ProcessLinkBuilder`, since they are required to have their
@@ -0,0 +1,213 @@
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream, TokioRunnable};
+
+/// `Pipeline` chains links one after another, handing the egressors of each link to the next as
+/// its ingressors and collecting the runnables of every link along the way. Each link must take
+/// the packets the link before it puts out, which the compiler checks, so a pipeline is wired
+/// correctly as soon as it builds.
+///
+/// Links are given to the pipeline fully configured, save for their ingressors.
+///
+/// ```
+/// use route_rs_runtime::link::primitive::{ProcessLink, QueueLink};
+/// use route_rs_runtime::link::{Pipeline, ProcessLinkBuilder};
+/// use route_rs_runtime::processor::{Identity, TransformFrom};
+/// use route_rs_runtime::utils::test::harness::{initialize_runtime, run_link};
+/// use route_rs_runtime::utils::test::packet_generators::immediate_stream;
+/// use std::net::Ipv4Addr;
+///
+/// let mut runtime = initialize_runtime();
+/// let results = runtime.block_on(async {
+///     let link = Pipeline::new(immediate_stream(vec![0x0A00_0001u32]))
+///         .then(ProcessLink::new().processor(Identity::new()))
+///         .then(QueueLink::new().processor(TransformFrom::<u32, Ipv4Addr>::new()))
+///         .build();
+///
+///     run_link(link).await
+/// });
+/// assert_eq!(results[0], vec![Ipv4Addr::new(10, 0, 0, 1)]);
+/// ```
+pub struct Pipeline<Packet> {
+    runnables: Vec<TokioRunnable>,
+    egressors: Vec<PacketStream<Packet>>,
+}
+
+impl<Packet: Send + 'static> Pipeline<Packet> {
+    /// Starts a pipeline that feeds `in_stream` to its first link.
+    pub fn new(in_stream: PacketStream<Packet>) -> Self {
+        Pipeline {
+            runnables: vec![],
+            egressors: vec![in_stream],
+        }
+    }
+
+    /// Starts a pipeline that feeds the egressors of `link` to its next link, and runs the
+    /// runnables of `link` along with its own.
+    pub fn from_link(link: Link<Packet>) -> Self {
+        let (runnables, egressors) = link;
+        Pipeline {
+            runnables,
+            egressors,
+        }
+    }
+
+    /// Appends `link`, which is handed every egressor of the pipeline so far as its ingressors.
+    /// Panics if `link` can not be built, like `LinkBuilder::build_link`.
+    pub fn then<Output, L>(self, link: L) -> Pipeline<Output>
+    where
+        L: LinkBuilder<Packet, Output>,
+    {
+        self.try_then(link)
+            .unwrap_or_else(|err| panic!("Cannot build link! {}", err))
+    }
+
+    /// Like `then`, but returns an error if `link` can not be built, including if it can not take
+    /// every egressor of the pipeline so far.
+    pub fn try_then<Output, L>(self, link: L) -> Result<Pipeline<Output>, LinkBuildError>
+    where
+        L: LinkBuilder<Packet, Output>,
+    {
+        let (mut runnables, egressors) = link.try_ingressors(self.egressors)?.try_build_link()?;
+        let mut all_runnables = self.runnables;
+        all_runnables.append(&mut runnables);
+        Ok(Pipeline {
+            runnables: all_runnables,
+            egressors,
+        })
+    }
+
+    /// The runnables of every link in the pipeline, followed by the egressors of the last link.
+    pub fn build(self) -> Link<Packet> {
+        (self.runnables, self.egressors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::Even;
+    use crate::link::primitive::{ClassifyLink, ProcessLink, QueueLink};
+    use crate::link::ProcessLinkBuilder;
+    use crate::processor::{DecIpv4HopLimit, Identity, Processor, TransformFrom};
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use route_rs_packets::Ipv4Packet;
+
+    /// Sets the TTL of each packet to the number it was created from.
+    struct PacketWithTtl;
+
+    impl Processor for PacketWithTtl {
+        type Input = u8;
+        type Output = Ipv4Packet;
+
+        fn process(&mut self, ttl: Self::Input) -> Option<Self::Output> {
+            let mut packet = Ipv4Packet::empty();
+            packet.set_ttl(ttl);
+            Some(packet)
+        }
+    }
+
+    /// Reads back the TTL of each packet.
+    struct Ttl;
+
+    impl Processor for Ttl {
+        type Input = Ipv4Packet;
+        type Output = u8;
+
+        fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+            Some(packet.ttl())
+        }
+    }
+
+    #[test]
+    fn chains_three_process_links() {
+        let mut runtime = initialize_runtime();
+        let (results, num_runnables) = runtime.block_on(async {
+            let link = Pipeline::new(immediate_stream(vec![64, 2, 1, 128]))
+                .then(ProcessLink::new().processor(PacketWithTtl))
+                .then(QueueLink::new().processor(DecIpv4HopLimit::new()))
+                .then(ProcessLink::new().processor(Ttl))
+                .build();
+            let num_runnables = link.0.len();
+
+            (run_link(link).await, num_runnables)
+        });
+
+        // Only the QueueLink needs a runnable.
+        assert_eq!(num_runnables, 1);
+        assert_eq!(results[0], vec![63, 1, 0, 127]);
+    }
+
+    #[test]
+    fn runnables_accumulate_across_links() {
+        let mut runtime = initialize_runtime();
+        let (results, num_runnables) = runtime.block_on(async {
+            let first = QueueLink::new()
+                .ingressor(immediate_stream(0..10u32))
+                .processor(Identity::new())
+                .build_link();
+            let link = Pipeline::from_link(first)
+                .then(QueueLink::new().processor(Identity::new()))
+                .then(QueueLink::new().processor(TransformFrom::<u32, u64>::new()))
+                .build();
+            let num_runnables = link.0.len();
+
+            (run_link(link).await, num_runnables)
+        });
+
+        assert_eq!(num_runnables, 3);
+        assert_eq!(results[0], (0..10u64).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn egressors_of_the_last_link_are_exposed() {
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = Pipeline::new(immediate_stream(0..6))
+                .then(
+                    ClassifyLink::new()
+                        .num_egressors(2)
+                        .classifier(Even::new())
+                        .dispatcher(Box::new(|is_even| if is_even { 0 } else { 1 })),
+                )
+                .build();
+
+            run_link(link).await
+        });
+
+        assert_eq!(results, vec![vec![0, 2, 4], vec![1, 3, 5]]);
+    }
+
+    #[test]
+    fn try_then_reports_build_errors() {
+        let result = Pipeline::new(immediate_stream(0..6)).try_then(
+            ClassifyLink::<Even>::new()
+                .num_egressors(2)
+                .classifier(Even::new()),
+        );
+
+        assert_eq!(
+            result.err().map(|err| err.to_string()),
+            Some("Missing dispatcher".to_string())
+        );
+    }
+
+    #[test]
+    fn try_then_reports_too_many_ingressors() {
+        let result = Pipeline::new(immediate_stream(0..6))
+            .then(
+                ClassifyLink::new()
+                    .num_egressors(2)
+                    .classifier(Even::new())
+                    .dispatcher(Box::new(|is_even| if is_even { 0 } else { 1 })),
+            )
+            .try_then(ProcessLink::new().processor(Identity::new()));
+
+        assert_eq!(
+            result.err(),
+            Some(LinkBuildError::TooManyIngressors {
+                ingressors: 2,
+                capacity: 1,
+            })
+        );
+    }
+}
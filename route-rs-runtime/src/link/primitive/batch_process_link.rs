@@ -0,0 +1,296 @@
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream};
+use crate::link_builder;
+use crate::processor::BatchProcessor;
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+use std::collections::VecDeque;
+use std::mem;
+use std::pin::Pin;
+use tokio::time::{delay_for, Delay, Duration};
+
+/// `BatchProcessLink` processes packets through a user-defined `BatchProcessor`.
+/// Like `ProcessLink`, it is pull based and only does work when a packet is requested from its
+/// egressor. Rather than processing packets one at a time, it collects up to `batch_size` packets
+/// and hands them to the processor together. If a `batch_timeout` is set, a partial batch is
+/// flushed once the first packet in it has waited that long, so a quiet input can not hold
+/// packets back indefinitely.
+pub struct BatchProcessLink<P: BatchProcessor> {
+    in_stream: Option<PacketStream<P::Input>>,
+    processor: Option<P>,
+    batch_size: usize,
+    batch_timeout: Option<Duration>,
+}
+
+impl<P: BatchProcessor> BatchProcessLink<P> {
+    pub fn new() -> Self {
+        BatchProcessLink {
+            in_stream: None,
+            processor: None,
+            batch_size: 32,
+            batch_timeout: None,
+        }
+    }
+
+    link_builder! {
+        processor: Option<P>,
+        /// Changes batch_size, default value is 32.
+        batch_size: usize where batch_size > 0,
+        /// Flush partial batches after `batch_timeout`. By default, partial batches are only
+        /// flushed when the input stream ends.
+        batch_timeout: Option<Duration>,
+    }
+}
+
+impl<P: BatchProcessor> Default for BatchProcessLink<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: BatchProcessor + Send + 'static> LinkBuilder<P::Input, P::Output> for BatchProcessLink<P> {
+    fn ingressors(self, mut in_streams: Vec<PacketStream<P::Input>>) -> Self {
+        assert_eq!(
+            in_streams.len(),
+            1,
+            "BatchProcessLink may only take 1 input stream"
+        );
+
+        if self.in_stream.is_some() {
+            panic!("BatchProcessLink may only take 1 input stream")
+        }
+
+        BatchProcessLink {
+            in_stream: Some(in_streams.remove(0)),
+            ..self
+        }
+    }
+
+    fn ingressor(self, in_stream: PacketStream<P::Input>) -> Self {
+        if self.in_stream.is_some() {
+            panic!("BatchProcessLink may only take 1 input stream")
+        }
+
+        BatchProcessLink {
+            in_stream: Some(in_stream),
+            ..self
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<P::Output>, LinkBuildError> {
+        let in_stream = self.in_stream.ok_or(LinkBuildError::MissingIngressors)?;
+        let processor = self.processor.ok_or(LinkBuildError::Missing("processor"))?;
+
+        let runner = BatchProcessRunner {
+            in_stream,
+            processor,
+            batch_size: self.batch_size,
+            batch_timeout: self.batch_timeout,
+            batch: Vec::with_capacity(self.batch_size),
+            timer: None,
+            processed: VecDeque::new(),
+            finished: false,
+        };
+        Ok((vec![], vec![Box::new(runner)]))
+    }
+}
+
+/// The single egressor of BatchProcessLink
+struct BatchProcessRunner<P: BatchProcessor> {
+    in_stream: PacketStream<P::Input>,
+    processor: P,
+    batch_size: usize,
+    batch_timeout: Option<Duration>,
+    batch: Vec<P::Input>,
+    timer: Option<Delay>,
+    processed: VecDeque<P::Output>,
+    finished: bool,
+}
+
+impl<P: BatchProcessor> BatchProcessRunner<P> {
+    fn flush(&mut self) {
+        self.timer = None;
+        if !self.batch.is_empty() {
+            let batch = mem::replace(&mut self.batch, Vec::with_capacity(self.batch_size));
+            self.processed.extend(self.processor.process_batch(batch));
+        }
+    }
+}
+
+impl<P: BatchProcessor> Unpin for BatchProcessRunner<P> {}
+
+impl<P: BatchProcessor> Stream for BatchProcessRunner<P> {
+    type Item = P::Output;
+
+    /// Hands out already processed packets first. Otherwise, pulls packets from the input stream
+    /// into the current batch until either the batch is full, the input stream ends, or the input
+    /// stream returns `Poll::Pending`. In the last case, we fall back on the batch timer: if it
+    /// has fired, the partial batch is flushed, and if not, the timer registers to wake us up.
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let runner = &mut *self;
+        loop {
+            if let Some(packet) = runner.processed.pop_front() {
+                return Poll::Ready(Some(packet));
+            }
+            if runner.finished {
+                return Poll::Ready(None);
+            }
+
+            match Pin::new(&mut runner.in_stream).poll_next(cx) {
+                Poll::Ready(Some(packet)) => {
+                    if runner.batch.is_empty() {
+                        runner.timer = runner.batch_timeout.map(delay_for);
+                    }
+                    runner.batch.push(packet);
+                    if runner.batch.len() >= runner.batch_size {
+                        runner.flush();
+                    }
+                }
+                Poll::Ready(None) => {
+                    runner.flush();
+                    runner.finished = true;
+                }
+                Poll::Pending => match runner.timer.as_mut() {
+                    Some(timer) => {
+                        ready!(Pin::new(timer).poll(cx));
+                        runner.flush();
+                    }
+                    None => return Poll::Pending,
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use std::sync::{Arc, Mutex};
+
+    /// Doubles every packet, and records the size of each batch it was handed.
+    struct BatchDouble {
+        batch_sizes: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl BatchProcessor for BatchDouble {
+        type Input = i32;
+        type Output = i32;
+
+        fn process_batch(&mut self, packets: Vec<Self::Input>) -> Vec<Self::Output> {
+            self.batch_sizes.lock().unwrap().push(packets.len());
+            packets.into_iter().map(|packet| packet * 2).collect()
+        }
+    }
+
+    /// Yields `first`, then stalls for `stall` before yielding `second`.
+    fn stalled_stream(first: Vec<i32>, stall: Duration, second: Vec<i32>) -> PacketStream<i32> {
+        let gap = stream::once(delay_for(stall)).filter_map(|_| future::ready(None));
+        Box::new(stream::iter(first).chain(gap).chain(stream::iter(second)))
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_when_built_without_input_streams() {
+        BatchProcessLink::new()
+            .processor(BatchDouble {
+                batch_sizes: Arc::new(Mutex::new(vec![])),
+            })
+            .build_link();
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_when_built_without_processor() {
+        BatchProcessLink::<BatchDouble>::new()
+            .ingressor(immediate_stream(vec![]))
+            .build_link();
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_zero_batch_size() {
+        BatchProcessLink::<BatchDouble>::new().batch_size(0);
+    }
+
+    #[test]
+    fn preserves_order() {
+        let packets: Vec<i32> = (0..100).collect();
+        let batch_sizes = Arc::new(Mutex::new(vec![]));
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = BatchProcessLink::new()
+                .ingressor(immediate_stream(packets.clone()))
+                .processor(BatchDouble {
+                    batch_sizes: Arc::clone(&batch_sizes),
+                })
+                .batch_size(16)
+                .build_link();
+
+            run_link(link).await
+        });
+
+        let expected: Vec<i32> = packets.iter().map(|packet| packet * 2).collect();
+        assert_eq!(results[0], expected);
+        assert_eq!(
+            *batch_sizes.lock().unwrap(),
+            vec![16, 16, 16, 16, 16, 16, 4]
+        );
+    }
+
+    #[test]
+    fn partial_batch_flushes_on_timeout() {
+        let batch_sizes = Arc::new(Mutex::new(vec![]));
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = BatchProcessLink::new()
+                .ingressor(stalled_stream(
+                    vec![1, 2, 3],
+                    Duration::from_millis(500),
+                    vec![4, 5],
+                ))
+                .processor(BatchDouble {
+                    batch_sizes: Arc::clone(&batch_sizes),
+                })
+                .batch_size(10)
+                .batch_timeout(Duration::from_millis(10))
+                .build_link();
+
+            run_link(link).await
+        });
+
+        assert_eq!(results[0], vec![2, 4, 6, 8, 10]);
+        assert_eq!(*batch_sizes.lock().unwrap(), vec![3, 2]);
+    }
+
+    #[test]
+    fn partial_batch_waits_without_timeout() {
+        let batch_sizes = Arc::new(Mutex::new(vec![]));
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = BatchProcessLink::new()
+                .ingressor(stalled_stream(
+                    vec![1, 2, 3],
+                    Duration::from_millis(50),
+                    vec![4, 5],
+                ))
+                .processor(BatchDouble {
+                    batch_sizes: Arc::clone(&batch_sizes),
+                })
+                .batch_size(10)
+                .build_link();
+
+            run_link(link).await
+        });
+
+        assert_eq!(results[0], vec![2, 4, 6, 8, 10]);
+        assert_eq!(*batch_sizes.lock().unwrap(), vec![5]);
+    }
+}
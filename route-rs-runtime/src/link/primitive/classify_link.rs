@@ -1,22 +1,61 @@
 use crate::classifier::Classifier;
+use crate::link::utils::queue_depth::QueueDepths;
 use crate::link::utils::task_park::*;
-use crate::link::{primitive::QueueEgressor, Link, LinkBuilder, PacketStream};
+use crate::link::{primitive::QueueEgressor, Link, LinkBuildError, LinkBuilder, PacketStream};
+use crate::link_builder;
 use crossbeam::atomic::AtomicCell;
 use crossbeam::crossbeam_channel;
 use crossbeam::crossbeam_channel::{Receiver, Sender};
 use futures::prelude::*;
 use futures::task::{Context, Poll};
+use log::trace;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::stream::Stream;
 
+/// A handle to the number of packets a `ClassifyLink` dropped because its dispatcher sent them to
+/// an egressor it does not have. It can be cloned and read while the pipeline is running; anything
+/// above zero means the dispatcher has a bug.
+#[derive(Clone, Debug, Default)]
+pub struct MisdirectedCount {
+    count: Arc<AtomicU64>,
+}
+
+impl MisdirectedCount {
+    pub fn new() -> Self {
+        MisdirectedCount {
+            count: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Number of packets dropped so far for being sent to an egressor that does not exist.
+    pub fn get(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    fn increment(&self) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Where a `ClassifyLink` sends each class of packet.
+pub enum Dispatcher<'a, Class> {
+    /// To exactly one egressor.
+    Single(Box<dyn Fn(Class) -> usize + Send + Sync + 'a>),
+    /// To every egressor in a set, cloning the packet for each one. An empty set drops the packet.
+    Multi(Box<dyn Fn(Class) -> Vec<usize> + Send + Sync + 'a>),
+}
+
 #[derive(Default)]
 pub struct ClassifyLink<C: Classifier> {
     in_stream: Option<PacketStream<C::Packet>>,
     classifier: Option<C>,
-    dispatcher: Option<Box<dyn Fn(C::Class) -> usize + Send + Sync + 'static>>,
+    dispatcher: Option<Dispatcher<'static, C::Class>>,
     queue_capacity: usize,
     num_egressors: Option<usize>,
+    queue_depths: QueueDepths,
+    misdirected: MisdirectedCount,
 }
 
 impl<C: Classifier> ClassifyLink<C> {
@@ -27,57 +66,50 @@ impl<C: Classifier> ClassifyLink<C> {
             dispatcher: None,
             queue_capacity: 10,
             num_egressors: None,
+            queue_depths: QueueDepths::new(),
+            misdirected: MisdirectedCount::new(),
         }
     }
 
-    pub fn classifier(self, classifier: C) -> Self {
-        ClassifyLink {
-            in_stream: self.in_stream,
-            classifier: Some(classifier),
-            dispatcher: self.dispatcher,
-            queue_capacity: self.queue_capacity,
-            num_egressors: self.num_egressors,
-        }
+    link_builder! {
+        /// Changes queue_capacity, default value is 10.
+        queue_capacity: usize where queue_capacity > 0,
+        classifier: Option<C>,
+        /// Sets the number of egressors, which must be > 0.
+        num_egressors: Option<usize>,
     }
 
-    pub fn dispatcher(
-        self,
-        dispatcher: Box<dyn Fn(C::Class) -> usize + Send + Sync + 'static>,
-    ) -> Self {
-        ClassifyLink {
-            in_stream: self.in_stream,
-            classifier: self.classifier,
-            dispatcher: Some(dispatcher),
-            queue_capacity: self.queue_capacity,
-            num_egressors: self.num_egressors,
-        }
+    /// Returns a handle to the depths of the internal queues of this link, which remains valid
+    /// after the link is built.
+    pub fn queue_depths(&self) -> QueueDepths {
+        self.queue_depths.clone()
     }
 
-    pub fn queue_capacity(self, queue_capacity: usize) -> Self {
-        assert!(
-            queue_capacity > 0,
-            format!("Queue capacity: {}, must be > 0", queue_capacity)
-        );
+    /// Returns a handle to the number of packets this link dropped because the dispatcher sent
+    /// them to an egressor index of `num_egressors` or more, which remains valid after the link is
+    /// built. Such packets are dropped rather than bringing down the router.
+    pub fn misdirected(&self) -> MisdirectedCount {
+        self.misdirected.clone()
+    }
+
+    /// Sends each packet to the one egressor its class maps to.
+    pub fn dispatcher(self, dispatcher: Box<dyn Fn(C::Class) -> usize + Send + Sync>) -> Self {
         ClassifyLink {
-            in_stream: self.in_stream,
-            classifier: self.classifier,
-            dispatcher: self.dispatcher,
-            queue_capacity,
-            num_egressors: self.num_egressors,
+            dispatcher: Some(Dispatcher::Single(dispatcher)),
+            ..self
         }
     }
 
-    pub fn num_egressors(self, num_egressors: usize) -> Self {
-        assert!(
-            num_egressors > 0,
-            format!("num_egressors: {}, must be > 0", num_egressors)
-        );
+    /// Sends a copy of each packet to every egressor its class maps to, for example to mirror
+    /// some classes of traffic. An egressor listed more than once still gets one copy. Replaces
+    /// any `dispatcher`.
+    pub fn multi_dispatcher(
+        self,
+        dispatcher: Box<dyn Fn(C::Class) -> Vec<usize> + Send + Sync>,
+    ) -> Self {
         ClassifyLink {
-            in_stream: self.in_stream,
-            classifier: self.classifier,
-            dispatcher: self.dispatcher,
-            queue_capacity: self.queue_capacity,
-            num_egressors: Some(num_egressors),
+            dispatcher: Some(Dispatcher::Multi(dispatcher)),
+            ..self
         }
     }
 }
@@ -96,10 +128,7 @@ impl<C: Classifier + Send + 'static> LinkBuilder<C::Packet, C::Packet> for Class
 
         ClassifyLink {
             in_stream: Some(in_streams.remove(0)),
-            classifier: self.classifier,
-            dispatcher: self.dispatcher,
-            queue_capacity: self.queue_capacity,
-            num_egressors: self.num_egressors,
+            ..self
         }
     }
 
@@ -110,22 +139,25 @@ impl<C: Classifier + Send + 'static> LinkBuilder<C::Packet, C::Packet> for Class
 
         ClassifyLink {
             in_stream: Some(in_stream),
-            classifier: self.classifier,
-            dispatcher: self.dispatcher,
-            queue_capacity: self.queue_capacity,
-            num_egressors: self.num_egressors,
+            ..self
         }
     }
 
-    fn build_link(self) -> Link<C::Packet> {
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<C::Packet>, LinkBuildError> {
         if self.in_stream.is_none() {
-            panic!("Cannot build link! Missing input streams");
+            Err(LinkBuildError::MissingIngressors)
         } else if self.classifier.is_none() {
-            panic!("Cannot build link! Missing classifier");
+            Err(LinkBuildError::Missing("classifier"))
         } else if self.dispatcher.is_none() {
-            panic!("Cannot build link! Missing dispatcher");
+            Err(LinkBuildError::Missing("dispatcher"))
         } else if self.num_egressors.is_none() {
-            panic!("Cannot build link! Missing num_egressors");
+            Err(LinkBuildError::Missing("num_egressors"))
+        } else if self.num_egressors == Some(0) {
+            Err(LinkBuildError::NoEgressors)
         } else {
             let mut to_egressors: Vec<Sender<Option<C::Packet>>> = Vec::new();
             let mut egressors: Vec<PacketStream<C::Packet>> = Vec::new();
@@ -137,6 +169,7 @@ impl<C: Classifier + Send + 'static> LinkBuilder<C::Packet, C::Packet> for Class
             for _ in 0..self.num_egressors.unwrap() {
                 let (to_egressor, from_ingressor) =
                     crossbeam_channel::bounded::<Option<C::Packet>>(self.queue_capacity);
+                self.queue_depths.watch(&from_ingressor);
                 let task_park = Arc::new(AtomicCell::new(TaskParkState::Empty));
 
                 let provider = QueueEgressor::new(from_ingressor.clone(), Arc::clone(&task_park));
@@ -152,18 +185,20 @@ impl<C: Classifier + Send + 'static> LinkBuilder<C::Packet, C::Packet> for Class
                 to_egressors,
                 self.classifier.unwrap(),
                 task_parks,
+                self.misdirected,
             );
-            (vec![Box::new(ingressor)], egressors)
+            Ok((vec![Box::new(ingressor)], egressors))
         }
     }
 }
 
 pub struct ClassifyIngressor<'a, C: Classifier> {
     input_stream: PacketStream<C::Packet>,
-    dispatcher: Box<dyn Fn(C::Class) -> usize + Send + Sync + 'a>,
+    dispatcher: Dispatcher<'a, C::Class>,
     to_egressors: Vec<Sender<Option<C::Packet>>>,
     classifier: C,
     task_parks: Vec<Arc<AtomicCell<TaskParkState>>>,
+    misdirected: MisdirectedCount,
 }
 
 impl<'a, C: Classifier> Unpin for ClassifyIngressor<'a, C> {}
@@ -171,10 +206,11 @@ impl<'a, C: Classifier> Unpin for ClassifyIngressor<'a, C> {}
 impl<'a, C: Classifier> ClassifyIngressor<'a, C> {
     fn new(
         input_stream: PacketStream<C::Packet>,
-        dispatcher: Box<dyn Fn(C::Class) -> usize + Send + Sync + 'a>,
+        dispatcher: Dispatcher<'a, C::Class>,
         to_egressors: Vec<Sender<Option<C::Packet>>>,
         classifier: C,
         task_parks: Vec<Arc<AtomicCell<TaskParkState>>>,
+        misdirected: MisdirectedCount,
     ) -> Self {
         ClassifyIngressor {
             input_stream,
@@ -182,7 +218,28 @@ impl<'a, C: Classifier> ClassifyIngressor<'a, C> {
             to_egressors,
             classifier,
             task_parks,
+            misdirected,
+        }
+    }
+
+    fn send(&self, port: usize, packet: C::Packet) {
+        if port >= self.to_egressors.len() {
+            trace!(
+                "dropped packet classified to egressor {}, of {}",
+                port,
+                self.to_egressors.len()
+            );
+            self.misdirected.increment();
+            return;
         }
+        trace!("packet classified to egressor {}", port);
+        if let Err(err) = self.to_egressors[port].try_send(Some(packet)) {
+            panic!(
+                "Error in to_egressors[{}] sender, have nowhere to put packet: {:?}",
+                port, err
+            );
+        }
+        unpark_and_wake(&self.task_parks[port]);
     }
 }
 
@@ -198,6 +255,7 @@ impl<'a, C: Classifier> Future for ClassifyIngressor<'a, C> {
         loop {
             for (port, to_egressor) in ingressor.to_egressors.iter().enumerate() {
                 if to_egressor.is_full() {
+                    trace!("queue to egressor {} full, waiting for it to drain", port);
                     park_and_wake(&ingressor.task_parks[port], cx.waker().clone());
                     return Poll::Pending;
                 }
@@ -221,17 +279,28 @@ impl<'a, C: Classifier> Future for ClassifyIngressor<'a, C> {
                 }
                 Some(packet) => {
                     let class = ingressor.classifier.classify(&packet);
-                    let port = (ingressor.dispatcher)(class);
-                    if port >= ingressor.to_egressors.len() {
-                        panic!("Tried to access invalid port: {}", port);
-                    }
-                    if let Err(err) = ingressor.to_egressors[port].try_send(Some(packet)) {
-                        panic!(
-                            "Error in to_egressors[{}] sender, have nowhere to put packet: {:?}",
-                            port, err
-                        );
+                    match &ingressor.dispatcher {
+                        Dispatcher::Single(dispatcher) => {
+                            let port = dispatcher(class);
+                            ingressor.send(port, packet);
+                        }
+                        Dispatcher::Multi(dispatcher) => {
+                            let mut ports = dispatcher(class);
+                            // At most one copy per egressor: only room for one packet in each
+                            // queue is waited for, so a second copy could overflow it.
+                            ports.sort_unstable();
+                            ports.dedup();
+                            // Every port but the last gets a clone, and the last gets the original.
+                            if let Some(last) = ports.pop() {
+                                for port in ports {
+                                    ingressor.send(port, packet.clone());
+                                }
+                                ingressor.send(last, packet);
+                            } else {
+                                trace!("dropped packet classified to no egressors");
+                            }
+                        }
                     }
-                    unpark_and_wake(&ingressor.task_parks[port]);
                 }
             }
         }
@@ -242,10 +311,24 @@ impl<'a, C: Classifier> Future for ClassifyIngressor<'a, C> {
 mod tests {
     use super::*;
     use crate::classifier::{even_link, fizz_buzz_link, Even};
-    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::harness::{
+        initialize_runtime, initialize_runtime_deterministic, run_link, run_link_named,
+    };
+    use crate::utils::test::logger::capture_logs;
     use crate::utils::test::packet_generators::{immediate_stream, PacketIntervalGenerator};
     use core::time;
 
+    #[test]
+    fn try_build_link_rejects_zero_egressors() {
+        let result = ClassifyLink::new()
+            .ingressor(immediate_stream(vec![0, 1, 2]))
+            .num_egressors(0)
+            .classifier(Even::new())
+            .dispatcher(Box::new(|is_even| if is_even { 0 } else { 1 }))
+            .try_build_link();
+        assert_eq!(result.err(), Some(LinkBuildError::NoEgressors));
+    }
+
     #[test]
     #[should_panic]
     fn panics_when_built_without_input_streams() {
@@ -306,6 +389,29 @@ mod tests {
         assert_eq!(results[1], vec![1, 1337, 3, 5, 7, 9]);
     }
 
+    #[test]
+    fn even_odd_named() {
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let packet_generator = immediate_stream(vec![0, 1, 2, 420, 1337, 3, 4, 5, 6, 7, 8, 9]);
+
+            run_link_named(even_link(packet_generator), &["even", "odd"]).await
+        });
+        assert_eq!(results["even"], vec![0, 2, 420, 4, 6, 8]);
+        assert_eq!(results["odd"], vec![1, 1337, 3, 5, 7, 9]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn named_panics_on_label_count_mismatch() {
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let packet_generator = immediate_stream(vec![0, 1, 2]);
+
+            run_link_named(even_link(packet_generator), &["even"]).await
+        });
+    }
+
     #[test]
     fn even_odd_wait_between_packets() {
         let packets = vec![0, 1, 2, 420, 1337, 3, 4, 5, 6, 7, 8, 9];
@@ -385,4 +491,139 @@ mod tests {
         assert_eq!(results[0], vec![2, 4, 8, 14, 16, 22, 26, 28]);
         assert_eq!(results[1], vec![1, 7, 11, 13, 17, 19, 23, 29]);
     }
+
+    #[test]
+    fn multi_dispatcher_copies_to_each_egressor() {
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = ClassifyLink::new()
+                .ingressor(immediate_stream(0..10))
+                .num_egressors(3)
+                .classifier(Even::new())
+                .multi_dispatcher(Box::new(
+                    |is_even| {
+                        if is_even {
+                            vec![0, 2]
+                        } else {
+                            vec![1]
+                        }
+                    },
+                ))
+                .build_link();
+
+            run_link(link).await
+        });
+        assert_eq!(results[0], vec![0, 2, 4, 6, 8]);
+        assert_eq!(results[1], vec![1, 3, 5, 7, 9]);
+        assert_eq!(results[2], vec![0, 2, 4, 6, 8]);
+    }
+
+    #[test]
+    fn multi_dispatcher_drops_on_empty_set() {
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = ClassifyLink::new()
+                .ingressor(immediate_stream(0..10))
+                .num_egressors(2)
+                .classifier(Even::new())
+                .multi_dispatcher(Box::new(
+                    |is_even| if is_even { vec![0, 1] } else { vec![] },
+                ))
+                .build_link();
+
+            run_link(link).await
+        });
+        assert_eq!(results[0], vec![0, 2, 4, 6, 8]);
+        assert_eq!(results[1], vec![0, 2, 4, 6, 8]);
+    }
+
+    #[test]
+    fn drops_and_counts_misdirected_packets() {
+        let link = ClassifyLink::new()
+            .ingressor(immediate_stream(0..10))
+            .num_egressors(2)
+            .classifier(Even::new())
+            .dispatcher(Box::new(|is_even| if is_even { 0 } else { 2 }));
+        let misdirected = link.misdirected();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(run_link(link.build_link()));
+
+        assert_eq!(results[0], vec![0, 2, 4, 6, 8]);
+        assert_eq!(results[1], vec![]);
+        assert_eq!(misdirected.get(), 5);
+    }
+
+    #[test]
+    fn multi_dispatcher_drops_only_misdirected_copies() {
+        let link = ClassifyLink::new()
+            .ingressor(immediate_stream(0..10))
+            .num_egressors(2)
+            .classifier(Even::new())
+            .multi_dispatcher(Box::new(
+                |is_even| if is_even { vec![0, 7] } else { vec![1] },
+            ));
+        let misdirected = link.misdirected();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(run_link(link.build_link()));
+
+        assert_eq!(results[0], vec![0, 2, 4, 6, 8]);
+        assert_eq!(results[1], vec![1, 3, 5, 7, 9]);
+        assert_eq!(misdirected.get(), 5);
+    }
+
+    #[test]
+    fn multi_dispatcher_sends_one_copy_per_egressor() {
+        let link = ClassifyLink::new()
+            .ingressor(immediate_stream(0..10))
+            .num_egressors(2)
+            .queue_capacity(1)
+            .classifier(Even::new())
+            .multi_dispatcher(Box::new(
+                |is_even| if is_even { vec![0, 0] } else { vec![1, 0, 1] },
+            ));
+        let misdirected = link.misdirected();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(run_link(link.build_link()));
+
+        assert_eq!(results[0], (0..10).collect::<Vec<i32>>());
+        assert_eq!(results[1], vec![1, 3, 5, 7, 9]);
+        assert_eq!(misdirected.get(), 0);
+    }
+
+    #[test]
+    fn traces_classification_and_drops() {
+        let (results, records) = capture_logs(|| {
+            let mut runtime = initialize_runtime_deterministic();
+            runtime.block_on(async {
+                let link = ClassifyLink::new()
+                    .ingressor(immediate_stream(vec![0, 1, 2]))
+                    .num_egressors(2)
+                    .classifier(Even::new())
+                    .dispatcher(Box::new(|is_even| if is_even { 0 } else { 2 }))
+                    .build_link();
+                run_link(link).await
+            })
+        });
+
+        assert_eq!(results[0], vec![0, 2]);
+        let events: Vec<(log::Level, &str)> = records
+            .iter()
+            .filter(|record| record.target == module_path!().trim_end_matches("::tests"))
+            .map(|record| (record.level, record.message.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (log::Level::Trace, "packet classified to egressor 0"),
+                (
+                    log::Level::Trace,
+                    "dropped packet classified to egressor 2, of 2"
+                ),
+                (log::Level::Trace, "packet classified to egressor 0"),
+            ]
+        );
+    }
 }
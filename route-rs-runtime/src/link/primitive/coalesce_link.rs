@@ -0,0 +1,297 @@
+use crate::link::utils::clock::{Clock, SystemClock};
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream};
+use crate::link_builder;
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tokio::time::{delay_for, Delay};
+
+/// `CoalesceLink` holds packets back and releases them as a group, trading latency for
+/// throughput. A group is released as soon as `max_batch` packets have been collected, or once the
+/// oldest held packet has waited `max_latency`, whichever comes first. Packets are never
+/// reordered or modified.
+pub struct CoalesceLink<Packet> {
+    in_stream: Option<PacketStream<Packet>>,
+    max_batch: usize,
+    max_latency: Duration,
+    clock: Box<dyn Clock>,
+}
+
+impl<Packet> CoalesceLink<Packet> {
+    pub fn new() -> Self {
+        CoalesceLink {
+            in_stream: None,
+            max_batch: 32,
+            max_latency: Duration::from_millis(1),
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    link_builder! {
+        /// Changes max_batch, default value is 32.
+        max_batch: usize where max_batch > 0,
+        /// Changes max_latency, default value is 1ms.
+        max_latency: Duration,
+    }
+
+    /// Changes the clock used to measure how long packets have waited, default is `SystemClock`.
+    pub fn clock<C: Clock + 'static>(self, clock: C) -> Self {
+        CoalesceLink {
+            clock: Box::new(clock),
+            ..self
+        }
+    }
+}
+
+impl<Packet> Default for CoalesceLink<Packet> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Packet: Send + 'static> LinkBuilder<Packet, Packet> for CoalesceLink<Packet> {
+    fn ingressors(self, mut in_streams: Vec<PacketStream<Packet>>) -> Self {
+        assert_eq!(
+            in_streams.len(),
+            1,
+            "CoalesceLink may only take 1 input stream"
+        );
+
+        if self.in_stream.is_some() {
+            panic!("CoalesceLink may only take 1 input stream")
+        }
+
+        CoalesceLink {
+            in_stream: Some(in_streams.remove(0)),
+            ..self
+        }
+    }
+
+    fn ingressor(self, in_stream: PacketStream<Packet>) -> Self {
+        if self.in_stream.is_some() {
+            panic!("CoalesceLink may only take 1 input stream")
+        }
+
+        CoalesceLink {
+            in_stream: Some(in_stream),
+            ..self
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<Packet>, LinkBuildError> {
+        let in_stream = self.in_stream.ok_or(LinkBuildError::MissingIngressors)?;
+
+        let egressor = CoalesceEgressor {
+            in_stream,
+            max_batch: self.max_batch,
+            max_latency: self.max_latency,
+            clock: self.clock,
+            held: Vec::with_capacity(self.max_batch),
+            oldest: None,
+            timer: None,
+            released: VecDeque::new(),
+            finished: false,
+        };
+        Ok((vec![], vec![Box::new(egressor)]))
+    }
+}
+
+/// The single egressor of CoalesceLink
+struct CoalesceEgressor<Packet> {
+    in_stream: PacketStream<Packet>,
+    max_batch: usize,
+    max_latency: Duration,
+    clock: Box<dyn Clock>,
+    held: Vec<Packet>,
+    oldest: Option<Instant>,
+    timer: Option<Delay>,
+    released: VecDeque<Packet>,
+    finished: bool,
+}
+
+impl<Packet> CoalesceEgressor<Packet> {
+    fn release(&mut self) {
+        self.oldest = None;
+        self.timer = None;
+        self.released.extend(self.held.drain(..));
+    }
+
+    /// How much longer the oldest held packet may wait, or `None` if nothing is held.
+    fn remaining_latency(&self) -> Option<Duration> {
+        self.oldest.map(|oldest| {
+            let waited = self.clock.now().saturating_duration_since(oldest);
+            self.max_latency.checked_sub(waited).unwrap_or_default()
+        })
+    }
+}
+
+impl<Packet> Unpin for CoalesceEgressor<Packet> {}
+
+impl<Packet> Stream for CoalesceEgressor<Packet> {
+    type Item = Packet;
+
+    /// Hands out released packets first. Otherwise, pulls packets from the input stream until the
+    /// group is full, the input stream ends, or the input stream returns `Poll::Pending`. When the
+    /// input is pending and packets are held, we check the clock: if the oldest packet has waited
+    /// `max_latency` the group is released, otherwise a timer is armed for the remaining time so
+    /// that we are woken up to check again.
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let egressor = &mut *self;
+        loop {
+            if let Some(packet) = egressor.released.pop_front() {
+                return Poll::Ready(Some(packet));
+            }
+            if egressor.finished {
+                return Poll::Ready(None);
+            }
+
+            match Pin::new(&mut egressor.in_stream).poll_next(cx) {
+                Poll::Ready(Some(packet)) => {
+                    if egressor.oldest.is_none() {
+                        egressor.oldest = Some(egressor.clock.now());
+                    }
+                    egressor.held.push(packet);
+                    if egressor.held.len() >= egressor.max_batch {
+                        egressor.release();
+                    }
+                }
+                Poll::Ready(None) => {
+                    egressor.release();
+                    egressor.finished = true;
+                }
+                Poll::Pending => match egressor.remaining_latency() {
+                    None => return Poll::Pending,
+                    Some(remaining) if remaining == Duration::from_secs(0) => egressor.release(),
+                    Some(remaining) => {
+                        let timer = egressor.timer.get_or_insert_with(|| delay_for(remaining));
+                        ready!(Pin::new(timer).poll(cx));
+                        // The timer fired, but the clock decides whether the group is due. Loop
+                        // around to check it again, arming a fresh timer if it is not.
+                        egressor.timer = None;
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::clock::ManualClock;
+    use crate::utils::test::harness::{initialize_runtime, poll_once, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use futures::channel::mpsc;
+
+    #[test]
+    #[should_panic]
+    fn panics_when_built_without_input_streams() {
+        CoalesceLink::<i32>::new().build_link();
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_zero_max_batch() {
+        CoalesceLink::<i32>::new().max_batch(0);
+    }
+
+    #[test]
+    fn passes_all_packets_in_order() {
+        let packets: Vec<i32> = (0..100).collect();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = CoalesceLink::new()
+                .ingressor(immediate_stream(packets.clone()))
+                .max_batch(7)
+                .build_link();
+
+            run_link(link).await
+        });
+        assert_eq!(results[0], packets);
+    }
+
+    #[test]
+    fn single_packet_flushes_after_timer() {
+        let clock = ManualClock::new();
+
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded::<i32>();
+            let (_, mut egressors) = CoalesceLink::new()
+                .ingressor(Box::new(receiver))
+                .max_batch(10)
+                .max_latency(Duration::from_millis(5))
+                .clock(clock.clone())
+                .build_link();
+            let mut egressor = egressors.remove(0);
+
+            sender.unbounded_send(1).unwrap();
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+
+            clock.advance(Duration::from_millis(4));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+
+            clock.advance(Duration::from_millis(1));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(Some(1)));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+        });
+    }
+
+    #[test]
+    fn full_batch_flushes_immediately() {
+        let clock = ManualClock::new();
+
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded::<i32>();
+            let (_, mut egressors) = CoalesceLink::new()
+                .ingressor(Box::new(receiver))
+                .max_batch(3)
+                .max_latency(Duration::from_secs(3600))
+                .clock(clock.clone())
+                .build_link();
+            let mut egressor = egressors.remove(0);
+
+            sender.unbounded_send(1).unwrap();
+            sender.unbounded_send(2).unwrap();
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+
+            sender.unbounded_send(3).unwrap();
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(Some(1)));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(Some(2)));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(Some(3)));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+        });
+    }
+
+    #[test]
+    fn held_packets_flush_when_input_ends() {
+        let clock = ManualClock::new();
+
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded::<i32>();
+            let (_, mut egressors) = CoalesceLink::new()
+                .ingressor(Box::new(receiver))
+                .max_batch(10)
+                .max_latency(Duration::from_secs(3600))
+                .clock(clock)
+                .build_link();
+            let mut egressor = egressors.remove(0);
+
+            sender.unbounded_send(1).unwrap();
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+
+            drop(sender);
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(Some(1)));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(None));
+        });
+    }
+}
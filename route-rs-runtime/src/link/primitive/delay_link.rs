@@ -0,0 +1,353 @@
+use crate::link::utils::clock::{Clock, SystemClock};
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream};
+use crate::link_builder;
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tokio::time::{delay_for, Delay};
+
+/// `DelayLink` emulates the latency of a WAN link. Each packet is held for `delay`, plus a random
+/// jitter of up to `jitter`, before it is released. Packets are never reordered: a packet whose
+/// jitter would release it before the packet ahead of it is held until that packet is released.
+///
+/// At most `capacity` packets are held at once. Once that many are, the input stream is not polled
+/// again until a packet is released, so that backpressure builds upstream, as it does behind a
+/// full `QueueLink`.
+///
+/// The clock and the seed of the random jitter can be set, so tests are deterministic.
+pub struct DelayLink<Packet> {
+    in_stream: Option<PacketStream<Packet>>,
+    delay: Duration,
+    jitter: Duration,
+    capacity: usize,
+    clock: Box<dyn Clock>,
+    rng: StdRng,
+}
+
+impl<Packet> DelayLink<Packet> {
+    pub fn new() -> Self {
+        DelayLink {
+            in_stream: None,
+            delay: Duration::from_secs(0),
+            jitter: Duration::from_secs(0),
+            capacity: 1000,
+            clock: Box::new(SystemClock),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    link_builder! {
+        /// Changes capacity, the most packets held at once, default value is 1000.
+        capacity: usize where capacity > 0,
+    }
+
+    /// Changes delay, the least time each packet is held for, default value is 0.
+    pub fn delay(self, delay: Duration) -> Self {
+        DelayLink { delay, ..self }
+    }
+
+    /// Changes jitter, the most extra time each packet may be held for, default value is 0.
+    pub fn jitter(self, jitter: Duration) -> Self {
+        DelayLink { jitter, ..self }
+    }
+
+    /// Seeds the random jitter, default is seeded from entropy.
+    pub fn seed(self, seed: u64) -> Self {
+        DelayLink {
+            rng: StdRng::seed_from_u64(seed),
+            ..self
+        }
+    }
+
+    /// Changes the clock used to time packets, default is `SystemClock`.
+    pub fn clock<C: Clock + 'static>(self, clock: C) -> Self {
+        DelayLink {
+            clock: Box::new(clock),
+            ..self
+        }
+    }
+}
+
+impl<Packet> Default for DelayLink<Packet> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Packet: Send + 'static> LinkBuilder<Packet, Packet> for DelayLink<Packet> {
+    fn ingressors(self, mut in_streams: Vec<PacketStream<Packet>>) -> Self {
+        assert_eq!(
+            in_streams.len(),
+            1,
+            "DelayLink may only take 1 input stream"
+        );
+
+        if self.in_stream.is_some() {
+            panic!("DelayLink may only take 1 input stream")
+        }
+
+        DelayLink {
+            in_stream: Some(in_streams.remove(0)),
+            ..self
+        }
+    }
+
+    fn ingressor(self, in_stream: PacketStream<Packet>) -> Self {
+        if self.in_stream.is_some() {
+            panic!("DelayLink may only take 1 input stream")
+        }
+
+        DelayLink {
+            in_stream: Some(in_stream),
+            ..self
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<Packet>, LinkBuildError> {
+        let in_stream = self.in_stream.ok_or(LinkBuildError::MissingIngressors)?;
+
+        let egressor = DelayEgressor {
+            in_stream,
+            delay: self.delay,
+            jitter: self.jitter,
+            capacity: self.capacity,
+            clock: self.clock,
+            rng: self.rng,
+            held: VecDeque::new(),
+            timer: None,
+            finished: false,
+        };
+        Ok((vec![], vec![Box::new(egressor)]))
+    }
+}
+
+/// The single egressor of DelayLink
+struct DelayEgressor<Packet> {
+    in_stream: PacketStream<Packet>,
+    delay: Duration,
+    jitter: Duration,
+    capacity: usize,
+    clock: Box<dyn Clock>,
+    rng: StdRng,
+    held: VecDeque<(Instant, Packet)>,
+    timer: Option<Delay>,
+    finished: bool,
+}
+
+impl<Packet> DelayEgressor<Packet> {
+    fn hold(&mut self, packet: Packet) {
+        let jitter = if self.jitter > Duration::from_secs(0) {
+            self.rng.gen_range(Duration::from_secs(0), self.jitter)
+        } else {
+            Duration::from_secs(0)
+        };
+        let mut release_at = self.clock.now() + self.delay + jitter;
+        if let Some((last_release_at, _)) = self.held.back() {
+            release_at = release_at.max(*last_release_at);
+        }
+        self.held.push_back((release_at, packet));
+    }
+}
+
+impl<Packet> Unpin for DelayEgressor<Packet> {}
+
+impl<Packet> Stream for DelayEgressor<Packet> {
+    type Item = Packet;
+
+    /// Pulls every packet the input stream has ready, up to capacity, stamping each with when it
+    /// is to be released. Then hands out the oldest held packet if it is due. Otherwise, a timer is armed
+    /// for when it will be, so that we are woken up to check the clock again.
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let egressor = &mut *self;
+        while !egressor.finished && egressor.held.len() < egressor.capacity {
+            match Pin::new(&mut egressor.in_stream).poll_next(cx) {
+                Poll::Ready(Some(packet)) => egressor.hold(packet),
+                Poll::Ready(None) => egressor.finished = true,
+                Poll::Pending => break,
+            }
+        }
+
+        loop {
+            let release_at = match egressor.held.front() {
+                Some((release_at, _)) => *release_at,
+                None if egressor.finished => return Poll::Ready(None),
+                None => return Poll::Pending,
+            };
+
+            let remaining = release_at.saturating_duration_since(egressor.clock.now());
+            if remaining == Duration::from_secs(0) {
+                egressor.timer = None;
+                let (_, packet) = egressor.held.pop_front().unwrap();
+                return Poll::Ready(Some(packet));
+            }
+
+            let timer = egressor.timer.get_or_insert_with(|| delay_for(remaining));
+            ready!(Pin::new(timer).poll(cx));
+            // The timer fired, but the clock decides whether the packet is due. Loop around to
+            // check it again, arming a fresh timer if it is not.
+            egressor.timer = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::clock::ManualClock;
+    use crate::utils::test::harness::{initialize_runtime, poll_once, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use futures::channel::mpsc;
+
+    /// Sends `count` packets at once, then steps the clock forward a millisecond at a time,
+    /// returning each packet with how many milliseconds after sending it was released.
+    fn release_times(delay: u64, jitter: u64, seed: u64, count: i32) -> Vec<(i32, u64)> {
+        let clock = ManualClock::new();
+
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded::<i32>();
+            let (_, mut egressors) = DelayLink::new()
+                .ingressor(Box::new(receiver))
+                .delay(Duration::from_millis(delay))
+                .jitter(Duration::from_millis(jitter))
+                .seed(seed)
+                .clock(clock.clone())
+                .build_link();
+            let mut egressor = egressors.remove(0);
+
+            for packet in 0..count {
+                sender.unbounded_send(packet).unwrap();
+            }
+            drop(sender);
+
+            let mut released = vec![];
+            for elapsed in 0..=delay + jitter {
+                while let Poll::Ready(Some(packet)) = poll_once(&mut egressor).await {
+                    released.push((packet, elapsed));
+                }
+                clock.advance(Duration::from_millis(1));
+            }
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(None));
+            released
+        })
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_when_built_without_input_streams() {
+        DelayLink::<i32>::new().build_link();
+    }
+
+    #[test]
+    fn holds_for_delay() {
+        let clock = ManualClock::new();
+
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded::<i32>();
+            let (_, mut egressors) = DelayLink::new()
+                .ingressor(Box::new(receiver))
+                .delay(Duration::from_millis(40))
+                .clock(clock.clone())
+                .build_link();
+            let mut egressor = egressors.remove(0);
+
+            sender.unbounded_send(1).unwrap();
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+
+            clock.advance(Duration::from_millis(39));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+
+            clock.advance(Duration::from_millis(1));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(Some(1)));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+        });
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_zero_capacity() {
+        DelayLink::<i32>::new().capacity(0);
+    }
+
+    #[test]
+    fn stops_pulling_at_capacity() {
+        let clock = ManualClock::new();
+
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded::<i32>();
+            let (_, mut egressors) = DelayLink::new()
+                .ingressor(Box::new(receiver))
+                .delay(Duration::from_millis(40))
+                .capacity(2)
+                .clock(clock.clone())
+                .build_link();
+            let mut egressor = egressors.remove(0);
+
+            for packet in 0..3 {
+                sender.unbounded_send(packet).unwrap();
+            }
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+
+            // The third packet waited upstream, so its delay starts once there is room for it.
+            clock.advance(Duration::from_millis(40));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(Some(0)));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(Some(1)));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+
+            clock.advance(Duration::from_millis(39));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+            clock.advance(Duration::from_millis(1));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(Some(2)));
+        });
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let released = release_times(40, 20, 1, 100);
+
+        assert_eq!(
+            released
+                .iter()
+                .map(|(packet, _)| *packet)
+                .collect::<Vec<i32>>(),
+            (0..100).collect::<Vec<i32>>()
+        );
+        for (_, elapsed) in released.iter() {
+            assert!(*elapsed >= 40 && *elapsed <= 60, "elapsed: {}", elapsed);
+        }
+        // Some packets are held past the base delay.
+        assert!(released.iter().any(|(_, elapsed)| *elapsed > 40));
+    }
+
+    #[test]
+    fn seed_makes_jitter_reproducible() {
+        assert_eq!(release_times(10, 30, 7, 50), release_times(10, 30, 7, 50));
+    }
+
+    #[test]
+    fn passes_all_packets_in_order() {
+        let packets: Vec<i32> = (0..100).collect();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = DelayLink::new()
+                .ingressor(immediate_stream(packets.clone()))
+                .delay(Duration::from_millis(2))
+                .jitter(Duration::from_millis(2))
+                .build_link();
+
+            run_link(link).await
+        });
+        assert_eq!(results[0], packets);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream, TokioRunnable};
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A handle to the tally of a `DiscardLink`. It can be cloned and read while the pipeline is
+/// running.
+#[derive(Clone, Default)]
+pub struct DiscardCount {
+    count: Arc<AtomicU64>,
+}
+
+impl DiscardCount {
+    pub fn new() -> Self {
+        DiscardCount {
+            count: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Number of packets discarded so far.
+    pub fn get(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+/// `DiscardLink` terminates branches of a pipeline whose packets should go nowhere. It consumes
+/// any number of streams to completion, discarding and counting every packet, and has no
+/// egressors of its own. Unlike `DropSink`, the packets need no `DropReason`, so any egressor can
+/// be terminated as is. The tally is read through the `DiscardCount` handle returned by `count`.
+#[derive(Default)]
+pub struct DiscardLink<Packet> {
+    in_streams: Option<Vec<PacketStream<Packet>>>,
+    count: DiscardCount,
+}
+
+impl<Packet> DiscardLink<Packet> {
+    pub fn new() -> Self {
+        DiscardLink {
+            in_streams: None,
+            count: DiscardCount::new(),
+        }
+    }
+
+    /// Returns a handle to the tally of this link, which remains valid after the link is built.
+    pub fn count(&self) -> DiscardCount {
+        self.count.clone()
+    }
+}
+
+impl<Packet: Send + 'static> LinkBuilder<Packet, ()> for DiscardLink<Packet> {
+    fn ingressors(self, in_streams: Vec<PacketStream<Packet>>) -> Self {
+        assert!(
+            !in_streams.is_empty(),
+            "number of in_streams: {}, must be greater than 0",
+            in_streams.len()
+        );
+
+        if self.in_streams.is_some() {
+            panic!("DiscardLink already has input streams")
+        }
+
+        DiscardLink {
+            in_streams: Some(in_streams),
+            ..self
+        }
+    }
+
+    fn ingressor(self, in_stream: PacketStream<Packet>) -> Self {
+        let mut in_streams = self.in_streams.unwrap_or_default();
+        in_streams.push(in_stream);
+
+        DiscardLink {
+            in_streams: Some(in_streams),
+            ..self
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        if self.in_streams.is_some() {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn try_build_link(self) -> Result<Link<()>, LinkBuildError> {
+        let in_streams = self.in_streams.ok_or(LinkBuildError::MissingIngressors)?;
+        let count = self.count;
+
+        let runnables: Vec<TokioRunnable> = in_streams
+            .into_iter()
+            .map(|in_stream| -> TokioRunnable {
+                Box::new(DiscardIngressor {
+                    in_stream,
+                    count: count.clone(),
+                })
+            })
+            .collect();
+        Ok((runnables, vec![]))
+    }
+}
+
+/// Drains one stream into the shared tally.
+struct DiscardIngressor<Packet> {
+    in_stream: PacketStream<Packet>,
+    count: DiscardCount,
+}
+
+impl<Packet> Unpin for DiscardIngressor<Packet> {}
+
+impl<Packet> Future for DiscardIngressor<Packet> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        loop {
+            match ready!(Pin::new(&mut self.in_stream).poll_next(cx)) {
+                Some(_packet) => {
+                    self.count.count.fetch_add(1, Ordering::Relaxed);
+                }
+                None => return Poll::Ready(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::Even;
+    use crate::link::primitive::ClassifyLink;
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+
+    #[test]
+    #[should_panic]
+    fn panics_when_built_without_input_streams() {
+        DiscardLink::<i32>::new().build_link();
+    }
+
+    #[test]
+    fn counts_discarded_branch() {
+        let discard = DiscardLink::new();
+        let count = discard.count();
+
+        let mut runtime = initialize_runtime();
+        let (results, discard_egressors) = runtime.block_on(async {
+            let (mut runnables, mut egressors) = ClassifyLink::new()
+                .ingressor(immediate_stream(0..100))
+                .num_egressors(2)
+                .classifier(Even::new())
+                .dispatcher(Box::new(|is_even| if is_even { 0 } else { 1 }))
+                .build_link();
+
+            let (mut discard_runnables, discard_egressors) =
+                discard.ingressor(egressors.pop().unwrap()).build_link();
+            runnables.append(&mut discard_runnables);
+            let discard_egressors = discard_egressors.len();
+
+            (run_link((runnables, egressors)).await, discard_egressors)
+        });
+
+        assert_eq!(discard_egressors, 0);
+        assert_eq!(results[0], (0..100).step_by(2).collect::<Vec<i32>>());
+        assert_eq!(count.get(), 50);
+    }
+
+    #[test]
+    fn counts_across_input_streams() {
+        let discard = DiscardLink::new();
+        let count = discard.count();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = discard
+                .ingressors(vec![immediate_stream(0..7), immediate_stream(0..5)])
+                .build_link();
+
+            run_link(link).await
+        });
+
+        assert!(results.is_empty());
+        assert_eq!(count.get(), 12);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream, TokioRunnable};
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// Why a packet was dropped. Links that drop packets can expose a drop egressor of
+/// `(DropReason, Packet)` so that the drops can be accounted for by a `DropSink`.
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
+pub enum DropReason {
+    /// The TTL or hop limit of the packet reached zero.
+    TtlExpired,
+    /// There was no route to the destination of the packet.
+    NoRoute,
+    /// The packet could not be parsed.
+    Malformed,
+    /// The packet was rejected by policy, such as a filter or a null route.
+    Filtered,
+    /// The packet was too large for the egress interface.
+    TooBig,
+    /// A queue had no room for the packet.
+    QueueFull,
+}
+
+/// A handle to the per-`DropReason` tallies of a `DropSink`. It can be cloned and read while the
+/// pipeline is running.
+#[derive(Clone, Default)]
+pub struct DropCounts {
+    counts: Arc<Mutex<HashMap<DropReason, u64>>>,
+}
+
+impl DropCounts {
+    pub fn new() -> Self {
+        DropCounts {
+            counts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Number of packets dropped for `reason` so far.
+    pub fn get(&self, reason: DropReason) -> u64 {
+        *self.counts.lock().unwrap().get(&reason).unwrap_or(&0)
+    }
+
+    /// Number of packets dropped for any reason so far.
+    pub fn total(&self) -> u64 {
+        self.counts.lock().unwrap().values().sum()
+    }
+
+    /// A copy of the tallies at this point in time.
+    pub fn snapshot(&self) -> HashMap<DropReason, u64> {
+        self.counts.lock().unwrap().clone()
+    }
+
+    fn record(&self, reason: DropReason) {
+        *self.counts.lock().unwrap().entry(reason).or_insert(0) += 1;
+    }
+}
+
+/// `DropSink` is the single place dropped packets go to die. It consumes any number of drop
+/// egressors and tallies each packet against its `DropReason`. It has no egressors of its own; the
+/// tallies are read through the `DropCounts` handle returned by `counts`.
+#[derive(Default)]
+pub struct DropSink<Packet> {
+    in_streams: Option<Vec<PacketStream<(DropReason, Packet)>>>,
+    counts: DropCounts,
+}
+
+impl<Packet> DropSink<Packet> {
+    pub fn new() -> Self {
+        DropSink {
+            in_streams: None,
+            counts: DropCounts::new(),
+        }
+    }
+
+    /// Returns a handle to the tallies of this sink, which remains valid after the sink is built.
+    pub fn counts(&self) -> DropCounts {
+        self.counts.clone()
+    }
+}
+
+impl<Packet: Send + 'static> LinkBuilder<(DropReason, Packet), ()> for DropSink<Packet> {
+    fn ingressors(self, in_streams: Vec<PacketStream<(DropReason, Packet)>>) -> Self {
+        assert!(
+            !in_streams.is_empty(),
+            "number of in_streams: {}, must be greater than 0",
+            in_streams.len()
+        );
+
+        if self.in_streams.is_some() {
+            panic!("DropSink already has input streams")
+        }
+
+        DropSink {
+            in_streams: Some(in_streams),
+            ..self
+        }
+    }
+
+    fn ingressor(self, in_stream: PacketStream<(DropReason, Packet)>) -> Self {
+        let mut in_streams = self.in_streams.unwrap_or_default();
+        in_streams.push(in_stream);
+
+        DropSink {
+            in_streams: Some(in_streams),
+            ..self
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        if self.in_streams.is_some() {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn try_build_link(self) -> Result<Link<()>, LinkBuildError> {
+        let in_streams = self.in_streams.ok_or(LinkBuildError::MissingIngressors)?;
+        let counts = self.counts;
+
+        let runnables: Vec<TokioRunnable> = in_streams
+            .into_iter()
+            .map(|in_stream| -> TokioRunnable {
+                Box::new(DropSinkIngressor {
+                    in_stream,
+                    counts: counts.clone(),
+                })
+            })
+            .collect();
+        Ok((runnables, vec![]))
+    }
+}
+
+/// Drains one drop egressor into the shared tallies.
+struct DropSinkIngressor<Packet> {
+    in_stream: PacketStream<(DropReason, Packet)>,
+    counts: DropCounts,
+}
+
+impl<Packet> Unpin for DropSinkIngressor<Packet> {}
+
+impl<Packet> Future for DropSinkIngressor<Packet> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        loop {
+            match ready!(Pin::new(&mut self.in_stream).poll_next(cx)) {
+                Some((reason, _packet)) => self.counts.record(reason),
+                None => return Poll::Ready(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::primitive::ProcessLink;
+    use crate::link::ProcessLinkBuilder;
+    use crate::processor::Processor;
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use route_rs_packets::Ipv4Packet;
+    use std::net::Ipv4Addr;
+
+    /// Drops packets with an expired TTL, or that are not addressed to 10.0.0.0/8.
+    struct TtlAndRouteCheck;
+
+    impl Processor for TtlAndRouteCheck {
+        type Input = Ipv4Packet;
+        type Output = (DropReason, Ipv4Packet);
+
+        fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+            if packet.ttl() == 0 {
+                Some((DropReason::TtlExpired, packet))
+            } else if packet.dest_addr().octets()[0] != 10 {
+                Some((DropReason::NoRoute, packet))
+            } else {
+                None
+            }
+        }
+    }
+
+    fn packet(ttl: u8, dest_addr: Ipv4Addr) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_ttl(ttl);
+        packet.set_dest_addr(dest_addr);
+        packet
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_when_built_without_input_streams() {
+        DropSink::<i32>::new().build_link();
+    }
+
+    #[test]
+    fn tallies_per_reason() {
+        let sink = DropSink::new();
+        let counts = sink.counts();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let (_, mut wan_drops) = ProcessLink::new()
+                .ingressor(immediate_stream(vec![
+                    packet(0, Ipv4Addr::new(10, 0, 0, 1)),
+                    packet(64, Ipv4Addr::new(10, 0, 0, 1)),
+                    packet(64, Ipv4Addr::new(192, 168, 0, 1)),
+                ]))
+                .processor(TtlAndRouteCheck)
+                .build_link();
+            let (_, mut lan_drops) = ProcessLink::new()
+                .ingressor(immediate_stream(vec![
+                    packet(0, Ipv4Addr::new(172, 16, 0, 1)),
+                    packet(0, Ipv4Addr::new(10, 1, 1, 1)),
+                    packet(64, Ipv4Addr::new(8, 8, 8, 8)),
+                    packet(64, Ipv4Addr::new(1, 1, 1, 1)),
+                ]))
+                .processor(TtlAndRouteCheck)
+                .build_link();
+
+            let link = sink
+                .ingressor(wan_drops.remove(0))
+                .ingressor(lan_drops.remove(0))
+                .build_link();
+
+            run_link(link).await
+        });
+
+        assert!(results.is_empty());
+        assert_eq!(counts.get(DropReason::TtlExpired), 3);
+        assert_eq!(counts.get(DropReason::NoRoute), 3);
+        assert_eq!(counts.get(DropReason::Malformed), 0);
+        assert_eq!(counts.total(), 6);
+        assert_eq!(counts.snapshot().len(), 2);
+    }
+}
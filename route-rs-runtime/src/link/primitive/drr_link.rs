@@ -0,0 +1,284 @@
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream};
+use crate::link_builder;
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+use std::pin::Pin;
+
+/// Measures the size of a packet, in bytes.
+pub type PacketSize<Packet> = Box<dyn Fn(&Packet) -> usize + Send + Sync + 'static>;
+
+/// `DrrLink` combines several input queues into one output using deficit round robin. Each visit,
+/// a queue is credited its quantum of bytes and may send packets for as long as its credit covers
+/// them, so queues share the output fairly by bytes rather than by packets, regardless of packet
+/// size. Credit left over is carried to the next visit while a queue is backlogged, and reset once
+/// it runs empty.
+///
+/// Each ingressor is one queue, credited with the quantum at the same index. The size of a packet,
+/// in bytes, is measured with the `packet_size` function.
+pub struct DrrLink<Packet> {
+    in_streams: Option<Vec<PacketStream<Packet>>>,
+    quanta: Option<Vec<usize>>,
+    packet_size: Option<PacketSize<Packet>>,
+}
+
+impl<Packet> DrrLink<Packet> {
+    pub fn new() -> Self {
+        DrrLink {
+            in_streams: None,
+            quanta: None,
+            packet_size: None,
+        }
+    }
+
+    link_builder! {
+        /// Sets the quantum, in bytes, credited to each queue per round, one per ingressor.
+        quanta: Option<Vec<usize>> where quanta.iter().all(|quantum| *quantum > 0),
+        packet_size: Option<PacketSize<Packet>>,
+    }
+}
+
+impl<Packet> Default for DrrLink<Packet> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Packet: Send + 'static> LinkBuilder<Packet, Packet> for DrrLink<Packet> {
+    fn ingressors(self, in_streams: Vec<PacketStream<Packet>>) -> Self {
+        assert!(
+            !in_streams.is_empty(),
+            "number of in_streams: {}, must be greater than 0",
+            in_streams.len()
+        );
+
+        if self.in_streams.is_some() {
+            panic!("DrrLink already has input streams")
+        }
+
+        DrrLink {
+            in_streams: Some(in_streams),
+            ..self
+        }
+    }
+
+    /// Appends the ingressor to the ingressors of the link.
+    fn ingressor(self, in_stream: PacketStream<Packet>) -> Self {
+        let mut in_streams = self.in_streams.unwrap_or_default();
+        in_streams.push(in_stream);
+
+        DrrLink {
+            in_streams: Some(in_streams),
+            ..self
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        if self.in_streams.is_some() {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn try_build_link(self) -> Result<Link<Packet>, LinkBuildError> {
+        let in_streams = self.in_streams.ok_or(LinkBuildError::MissingIngressors)?;
+        let quanta = self.quanta.ok_or(LinkBuildError::Missing("quanta"))?;
+        let packet_size = self
+            .packet_size
+            .ok_or(LinkBuildError::Missing("packet_size"))?;
+        if in_streams.len() != quanta.len() {
+            return Err(LinkBuildError::Mismatch {
+                setting: "quanta",
+                entries: quanta.len(),
+                ingressors: in_streams.len(),
+            });
+        }
+
+        let queues = in_streams
+            .into_iter()
+            .zip(quanta)
+            .map(|(in_stream, quantum)| DrrQueue {
+                in_stream,
+                head: None,
+                finished: false,
+                quantum,
+                deficit: 0,
+            })
+            .collect();
+
+        let egressor = DrrEgressor {
+            queues,
+            packet_size,
+            current: 0,
+            credited: false,
+        };
+        Ok((vec![], vec![Box::new(egressor)]))
+    }
+}
+
+struct DrrQueue<Packet> {
+    in_stream: PacketStream<Packet>,
+    head: Option<Packet>,
+    finished: bool,
+    quantum: usize,
+    deficit: usize,
+}
+
+/// The single egressor of DrrLink
+struct DrrEgressor<Packet> {
+    queues: Vec<DrrQueue<Packet>>,
+    packet_size: PacketSize<Packet>,
+    current: usize,
+    /// Whether the current queue has been credited its quantum for this visit.
+    credited: bool,
+}
+
+impl<Packet> DrrEgressor<Packet> {
+    fn next_queue(&mut self) {
+        self.current = (self.current + 1) % self.queues.len();
+        self.credited = false;
+    }
+}
+
+impl<Packet> Unpin for DrrEgressor<Packet> {}
+
+impl<Packet> Stream for DrrEgressor<Packet> {
+    type Item = Packet;
+
+    /// Fills the head slot of every queue from its input stream, then visits queues in turn
+    /// until one has enough credit to send its head. Since every backlogged queue is credited on
+    /// each visit, some queue will eventually have enough.
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let egressor = &mut *self;
+        for queue in egressor.queues.iter_mut() {
+            if queue.head.is_none() && !queue.finished {
+                match Pin::new(&mut queue.in_stream).poll_next(cx) {
+                    Poll::Ready(Some(packet)) => queue.head = Some(packet),
+                    Poll::Ready(None) => queue.finished = true,
+                    Poll::Pending => {}
+                }
+            }
+        }
+
+        if egressor.queues.iter().all(|queue| queue.head.is_none()) {
+            if egressor.queues.iter().all(|queue| queue.finished) {
+                return Poll::Ready(None);
+            }
+            return Poll::Pending;
+        }
+
+        loop {
+            let credited = egressor.credited;
+            let queue = &mut egressor.queues[egressor.current];
+            let size = match queue.head.as_ref() {
+                Some(head) => (egressor.packet_size)(head),
+                None => {
+                    queue.deficit = 0;
+                    egressor.next_queue();
+                    continue;
+                }
+            };
+
+            if !credited {
+                queue.deficit += queue.quantum;
+                egressor.credited = true;
+            }
+            if size <= queue.deficit {
+                queue.deficit -= size;
+                return Poll::Ready(queue.head.take());
+            }
+            egressor.next_queue();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+
+    fn drr(quanta: Vec<usize>, queues: Vec<Vec<Vec<u8>>>) -> Vec<Vec<u8>> {
+        let mut runtime = initialize_runtime();
+        let mut results = runtime.block_on(async {
+            let link = DrrLink::new()
+                .ingressors(queues.into_iter().map(immediate_stream).collect())
+                .quanta(quanta)
+                .packet_size(Box::new(|packet: &Vec<u8>| packet.len()))
+                .build_link();
+
+            run_link(link).await
+        });
+        results.remove(0)
+    }
+
+    /// Bytes sent by each queue in the first `count` packets, where each packet's first byte
+    /// names its queue.
+    fn bytes_per_queue(output: &[Vec<u8>], count: usize, num_queues: usize) -> Vec<usize> {
+        let mut bytes = vec![0; num_queues];
+        for packet in output.iter().take(count) {
+            bytes[packet[0] as usize] += packet.len();
+        }
+        bytes
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_when_built_without_quanta() {
+        DrrLink::<Vec<u8>>::new()
+            .ingressor(immediate_stream(vec![]))
+            .packet_size(Box::new(|packet: &Vec<u8>| packet.len()))
+            .build_link();
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_when_quanta_do_not_match_inputs() {
+        DrrLink::<Vec<u8>>::new()
+            .ingressor(immediate_stream(vec![]))
+            .quanta(vec![100, 100])
+            .packet_size(Box::new(|packet: &Vec<u8>| packet.len()))
+            .build_link();
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_zero_quantum() {
+        DrrLink::<Vec<u8>>::new().quanta(vec![100, 0]);
+    }
+
+    #[test]
+    fn byte_fair_across_packet_sizes() {
+        let small = vec![vec![0; 100]; 100];
+        let large = vec![vec![1; 1000]; 10];
+
+        let output = drr(vec![1000, 1000], vec![small, large]);
+
+        assert_eq!(output.len(), 110);
+        // Each round, the small queue sends ten packets for every large one.
+        assert_eq!(bytes_per_queue(&output, 33, 2), vec![3000, 3000]);
+    }
+
+    #[test]
+    fn shares_follow_quanta() {
+        let first = vec![vec![0; 300]; 100];
+        let second = vec![vec![1; 300]; 100];
+
+        let output = drr(vec![1200, 600], vec![first, second]);
+
+        assert_eq!(output.len(), 200);
+        assert_eq!(bytes_per_queue(&output, 60, 2), vec![12000, 6000]);
+    }
+
+    #[test]
+    fn deficit_carries_over() {
+        // The large queue needs two rounds of credit before it can send at all.
+        let small = vec![vec![0; 500]; 8];
+        let large = vec![vec![1; 1500]; 2];
+
+        let output = drr(vec![1000, 1000], vec![small, large]);
+
+        let order: Vec<u8> = output.iter().map(|packet| packet[0]).collect();
+        assert_eq!(order, vec![0, 0, 0, 0, 1, 0, 0, 1, 0, 0]);
+    }
+}
@@ -1,5 +1,7 @@
+use crate::link::utils::queue_depth::QueueDepths;
 use crate::link::utils::task_park::*;
-use crate::link::{primitive::QueueEgressor, Link, LinkBuilder, PacketStream};
+use crate::link::{primitive::QueueEgressor, Link, LinkBuildError, LinkBuilder, PacketStream};
+use crate::link_builder;
 use crossbeam::atomic::AtomicCell;
 use crossbeam::crossbeam_channel;
 use crossbeam::crossbeam_channel::{Receiver, Sender};
@@ -13,6 +15,7 @@ pub struct ForkLink<Packet: Clone + Send> {
     in_stream: Option<PacketStream<Packet>>,
     queue_capacity: usize,
     num_egressors: Option<usize>,
+    queue_depths: QueueDepths,
 }
 
 impl<Packet: Clone + Send> ForkLink<Packet> {
@@ -21,34 +24,21 @@ impl<Packet: Clone + Send> ForkLink<Packet> {
             in_stream: None,
             queue_capacity: 10,
             num_egressors: None,
+            queue_depths: QueueDepths::new(),
         }
     }
 
-    /// Changes queue_capacity, default value is 10.
-    pub fn queue_capacity(self, queue_capacity: usize) -> Self {
-        assert!(
-            queue_capacity > 0,
-            format!("queue_capacity: {}, must be > 0", queue_capacity)
-        );
-
-        ForkLink {
-            in_stream: self.in_stream,
-            queue_capacity,
-            num_egressors: self.num_egressors,
-        }
+    link_builder! {
+        /// Changes queue_capacity, default value is 10.
+        queue_capacity: usize where queue_capacity > 0,
+        /// Sets the number of egressors, which must be > 0.
+        num_egressors: Option<usize>,
     }
 
-    pub fn num_egressors(self, num_egressors: usize) -> Self {
-        assert!(
-            num_egressors > 0,
-            format!("num_egressors: {}, must be > 0", num_egressors)
-        );
-
-        ForkLink {
-            in_stream: self.in_stream,
-            queue_capacity: self.queue_capacity,
-            num_egressors: Some(num_egressors),
-        }
+    /// Returns a handle to the depths of the internal queues of this link, which remains valid
+    /// after the link is built.
+    pub fn queue_depths(&self) -> QueueDepths {
+        self.queue_depths.clone()
     }
 }
 
@@ -66,8 +56,7 @@ impl<Packet: Send + Clone + 'static> LinkBuilder<Packet, Packet> for ForkLink<Pa
 
         ForkLink {
             in_stream: Some(in_streams.remove(0)),
-            queue_capacity: self.queue_capacity,
-            num_egressors: self.num_egressors,
+            ..self
         }
     }
 
@@ -78,17 +67,29 @@ impl<Packet: Send + Clone + 'static> LinkBuilder<Packet, Packet> for ForkLink<Pa
 
         ForkLink {
             in_stream: Some(in_stream),
-            queue_capacity: self.queue_capacity,
-            num_egressors: self.num_egressors,
+            ..self
         }
     }
 
-    fn build_link(self) -> Link<Packet> {
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<Packet>, LinkBuildError> {
         if self.in_stream.is_none() {
-            panic!("Cannot build link! Missing input stream");
+            Err(LinkBuildError::MissingIngressors)
         } else if self.num_egressors.is_none() {
-            panic!("Cannot build link! Missing number of num_egressors");
+            Err(LinkBuildError::Missing("num_egressors"))
+        } else if self.num_egressors == Some(0) {
+            Err(LinkBuildError::NoEgressors)
         } else {
+            let in_stream = self.in_stream.unwrap();
+            if self.num_egressors == Some(1) {
+                // With a single egressor there is nothing to fan out to, so hand the input stream
+                // straight downstream instead of cloning every packet through a queue.
+                return Ok((vec![], vec![in_stream]));
+            }
+
             let mut to_egressors: Vec<Sender<Option<Packet>>> = Vec::new();
             let mut egressors: Vec<PacketStream<Packet>> = Vec::new();
 
@@ -99,6 +100,7 @@ impl<Packet: Send + Clone + 'static> LinkBuilder<Packet, Packet> for ForkLink<Pa
             for _ in 0..self.num_egressors.unwrap() {
                 let (to_egressor, from_ingressor) =
                     crossbeam_channel::bounded::<Option<Packet>>(self.queue_capacity);
+                self.queue_depths.watch(&from_ingressor);
                 let task_park = Arc::new(AtomicCell::new(TaskParkState::Empty));
 
                 let egressor = QueueEgressor::new(from_ingressor.clone(), Arc::clone(&task_park));
@@ -109,9 +111,9 @@ impl<Packet: Send + Clone + 'static> LinkBuilder<Packet, Packet> for ForkLink<Pa
                 task_parks.push(task_park);
             }
 
-            let ingressor = ForkIngressor::new(self.in_stream.unwrap(), to_egressors, task_parks);
+            let ingressor = ForkIngressor::new(in_stream, to_egressors, task_parks);
 
-            (vec![Box::new(ingressor)], egressors)
+            Ok((vec![Box::new(ingressor)], egressors))
         }
     }
 }
@@ -185,6 +187,24 @@ mod tests {
     use super::*;
     use crate::utils::test::harness::{initialize_runtime, run_link};
     use crate::utils::test::packet_generators::immediate_stream;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A packet that records every time it is cloned.
+    #[derive(Debug)]
+    struct CountedPacket {
+        id: usize,
+        clones: Arc<AtomicUsize>,
+    }
+
+    impl Clone for CountedPacket {
+        fn clone(&self) -> Self {
+            self.clones.fetch_add(1, Ordering::SeqCst);
+            CountedPacket {
+                id: self.id,
+                clones: Arc::clone(&self.clones),
+            }
+        }
+    }
 
     #[test]
     #[should_panic]
@@ -192,6 +212,26 @@ mod tests {
         ForkLink::<i32>::new().num_egressors(10).build_link();
     }
 
+    #[test]
+    fn try_build_link_rejects_zero_egressors() {
+        let result = ForkLink::<i32>::new()
+            .ingressor(immediate_stream(vec![]))
+            .num_egressors(0)
+            .try_build_link();
+        assert_eq!(result.err(), Some(LinkBuildError::NoEgressors));
+    }
+
+    #[test]
+    fn try_build_link_reports_missing_settings() {
+        let result = ForkLink::<i32>::new().num_egressors(2).try_build_link();
+        assert_eq!(result.err(), Some(LinkBuildError::MissingIngressors));
+
+        let result = ForkLink::<i32>::new()
+            .ingressor(immediate_stream(vec![]))
+            .try_build_link();
+        assert_eq!(result.err(), Some(LinkBuildError::Missing("num_egressors")));
+    }
+
     #[test]
     #[should_panic]
     fn panics_when_built_without_num_egressors() {
@@ -243,6 +283,53 @@ mod tests {
         assert_eq!(results[0], packets);
     }
 
+    #[test]
+    fn one_way_passes_stream_through() {
+        let clones = Arc::new(AtomicUsize::new(0));
+        let packets: Vec<CountedPacket> = (0..12)
+            .map(|id| CountedPacket {
+                id,
+                clones: Arc::clone(&clones),
+            })
+            .collect();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let (runnables, egressors) = ForkLink::new()
+                .ingressor(immediate_stream(packets))
+                .num_egressors(1)
+                .build_link();
+            // No ingressor task and no queue stand between the input and the egressor.
+            assert!(runnables.is_empty());
+            assert_eq!(egressors.len(), 1);
+
+            run_link((runnables, egressors)).await
+        });
+
+        let ids: Vec<usize> = results[0].iter().map(|packet| packet.id).collect();
+        assert_eq!(ids, (0..12).collect::<Vec<usize>>());
+        assert_eq!(clones.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn capacity_one_survives_bursts() {
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = ForkLink::new()
+                .ingressor(immediate_stream(0..1000))
+                .num_egressors(3)
+                .queue_capacity(1)
+                .build_link();
+
+            run_link(link).await
+        });
+
+        assert_eq!(results.len(), 3);
+        for output in results {
+            assert_eq!(output, (0..1000).collect::<Vec<i32>>());
+        }
+    }
+
     #[test]
     fn two_way() {
         let packets = vec![0, 1, 2, 420, 1337, 3, 4, 5, 6, 7, 8, 9];
@@ -0,0 +1,448 @@
+use crate::link::utils::clock::{Clock, SystemClock};
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream};
+use crate::link_builder;
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tokio::time::{delay_for, Delay};
+
+/// The shaping parameters of one class of an `HtbLink`, in packets per second. A class may always
+/// send at `rate`, and may borrow capacity its siblings leave unused up to `ceil`.
+#[derive(Clone, Copy, Debug)]
+pub struct HtbClass {
+    pub rate: u64,
+    pub ceil: u64,
+}
+
+impl HtbClass {
+    pub fn new(rate: u64, ceil: u64) -> Self {
+        assert!(rate > 0, "rate: {}, must be > 0", rate);
+        assert!(ceil >= rate, "ceil: {}, must be >= rate: {}", ceil, rate);
+        HtbClass { rate, ceil }
+    }
+}
+
+/// `HtbLink` shapes several classes of traffic under a shared parent rate, in the style of the
+/// hierarchical token bucket. Each ingressor is one class, configured by the `HtbClass` at the
+/// same index, and ingressors earlier in the list have priority over later ones.
+///
+/// Classes that are under their own `rate` are served first. Once no class can send at its own
+/// rate, classes may borrow whatever the parent has left, up to their `ceil`. This lets a busy
+/// class use the bandwidth of an idle sibling, while every class keeps its guaranteed rate when
+/// all are busy. The parent `rate` is never exceeded. Rates are counted in packets, and buckets
+/// hold at most one second of tokens. Only one level of classes under the parent is supported.
+pub struct HtbLink<Packet> {
+    in_streams: Option<Vec<PacketStream<Packet>>>,
+    rate: Option<u64>,
+    classes: Vec<HtbClass>,
+    clock: Box<dyn Clock>,
+}
+
+impl<Packet> HtbLink<Packet> {
+    pub fn new() -> Self {
+        HtbLink {
+            in_streams: None,
+            rate: None,
+            classes: vec![],
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    link_builder! {
+        /// Sets the rate of the parent, in packets per second, which caps the combined output.
+        rate: Option<u64> where rate > 0,
+        /// Sets the classes, one per ingressor, in priority order.
+        classes: Vec<HtbClass>,
+    }
+
+    /// Changes the clock used to refill the token buckets, default is `SystemClock`.
+    pub fn clock<C: Clock + 'static>(self, clock: C) -> Self {
+        HtbLink {
+            clock: Box::new(clock),
+            ..self
+        }
+    }
+}
+
+impl<Packet> Default for HtbLink<Packet> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Packet: Send + 'static> LinkBuilder<Packet, Packet> for HtbLink<Packet> {
+    fn ingressors(self, in_streams: Vec<PacketStream<Packet>>) -> Self {
+        assert!(
+            !in_streams.is_empty(),
+            "number of in_streams: {}, must be greater than 0",
+            in_streams.len()
+        );
+
+        if self.in_streams.is_some() {
+            panic!("HtbLink already has input streams")
+        }
+
+        HtbLink {
+            in_streams: Some(in_streams),
+            ..self
+        }
+    }
+
+    /// Appends the ingressor to the ingressors of the link, as the lowest priority class.
+    fn ingressor(self, in_stream: PacketStream<Packet>) -> Self {
+        let mut in_streams = self.in_streams.unwrap_or_default();
+        in_streams.push(in_stream);
+
+        HtbLink {
+            in_streams: Some(in_streams),
+            ..self
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        if self.in_streams.is_some() {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn try_build_link(self) -> Result<Link<Packet>, LinkBuildError> {
+        let in_streams = self.in_streams.ok_or(LinkBuildError::MissingIngressors)?;
+        let rate = self.rate.ok_or(LinkBuildError::Missing("rate"))?;
+        if in_streams.len() != self.classes.len() {
+            return Err(LinkBuildError::Mismatch {
+                setting: "classes",
+                entries: self.classes.len(),
+                ingressors: in_streams.len(),
+            });
+        }
+
+        let now = self.clock.now();
+        let classes = in_streams
+            .into_iter()
+            .zip(self.classes)
+            .map(|(in_stream, class)| ClassState {
+                in_stream,
+                head: None,
+                finished: false,
+                assured: TokenBucket::new(class.rate),
+                ceil: TokenBucket::new(class.ceil),
+            })
+            .collect();
+
+        let egressor = HtbEgressor {
+            classes,
+            parent: TokenBucket::new(rate),
+            clock: self.clock,
+            last_refill: now,
+            timer: None,
+        };
+        Ok((vec![], vec![Box::new(egressor)]))
+    }
+}
+
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+}
+
+impl TokenBucket {
+    /// Buckets start empty, so nothing bursts out as soon as the link starts.
+    fn new(rate: u64) -> Self {
+        TokenBucket {
+            rate: rate as f64,
+            tokens: 0.0,
+        }
+    }
+
+    fn refill(&mut self, elapsed: Duration) {
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.rate);
+    }
+
+    fn has_token(&self) -> bool {
+        self.tokens >= 1.0
+    }
+
+    fn take(&mut self) {
+        self.tokens -= 1.0;
+    }
+
+    fn time_to_token(&self) -> Duration {
+        Duration::from_secs_f64((1.0 - self.tokens).max(0.0) / self.rate)
+    }
+}
+
+struct ClassState<Packet> {
+    in_stream: PacketStream<Packet>,
+    head: Option<Packet>,
+    finished: bool,
+    assured: TokenBucket,
+    ceil: TokenBucket,
+}
+
+/// The single egressor of HtbLink
+struct HtbEgressor<Packet> {
+    classes: Vec<ClassState<Packet>>,
+    parent: TokenBucket,
+    clock: Box<dyn Clock>,
+    last_refill: Instant,
+    timer: Option<Delay>,
+}
+
+impl<Packet> HtbEgressor<Packet> {
+    fn refill(&mut self) {
+        let now = self.clock.now();
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.last_refill = now;
+
+        self.parent.refill(elapsed);
+        for class in self.classes.iter_mut() {
+            class.assured.refill(elapsed);
+            class.ceil.refill(elapsed);
+        }
+    }
+
+    /// Picks the class to send from: the first class under its rate, otherwise the first class
+    /// that may borrow.
+    fn pick(&self) -> Option<usize> {
+        if !self.parent.has_token() {
+            return None;
+        }
+        let waiting = || {
+            self.classes
+                .iter()
+                .enumerate()
+                .filter(|(_, class)| class.head.is_some())
+        };
+        waiting()
+            .find(|(_, class)| class.assured.has_token() && class.ceil.has_token())
+            .or_else(|| waiting().find(|(_, class)| class.ceil.has_token()))
+            .map(|(index, _)| index)
+    }
+
+    /// How long until some waiting class may send.
+    fn time_to_send(&self) -> Duration {
+        let class_wait = self
+            .classes
+            .iter()
+            .filter(|class| class.head.is_some())
+            .map(|class| class.ceil.time_to_token())
+            .min()
+            .unwrap_or_default();
+        class_wait.max(self.parent.time_to_token())
+    }
+}
+
+impl<Packet> Unpin for HtbEgressor<Packet> {}
+
+impl<Packet> Stream for HtbEgressor<Packet> {
+    type Item = Packet;
+
+    /// Fills the head slot of every class from its input stream, then refills the buckets and
+    /// sends the head of the class picked by the scheduler. If packets are waiting but no class
+    /// has tokens, a timer is armed for when the next token arrives.
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let egressor = &mut *self;
+        loop {
+            for class in egressor.classes.iter_mut() {
+                if class.head.is_none() && !class.finished {
+                    match Pin::new(&mut class.in_stream).poll_next(cx) {
+                        Poll::Ready(Some(packet)) => class.head = Some(packet),
+                        Poll::Ready(None) => class.finished = true,
+                        Poll::Pending => {}
+                    }
+                }
+            }
+
+            if egressor.classes.iter().all(|class| class.head.is_none()) {
+                if egressor.classes.iter().all(|class| class.finished) {
+                    return Poll::Ready(None);
+                }
+                return Poll::Pending;
+            }
+
+            egressor.refill();
+            if let Some(index) = egressor.pick() {
+                egressor.timer = None;
+                egressor.parent.take();
+                let class = &mut egressor.classes[index];
+                if class.assured.has_token() {
+                    class.assured.take();
+                }
+                class.ceil.take();
+                return Poll::Ready(class.head.take());
+            }
+
+            let wait = egressor.time_to_send();
+            let timer = egressor.timer.get_or_insert_with(|| delay_for(wait));
+            ready!(Pin::new(timer).poll(cx));
+            egressor.timer = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::clock::ManualClock;
+    use crate::utils::test::harness::{initialize_runtime, poll_once, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+
+    const HIGH: i32 = 0;
+    const LOW: i32 = 1;
+
+    /// Pulls packets from the egressor until it stops being ready.
+    async fn drain(egressor: &mut PacketStream<i32>) -> Vec<i32> {
+        let mut packets = vec![];
+        while let Poll::Ready(Some(packet)) = poll_once(egressor).await {
+            packets.push(packet);
+        }
+        packets
+    }
+
+    fn count(packets: &[i32], class: i32) -> usize {
+        packets.iter().filter(|packet| **packet == class).count()
+    }
+
+    fn shaped(
+        clock: &ManualClock,
+        rate: u64,
+        classes: Vec<HtbClass>,
+        high_demand: usize,
+        low_demand: usize,
+    ) -> PacketStream<i32> {
+        let (_, mut egressors) = HtbLink::new()
+            .ingressor(immediate_stream(vec![HIGH; high_demand]))
+            .ingressor(immediate_stream(vec![LOW; low_demand]))
+            .rate(rate)
+            .classes(classes)
+            .clock(clock.clone())
+            .build_link();
+        egressors.remove(0)
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_when_built_without_rate() {
+        HtbLink::<i32>::new()
+            .ingressor(immediate_stream(vec![]))
+            .classes(vec![HtbClass::new(1, 1)])
+            .build_link();
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_when_classes_do_not_match_inputs() {
+        HtbLink::<i32>::new()
+            .ingressor(immediate_stream(vec![]))
+            .rate(10)
+            .build_link();
+    }
+
+    #[test]
+    fn try_build_link_reports_class_mismatch() {
+        let result = HtbLink::<i32>::new()
+            .ingressor(immediate_stream(vec![]))
+            .ingressor(immediate_stream(vec![]))
+            .rate(10)
+            .classes(vec![HtbClass::new(1, 1)])
+            .try_build_link();
+        assert_eq!(
+            result.err(),
+            Some(LinkBuildError::Mismatch {
+                setting: "classes",
+                entries: 1,
+                ingressors: 2,
+            })
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_when_ceil_below_rate() {
+        HtbClass::new(10, 5);
+    }
+
+    #[test]
+    fn starving_class_borrows_from_idle_sibling() {
+        let clock = ManualClock::new();
+
+        let mut runtime = initialize_runtime();
+        let sent = runtime.block_on(async {
+            let mut egressor = shaped(
+                &clock,
+                100,
+                vec![HtbClass::new(20, 100), HtbClass::new(80, 100)],
+                1000,
+                0,
+            );
+            assert!(drain(&mut egressor).await.is_empty());
+
+            clock.advance(Duration::from_secs(1));
+            drain(&mut egressor).await
+        });
+
+        assert_eq!(count(&sent, HIGH), 100);
+    }
+
+    #[test]
+    fn borrowing_stops_at_ceil() {
+        let clock = ManualClock::new();
+
+        let mut runtime = initialize_runtime();
+        let sent = runtime.block_on(async {
+            let mut egressor = shaped(
+                &clock,
+                100,
+                vec![HtbClass::new(20, 50), HtbClass::new(80, 100)],
+                1000,
+                0,
+            );
+            clock.advance(Duration::from_secs(1));
+            drain(&mut egressor).await
+        });
+
+        assert_eq!(count(&sent, HIGH), 50);
+    }
+
+    #[test]
+    fn busy_classes_get_their_rate() {
+        let clock = ManualClock::new();
+
+        let mut runtime = initialize_runtime();
+        let sent = runtime.block_on(async {
+            let mut egressor = shaped(
+                &clock,
+                100,
+                vec![HtbClass::new(20, 100), HtbClass::new(80, 100)],
+                1000,
+                1000,
+            );
+            clock.advance(Duration::from_secs(1));
+            drain(&mut egressor).await
+        });
+
+        assert_eq!(count(&sent, HIGH), 20);
+        assert_eq!(count(&sent, LOW), 80);
+    }
+
+    #[test]
+    fn passes_all_packets_eventually() {
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = HtbLink::new()
+                .ingressor(immediate_stream(vec![HIGH; 10]))
+                .ingressor(immediate_stream(vec![LOW; 10]))
+                .rate(1000)
+                .classes(vec![HtbClass::new(500, 1000), HtbClass::new(500, 1000)])
+                .build_link();
+
+            run_link(link).await
+        });
+
+        assert_eq!(count(&results[0], HIGH), 10);
+        assert_eq!(count(&results[0], LOW), 10);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::link::{Link, LinkBuilder, PacketStream};
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream};
 use crossbeam::crossbeam_channel;
 use futures::prelude::*;
 use futures::task::{Context, Poll};
@@ -32,16 +32,20 @@ impl<Packet: Send + 'static> LinkBuilder<(), Packet> for InputChannelLink<Packet
         panic!("InputChannelLink does not take any stream ingressors")
     }
 
-    fn build_link(self) -> Link<Packet> {
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(0)
+    }
+
+    fn try_build_link(self) -> Result<Link<Packet>, LinkBuildError> {
         if self.channel_receiver.is_none() {
-            panic!("Cannot build link! Missing channel");
+            Err(LinkBuildError::Missing("channel"))
         } else {
-            (
+            Ok((
                 vec![],
                 vec![Box::new(StreamFromChannel {
                     channel_receiver: self.channel_receiver.unwrap(),
                 })],
-            )
+            ))
         }
     }
 }
@@ -1,17 +1,93 @@
+use crate::link::utils::queue_depth::QueueDepths;
 use crate::link::utils::task_park::*;
-use crate::link::{Link, LinkBuilder, PacketStream, TokioRunnable};
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream, TokioRunnable};
+use crate::link_builder;
 use crossbeam::atomic::AtomicCell;
 use crossbeam::crossbeam_channel;
 use crossbeam::crossbeam_channel::{Receiver, Sender};
 use futures::prelude::*;
 use futures::task::{Context, Poll};
+use log::trace;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+
+/// How a `JoinLink` chooses which ingressor to take its next packet from. Packets from any one
+/// ingressor always keep their order; the modes differ in how ingressors are merged.
+///
+/// ```
+/// use route_rs_runtime::link::primitive::{JoinLink, JoinOrdering};
+/// use route_rs_runtime::link::{LinkBuilder, PacketStream};
+/// use route_rs_runtime::utils::test::harness::{initialize_runtime, run_link};
+/// use route_rs_runtime::utils::test::packet_generators::immediate_stream;
+///
+/// let mut runtime = initialize_runtime();
+/// let results = runtime.block_on(async {
+///     let input_streams: Vec<PacketStream<i32>> = vec![
+///         immediate_stream(vec![0, 1, 2]),
+///         immediate_stream(vec![10, 11]),
+///     ];
+///     let link = JoinLink::new()
+///         .ingressors(input_streams)
+///         .ordering(JoinOrdering::Interleaved)
+///         .build_link();
+///
+///     run_link(link).await
+/// });
+/// assert_eq!(results[0], vec![0, 10, 1, 11, 2]);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JoinOrdering {
+    /// Takes a packet from whichever ingressor has one ready, going round robin between them so
+    /// that none is starved. The merged order depends on when packets arrive, so it can differ
+    /// from run to run.
+    #[default]
+    Fair,
+    /// Takes every packet from the first ingressor until it ends, then from the second, and so on.
+    /// Ingressors that are not being drained fill their queues and then exert backpressure, so
+    /// they must not depend on the progress of an earlier ingressor.
+    PreserveIngressorOrder,
+    /// Takes exactly one packet from each ingressor in turn, waiting for the ingressor whose turn
+    /// it is. Ingressors that end drop out of the rotation.
+    Interleaved,
+}
+
+/// A handle to the number of packets a `JoinLink` dropped from each of its ingressors for being
+/// over their `max_in_flight` budget. It can be cloned and read while the pipeline is running.
+#[derive(Clone, Debug, Default)]
+pub struct FairDropCounts {
+    counts: Arc<Mutex<Vec<u64>>>,
+}
+
+impl FairDropCounts {
+    pub fn new() -> Self {
+        FairDropCounts {
+            counts: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// Number of packets dropped so far from each ingressor, in the order the ingressors were
+    /// given. Empty until the link is built.
+    pub fn get(&self) -> Vec<u64> {
+        self.counts.lock().unwrap().clone()
+    }
+
+    fn reset(&self, num_ingressors: usize) {
+        *self.counts.lock().unwrap() = vec![0; num_ingressors];
+    }
+
+    fn increment(&self, port: usize) {
+        self.counts.lock().unwrap()[port] += 1;
+    }
+}
 
 #[derive(Default)]
 pub struct JoinLink<Packet: Send + Clone> {
     in_streams: Option<Vec<PacketStream<Packet>>>,
     queue_capacity: usize,
+    ordering: JoinOrdering,
+    max_in_flight: Option<usize>,
+    queue_depths: QueueDepths,
+    fair_drops: FairDropCounts,
 }
 
 impl<Packet: Send + Clone> JoinLink<Packet> {
@@ -19,20 +95,37 @@ impl<Packet: Send + Clone> JoinLink<Packet> {
         JoinLink {
             in_streams: None,
             queue_capacity: 10,
+            ordering: JoinOrdering::Fair,
+            max_in_flight: None,
+            queue_depths: QueueDepths::new(),
+            fair_drops: FairDropCounts::new(),
         }
     }
 
-    /// Changes queue_capacity, default value is 10.
-    pub fn queue_capacity(self, queue_capacity: usize) -> Self {
-        assert!(
-            queue_capacity > 0,
-            format!("Queue capacity: {}, must be > 0", queue_capacity)
-        );
+    /// Returns a handle to the depths of the internal queues of this link, which remains valid
+    /// after the link is built.
+    pub fn queue_depths(&self) -> QueueDepths {
+        self.queue_depths.clone()
+    }
 
-        JoinLink {
-            in_streams: self.in_streams,
-            queue_capacity,
-        }
+    /// Returns a handle to the number of packets dropped from each ingressor for being over the
+    /// `max_in_flight` limit, which remains valid after the link is built.
+    pub fn fair_drops(&self) -> FairDropCounts {
+        self.fair_drops.clone()
+    }
+
+    link_builder! {
+        /// Changes ordering, how ingressors are merged, default is `JoinOrdering::Fair`.
+        ordering: JoinOrdering,
+        /// Limits each ingressor to `max_in_flight` packets waiting in the link, by default an
+        /// ingressor may fill its whole queue. A packet that arrives while its ingressor is at
+        /// the limit is dropped, rather than held back, so an ingressor that floods the link
+        /// sheds its excess and can not build up a backlog that monopolizes the output, while an
+        /// ingressor that trickles is never dropped from. A limit of `queue_capacity` or more has
+        /// no effect.
+        max_in_flight: Option<usize> where max_in_flight > 0,
+        /// Changes queue_capacity, default value is 10.
+        queue_capacity: usize where queue_capacity > 0,
     }
 }
 
@@ -52,7 +145,7 @@ impl<Packet: Send + Clone + 'static> LinkBuilder<Packet, Packet> for JoinLink<Pa
 
         JoinLink {
             in_streams: Some(in_streams),
-            queue_capacity: self.queue_capacity,
+            ..self
         }
     }
 
@@ -61,46 +154,64 @@ impl<Packet: Send + Clone + 'static> LinkBuilder<Packet, Packet> for JoinLink<Pa
         match self.in_streams {
             None => {
                 let in_streams = Some(vec![in_stream]);
-                JoinLink {
-                    in_streams,
-                    queue_capacity: self.queue_capacity,
-                }
+                JoinLink { in_streams, ..self }
             }
             Some(mut in_streams) => {
                 in_streams.push(in_stream);
                 JoinLink {
                     in_streams: Some(in_streams),
-                    queue_capacity: self.queue_capacity,
+                    ..self
                 }
             }
         }
     }
 
-    fn build_link(self) -> Link<Packet> {
+    fn ingressor_capacity(&self) -> Option<usize> {
+        if self.in_streams.is_some() {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn try_build_link(self) -> Result<Link<Packet>, LinkBuildError> {
         if self.in_streams.is_none() {
-            panic!("Cannot build link! Missing input streams");
+            Err(LinkBuildError::MissingIngressors)
         } else {
             let input_streams = self.in_streams.unwrap();
             let number_ingressors = input_streams.len();
             let mut ingressors: Vec<TokioRunnable> = Vec::new();
             let mut from_ingressors: Vec<Receiver<Option<Packet>>> = Vec::new();
             let mut task_parks: Vec<Arc<AtomicCell<TaskParkState>>> = Vec::new();
+            self.fair_drops.reset(number_ingressors);
 
-            for input_stream in input_streams {
+            for (port, input_stream) in input_streams.into_iter().enumerate() {
                 let (to_egressor, from_ingressor) =
                     crossbeam_channel::bounded::<Option<Packet>>(self.queue_capacity);
+                self.queue_depths.watch(&from_ingressor);
                 let task_park = Arc::new(AtomicCell::new(TaskParkState::Empty));
 
-                let ingressor =
-                    JoinIngressor::new(input_stream, to_egressor, Arc::clone(&task_park));
+                let ingressor = JoinIngressor::new(
+                    input_stream,
+                    to_egressor,
+                    Arc::clone(&task_park),
+                    port,
+                    self.max_in_flight,
+                    self.fair_drops.clone(),
+                );
                 ingressors.push(Box::new(ingressor));
                 from_ingressors.push(from_ingressor);
                 task_parks.push(task_park);
             }
 
-            let egressor = JoinEgressor::new(from_ingressors, task_parks, number_ingressors);
+            let egressor = JoinEgressor::new(
+                from_ingressors,
+                task_parks,
+                number_ingressors,
+                self.ordering,
+            );
 
-            (ingressors, vec![Box::new(egressor)])
+            Ok((ingressors, vec![Box::new(egressor)]))
         }
     }
 }
@@ -109,6 +220,9 @@ pub struct JoinIngressor<Packet: Sized> {
     input_stream: PacketStream<Packet>,
     to_egressor: Sender<Option<Packet>>,
     task_park: Arc<AtomicCell<TaskParkState>>,
+    port: usize,
+    max_in_flight: Option<usize>,
+    fair_drops: FairDropCounts,
 }
 
 impl<Packet: Sized> Unpin for JoinIngressor<Packet> {}
@@ -118,11 +232,25 @@ impl<Packet: Sized> JoinIngressor<Packet> {
         input_stream: PacketStream<Packet>,
         to_egressor: Sender<Option<Packet>>,
         task_park: Arc<AtomicCell<TaskParkState>>,
+        port: usize,
+        max_in_flight: Option<usize>,
+        fair_drops: FairDropCounts,
     ) -> Self {
         JoinIngressor {
             input_stream,
             to_egressor,
             task_park,
+            port,
+            max_in_flight,
+            fair_drops,
+        }
+    }
+
+    /// Whether this ingressor already has as many packets waiting as `max_in_flight` allows.
+    fn over_budget(&self) -> bool {
+        match self.max_in_flight {
+            Some(max_in_flight) => self.to_egressor.len() >= max_in_flight,
+            None => false,
         }
     }
 }
@@ -147,6 +275,10 @@ impl<Packet: Sized> Future for JoinIngressor<Packet> {
     /// is no futher work to complete.
     /// ###
     /// By Sleep, we mean we return a NotReady to the runtime which will sleep the task.
+    ///
+    /// With a `max_in_flight` below the queue capacity, case #1 never happens: a packet that
+    /// would take the ingressor over its budget is dropped instead, and we yield to the runtime
+    /// after each drop.
     fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
         let ingressor = Pin::into_inner(self);
         loop {
@@ -165,6 +297,17 @@ impl<Packet: Sized> Future for JoinIngressor<Packet> {
                     die_and_wake(&ingressor.task_park);
                     return Poll::Ready(());
                 }
+                Some(_) if ingressor.over_budget() => {
+                    trace!(
+                        "dropped packet from ingressor {}, over its in-flight budget",
+                        ingressor.port
+                    );
+                    ingressor.fair_drops.increment(ingressor.port);
+                    // Yield, so that an ingressor flooding the link can not hog its thread and
+                    // hold back the egressor from draining the other ingressors.
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
                 Some(packet) => {
                     ingressor.to_egressor.try_send(Some(packet)).expect(
                         "JoinIngressor::Poll:Ready(Some(Val)) try_send to_egressor shouldn't fail",
@@ -182,6 +325,8 @@ pub struct JoinEgressor<Packet: Sized> {
     task_parks: Vec<Arc<AtomicCell<TaskParkState>>>,
     ingressors_alive: usize,
     next_pull_ingressor: usize,
+    ordering: JoinOrdering,
+    ended: Vec<bool>,
 }
 
 impl<Packet: Sized> JoinEgressor<Packet> {
@@ -189,13 +334,68 @@ impl<Packet: Sized> JoinEgressor<Packet> {
         from_ingressors: Vec<Receiver<Option<Packet>>>,
         task_parks: Vec<Arc<AtomicCell<TaskParkState>>>,
         ingressors_alive: usize,
+        ordering: JoinOrdering,
     ) -> Self {
         let next_pull_ingressor = 0;
+        let ended = vec![false; from_ingressors.len()];
         JoinEgressor {
             from_ingressors,
             task_parks,
             ingressors_alive,
             next_pull_ingressor,
+            ordering,
+            ended,
+        }
+    }
+
+    /// Moves `next_pull_ingressor` on to the next ingressor that has not ended.
+    fn advance(&mut self) {
+        let num_ingressors = self.from_ingressors.len();
+        for offset in 1..=num_ingressors {
+            let port = (self.next_pull_ingressor + offset) % num_ingressors;
+            if !self.ended[port] {
+                self.next_pull_ingressor = port;
+                return;
+            }
+        }
+    }
+
+    /// Polls for the next packet in the `PreserveIngressorOrder` and `Interleaved` orderings,
+    /// where only the ingressor at `next_pull_ingressor` may provide it. When that ingressor has
+    /// nothing ready, we park on it alone, and check its channel once more afterwards in case a
+    /// packet arrived before we parked.
+    fn poll_in_turn(&mut self, cx: &mut Context) -> Poll<Option<Packet>> {
+        loop {
+            let port = self.next_pull_ingressor;
+            match self.from_ingressors[port].try_recv() {
+                Ok(Some(packet)) => {
+                    unpark_and_wake(&self.task_parks[port]);
+                    if self.ordering == JoinOrdering::Interleaved {
+                        self.advance();
+                    }
+                    return Poll::Ready(Some(packet));
+                }
+                Ok(None) => {
+                    self.ended[port] = true;
+                    self.ingressors_alive -= 1;
+                    if self.ingressors_alive == 0 {
+                        for task_park in self.task_parks.iter() {
+                            die_and_wake(&task_park);
+                        }
+                        return Poll::Ready(None);
+                    }
+                    self.advance();
+                }
+                Err(_) => {
+                    let egressor_task = Arc::new(AtomicCell::new(Some(cx.waker().clone())));
+                    if !indirect_park_and_wake(&self.task_parks[port], egressor_task)
+                        || !self.from_ingressors[port].is_empty()
+                    {
+                        cx.waker().wake_by_ref();
+                    }
+                    return Poll::Pending;
+                }
+            }
         }
     }
 }
@@ -211,9 +411,14 @@ impl<Packet: Sized> Stream for JoinEgressor<Packet> {
 
     /// Iterate over all the channels, pull the first packet that is available.
     /// This starts at the next index after the last successful recv
+    ///
+    /// That is the `Fair` ordering; the other orderings are handled by `poll_in_turn`.
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
         //rotate_slice exists in 1.22 nightly experimental
         let egressor = Pin::into_inner(self);
+        if egressor.ordering != JoinOrdering::Fair {
+            return egressor.poll_in_turn(cx);
+        }
         let rotated_iter = egressor
             .from_ingressors
             .iter()
@@ -271,7 +476,9 @@ mod tests {
     use core::time;
     use rand::{thread_rng, Rng};
 
-    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::harness::{
+        initialize_runtime, initialize_runtime_deterministic, run_link,
+    };
 
     #[test]
     #[should_panic]
@@ -397,6 +604,204 @@ mod tests {
         assert_eq!(results[0][0..10].iter().sum::<usize>(), 4);
     }
 
+    #[test]
+    fn deterministic_runtime_is_reproducible() {
+        let run = || {
+            let mut runtime = initialize_runtime_deterministic();
+            runtime.block_on(async {
+                let mut input_streams: Vec<PacketStream<usize>> = Vec::new();
+                input_streams.push(immediate_stream(0..50));
+                input_streams.push(immediate_stream(100..120));
+                input_streams.push(immediate_stream(200..280));
+
+                let link = JoinLink::new()
+                    .ingressors(input_streams)
+                    .queue_capacity(3)
+                    .build_link();
+
+                run_link(link).await
+            })
+        };
+
+        let first = run();
+        assert_eq!(first[0].len(), 150);
+        for _ in 0..100 {
+            assert_eq!(run(), first);
+        }
+    }
+
+    #[test]
+    fn capacity_one_survives_bursts() {
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let mut input_streams: Vec<PacketStream<usize>> = Vec::new();
+            input_streams.push(immediate_stream(0..1000));
+            input_streams.push(immediate_stream(1000..2000));
+            input_streams.push(immediate_stream(2000..3000));
+
+            let link = JoinLink::new()
+                .ingressors(input_streams)
+                .queue_capacity(1)
+                .build_link();
+
+            run_link(link).await
+        });
+
+        assert_eq!(results[0].len(), 3000);
+        // Every packet arrives exactly once, and each input keeps its own order.
+        for input in 0..3 {
+            let from_input: Vec<usize> = results[0]
+                .iter()
+                .cloned()
+                .filter(|packet| packet / 1000 == input)
+                .collect();
+            assert_eq!(
+                from_input,
+                (input * 1000..(input + 1) * 1000).collect::<Vec<usize>>()
+            );
+        }
+    }
+
+    fn join_in_order(ordering: JoinOrdering, queue_capacity: usize) -> Vec<usize> {
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let packet_generator = PacketIntervalGenerator::new(
+                time::Duration::from_millis(1),
+                (200..205).collect::<Vec<usize>>().into_iter(),
+            );
+
+            let mut input_streams: Vec<PacketStream<usize>> = Vec::new();
+            input_streams.push(immediate_stream(0..10));
+            input_streams.push(immediate_stream(100..103));
+            input_streams.push(Box::new(packet_generator));
+
+            let link = JoinLink::new()
+                .ingressors(input_streams)
+                .queue_capacity(queue_capacity)
+                .ordering(ordering)
+                .build_link();
+
+            run_link(link).await
+        });
+        results.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn preserve_ingressor_order() {
+        let expected: Vec<usize> = (0..10).chain(100..103).chain(200..205).collect();
+        for queue_capacity in &[1, 2, 10] {
+            for _ in 0..10 {
+                assert_eq!(
+                    join_in_order(JoinOrdering::PreserveIngressorOrder, *queue_capacity),
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn interleaved() {
+        let expected = vec![
+            0, 100, 200, 1, 101, 201, 2, 102, 202, 3, 203, 4, 204, 5, 6, 7, 8, 9,
+        ];
+        for queue_capacity in &[1, 2, 10] {
+            for _ in 0..10 {
+                assert_eq!(
+                    join_in_order(JoinOrdering::Interleaved, *queue_capacity),
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fair_is_the_default() {
+        assert_eq!(JoinLink::<i32>::new().ordering, JoinOrdering::Fair);
+        assert_eq!(JoinLink::<i32>::default().ordering, JoinOrdering::Fair);
+    }
+
+    #[test]
+    fn max_in_flight_keeps_slow_ingressor_moving() {
+        let slow_packets: Vec<usize> = (1_000_000..1_000_010).collect();
+
+        let mut runtime = initialize_runtime();
+        let (results, fair_drops) = runtime.block_on(async {
+            let slow = PacketIntervalGenerator::new(
+                time::Duration::from_millis(5),
+                slow_packets.clone().into_iter(),
+            );
+
+            let input_streams: Vec<PacketStream<usize>> =
+                vec![immediate_stream(0..100_000), Box::new(slow)];
+
+            let link = JoinLink::new().ingressors(input_streams).max_in_flight(4);
+            let fair_drops = link.fair_drops();
+
+            (run_link(link.build_link()).await, fair_drops)
+        });
+
+        // Every packet of the slow ingressor makes it through, in order, while the fast one
+        // sheds what it can not get through within its budget.
+        let (fast, slow): (Vec<usize>, Vec<usize>) =
+            results[0].iter().partition(|packet| **packet < 1_000_000);
+        assert_eq!(slow, slow_packets);
+        assert_eq!(fair_drops.get()[1], 0);
+        assert_eq!(fast.len() as u64 + fair_drops.get()[0], 100_000);
+        assert!(fast.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn max_in_flight_drops_excess() {
+        let link = JoinLink::new()
+            .ingressor(immediate_stream(0..10))
+            .max_in_flight(4);
+        let fair_drops = link.fair_drops();
+        let (mut ingressors, mut egressors) = link.build_link();
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+
+        // The first poll queues four packets, then drops the fifth and yields. Every poll after
+        // that drops one more, while nothing drains the queue.
+        for _ in 0..6 {
+            assert_eq!(Pin::new(&mut ingressors[0]).poll(&mut cx), Poll::Pending);
+        }
+        assert_eq!(fair_drops.get(), vec![6]);
+        assert_eq!(Pin::new(&mut ingressors[0]).poll(&mut cx), Poll::Ready(()));
+
+        for packet in 0..4 {
+            assert_eq!(
+                Pin::new(&mut egressors[0]).poll_next(&mut cx),
+                Poll::Ready(Some(packet))
+            );
+        }
+        assert_eq!(
+            Pin::new(&mut egressors[0]).poll_next(&mut cx),
+            Poll::Ready(None)
+        );
+    }
+
+    #[test]
+    fn no_fair_drops_by_default() {
+        let mut runtime = initialize_runtime();
+        let (results, fair_drops) = runtime.block_on(async {
+            let link = JoinLink::new()
+                .ingressor(immediate_stream(0..1000))
+                .ingressor(immediate_stream(1000..1010))
+                .queue_capacity(1);
+            let fair_drops = link.fair_drops();
+
+            (run_link(link.build_link()).await, fair_drops)
+        });
+
+        assert_eq!(results[0].len(), 1010);
+        assert_eq!(fair_drops.get(), vec![0, 0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn max_in_flight_must_be_positive() {
+        JoinLink::<usize>::new().max_in_flight(0);
+    }
+
     #[test]
     fn small_channel() {
         let mut runtime = initialize_runtime();
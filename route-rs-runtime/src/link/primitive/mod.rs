@@ -5,6 +5,11 @@
 mod process_link;
 pub use self::process_link::*;
 
+/// Like `ProcessLink`, but collects packets into batches before handing them to a `BatchProcessor`.
+/// A partial batch is flushed when the input ends, or optionally after a timeout, synchronous.
+mod batch_process_link;
+pub use self::batch_process_link::*;
+
 /// Input packets are placed into an intermediate channel that are pulled from the output asynchronously.
 /// Asynchronous in that a packets may enter and leave this link asynchronously to each other.  This link is
 /// useful for creating queues in the router, buffering, and creating `Task` boundries that can be processed on
@@ -14,6 +19,24 @@ pub use self::process_link::*;
 mod queue_link;
 pub use self::queue_link::*;
 
+/// Holds packets back and releases them as a group, either when enough packets have been collected
+/// or when the oldest packet has waited long enough, synchronous.
+mod coalesce_link;
+pub use self::coalesce_link::*;
+
+/// Releases packets in the order of their sequence numbers, holding back packets that arrive early,
+/// synchronous.
+mod reorder_buffer_link;
+pub use self::reorder_buffer_link::*;
+
+/// Holds each packet for a fixed delay plus random jitter to emulate a WAN link, synchronous.
+mod delay_link;
+pub use self::delay_link::*;
+
+/// Passes packets through unchanged while measuring their rate over a sliding window, synchronous.
+mod throughput_meter_link;
+pub use self::throughput_meter_link::*;
+
 /// Uses processor defined classifications to sort input into different channels, a good example would
 /// be a flow that splits IPv4 and IPv6 packets, asynchronous.
 mod classify_link;
@@ -27,6 +50,10 @@ pub use self::join_link::*;
 mod fork_link;
 pub use self::fork_link::*;
 
+/// Sends each input packet to the next of its outputs in rotation, asynchronous.
+mod round_robin_tee_link;
+pub use self::round_robin_tee_link::*;
+
 /// Takes a channel for input and converts it to a stream.
 mod input_channel_link;
 pub use self::input_channel_link::*;
@@ -34,3 +61,41 @@ pub use self::input_channel_link::*;
 /// Takes a stream and converts it to a channel for output.
 mod output_channel_link;
 pub use self::output_channel_link::*;
+
+/// Receives frames from a network interface into a stream, and sends a stream of frames out of
+/// one, through sockets such as `AF_PACKET`.
+mod raw_socket_link;
+pub use self::raw_socket_link::*;
+
+/// Sends IPv6 Router Advertisements periodically and in answer to Router Solicitations, so that
+/// hosts on a LAN can autoconfigure their addresses.
+mod ra_sender;
+pub use self::ra_sender::*;
+
+/// Holds frames whose next hop is unresolved while it is resolved with ARP, releasing them
+/// addressed for the next hop once it is.
+mod pending_arp_queue;
+pub use self::pending_arp_queue::*;
+
+/// Consumes the drop egressors of other links and tallies the dropped packets by `DropReason`.
+mod drop_sink;
+pub use self::drop_sink::*;
+
+/// Consumes streams that should go nowhere, counting the discarded packets.
+mod discard_link;
+pub use self::discard_link::*;
+
+/// Shapes several classes of traffic under a shared rate, letting busy classes borrow the unused
+/// rate of idle ones, synchronous.
+mod htb_link;
+pub use self::htb_link::*;
+
+/// Combines several inputs into a single output with deficit round robin, sharing the output
+/// fairly by bytes, synchronous.
+mod drr_link;
+pub use self::drr_link::*;
+
+/// Shares its output between 5-tuple flows with weighted fair queuing, so that no single flow
+/// dominates an uplink, synchronous.
+mod wfq_link;
+pub use self::wfq_link::*;
@@ -1,4 +1,4 @@
-use crate::link::{Link, LinkBuilder, PacketStream};
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream};
 use futures::prelude::*;
 use futures::task::{Context, Poll};
 use std::pin::Pin;
@@ -19,8 +19,8 @@ impl<Packet> OutputChannelLink<Packet> {
 
     pub fn channel(self, channel_sender: crossbeam::Sender<Packet>) -> Self {
         OutputChannelLink {
-            in_stream: self.in_stream,
             channel_sender: Some(channel_sender),
+            ..self
         }
     }
 }
@@ -39,7 +39,7 @@ impl<Packet: Send + 'static> LinkBuilder<Packet, ()> for OutputChannelLink<Packe
 
         OutputChannelLink {
             in_stream: Some(in_streams.remove(0)),
-            channel_sender: self.channel_sender,
+            ..self
         }
     }
 
@@ -49,21 +49,25 @@ impl<Packet: Send + 'static> LinkBuilder<Packet, ()> for OutputChannelLink<Packe
         }
         OutputChannelLink {
             in_stream: Some(in_stream),
-            channel_sender: self.channel_sender,
+            ..self
         }
     }
 
-    fn build_link(self) -> Link<()> {
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<()>, LinkBuildError> {
         match (self.in_stream, self.channel_sender) {
-            (None, _) => panic!("Cannot build link! Missing input streams"),
-            (_, None) => panic!("Cannot build link! Missing channel"),
-            (Some(in_stream), Some(sender)) => (
+            (None, _) => Err(LinkBuildError::MissingIngressors),
+            (_, None) => Err(LinkBuildError::Missing("channel")),
+            (Some(in_stream), Some(sender)) => Ok((
                 vec![Box::new(StreamToChannel {
                     stream: in_stream,
                     channel_sender: sender,
                 })],
                 vec![],
-            ),
+            )),
         }
     }
 }
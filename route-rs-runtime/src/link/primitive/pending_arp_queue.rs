@@ -0,0 +1,567 @@
+use crate::interface::InterfaceConfig;
+use crate::link::utils::clock::{Clock, SystemClock};
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream};
+use crate::link_builder;
+use crate::processor::{ArpCache, Resolution, RewriteL2};
+use crate::routing::NextHop;
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+use route_rs_packets::{EthernetFrame, MacAddr};
+use std::collections::{HashMap, VecDeque};
+use std::net::Ipv4Addr;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tokio::time::{delay_for, Delay};
+
+const ETHER_TYPE_ARP: u16 = 0x0806;
+const ARP_REQUEST: u16 = 1;
+
+/// An ARP request (RFC 826) from `src_mac` and `src_addr`, asking for the MAC of `target`.
+fn arp_request(src_mac: MacAddr, src_addr: Ipv4Addr, target: Ipv4Addr) -> EthernetFrame {
+    // Ethernet hardware, IPv4 protocol, and their address lengths.
+    let mut payload = vec![0, 1, 0x08, 0x00, 6, 4];
+    payload.extend_from_slice(&ARP_REQUEST.to_be_bytes());
+    payload.extend_from_slice(&src_mac.bytes);
+    payload.extend_from_slice(&src_addr.octets());
+    payload.extend_from_slice(&[0; 6]);
+    payload.extend_from_slice(&target.octets());
+
+    let mut frame = EthernetFrame::empty();
+    frame.set_dest_mac(MacAddr::new([0xFF; 6]));
+    frame.set_src_mac(src_mac);
+    frame.set_ether_type(ETHER_TYPE_ARP);
+    frame.set_payload(&payload);
+    frame
+}
+
+/// `PendingArpQueue` addresses frames for their next hop like `RewriteL2`, but rather than
+/// dropping frames whose next hop is unresolved, it holds up to `capacity` of them per next hop
+/// while it resolves the next hop with ARP, as RFC 1122 section 2.3.2.2 recommends. When a next
+/// hop is first missing from the `ArpCache`, an ARP request for it is sent out of the egress
+/// interface, and resent every `retry` until the next hop is resolved. Once whatever handles ARP
+/// replies inserts the next hop into the cache, its held frames are released, in order, with the
+/// resolved MAC. If that takes longer than `timeout`, they are dropped instead, and the next hop
+/// is no longer marked as pending in the cache.
+///
+/// The egressor carries both the released frames and the ARP requests, each tagged with its
+/// egress interface, so it can be classified to the interfaces like the output of `RewriteL2`.
+/// When a next hop's queue is full, the oldest frame is dropped to make room, so that the latest
+/// frame is kept. Frames are held for at most `max_next_hops` next hops at once; frames for any
+/// other unresolved next hop are dropped, and no request is sent for it, until one is resolved or
+/// expires.
+pub struct PendingArpQueue {
+    in_stream: Option<PacketStream<(NextHop, EthernetFrame)>>,
+    interfaces: Option<InterfaceConfig>,
+    arp_cache: Option<ArpCache>,
+    capacity: usize,
+    max_next_hops: usize,
+    timeout: Duration,
+    retry: Duration,
+    clock: Box<dyn Clock>,
+}
+
+impl Default for PendingArpQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PendingArpQueue {
+    pub fn new() -> Self {
+        PendingArpQueue {
+            in_stream: None,
+            interfaces: None,
+            arp_cache: None,
+            capacity: 3,
+            max_next_hops: 256,
+            timeout: Duration::from_secs(3),
+            retry: Duration::from_secs(1),
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    link_builder! {
+        /// Sets the interfaces, whose MACs and addresses frames and ARP requests are sent from.
+        interfaces: Option<InterfaceConfig>,
+        /// Sets the cache next hops are resolved with, and whose inserts release held frames.
+        arp_cache: Option<ArpCache>,
+        /// Changes how long frames are held for an unresolved next hop, default value is 3
+        /// seconds.
+        timeout: Duration,
+        /// Changes how often ARP requests are resent for an unresolved next hop, default value is
+        /// 1 second.
+        retry: Duration,
+        /// Changes how many frames are held per unresolved next hop, default value is 3.
+        capacity: usize where capacity > 0,
+        /// Changes how many unresolved next hops frames are held for at once, default value is
+        /// 256.
+        max_next_hops: usize where max_next_hops > 0,
+    }
+
+    /// Changes the clock used to time requests and timeouts, default is `SystemClock`.
+    pub fn clock<C: Clock + 'static>(self, clock: C) -> Self {
+        PendingArpQueue {
+            clock: Box::new(clock),
+            ..self
+        }
+    }
+}
+
+impl LinkBuilder<(NextHop, EthernetFrame), (usize, EthernetFrame)> for PendingArpQueue {
+    fn ingressors(self, mut in_streams: Vec<PacketStream<(NextHop, EthernetFrame)>>) -> Self {
+        assert_eq!(
+            in_streams.len(),
+            1,
+            "PendingArpQueue may only take 1 input stream"
+        );
+        self.ingressor(in_streams.remove(0))
+    }
+
+    fn ingressor(self, in_stream: PacketStream<(NextHop, EthernetFrame)>) -> Self {
+        if self.in_stream.is_some() {
+            panic!("PendingArpQueue may only take 1 input stream");
+        }
+        PendingArpQueue {
+            in_stream: Some(in_stream),
+            ..self
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<(usize, EthernetFrame)>, LinkBuildError> {
+        let interfaces = self
+            .interfaces
+            .ok_or(LinkBuildError::Missing("interfaces"))?;
+        let arp_cache = self.arp_cache.ok_or(LinkBuildError::Missing("arp_cache"))?;
+        let in_stream = self.in_stream.ok_or(LinkBuildError::MissingIngressors)?;
+
+        Ok((
+            vec![],
+            vec![Box::new(PendingArpEgressor {
+                in_stream,
+                rewrite: RewriteL2::new(interfaces.clone(), arp_cache.clone()),
+                interfaces,
+                arp_cache,
+                capacity: self.capacity,
+                max_next_hops: self.max_next_hops,
+                timeout: self.timeout,
+                retry: self.retry,
+                clock: self.clock,
+                pending: HashMap::new(),
+                released: VecDeque::new(),
+                timer: None,
+                finished: false,
+            })],
+        ))
+    }
+}
+
+/// The frames held for one unresolved next hop.
+struct Pending {
+    /// The interface ARP requests for the next hop are sent out of.
+    interface: usize,
+    frames: VecDeque<(NextHop, EthernetFrame)>,
+    /// When the frames are dropped, if the next hop is still unresolved.
+    expires: Instant,
+    /// When the next ARP request is due.
+    next_request: Instant,
+}
+
+/// The single egressor of PendingArpQueue
+struct PendingArpEgressor {
+    in_stream: PacketStream<(NextHop, EthernetFrame)>,
+    rewrite: RewriteL2,
+    interfaces: InterfaceConfig,
+    arp_cache: ArpCache,
+    capacity: usize,
+    max_next_hops: usize,
+    timeout: Duration,
+    retry: Duration,
+    clock: Box<dyn Clock>,
+    pending: HashMap<Ipv4Addr, Pending>,
+    released: VecDeque<(usize, EthernetFrame)>,
+    timer: Option<Delay>,
+    finished: bool,
+}
+
+impl PendingArpEgressor {
+    fn enqueue(&mut self, next_hop: NextHop, frame: EthernetFrame) {
+        let neighbor = match self.rewrite.resolve(next_hop, frame) {
+            Resolution::Resolved(interface, frame) => {
+                self.released.push_back((interface, frame));
+                return;
+            }
+            Resolution::Unresolved(neighbor, frame) => {
+                if !self.pending.contains_key(&neighbor) && self.pending.len() >= self.max_next_hops
+                {
+                    return;
+                }
+                let now = self.clock.now();
+                let expires = now + self.timeout;
+                let pending = self.pending.entry(neighbor).or_insert_with(|| Pending {
+                    interface: next_hop.interface,
+                    frames: VecDeque::new(),
+                    expires,
+                    next_request: now,
+                });
+                pending.frames.push_back((next_hop, frame));
+                if pending.frames.len() > self.capacity {
+                    pending.frames.pop_front();
+                }
+                neighbor
+            }
+            Resolution::Undeliverable => return,
+        };
+        self.arp_cache.request(neighbor);
+    }
+
+    /// Releases the frames of next hops that have been resolved, drops those of next hops that
+    /// have expired, and sends the ARP requests that are due. Returns when the next request or
+    /// expiry is due, if any frames are still held.
+    fn service(&mut self) -> Option<Instant> {
+        let now = self.clock.now();
+        let mut due = None;
+        let neighbors: Vec<Ipv4Addr> = self.pending.keys().copied().collect();
+        for neighbor in neighbors {
+            if self.arp_cache.lookup(&neighbor).is_some() {
+                let pending = self.pending.remove(&neighbor).unwrap();
+                for (next_hop, frame) in pending.frames {
+                    if let Resolution::Resolved(interface, frame) =
+                        self.rewrite.resolve(next_hop, frame)
+                    {
+                        self.released.push_back((interface, frame));
+                    }
+                }
+                continue;
+            }
+
+            let pending = self.pending.get_mut(&neighbor).unwrap();
+            if pending.expires <= now {
+                self.pending.remove(&neighbor);
+                self.arp_cache.cancel(&neighbor);
+                continue;
+            }
+            if pending.next_request <= now {
+                if let Some(settings) = self.interfaces.get(pending.interface) {
+                    let src_addr = settings
+                        .primary_ipv4_addr()
+                        .unwrap_or(Ipv4Addr::UNSPECIFIED);
+                    let request = arp_request(settings.mac, src_addr, neighbor);
+                    self.released.push_back((pending.interface, request));
+                }
+                pending.next_request = now + self.retry;
+            }
+            let next = pending.next_request.min(pending.expires);
+            due = Some(due.map_or(next, |due: Instant| due.min(next)));
+        }
+        due
+    }
+}
+
+impl Unpin for PendingArpEgressor {}
+
+impl Stream for PendingArpEgressor {
+    type Item = (usize, EthernetFrame);
+
+    /// Hands out released frames and ARP requests first. Otherwise, takes frames from the input
+    /// stream until it returns `Poll::Pending`, then services the held frames. The task is woken
+    /// by the `ArpCache` when an entry is inserted, and by a timer when the next request or expiry
+    /// is due. The stream ends once the input stream has ended and no frames are held.
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let egressor = &mut *self;
+        loop {
+            if let Some(frame) = egressor.released.pop_front() {
+                return Poll::Ready(Some(frame));
+            }
+
+            if !egressor.finished {
+                match Pin::new(&mut egressor.in_stream).poll_next(cx) {
+                    Poll::Ready(Some((next_hop, frame))) => {
+                        egressor.enqueue(next_hop, frame);
+                        continue;
+                    }
+                    Poll::Ready(None) => egressor.finished = true,
+                    Poll::Pending => {}
+                }
+            }
+
+            // Watch the cache before checking it, so an insert in between still wakes us.
+            egressor.arp_cache.watch(cx.waker());
+            let due = egressor.service();
+            if !egressor.released.is_empty() {
+                continue;
+            }
+            match due {
+                None if egressor.finished => return Poll::Ready(None),
+                None => return Poll::Pending,
+                Some(due) => {
+                    let remaining = due.saturating_duration_since(egressor.clock.now());
+                    let timer = egressor.timer.get_or_insert_with(|| delay_for(remaining));
+                    ready!(Pin::new(timer).poll(cx));
+                    // The timer fired, but the clock decides what is due. Loop around to check
+                    // again, arming a fresh timer if nothing is.
+                    egressor.timer = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::InterfaceSettings;
+    use crate::utils::test::clock::ManualClock;
+    use crate::utils::test::harness::{initialize_runtime, poll_once, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use futures::channel::mpsc;
+    use route_rs_packets::Ipv4Packet;
+
+    const LAN: usize = 0;
+    const WAN: usize = 1;
+
+    const LAN_MAC: MacAddr = MacAddr {
+        bytes: [0x02, 0, 0, 0, 0, 0x01],
+    };
+    const WAN_MAC: MacAddr = MacAddr {
+        bytes: [0x02, 0, 0, 0, 0, 0x02],
+    };
+    const HOST_MAC: MacAddr = MacAddr {
+        bytes: [0x02, 0, 0, 0, 0, 0x10],
+    };
+    const GATEWAY_MAC: MacAddr = MacAddr {
+        bytes: [0x02, 0, 0, 0, 0, 0x20],
+    };
+
+    const LAN_ADDR: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 1);
+    const HOST: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 10);
+    const GATEWAY: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 1);
+
+    fn interfaces() -> InterfaceConfig {
+        let interfaces = InterfaceConfig::new();
+        interfaces.set(LAN, InterfaceSettings::new(1500, LAN_MAC, vec![LAN_ADDR]));
+        interfaces.set(
+            WAN,
+            InterfaceSettings::new(1500, WAN_MAC, vec![Ipv4Addr::new(203, 0, 113, 2)]),
+        );
+        interfaces
+    }
+
+    /// A frame to `dest_addr`, with `id` as its IPv4 identification to tell frames apart.
+    fn frame(dest_addr: Ipv4Addr, id: u16) -> EthernetFrame {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(Ipv4Addr::new(198, 51, 100, 7));
+        packet.set_dest_addr(dest_addr);
+        packet.set_identification(id);
+        packet.set_ttl(64);
+        EthernetFrame::encap_ipv4(packet)
+    }
+
+    fn queue(arp_cache: &ArpCache, clock: &ManualClock) -> PendingArpQueue {
+        PendingArpQueue::new()
+            .interfaces(interfaces())
+            .arp_cache(arp_cache.clone())
+            .timeout(Duration::from_secs(3))
+            .retry(Duration::from_secs(1))
+            .clock(clock.clone())
+    }
+
+    fn assert_arp_request(output: Poll<Option<(usize, EthernetFrame)>>, target: Ipv4Addr) {
+        let (interface, request) = match output {
+            Poll::Ready(Some(output)) => output,
+            other => panic!("Expected an ARP request, got {:?}", other),
+        };
+        assert_eq!(interface, LAN);
+        assert_eq!(request.dest_mac(), MacAddr::new([0xFF; 6]));
+        assert_eq!(request.src_mac(), LAN_MAC);
+        assert_eq!(request.ether_type(), ETHER_TYPE_ARP);
+        let payload = request.payload();
+        assert_eq!(payload[..8], [0, 1, 0x08, 0x00, 6, 4, 0, 1]);
+        assert_eq!(payload[8..14], LAN_MAC.bytes);
+        assert_eq!(payload[14..18], LAN_ADDR.octets());
+        assert_eq!(payload[24..28], target.octets());
+    }
+
+    #[test]
+    fn try_build_link_requires_arp_cache() {
+        let result = PendingArpQueue::new()
+            .ingressor(immediate_stream(vec![]))
+            .interfaces(interfaces())
+            .try_build_link();
+        assert_eq!(result.err(), Some(LinkBuildError::Missing("arp_cache")));
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_zero_capacity() {
+        PendingArpQueue::new().capacity(0);
+    }
+
+    #[test]
+    fn passes_resolved_frames() {
+        let arp_cache = ArpCache::new();
+        arp_cache.insert(GATEWAY, GATEWAY_MAC);
+        let frames = vec![(
+            NextHop::via(GATEWAY, WAN),
+            frame(Ipv4Addr::new(8, 8, 8, 8), 1),
+        )];
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = queue(&arp_cache, &ManualClock::new())
+                .ingressor(immediate_stream(frames))
+                .build_link();
+            run_link(link).await
+        });
+
+        assert_eq!(results[0].len(), 1);
+        let (interface, frame) = &results[0][0];
+        assert_eq!(*interface, WAN);
+        assert_eq!(frame.src_mac(), WAN_MAC);
+        assert_eq!(frame.dest_mac(), GATEWAY_MAC);
+    }
+
+    #[test]
+    fn releases_queued_frame_on_arp_reply() {
+        let arp_cache = ArpCache::new();
+        let clock = ManualClock::new();
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded();
+            let (_, mut egressors) = queue(&arp_cache, &clock)
+                .ingressor(Box::new(receiver))
+                .build_link();
+            let mut egressor = egressors.remove(0);
+
+            sender
+                .unbounded_send((NextHop::connected(LAN), frame(HOST, 1)))
+                .unwrap();
+            assert_arp_request(poll_once(&mut egressor).await, HOST);
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+            assert_eq!(arp_cache.pending(), vec![HOST]);
+
+            arp_cache.insert(HOST, HOST_MAC);
+            let (interface, released) = match poll_once(&mut egressor).await {
+                Poll::Ready(Some(output)) => output,
+                other => panic!("Expected the queued frame, got {:?}", other),
+            };
+            assert_eq!(interface, LAN);
+            assert_eq!(released.src_mac(), LAN_MAC);
+            assert_eq!(released.dest_mac(), HOST_MAC);
+            assert_eq!(released.payload(), frame(HOST, 1).payload());
+
+            drop(sender);
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(None));
+        });
+    }
+
+    #[test]
+    fn keeps_latest_frames_up_to_capacity() {
+        let arp_cache = ArpCache::new();
+        let clock = ManualClock::new();
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded();
+            let (_, mut egressors) = queue(&arp_cache, &clock)
+                .capacity(2)
+                .ingressor(Box::new(receiver))
+                .build_link();
+            let mut egressor = egressors.remove(0);
+
+            for id in 1..=3 {
+                sender
+                    .unbounded_send((NextHop::connected(LAN), frame(HOST, id)))
+                    .unwrap();
+            }
+            assert_arp_request(poll_once(&mut egressor).await, HOST);
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+
+            arp_cache.insert(HOST, HOST_MAC);
+            for id in 2..=3 {
+                match poll_once(&mut egressor).await {
+                    Poll::Ready(Some((_, released))) => {
+                        assert_eq!(released.payload(), frame(HOST, id).payload())
+                    }
+                    other => panic!("Expected frame {}, got {:?}", id, other),
+                }
+            }
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+        });
+    }
+
+    #[test]
+    fn retries_then_drops_after_timeout() {
+        let arp_cache = ArpCache::new();
+        let clock = ManualClock::new();
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded();
+            let (_, mut egressors) = queue(&arp_cache, &clock)
+                .ingressor(Box::new(receiver))
+                .build_link();
+            let mut egressor = egressors.remove(0);
+
+            sender
+                .unbounded_send((NextHop::connected(LAN), frame(HOST, 1)))
+                .unwrap();
+            assert_arp_request(poll_once(&mut egressor).await, HOST);
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+
+            clock.advance(Duration::from_secs(1));
+            assert_arp_request(poll_once(&mut egressor).await, HOST);
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+
+            clock.advance(Duration::from_secs(2));
+            drop(sender);
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(None));
+            assert!(arp_cache.pending().is_empty());
+
+            // A reply after the timeout finds nothing left to release.
+            arp_cache.insert(HOST, HOST_MAC);
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(None));
+        });
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_zero_max_next_hops() {
+        PendingArpQueue::new().max_next_hops(0);
+    }
+
+    #[test]
+    fn drops_frames_beyond_max_next_hops() {
+        let other_host = Ipv4Addr::new(192, 168, 1, 11);
+        let arp_cache = ArpCache::new();
+        let clock = ManualClock::new();
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded();
+            let (_, mut egressors) = queue(&arp_cache, &clock)
+                .max_next_hops(1)
+                .ingressor(Box::new(receiver))
+                .build_link();
+            let mut egressor = egressors.remove(0);
+
+            sender
+                .unbounded_send((NextHop::connected(LAN), frame(HOST, 1)))
+                .unwrap();
+            sender
+                .unbounded_send((NextHop::connected(LAN), frame(other_host, 2)))
+                .unwrap();
+            assert_arp_request(poll_once(&mut egressor).await, HOST);
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+            assert_eq!(arp_cache.pending(), vec![HOST]);
+
+            // Once the first next hop expires, there is room for another.
+            clock.advance(Duration::from_secs(3));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+            sender
+                .unbounded_send((NextHop::connected(LAN), frame(other_host, 3)))
+                .unwrap();
+            assert_arp_request(poll_once(&mut egressor).await, other_host);
+        });
+    }
+}
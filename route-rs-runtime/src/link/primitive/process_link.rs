@@ -1,7 +1,8 @@
-use crate::link::{Link, LinkBuilder, PacketStream, ProcessLinkBuilder};
-use crate::processor::Processor;
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream, ProcessLinkBuilder};
+use crate::processor::{Chained, Processor};
 use futures::prelude::*;
 use futures::task::{Context, Poll};
+use log::trace;
 use std::pin::Pin;
 
 /// `ProcessLink` processes packets through a user-defined processor.
@@ -20,6 +21,29 @@ impl<P: Processor> ProcessLink<P> {
             processor: None,
         }
     }
+
+    /// Fuses this link with `next`, a `ProcessLink` that would otherwise take this link's
+    /// egressor, into one link that runs both processors back to back, as `Processor::and_then`
+    /// does. Each packet is then polled through one egressor rather than two. The fused link keeps
+    /// the ingressor of this link; `next` must not have one.
+    pub fn fuse<Q>(self, next: ProcessLink<Q>) -> ProcessLink<Chained<P, Q>>
+    where
+        Q: Processor<Input = P::Output>,
+    {
+        if next.in_stream.is_some() {
+            panic!("ProcessLink fused onto another may not take an input stream")
+        }
+
+        let processor = match (self.processor, next.processor) {
+            (Some(first), Some(second)) => Some(first.and_then(second)),
+            _ => None,
+        };
+
+        ProcessLink {
+            in_stream: self.in_stream,
+            processor,
+        }
+    }
 }
 
 /// Although `Link` allows an arbitrary number of ingressors and egressors, `ProcessLink`
@@ -39,7 +63,7 @@ impl<P: Processor + Send + 'static> LinkBuilder<P::Input, P::Output> for Process
 
         ProcessLink {
             in_stream: Some(in_streams.remove(0)),
-            processor: self.processor,
+            ..self
         }
     }
 
@@ -50,18 +74,22 @@ impl<P: Processor + Send + 'static> LinkBuilder<P::Input, P::Output> for Process
 
         ProcessLink {
             in_stream: Some(in_stream),
-            processor: self.processor,
+            ..self
         }
     }
 
-    fn build_link(self) -> Link<P::Output> {
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<P::Output>, LinkBuildError> {
         if self.in_stream.is_none() {
-            panic!("Cannot build link! Missing input streams");
+            Err(LinkBuildError::MissingIngressors)
         } else if self.processor.is_none() {
-            panic!("Cannot build link! Missing processor");
+            Err(LinkBuildError::Missing("processor"))
         } else {
             let processor = ProcessRunner::new(self.in_stream.unwrap(), self.processor.unwrap());
-            (vec![], vec![Box::new(processor)])
+            Ok((vec![], vec![Box::new(processor)]))
         }
     }
 }
@@ -69,8 +97,8 @@ impl<P: Processor + Send + 'static> LinkBuilder<P::Input, P::Output> for Process
 impl<P: Processor + Send + 'static> ProcessLinkBuilder<P> for ProcessLink<P> {
     fn processor(self, processor: P) -> Self {
         ProcessLink {
-            in_stream: self.in_stream,
             processor: Some(processor),
+            ..self
         }
     }
 }
@@ -122,6 +150,7 @@ impl<P: Processor> Stream for ProcessRunner<P> {
                     if let Some(output_packet) = self.processor.process(input_packet) {
                         return Poll::Ready(Some(output_packet));
                     }
+                    trace!("processor dropped packet");
                 }
             }
         }
@@ -132,7 +161,10 @@ impl<P: Processor> Stream for ProcessRunner<P> {
 mod tests {
     use super::*;
     use crate::processor::{Drop, Identity, TransformFrom};
-    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::harness::{
+        initialize_runtime, initialize_runtime_deterministic, run_link, run_link_with_timeout,
+    };
+    use crate::utils::test::logger::capture_logs;
     use crate::utils::test::packet_generators::{immediate_stream, PacketIntervalGenerator};
     use core::time;
 
@@ -169,6 +201,30 @@ mod tests {
             .build_link();
     }
 
+    #[test]
+    fn try_ingressors_refuses_more_than_one_input_stream() {
+        let result = ProcessLink::<Identity<i32>>::new()
+            .try_ingressors(vec![immediate_stream(vec![]), immediate_stream(vec![])]);
+        assert_eq!(
+            result.err(),
+            Some(LinkBuildError::TooManyIngressors {
+                ingressors: 2,
+                capacity: 1,
+            })
+        );
+
+        let result = ProcessLink::<Identity<i32>>::new()
+            .ingressor(immediate_stream(vec![]))
+            .try_ingressors(vec![immediate_stream(vec![])]);
+        assert_eq!(
+            result.err(),
+            Some(LinkBuildError::TooManyIngressors {
+                ingressors: 1,
+                capacity: 0,
+            })
+        );
+    }
+
     #[test]
     fn identity() {
         let packets = vec![0, 1, 2, 420, 1337, 3, 4, 5, 6, 7, 8, 9];
@@ -206,6 +262,37 @@ mod tests {
         assert_eq!(results[0], packets);
     }
 
+    #[test]
+    fn completes_within_timeout() {
+        let packets = vec![0, 1, 2, 420, 1337, 3, 4, 5, 6, 7, 8, 9];
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = ProcessLink::new()
+                .ingressor(immediate_stream(packets.clone()))
+                .processor(Identity::new())
+                .build_link();
+
+            run_link_with_timeout(link, time::Duration::from_secs(10)).await
+        });
+        assert_eq!(results[0], packets);
+    }
+
+    #[test]
+    #[should_panic(expected = "did not complete within")]
+    fn stalled_link_times_out() {
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let stalled: PacketStream<i32> = Box::new(stream::pending());
+            let link = ProcessLink::new()
+                .ingressor(stalled)
+                .processor(Identity::new())
+                .build_link();
+
+            run_link_with_timeout(link, time::Duration::from_millis(50)).await
+        });
+    }
+
     #[test]
     fn type_transform() {
         let packets = "route-rs".chars();
@@ -240,4 +327,64 @@ mod tests {
         });
         assert_eq!(results[0], []);
     }
+
+    #[test]
+    fn traces_dropped_packets() {
+        let (results, records) = capture_logs(|| {
+            let mut runtime = initialize_runtime_deterministic();
+            runtime.block_on(async {
+                let link = ProcessLink::new()
+                    .ingressor(immediate_stream(vec![0, 1]))
+                    .processor(Drop::new())
+                    .build_link();
+                run_link(link).await
+            })
+        });
+
+        assert_eq!(results[0], []);
+        let dropped = records
+            .iter()
+            .filter(|record| record.target == module_path!().trim_end_matches("::tests"))
+            .filter(|record| record.message == "processor dropped packet")
+            .count();
+        assert_eq!(dropped, 2);
+    }
+
+    #[test]
+    fn fused_links_match_unfused() {
+        let packets: Vec<u32> = vec![0, 1, 2, 420, 1337, 3, 4];
+
+        let mut runtime = initialize_runtime();
+        let (unfused, fused) = runtime.block_on(async {
+            let (_, mut egressors) = ProcessLink::new()
+                .ingressor(immediate_stream(packets.clone()))
+                .processor(Identity::new())
+                .build_link();
+            let unfused = ProcessLink::new()
+                .ingressor(egressors.remove(0))
+                .processor(TransformFrom::<u32, u64>::new())
+                .build_link();
+
+            let fused = ProcessLink::new()
+                .ingressor(immediate_stream(packets.clone()))
+                .processor(Identity::new())
+                .fuse(ProcessLink::new().processor(TransformFrom::<u32, u64>::new()))
+                .build_link();
+
+            (run_link(unfused).await, run_link(fused).await)
+        });
+
+        assert_eq!(fused, unfused);
+        assert_eq!(fused[0], vec![0, 1, 2, 420, 1337, 3, 4]);
+    }
+
+    #[test]
+    #[should_panic(expected = "ProcessLink fused onto another may not take an input stream")]
+    fn panics_when_fused_link_has_input_stream() {
+        ProcessLink::new().processor(Identity::<i32>::new()).fuse(
+            ProcessLink::new()
+                .ingressor(immediate_stream(vec![]))
+                .processor(Identity::new()),
+        );
+    }
 }
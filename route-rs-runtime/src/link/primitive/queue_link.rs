@@ -1,11 +1,14 @@
+use crate::link::utils::queue_depth::QueueDepths;
 use crate::link::utils::task_park::*;
-use crate::link::{Link, LinkBuilder, PacketStream, ProcessLinkBuilder};
-use crate::processor::Processor;
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream, ProcessLinkBuilder};
+use crate::link_builder;
+use crate::processor::{Chained, Processor};
 use crossbeam::atomic::AtomicCell;
 use crossbeam::crossbeam_channel;
 use crossbeam::crossbeam_channel::{Receiver, Sender, TryRecvError};
 use futures::prelude::*;
 use futures::task::{Context, Poll};
+use log::trace;
 use std::pin::Pin;
 use std::sync::Arc;
 
@@ -16,6 +19,7 @@ pub struct QueueLink<P: Processor> {
     in_stream: Option<PacketStream<P::Input>>,
     processor: Option<P>,
     queue_capacity: usize,
+    queue_depths: QueueDepths,
 }
 
 impl<P: Processor> QueueLink<P> {
@@ -24,23 +28,48 @@ impl<P: Processor> QueueLink<P> {
             in_stream: None,
             processor: None,
             queue_capacity: 10,
+            queue_depths: QueueDepths::new(),
         }
     }
 
-    /// Changes queue_capacity, default value is 10.
-    pub fn queue_capacity(self, queue_capacity: usize) -> Self {
-        assert!(
-            queue_capacity > 0,
-            format!("QueueLink queue capacity: {} must be > 0", queue_capacity)
-        );
-        assert_ne!(queue_capacity, 0, "queue capacity must be non-zero");
+    /// Returns a handle to the depths of the internal queues of this link, which remains valid
+    /// after the link is built.
+    pub fn queue_depths(&self) -> QueueDepths {
+        self.queue_depths.clone()
+    }
+
+    /// Fuses this link with `next`, a `QueueLink` that would otherwise take this link's egressor,
+    /// into one link that runs both processors back to back, as `Processor::and_then` does. The
+    /// fused link spawns one task and fills one queue where the pair would spawn two tasks and
+    /// hand each packet across two queues, so chains of simple processors should be fused.
+    ///
+    /// The fused link keeps the ingressor, queue_capacity and queue_depths of this link. `next`
+    /// must not have an ingressor, since its input is this link's processor.
+    pub fn fuse<Q>(self, next: QueueLink<Q>) -> QueueLink<Chained<P, Q>>
+    where
+        Q: Processor<Input = P::Output>,
+    {
+        if next.in_stream.is_some() {
+            panic!("QueueLink fused onto another may not take an input stream")
+        }
+
+        let processor = match (self.processor, next.processor) {
+            (Some(first), Some(second)) => Some(first.and_then(second)),
+            _ => None,
+        };
 
         QueueLink {
             in_stream: self.in_stream,
-            processor: self.processor,
-            queue_capacity,
+            processor,
+            queue_capacity: self.queue_capacity,
+            queue_depths: self.queue_depths,
         }
     }
+
+    link_builder! {
+        /// Changes queue_capacity, default value is 10.
+        queue_capacity: usize where queue_capacity > 0,
+    }
 }
 
 impl<P: Processor + Send + 'static> LinkBuilder<P::Input, P::Output> for QueueLink<P> {
@@ -57,8 +86,7 @@ impl<P: Processor + Send + 'static> LinkBuilder<P::Input, P::Output> for QueueLi
 
         QueueLink {
             in_stream: Some(in_streams.remove(0)),
-            processor: self.processor,
-            queue_capacity: self.queue_capacity,
+            ..self
         }
     }
 
@@ -69,19 +97,23 @@ impl<P: Processor + Send + 'static> LinkBuilder<P::Input, P::Output> for QueueLi
 
         QueueLink {
             in_stream: Some(in_stream),
-            processor: self.processor,
-            queue_capacity: self.queue_capacity,
+            ..self
         }
     }
 
-    fn build_link(self) -> Link<P::Output> {
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<P::Output>, LinkBuildError> {
         if self.in_stream.is_none() {
-            panic!("Cannot build link! Missing input stream");
+            Err(LinkBuildError::MissingIngressors)
         } else if self.processor.is_none() {
-            panic!("Cannot build link! Missing processor");
+            Err(LinkBuildError::Missing("processor"))
         } else {
             let (to_egressor, from_ingressor) =
                 crossbeam_channel::bounded::<Option<P::Output>>(self.queue_capacity);
+            self.queue_depths.watch(&from_ingressor);
             let task_park: Arc<AtomicCell<TaskParkState>> =
                 Arc::new(AtomicCell::new(TaskParkState::Empty));
 
@@ -93,7 +125,7 @@ impl<P: Processor + Send + 'static> LinkBuilder<P::Input, P::Output> for QueueLi
             );
             let egressor = QueueEgressor::new(from_ingressor, task_park);
 
-            (vec![Box::new(ingresssor)], vec![Box::new(egressor)])
+            Ok((vec![Box::new(ingresssor)], vec![Box::new(egressor)]))
         }
     }
 }
@@ -101,9 +133,8 @@ impl<P: Processor + Send + 'static> LinkBuilder<P::Input, P::Output> for QueueLi
 impl<P: Processor + Send + 'static> ProcessLinkBuilder<P> for QueueLink<P> {
     fn processor(self, processor: P) -> Self {
         QueueLink {
-            in_stream: self.in_stream,
             processor: Some(processor),
-            queue_capacity: self.queue_capacity,
+            ..self
         }
     }
 }
@@ -169,6 +200,7 @@ impl<P: Processor> Future for QueueIngressor<P> {
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
         loop {
             if self.to_egressor.is_full() {
+                trace!("queue full, waiting for egressor to drain it");
                 park_and_wake(&self.task_park, cx.waker().clone());
                 return Poll::Pending;
             }
@@ -189,6 +221,8 @@ impl<P: Processor> Future for QueueIngressor<P> {
                             .try_send(Some(output_packet))
                             .expect("QueueIngressor::Poll::Ready(Some(val)) try_send to_egressor shouldn't fail");
                         unpark_and_wake(&self.task_park);
+                    } else {
+                        trace!("processor dropped packet");
                     }
                 }
             }
@@ -265,7 +299,10 @@ mod tests {
     use crate::link::primitive::ProcessLink;
     use crate::link::{LinkBuilder, ProcessLinkBuilder};
     use crate::processor::{Drop, Identity, TransformFrom};
-    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::harness::{
+        initialize_runtime, initialize_runtime_deterministic, run_link,
+    };
+    use crate::utils::test::logger::capture_logs;
     use crate::utils::test::packet_generators::{immediate_stream, PacketIntervalGenerator};
     use core::time;
     use rand::{thread_rng, Rng};
@@ -494,4 +531,132 @@ mod tests {
         });
         assert_eq!(results[0], [])
     }
+
+    #[test]
+    fn traces_full_queue_and_dropped_packets() {
+        let (results, records) = capture_logs(|| {
+            let mut runtime = initialize_runtime_deterministic();
+            runtime.block_on(async {
+                let link = QueueLink::new()
+                    .ingressor(immediate_stream(vec![0, 1, 2]))
+                    .processor(Identity::new())
+                    .queue_capacity(1)
+                    .build_link();
+                let full = run_link(link).await;
+
+                let link = QueueLink::new()
+                    .ingressor(immediate_stream(vec![0]))
+                    .processor(Drop::new())
+                    .build_link();
+                run_link(link).await;
+                full
+            })
+        });
+
+        assert_eq!(results[0], vec![0, 1, 2]);
+        let messages: Vec<&str> = records
+            .iter()
+            .filter(|record| record.target == module_path!().trim_end_matches("::tests"))
+            .map(|record| record.message.as_str())
+            .collect();
+        assert!(messages.contains(&"queue full, waiting for egressor to drain it"));
+        assert_eq!(messages.last(), Some(&"processor dropped packet"));
+    }
+
+    /// Drops odd packets.
+    struct KeepEven;
+
+    impl Processor for KeepEven {
+        type Input = u64;
+        type Output = u64;
+
+        fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+            if packet % 2 == 0 {
+                Some(packet)
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn fused_links_match_unfused_with_fewer_tasks() {
+        let packets: Vec<u32> = (0..1000).collect();
+
+        let mut runtime = initialize_runtime();
+        let (unfused, unfused_tasks, fused, fused_tasks) = runtime.block_on(async {
+            let (mut runnables, mut egressors) = QueueLink::new()
+                .ingressor(immediate_stream(packets.clone()))
+                .processor(Identity::new())
+                .build_link();
+            let (mut runnables1, mut egressors) = QueueLink::new()
+                .ingressor(egressors.remove(0))
+                .processor(TransformFrom::<u32, u64>::new())
+                .build_link();
+            let (mut runnables2, egressors) = QueueLink::new()
+                .ingressor(egressors.remove(0))
+                .processor(KeepEven)
+                .build_link();
+            runnables.append(&mut runnables1);
+            runnables.append(&mut runnables2);
+            let unfused_tasks = runnables.len();
+
+            let (fused_runnables, fused_egressors) = QueueLink::new()
+                .ingressor(immediate_stream(packets.clone()))
+                .processor(Identity::new())
+                .fuse(QueueLink::new().processor(TransformFrom::<u32, u64>::new()))
+                .fuse(QueueLink::new().processor(KeepEven))
+                .build_link();
+            let fused_tasks = fused_runnables.len();
+
+            (
+                run_link((runnables, egressors)).await,
+                unfused_tasks,
+                run_link((fused_runnables, fused_egressors)).await,
+                fused_tasks,
+            )
+        });
+
+        assert_eq!(fused, unfused);
+        assert_eq!(fused[0], (0..1000).step_by(2).collect::<Vec<u64>>());
+        assert_eq!(unfused_tasks, 3);
+        assert_eq!(fused_tasks, 1);
+    }
+
+    #[test]
+    fn fused_link_keeps_first_queue_capacity() {
+        let link = QueueLink::new()
+            .processor(Identity::<u32>::new())
+            .queue_capacity(3)
+            .fuse(
+                QueueLink::new()
+                    .processor(Identity::new())
+                    .queue_capacity(7),
+            );
+
+        assert_eq!(link.queue_capacity, 3);
+    }
+
+    #[test]
+    fn fused_link_without_processor_fails_to_build() {
+        let result = QueueLink::<Identity<u32>>::new()
+            .ingressor(immediate_stream(vec![]))
+            .fuse(QueueLink::new().processor(Identity::new()))
+            .try_build_link();
+
+        assert_eq!(
+            result.err().map(|err| err.to_string()),
+            Some("Missing processor".to_string())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "QueueLink fused onto another may not take an input stream")]
+    fn panics_when_fused_link_has_input_stream() {
+        QueueLink::new().processor(Identity::<i32>::new()).fuse(
+            QueueLink::new()
+                .ingressor(immediate_stream(vec![]))
+                .processor(Identity::new()),
+        );
+    }
 }
@@ -0,0 +1,431 @@
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream};
+use crate::link_builder;
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+use route_rs_packets::{
+    IpProtocol, Ipv6Packet, MacAddr, ICMPV6_ROUTER_ADVERTISEMENT, ICMPV6_ROUTER_SOLICITATION,
+};
+use std::net::Ipv6Addr;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::time::{delay_for, Delay};
+
+const OPTION_SOURCE_LINK_LAYER_ADDR: u8 = 1;
+const OPTION_PREFIX_INFORMATION: u8 = 3;
+const OPTION_MTU: u8 = 5;
+
+const FLAG_MANAGED: u8 = 0x80;
+const FLAG_OTHER_CONFIG: u8 = 0x40;
+/// Prefix flags: the prefix is on-link, and may be used for autonomous address configuration.
+const FLAG_ON_LINK: u8 = 0x80;
+const FLAG_AUTONOMOUS: u8 = 0x40;
+
+/// The longest router lifetime an advertisement may carry (RFC 4861 section 6.2.1).
+const MAX_ROUTER_LIFETIME: Duration = Duration::from_secs(9000);
+
+fn seconds(duration: Duration) -> [u8; 4] {
+    (duration.as_secs().min(u64::from(u32::MAX)) as u32).to_be_bytes()
+}
+
+/// `RaSender` sends IPv6 Router Advertisements (RFC 4861) on a LAN, advertising a prefix that
+/// hosts can configure addresses from with SLAAC (RFC 4862), and the router as their default
+/// router. An advertisement is sent when the link starts, then every `interval`, and right away in
+/// answer to every valid Router Solicitation on its ingressor, so that a host that has just come
+/// up need not wait for the next one. Every other packet on the ingressor is ignored, so it may be
+/// fed all ICMPv6 traffic from the LAN.
+///
+/// Advertisements are sent from `src_addr`, which must be the link-local address of the LAN
+/// interface, to all nodes. For SLAAC to work, the prefix must be a /64.
+pub struct RaSender {
+    in_stream: Option<PacketStream<Ipv6Packet>>,
+    src_addr: Option<Ipv6Addr>,
+    prefix: Option<(Ipv6Addr, u8)>,
+    mac: Option<MacAddr>,
+    mtu: Option<u32>,
+    interval: Duration,
+    router_lifetime: Duration,
+    valid_lifetime: Duration,
+    preferred_lifetime: Duration,
+    managed: bool,
+    other_config: bool,
+}
+
+impl Default for RaSender {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RaSender {
+    pub fn new() -> Self {
+        RaSender {
+            in_stream: None,
+            src_addr: None,
+            prefix: None,
+            mac: None,
+            mtu: None,
+            interval: Duration::from_secs(200),
+            router_lifetime: Duration::from_secs(1800),
+            valid_lifetime: Duration::from_secs(30 * 24 * 60 * 60),
+            preferred_lifetime: Duration::from_secs(7 * 24 * 60 * 60),
+            managed: false,
+            other_config: false,
+        }
+    }
+
+    link_builder! {
+        /// Changes how long hosts may use the router as a default router, default value is 1800
+        /// seconds. Must not exceed 9000 seconds; zero tells hosts the router is not a default
+        /// router.
+        router_lifetime: Duration where router_lifetime <= MAX_ROUTER_LIFETIME,
+        /// Sets the link-local address of the LAN interface, which advertisements are sent from.
+        src_addr: Option<Ipv6Addr>,
+        /// Sets the MAC address of the LAN interface, to include in advertisements so that hosts
+        /// need not resolve it.
+        mac: Option<MacAddr>,
+        /// Sets the MTU of the LAN, to include in advertisements.
+        mtu: Option<u32>,
+        /// Changes how often unsolicited advertisements are sent, default value is 200 seconds.
+        interval: Duration,
+        /// Sets the managed address configuration flag, telling hosts to get addresses from
+        /// DHCPv6, default value is false.
+        managed: bool,
+        /// Sets the other configuration flag, telling hosts to get other settings, such as DNS
+        /// servers, from DHCPv6, default value is false.
+        other_config: bool,
+    }
+
+    /// Sets the delegated prefix to advertise, as an address and a prefix length.
+    pub fn prefix(self, prefix: Ipv6Addr, prefix_len: u8) -> Self {
+        assert!(
+            prefix_len <= 128,
+            "prefix_len: {}, must be <= 128",
+            prefix_len
+        );
+        RaSender {
+            prefix: Some((prefix, prefix_len)),
+            ..self
+        }
+    }
+
+    /// Changes how long the prefix stays valid, and how long addresses configured from it stay
+    /// preferred for new connections, default values are 30 days and 7 days. The preferred
+    /// lifetime must not exceed the valid lifetime.
+    pub fn prefix_lifetimes(self, valid_lifetime: Duration, preferred_lifetime: Duration) -> Self {
+        assert!(
+            preferred_lifetime <= valid_lifetime,
+            "preferred_lifetime: {:?}, must be <= valid_lifetime: {:?}",
+            preferred_lifetime,
+            valid_lifetime
+        );
+        RaSender {
+            valid_lifetime,
+            preferred_lifetime,
+            ..self
+        }
+    }
+
+    fn advertisement(
+        &self,
+        src_addr: Ipv6Addr,
+        (prefix, prefix_len): (Ipv6Addr, u8),
+    ) -> Ipv6Packet {
+        let mut flags = 0;
+        if self.managed {
+            flags |= FLAG_MANAGED;
+        }
+        if self.other_config {
+            flags |= FLAG_OTHER_CONFIG;
+        }
+        let router_lifetime = (self.router_lifetime.as_secs() as u16).to_be_bytes();
+
+        // Current hop limit, flags and router lifetime, then reachable time and retransmit timer,
+        // which are left for hosts to choose.
+        let mut body = vec![64, flags, router_lifetime[0], router_lifetime[1]];
+        body.extend_from_slice(&[0; 8]);
+
+        if let Some(mac) = self.mac {
+            body.extend_from_slice(&[OPTION_SOURCE_LINK_LAYER_ADDR, 1]);
+            body.extend_from_slice(&mac.bytes);
+        }
+        if let Some(mtu) = self.mtu {
+            body.extend_from_slice(&[OPTION_MTU, 1, 0, 0]);
+            body.extend_from_slice(&mtu.to_be_bytes());
+        }
+        body.extend_from_slice(&[
+            OPTION_PREFIX_INFORMATION,
+            4,
+            prefix_len,
+            FLAG_ON_LINK | FLAG_AUTONOMOUS,
+        ]);
+        body.extend_from_slice(&seconds(self.valid_lifetime));
+        body.extend_from_slice(&seconds(self.preferred_lifetime));
+        body.extend_from_slice(&[0; 4]);
+        body.extend_from_slice(&mask(prefix, prefix_len).octets());
+
+        let all_nodes = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+        Ipv6Packet::icmpv6(src_addr, all_nodes, ICMPV6_ROUTER_ADVERTISEMENT, 0, &body)
+    }
+}
+
+/// `addr` with every bit past the first `prefix_len` cleared.
+fn mask(addr: Ipv6Addr, prefix_len: u8) -> Ipv6Addr {
+    let bits = u128::from(addr);
+    let mask = u128::MAX
+        .checked_shl(128 - u32::from(prefix_len))
+        .unwrap_or(0);
+    Ipv6Addr::from(bits & mask)
+}
+
+/// Whether `packet` is a Router Solicitation that passes the validation of RFC 4861 section 6.1.1.
+fn is_router_solicitation(packet: &Ipv6Packet) -> bool {
+    if packet.next_header() != IpProtocol::IPv6_ICMP
+        || packet.hop_limit() != 255
+        || !packet.validate_icmpv6_checksum()
+    {
+        return false;
+    }
+    let message = packet.payload();
+    message.len() >= 8 && message[0] == ICMPV6_ROUTER_SOLICITATION && message[1] == 0
+}
+
+impl LinkBuilder<Ipv6Packet, Ipv6Packet> for RaSender {
+    fn ingressors(self, mut in_streams: Vec<PacketStream<Ipv6Packet>>) -> Self {
+        assert_eq!(in_streams.len(), 1, "RaSender may only take 1 input stream");
+        self.ingressor(in_streams.remove(0))
+    }
+
+    fn ingressor(self, in_stream: PacketStream<Ipv6Packet>) -> Self {
+        if self.in_stream.is_some() {
+            panic!("RaSender may only take 1 input stream");
+        }
+        RaSender {
+            in_stream: Some(in_stream),
+            ..self
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<Ipv6Packet>, LinkBuildError> {
+        let src_addr = self.src_addr.ok_or(LinkBuildError::Missing("src_addr"))?;
+        let prefix = self.prefix.ok_or(LinkBuildError::Missing("prefix"))?;
+        let advertisement = self.advertisement(src_addr, prefix);
+        let in_stream = self.in_stream.ok_or(LinkBuildError::MissingIngressors)?;
+
+        Ok((
+            vec![],
+            vec![Box::new(RaEgressor {
+                in_stream,
+                advertisement,
+                interval: self.interval,
+                timer: None,
+            })],
+        ))
+    }
+}
+
+/// The single egressor of RaSender
+struct RaEgressor {
+    in_stream: PacketStream<Ipv6Packet>,
+    advertisement: Ipv6Packet,
+    interval: Duration,
+    /// Counts down to the next unsolicited advertisement, or `None` before the first one is sent.
+    timer: Option<Delay>,
+}
+
+impl Unpin for RaEgressor {}
+
+impl Stream for RaEgressor {
+    type Item = Ipv6Packet;
+
+    /// Sends the first advertisement right away. After that, answers solicitations from the input
+    /// stream as they come, and sends another advertisement whenever the timer fires. The stream
+    /// ends when the input stream does.
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let egressor = &mut *self;
+        if egressor.timer.is_none() {
+            egressor.timer = Some(delay_for(egressor.interval));
+            return Poll::Ready(Some(egressor.advertisement.clone()));
+        }
+
+        loop {
+            match Pin::new(&mut egressor.in_stream).poll_next(cx) {
+                Poll::Ready(Some(packet)) => {
+                    if is_router_solicitation(&packet) {
+                        return Poll::Ready(Some(egressor.advertisement.clone()));
+                    }
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => {
+                    let timer = egressor.timer.as_mut().unwrap();
+                    ready!(Pin::new(timer).poll(cx));
+                    egressor.timer = Some(delay_for(egressor.interval));
+                    return Poll::Ready(Some(egressor.advertisement.clone()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::{immediate_stream, PacketIntervalGenerator};
+
+    const ROUTER_MAC: MacAddr = MacAddr {
+        bytes: [0x02, 0, 0, 0, 0, 0x01],
+    };
+
+    fn addr(s: &str) -> Ipv6Addr {
+        s.parse().unwrap()
+    }
+
+    fn ra_sender() -> RaSender {
+        RaSender::new()
+            .src_addr(addr("fe80::1"))
+            .prefix(addr("2001:db8:0:1::"), 64)
+    }
+
+    fn router_solicitation() -> Ipv6Packet {
+        Ipv6Packet::icmpv6(
+            addr("fe80::2"),
+            addr("ff02::2"),
+            ICMPV6_ROUTER_SOLICITATION,
+            0,
+            &[0; 4],
+        )
+    }
+
+    /// The options of `advertisement`, by type.
+    fn options(advertisement: &Ipv6Packet) -> Vec<(u8, Vec<u8>)> {
+        let message = advertisement.payload();
+        let mut options = vec![];
+        let mut rest = &message[16..];
+        while !rest.is_empty() {
+            let len = usize::from(rest[1]) * 8;
+            options.push((rest[0], rest[2..len].to_vec()));
+            rest = &rest[len..];
+        }
+        options
+    }
+
+    #[test]
+    fn try_build_link_requires_prefix() {
+        let result = RaSender::new()
+            .ingressor(immediate_stream(vec![]))
+            .src_addr(addr("fe80::1"))
+            .try_build_link();
+        assert_eq!(result.err(), Some(LinkBuildError::Missing("prefix")));
+    }
+
+    #[test]
+    fn answers_router_solicitation() {
+        let link = ra_sender()
+            .ingressor(immediate_stream(vec![router_solicitation()]))
+            .build_link();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(run_link(link));
+
+        // One advertisement on startup, and one in answer to the solicitation.
+        assert_eq!(results[0].len(), 2);
+        let advertisement = &results[0][1];
+        assert_eq!(advertisement.src_addr(), addr("fe80::1"));
+        assert_eq!(advertisement.dest_addr(), addr("ff02::1"));
+        assert_eq!(advertisement.hop_limit(), 255);
+        assert!(advertisement.validate_icmpv6_checksum());
+
+        let message = advertisement.payload();
+        assert_eq!(message[0], ICMPV6_ROUTER_ADVERTISEMENT);
+        assert_eq!(message[1], 0);
+        assert_eq!(message[5], 0);
+        assert_eq!(message[6..8], 1800u16.to_be_bytes());
+
+        let options = options(advertisement);
+        assert_eq!(options.len(), 1);
+        let (option_type, prefix) = &options[0];
+        assert_eq!(*option_type, OPTION_PREFIX_INFORMATION);
+        assert_eq!(prefix[0], 64);
+        assert_eq!(prefix[1], FLAG_ON_LINK | FLAG_AUTONOMOUS);
+        assert_eq!(prefix[2..6], 2_592_000u32.to_be_bytes());
+        assert_eq!(prefix[6..10], 604_800u32.to_be_bytes());
+        assert_eq!(prefix[14..30], addr("2001:db8:0:1::").octets());
+    }
+
+    #[test]
+    fn honors_configuration() {
+        let link = ra_sender()
+            .prefix(addr("2001:db8:0:2::ff"), 64)
+            .mac(ROUTER_MAC)
+            .mtu(1480)
+            .managed(true)
+            .other_config(true)
+            .router_lifetime(Duration::from_secs(600))
+            .prefix_lifetimes(Duration::from_secs(3600), Duration::from_secs(1800))
+            .ingressor(immediate_stream(vec![]))
+            .build_link();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(run_link(link));
+
+        let advertisement = &results[0][0];
+        let message = advertisement.payload();
+        assert_eq!(message[5], FLAG_MANAGED | FLAG_OTHER_CONFIG);
+        assert_eq!(message[6..8], 600u16.to_be_bytes());
+
+        let options = options(advertisement);
+        assert_eq!(
+            options[0],
+            (OPTION_SOURCE_LINK_LAYER_ADDR, ROUTER_MAC.bytes.to_vec())
+        );
+        assert_eq!(options[1].0, OPTION_MTU);
+        assert_eq!(options[1].1[2..6], 1480u32.to_be_bytes());
+        let prefix = &options[2].1;
+        assert_eq!(prefix[2..6], 3600u32.to_be_bytes());
+        assert_eq!(prefix[6..10], 1800u32.to_be_bytes());
+        assert_eq!(prefix[14..30], addr("2001:db8:0:2::").octets());
+    }
+
+    #[test]
+    fn ignores_invalid_solicitations() {
+        let mut forwarded = router_solicitation();
+        forwarded.set_hop_limit(64);
+        let mut corrupt = router_solicitation();
+        corrupt.data[corrupt.payload_offset + 4] ^= 0xFF;
+        let link = ra_sender()
+            .ingressor(immediate_stream(vec![
+                forwarded,
+                corrupt,
+                Ipv6Packet::empty(),
+            ]))
+            .build_link();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(run_link(link));
+
+        assert_eq!(results[0].len(), 1);
+    }
+
+    #[test]
+    fn advertises_periodically() {
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = ra_sender()
+                .interval(Duration::from_millis(20))
+                .ingressor(Box::new(PacketIntervalGenerator::new(
+                    Duration::from_millis(50),
+                    vec![Ipv6Packet::empty(); 4].into_iter(),
+                )))
+                .build_link();
+
+            run_link(link).await
+        });
+
+        // The input lasts about 200ms, long enough for several unsolicited advertisements.
+        assert!(results[0].len() >= 4, "sent {}", results[0].len());
+    }
+}
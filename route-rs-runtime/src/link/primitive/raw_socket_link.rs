@@ -0,0 +1,382 @@
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream};
+use futures::prelude::*;
+use futures::ready;
+use futures::task::{Context, Poll};
+use route_rs_packets::EthernetFrame;
+use std::io;
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+#[cfg(feature = "afpacket")]
+use afpacket::AsyncBoundSocket;
+
+/// Large enough for any frame a Linux interface will hand up, jumbo frames included.
+const MAX_FRAME_LEN: usize = 65535;
+
+#[cfg(feature = "afpacket")]
+fn bind(interface: &str) -> io::Result<AsyncBoundSocket> {
+    let name = std::ffi::CString::new(interface)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    AsyncBoundSocket::from_interface(name)
+}
+
+/// Feeds the frames received on a network interface into the pipeline. The socket can be anything
+/// that reads one frame at a time, such as an `AF_PACKET` socket bound to an interface; build with
+/// the `afpacket` feature to `bind` one by name.
+///
+/// Reads that are too short to be Ethernet frames are skipped. The egressor ends when the socket
+/// reaches end of file, or fails with an error other than an interrupted read.
+#[derive(Default)]
+pub struct RawSocketSource<R> {
+    socket: Option<R>,
+}
+
+impl<R> RawSocketSource<R> {
+    pub fn new() -> Self {
+        RawSocketSource { socket: None }
+    }
+
+    pub fn socket(self, socket: R) -> Self {
+        RawSocketSource {
+            socket: Some(socket),
+        }
+    }
+}
+
+#[cfg(feature = "afpacket")]
+impl RawSocketSource<AsyncBoundSocket> {
+    /// Receives every frame on `interface`, such as `eth0`, through a new `AF_PACKET` socket.
+    /// Must be called from within the Tokio runtime that will run the link.
+    pub fn bind(interface: &str) -> io::Result<Self> {
+        Ok(RawSocketSource::new().socket(bind(interface)?))
+    }
+}
+
+impl<R: AsyncRead + Send + Unpin + 'static> LinkBuilder<(), EthernetFrame> for RawSocketSource<R> {
+    fn ingressors(self, _in_streams: Vec<PacketStream<()>>) -> Self {
+        panic!("RawSocketSource does not take stream ingressors")
+    }
+
+    fn ingressor(self, _in_stream: PacketStream<()>) -> Self {
+        panic!("RawSocketSource does not take stream ingressors")
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(0)
+    }
+
+    fn try_build_link(self) -> Result<Link<EthernetFrame>, LinkBuildError> {
+        let socket = self.socket.ok_or(LinkBuildError::Missing("socket"))?;
+        Ok((
+            vec![],
+            vec![Box::new(SocketIngressor {
+                socket,
+                buffer: vec![0; MAX_FRAME_LEN],
+                finished: false,
+            })],
+        ))
+    }
+}
+
+struct SocketIngressor<R> {
+    socket: R,
+    buffer: Vec<u8>,
+    finished: bool,
+}
+
+impl<R: AsyncRead + Unpin> Stream for SocketIngressor<R> {
+    type Item = EthernetFrame;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let ingressor = &mut *self;
+        while !ingressor.finished {
+            let read = Pin::new(&mut ingressor.socket).poll_read(cx, &mut ingressor.buffer);
+            match ready!(read) {
+                Ok(0) => ingressor.finished = true,
+                Ok(len) => {
+                    if let Ok(frame) =
+                        EthernetFrame::from_buffer(ingressor.buffer[..len].to_vec(), 0)
+                    {
+                        return Poll::Ready(Some(frame));
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => ingressor.finished = true,
+            }
+        }
+        Poll::Ready(None)
+    }
+}
+
+/// Sends the frames of its ingressor out of a network interface. The socket can be anything that
+/// writes one frame at a time, such as an `AF_PACKET` socket bound to an interface; build with the
+/// `afpacket` feature to `bind` one by name.
+///
+/// A frame the socket fails to send, such as one longer than the MTU of the interface, is dropped,
+/// as a NIC would drop it.
+#[derive(Default)]
+pub struct RawSocketSink<W> {
+    in_stream: Option<PacketStream<EthernetFrame>>,
+    socket: Option<W>,
+}
+
+impl<W> RawSocketSink<W> {
+    pub fn new() -> Self {
+        RawSocketSink {
+            in_stream: None,
+            socket: None,
+        }
+    }
+
+    pub fn socket(self, socket: W) -> Self {
+        RawSocketSink {
+            socket: Some(socket),
+            ..self
+        }
+    }
+}
+
+#[cfg(feature = "afpacket")]
+impl RawSocketSink<AsyncBoundSocket> {
+    /// Sends frames out of `interface`, such as `eth0`, through a new `AF_PACKET` socket. Must be
+    /// called from within the Tokio runtime that will run the link.
+    pub fn bind(interface: &str) -> io::Result<Self> {
+        Ok(RawSocketSink::new().socket(bind(interface)?))
+    }
+}
+
+impl<W: AsyncWrite + Send + Unpin + 'static> LinkBuilder<EthernetFrame, ()> for RawSocketSink<W> {
+    fn ingressors(self, mut in_streams: Vec<PacketStream<EthernetFrame>>) -> Self {
+        assert_eq!(
+            in_streams.len(),
+            1,
+            "RawSocketSink may only take 1 input stream"
+        );
+        self.ingressor(in_streams.remove(0))
+    }
+
+    fn ingressor(self, in_stream: PacketStream<EthernetFrame>) -> Self {
+        if self.in_stream.is_some() {
+            panic!("RawSocketSink may only take 1 input stream");
+        }
+        RawSocketSink {
+            in_stream: Some(in_stream),
+            ..self
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<()>, LinkBuildError> {
+        let in_stream = self.in_stream.ok_or(LinkBuildError::MissingIngressors)?;
+        let socket = self.socket.ok_or(LinkBuildError::Missing("socket"))?;
+        Ok((
+            vec![Box::new(SocketEgressor {
+                in_stream,
+                socket,
+                pending: None,
+            })],
+            vec![],
+        ))
+    }
+}
+
+struct SocketEgressor<W> {
+    in_stream: PacketStream<EthernetFrame>,
+    socket: W,
+    /// A frame the socket was not ready to take.
+    pending: Option<EthernetFrame>,
+}
+
+impl<W: AsyncWrite + Unpin> Future for SocketEgressor<W> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let egressor = &mut *self;
+        loop {
+            if let Some(frame) = &egressor.pending {
+                let bytes = &frame.data[frame.layer2_offset..];
+                // Sent or failed, the frame is done with either way.
+                let _ = ready!(Pin::new(&mut egressor.socket).poll_write(cx, bytes));
+                egressor.pending = None;
+            }
+
+            match ready!(Pin::new(&mut egressor.in_stream).poll_next(cx)) {
+                Some(frame) => egressor.pending = Some(frame),
+                None => {
+                    let _ = ready!(Pin::new(&mut egressor.socket).poll_flush(cx));
+                    return Poll::Ready(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::primitive::ProcessLink;
+    use crate::link::ProcessLinkBuilder;
+    use crate::processor::Identity;
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    /// Stands in for an `AF_PACKET` socket, receiving one queued frame per read, and recording
+    /// every frame written. Reads are ready every other poll, like a socket waiting on the NIC.
+    #[derive(Clone, Default)]
+    struct MockSocket {
+        received: Arc<Mutex<VecDeque<Vec<u8>>>>,
+        sent: Arc<Mutex<Vec<Vec<u8>>>>,
+        ready: bool,
+    }
+
+    impl MockSocket {
+        fn receiving(frames: Vec<Vec<u8>>) -> Self {
+            MockSocket {
+                received: Arc::new(Mutex::new(frames.into_iter().collect())),
+                ..Default::default()
+            }
+        }
+
+        fn sent(&self) -> Vec<Vec<u8>> {
+            self.sent.lock().unwrap().clone()
+        }
+    }
+
+    impl AsyncRead for MockSocket {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            self.ready = !self.ready;
+            if !self.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            match self.received.lock().unwrap().pop_front() {
+                Some(frame) => {
+                    buf[..frame.len()].copy_from_slice(&frame);
+                    Poll::Ready(Ok(frame.len()))
+                }
+                None => Poll::Ready(Ok(0)),
+            }
+        }
+    }
+
+    impl AsyncWrite for MockSocket {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.sent.lock().unwrap().push(buf.to_vec());
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn frame(ether_type: u16, payload_len: usize) -> Vec<u8> {
+        let mut frame = vec![0xde, 0xad, 0xbe, 0xef, 0xff, 0xff, 1, 2, 3, 4, 5, 6];
+        frame.extend_from_slice(&ether_type.to_be_bytes());
+        frame.resize(14 + payload_len, 0x5A);
+        frame
+    }
+
+    #[test]
+    #[should_panic]
+    fn source_panics_when_built_without_socket() {
+        RawSocketSource::<MockSocket>::new().build_link();
+    }
+
+    #[test]
+    #[should_panic]
+    fn sink_panics_when_built_without_socket() {
+        RawSocketSink::<MockSocket>::new()
+            .ingressor(immediate_stream(vec![]))
+            .build_link();
+    }
+
+    #[test]
+    fn source_reads_frames() {
+        let frames = vec![frame(0x0800, 46), frame(0x86DD, 1486)];
+        let socket = MockSocket::receiving(frames.clone());
+
+        let mut runtime = initialize_runtime();
+        let results =
+            runtime.block_on(run_link(RawSocketSource::new().socket(socket).build_link()));
+
+        let received: Vec<Vec<u8>> = results[0].iter().map(|frame| frame.data.clone()).collect();
+        assert_eq!(received, frames);
+        assert_eq!(results[0][0].ether_type(), 0x0800);
+    }
+
+    #[test]
+    fn source_skips_runts() {
+        let socket = MockSocket::receiving(vec![vec![0xde, 0xad], frame(0x0800, 46)]);
+
+        let mut runtime = initialize_runtime();
+        let results =
+            runtime.block_on(run_link(RawSocketSource::new().socket(socket).build_link()));
+
+        assert_eq!(results[0].len(), 1);
+        assert_eq!(results[0][0].data, frame(0x0800, 46));
+    }
+
+    #[test]
+    fn sink_writes_frames() {
+        let frames = vec![frame(0x0800, 46), frame(0x0806, 28)];
+        let socket = MockSocket::default();
+        let packets = frames
+            .iter()
+            .map(|frame| EthernetFrame::from_buffer(frame.clone(), 0).unwrap())
+            .collect::<Vec<_>>();
+
+        let mut runtime = initialize_runtime();
+        runtime.block_on(run_link(
+            RawSocketSink::new()
+                .ingressor(immediate_stream(packets))
+                .socket(socket.clone())
+                .build_link(),
+        ));
+
+        assert_eq!(socket.sent(), frames);
+    }
+
+    #[test]
+    fn interface_to_interface() {
+        let frames = vec![frame(0x0800, 46), frame(0x0800, 100), frame(0x86DD, 60)];
+        let lan = MockSocket::receiving(frames.clone());
+        let wan = MockSocket::default();
+
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (mut runnables, mut egressors) = RawSocketSource::new().socket(lan).build_link();
+            let (mut process_runnables, mut process_egressors) = ProcessLink::new()
+                .ingressor(egressors.remove(0))
+                .processor(Identity::new())
+                .build_link();
+            runnables.append(&mut process_runnables);
+            let (mut sink_runnables, _) = RawSocketSink::new()
+                .ingressor(process_egressors.remove(0))
+                .socket(wan.clone())
+                .build_link();
+            runnables.append(&mut sink_runnables);
+
+            run_link::<()>((runnables, vec![])).await
+        });
+
+        assert_eq!(wan.sent(), frames);
+    }
+}
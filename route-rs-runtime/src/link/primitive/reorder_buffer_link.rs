@@ -0,0 +1,330 @@
+use crate::link::utils::clock::{Clock, SystemClock};
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream};
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+use std::collections::{BTreeMap, VecDeque};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tokio::time::{delay_for, Delay};
+
+/// `ReorderBufferLink` puts packets that were reordered by parallel processing back in order.
+/// Each input packet is annotated with its sequence number; packets are released strictly in
+/// sequence order, with out of order packets held until the packets before them arrive.
+///
+/// When a sequence number has been missing for `timeout`, it is given up on, and the held packets
+/// after it are released. A packet that arrives after its sequence number was given up on is
+/// dropped, since releasing it would reorder it. When the input ends, every held packet is
+/// released.
+pub struct ReorderBufferLink<Packet> {
+    in_stream: Option<PacketStream<(u64, Packet)>>,
+    first_sequence: u64,
+    timeout: Duration,
+    clock: Box<dyn Clock>,
+}
+
+impl<Packet> ReorderBufferLink<Packet> {
+    pub fn new() -> Self {
+        ReorderBufferLink {
+            in_stream: None,
+            first_sequence: 0,
+            timeout: Duration::from_millis(10),
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Changes first_sequence, the sequence number of the first packet, default value is 0.
+    pub fn first_sequence(self, first_sequence: u64) -> Self {
+        ReorderBufferLink {
+            first_sequence,
+            ..self
+        }
+    }
+
+    /// Changes timeout, default value is 10ms.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        ReorderBufferLink { timeout, ..self }
+    }
+
+    /// Changes the clock used to measure how long a sequence number has been missing, default is
+    /// `SystemClock`.
+    pub fn clock<C: Clock + 'static>(self, clock: C) -> Self {
+        ReorderBufferLink {
+            clock: Box::new(clock),
+            ..self
+        }
+    }
+}
+
+impl<Packet> Default for ReorderBufferLink<Packet> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Packet: Send + 'static> LinkBuilder<(u64, Packet), Packet> for ReorderBufferLink<Packet> {
+    fn ingressors(self, mut in_streams: Vec<PacketStream<(u64, Packet)>>) -> Self {
+        assert_eq!(
+            in_streams.len(),
+            1,
+            "ReorderBufferLink may only take 1 input stream"
+        );
+
+        if self.in_stream.is_some() {
+            panic!("ReorderBufferLink may only take 1 input stream")
+        }
+
+        ReorderBufferLink {
+            in_stream: Some(in_streams.remove(0)),
+            ..self
+        }
+    }
+
+    fn ingressor(self, in_stream: PacketStream<(u64, Packet)>) -> Self {
+        if self.in_stream.is_some() {
+            panic!("ReorderBufferLink may only take 1 input stream")
+        }
+
+        ReorderBufferLink {
+            in_stream: Some(in_stream),
+            ..self
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<Packet>, LinkBuildError> {
+        let in_stream = self.in_stream.ok_or(LinkBuildError::MissingIngressors)?;
+
+        let egressor = ReorderBufferEgressor {
+            in_stream,
+            next: self.first_sequence,
+            timeout: self.timeout,
+            clock: self.clock,
+            held: BTreeMap::new(),
+            missing_since: None,
+            timer: None,
+            released: VecDeque::new(),
+            finished: false,
+        };
+        Ok((vec![], vec![Box::new(egressor)]))
+    }
+}
+
+/// The single egressor of ReorderBufferLink
+struct ReorderBufferEgressor<Packet> {
+    in_stream: PacketStream<(u64, Packet)>,
+    next: u64,
+    timeout: Duration,
+    clock: Box<dyn Clock>,
+    held: BTreeMap<u64, Packet>,
+    missing_since: Option<Instant>,
+    timer: Option<Delay>,
+    released: VecDeque<Packet>,
+    finished: bool,
+}
+
+impl<Packet> ReorderBufferEgressor<Packet> {
+    fn receive(&mut self, sequence: u64, packet: Packet) {
+        if sequence < self.next {
+            return;
+        }
+        self.held.insert(sequence, packet);
+        self.release_in_sequence();
+    }
+
+    /// Releases held packets for as long as they follow on from the last one released. If packets
+    /// are still held afterwards, the next sequence number is missing, so start timing it.
+    fn release_in_sequence(&mut self) {
+        let mut progressed = false;
+        while let Some(packet) = self.held.remove(&self.next) {
+            self.released.push_back(packet);
+            self.next += 1;
+            progressed = true;
+        }
+
+        if self.held.is_empty() {
+            self.missing_since = None;
+            self.timer = None;
+        } else if progressed || self.missing_since.is_none() {
+            self.missing_since = Some(self.clock.now());
+            self.timer = None;
+        }
+    }
+
+    /// Gives up on the missing sequence numbers before the first held packet.
+    fn skip_missing(&mut self) {
+        if let Some(&first_held) = self.held.keys().next() {
+            self.next = first_held;
+            self.missing_since = None;
+            self.release_in_sequence();
+        }
+    }
+
+    /// How much longer the missing sequence number may be waited for, or `None` if nothing is
+    /// missing.
+    fn remaining_wait(&self) -> Option<Duration> {
+        self.missing_since.map(|missing_since| {
+            let waited = self.clock.now().saturating_duration_since(missing_since);
+            self.timeout.checked_sub(waited).unwrap_or_default()
+        })
+    }
+}
+
+impl<Packet> Unpin for ReorderBufferEgressor<Packet> {}
+
+impl<Packet> Stream for ReorderBufferEgressor<Packet> {
+    type Item = Packet;
+
+    /// Hands out released packets first. Otherwise, pulls packets from the input stream until one
+    /// can be released, the input stream ends, or the input stream returns `Poll::Pending`. When
+    /// the input is pending and a sequence number is missing, we check the clock: if it has been
+    /// missing for `timeout` it is skipped, otherwise a timer is armed for the remaining time so
+    /// that we are woken up to check again.
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let egressor = &mut *self;
+        loop {
+            if let Some(packet) = egressor.released.pop_front() {
+                return Poll::Ready(Some(packet));
+            }
+            if egressor.finished {
+                return Poll::Ready(None);
+            }
+
+            match Pin::new(&mut egressor.in_stream).poll_next(cx) {
+                Poll::Ready(Some((sequence, packet))) => egressor.receive(sequence, packet),
+                Poll::Ready(None) => {
+                    egressor
+                        .released
+                        .extend(std::mem::take(&mut egressor.held).into_values());
+                    egressor.finished = true;
+                }
+                Poll::Pending => match egressor.remaining_wait() {
+                    None => return Poll::Pending,
+                    Some(remaining) if remaining == Duration::from_secs(0) => {
+                        egressor.skip_missing()
+                    }
+                    Some(remaining) => {
+                        let timer = egressor.timer.get_or_insert_with(|| delay_for(remaining));
+                        ready!(Pin::new(timer).poll(cx));
+                        // The timer fired, but the clock decides whether the wait is over. Loop
+                        // around to check it again, arming a fresh timer if it is not.
+                        egressor.timer = None;
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::clock::ManualClock;
+    use crate::utils::test::harness::{initialize_runtime, poll_once, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use futures::channel::mpsc;
+    use rand::seq::SliceRandom;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    #[should_panic]
+    fn panics_when_built_without_input_streams() {
+        ReorderBufferLink::<i32>::new().build_link();
+    }
+
+    #[test]
+    fn restores_order_of_shuffled_packets() {
+        let mut packets: Vec<(u64, u64)> = (0..200).map(|sequence| (sequence, sequence)).collect();
+        packets.shuffle(&mut StdRng::seed_from_u64(7));
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = ReorderBufferLink::new()
+                .ingressor(immediate_stream(packets))
+                .timeout(Duration::from_secs(3600))
+                .build_link();
+
+            run_link(link).await
+        });
+        assert_eq!(results[0], (0..200).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn starts_from_first_sequence() {
+        let packets = vec![(1001, 'b'), (1000, 'a'), (1002, 'c')];
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = ReorderBufferLink::new()
+                .ingressor(immediate_stream(packets))
+                .first_sequence(1000)
+                .build_link();
+
+            run_link(link).await
+        });
+        assert_eq!(results[0], vec!['a', 'b', 'c']);
+    }
+
+    #[test]
+    fn missing_sequence_times_out() {
+        let clock = ManualClock::new();
+
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded::<(u64, char)>();
+            let (_, mut egressors) = ReorderBufferLink::new()
+                .ingressor(Box::new(receiver))
+                .timeout(Duration::from_millis(5))
+                .clock(clock.clone())
+                .build_link();
+            let mut egressor = egressors.remove(0);
+
+            sender.unbounded_send((0, 'a')).unwrap();
+            sender.unbounded_send((2, 'c')).unwrap();
+            sender.unbounded_send((3, 'd')).unwrap();
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(Some('a')));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+
+            clock.advance(Duration::from_millis(4));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+
+            // Sequence 1 never arrives, so it is given up on and the rest are released.
+            clock.advance(Duration::from_millis(1));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(Some('c')));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(Some('d')));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+
+            // Arriving too late would reorder it, so it is dropped.
+            sender.unbounded_send((1, 'b')).unwrap();
+            sender.unbounded_send((4, 'e')).unwrap();
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(Some('e')));
+        });
+    }
+
+    #[test]
+    fn held_packets_flush_when_input_ends() {
+        let clock = ManualClock::new();
+
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded::<(u64, char)>();
+            let (_, mut egressors) = ReorderBufferLink::new()
+                .ingressor(Box::new(receiver))
+                .timeout(Duration::from_secs(3600))
+                .clock(clock)
+                .build_link();
+            let mut egressor = egressors.remove(0);
+
+            sender.unbounded_send((3, 'd')).unwrap();
+            sender.unbounded_send((1, 'b')).unwrap();
+            assert_eq!(poll_once(&mut egressor).await, Poll::Pending);
+
+            drop(sender);
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(Some('b')));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(Some('d')));
+            assert_eq!(poll_once(&mut egressor).await, Poll::Ready(None));
+        });
+    }
+}
@@ -0,0 +1,313 @@
+use crate::link::utils::queue_depth::QueueDepths;
+use crate::link::utils::task_park::*;
+use crate::link::{primitive::QueueEgressor, Link, LinkBuildError, LinkBuilder, PacketStream};
+use crate::link_builder;
+use crossbeam::atomic::AtomicCell;
+use crossbeam::crossbeam_channel;
+use crossbeam::crossbeam_channel::{Receiver, Sender};
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A handle to the rotation of a `RoundRobinTeeLink`: the number of packets it has sent so far.
+/// It can be cloned and read while the pipeline is running, and handed to `resume_from` on a
+/// rebuilt link so that the rotation carries on where it left off.
+#[derive(Clone, Debug, Default)]
+pub struct RoundRobinPosition {
+    sent: Arc<AtomicUsize>,
+}
+
+impl RoundRobinPosition {
+    pub fn new() -> Self {
+        RoundRobinPosition {
+            sent: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Number of packets sent so far. The next packet goes to egressor `get() % num_egressors`.
+    pub fn get(&self) -> usize {
+        self.sent.load(Ordering::SeqCst)
+    }
+}
+
+/// `RoundRobinTeeLink` partitions its input among its egressors, sending each successive packet
+/// to the next egressor in rotation. Where `ForkLink` copies every packet to every egressor, this
+/// link sends every packet to exactly one, so each egressor sees an even share of the traffic.
+/// A full egressor holds up the rotation rather than being skipped, which keeps the shares even.
+#[derive(Default)]
+pub struct RoundRobinTeeLink<Packet: Send> {
+    in_stream: Option<PacketStream<Packet>>,
+    queue_capacity: usize,
+    num_egressors: Option<usize>,
+    position: RoundRobinPosition,
+    queue_depths: QueueDepths,
+}
+
+impl<Packet: Send> RoundRobinTeeLink<Packet> {
+    pub fn new() -> Self {
+        RoundRobinTeeLink {
+            in_stream: None,
+            queue_capacity: 10,
+            num_egressors: None,
+            position: RoundRobinPosition::new(),
+            queue_depths: QueueDepths::new(),
+        }
+    }
+
+    /// Continues the rotation of `position`, which is shared with this link from then on.
+    pub fn resume_from(self, position: RoundRobinPosition) -> Self {
+        RoundRobinTeeLink { position, ..self }
+    }
+
+    /// Returns a handle to the rotation of this link, which remains valid after the link is built.
+    pub fn position(&self) -> RoundRobinPosition {
+        self.position.clone()
+    }
+
+    /// Returns a handle to the depths of the internal queues of this link, which remains valid
+    /// after the link is built.
+    pub fn queue_depths(&self) -> QueueDepths {
+        self.queue_depths.clone()
+    }
+
+    link_builder! {
+        /// Changes queue_capacity, default value is 10.
+        queue_capacity: usize where queue_capacity > 0,
+        /// Sets the number of egressors, which must be > 0.
+        num_egressors: Option<usize>,
+    }
+}
+
+impl<Packet: Send + Clone + 'static> LinkBuilder<Packet, Packet> for RoundRobinTeeLink<Packet> {
+    fn ingressors(self, mut in_streams: Vec<PacketStream<Packet>>) -> Self {
+        assert_eq!(
+            in_streams.len(),
+            1,
+            "RoundRobinTeeLinks may only take one input stream!"
+        );
+
+        self.ingressor(in_streams.remove(0))
+    }
+
+    fn ingressor(self, in_stream: PacketStream<Packet>) -> Self {
+        if self.in_stream.is_some() {
+            panic!("RoundRobinTeeLink may only take 1 input stream")
+        }
+
+        RoundRobinTeeLink {
+            in_stream: Some(in_stream),
+            ..self
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<Packet>, LinkBuildError> {
+        let in_stream = match self.in_stream {
+            Some(in_stream) => in_stream,
+            None => return Err(LinkBuildError::MissingIngressors),
+        };
+        match self.num_egressors {
+            None => Err(LinkBuildError::Missing("num_egressors")),
+            Some(0) => Err(LinkBuildError::NoEgressors),
+            Some(num_egressors) => {
+                let mut to_egressors: Vec<Sender<Option<Packet>>> = Vec::new();
+                let mut egressors: Vec<PacketStream<Packet>> = Vec::new();
+                let mut task_parks: Vec<Arc<AtomicCell<TaskParkState>>> = Vec::new();
+
+                for _ in 0..num_egressors {
+                    let (to_egressor, from_ingressor): (Sender<Option<Packet>>, Receiver<_>) =
+                        crossbeam_channel::bounded(self.queue_capacity);
+                    self.queue_depths.watch(&from_ingressor);
+                    let task_park = Arc::new(AtomicCell::new(TaskParkState::Empty));
+
+                    let egressor = QueueEgressor::new(from_ingressor, Arc::clone(&task_park));
+
+                    to_egressors.push(to_egressor);
+                    egressors.push(Box::new(egressor));
+                    task_parks.push(task_park);
+                }
+
+                let ingressor =
+                    RoundRobinTeeIngressor::new(in_stream, to_egressors, task_parks, self.position);
+
+                Ok((vec![Box::new(ingressor)], egressors))
+            }
+        }
+    }
+}
+
+pub struct RoundRobinTeeIngressor<P> {
+    input_stream: PacketStream<P>,
+    to_egressors: Vec<Sender<Option<P>>>,
+    task_parks: Vec<Arc<AtomicCell<TaskParkState>>>,
+    position: RoundRobinPosition,
+    ended: bool,
+}
+
+impl<P> RoundRobinTeeIngressor<P> {
+    fn new(
+        input_stream: PacketStream<P>,
+        to_egressors: Vec<Sender<Option<P>>>,
+        task_parks: Vec<Arc<AtomicCell<TaskParkState>>>,
+        position: RoundRobinPosition,
+    ) -> Self {
+        RoundRobinTeeIngressor {
+            input_stream,
+            to_egressors,
+            task_parks,
+            position,
+            ended: false,
+        }
+    }
+
+    /// Tells every egressor that the input has ended, once every one of them has room for it.
+    fn finish(&mut self, cx: &mut Context) -> Poll<()> {
+        self.ended = true;
+        for (port, to_egressor) in self.to_egressors.iter().enumerate() {
+            if to_egressor.is_full() {
+                park_and_wake(&self.task_parks[port], cx.waker().clone());
+                return Poll::Pending;
+            }
+        }
+        for to_egressor in self.to_egressors.iter() {
+            if let Err(err) = to_egressor.try_send(None) {
+                panic!("Ingressor: Drop: try_send to egressor, fail?: {:?}", err);
+            }
+        }
+        for task_park in self.task_parks.iter() {
+            die_and_wake(task_park);
+        }
+        Poll::Ready(())
+    }
+}
+
+impl<P: Send> Future for RoundRobinTeeIngressor<P> {
+    type Output = ();
+
+    /// Waits for the egressor whose turn it is to have room before taking the next packet, so
+    /// that the packet always has somewhere to go.
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if self.ended {
+            return self.finish(cx);
+        }
+        loop {
+            let port = self.position.get() % self.to_egressors.len();
+            if self.to_egressors[port].is_full() {
+                park_and_wake(&self.task_parks[port], cx.waker().clone());
+                return Poll::Pending;
+            }
+            let packet_option: Option<P> = ready!(Pin::new(&mut self.input_stream).poll_next(cx));
+
+            match packet_option {
+                None => return self.finish(cx),
+                Some(packet) => {
+                    if let Err(err) = self.to_egressors[port].try_send(Some(packet)) {
+                        panic!(
+                            "Error in to_egressors[{}] sender, have nowhere to put packet: {:?}",
+                            port, err
+                        );
+                    }
+                    self.position.sent.fetch_add(1, Ordering::SeqCst);
+                    unpark_and_wake(&self.task_parks[port]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+
+    #[test]
+    fn try_build_link_rejects_zero_egressors() {
+        let result = RoundRobinTeeLink::<i32>::new()
+            .ingressor(immediate_stream(vec![]))
+            .num_egressors(0)
+            .try_build_link();
+        assert_eq!(result.err(), Some(LinkBuildError::NoEgressors));
+    }
+
+    #[test]
+    fn try_build_link_reports_missing_settings() {
+        let result = RoundRobinTeeLink::<i32>::new()
+            .num_egressors(2)
+            .try_build_link();
+        assert_eq!(result.err(), Some(LinkBuildError::MissingIngressors));
+
+        let result = RoundRobinTeeLink::<i32>::new()
+            .ingressor(immediate_stream(vec![]))
+            .try_build_link();
+        assert_eq!(result.err(), Some(LinkBuildError::Missing("num_egressors")));
+    }
+
+    #[test]
+    fn no_input() {
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = RoundRobinTeeLink::<i32>::new()
+                .ingressor(immediate_stream(vec![]))
+                .num_egressors(2)
+                .build_link();
+
+            run_link(link).await
+        });
+        assert!(results.iter().all(|output| output.is_empty()));
+    }
+
+    #[test]
+    fn even_distribution() {
+        let k = 4;
+        let n = 250;
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = RoundRobinTeeLink::new()
+                .ingressor(immediate_stream(0..(n * k) as i32))
+                .num_egressors(k)
+                .queue_capacity(1)
+                .build_link();
+
+            run_link(link).await
+        });
+
+        assert_eq!(results.len(), k);
+        for (port, output) in results.iter().enumerate() {
+            assert_eq!(output.len(), n);
+            assert!(output.iter().all(|packet| *packet as usize % k == port));
+        }
+    }
+
+    #[test]
+    fn resumes_rotation() {
+        let first = RoundRobinTeeLink::<i32>::new();
+        let position = first.position();
+
+        let mut runtime = initialize_runtime();
+        let (before, after) = runtime.block_on(async {
+            let link = first
+                .ingressor(immediate_stream(vec![0, 1, 2, 3, 4]))
+                .num_egressors(3)
+                .build_link();
+            let before = run_link(link).await;
+
+            let link = RoundRobinTeeLink::new()
+                .resume_from(position.clone())
+                .ingressor(immediate_stream(vec![5, 6, 7, 8]))
+                .num_egressors(3)
+                .build_link();
+            (before, run_link(link).await)
+        });
+
+        assert_eq!(before, vec![vec![0, 3], vec![1, 4], vec![2]]);
+        assert_eq!(after, vec![vec![6], vec![7], vec![5, 8]]);
+        assert_eq!(position.get(), 9);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::link::utils::clock::{Clock, SystemClock};
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream};
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+type PacketLen<Packet> = Box<dyn Fn(&Packet) -> usize + Send + Sync>;
+
+struct MeterState {
+    samples: VecDeque<(Instant, usize)>,
+    bytes: usize,
+    window: Duration,
+    clock: Box<dyn Clock>,
+}
+
+impl MeterState {
+    fn record(&mut self, len: usize) {
+        let now = self.clock.now();
+        self.samples.push_back((now, len));
+        self.bytes += len;
+        self.evict(now);
+    }
+
+    /// Forgets the samples that have slid out of the window.
+    fn evict(&mut self, now: Instant) {
+        while let Some((seen, len)) = self.samples.front() {
+            if now.saturating_duration_since(*seen) < self.window {
+                break;
+            }
+            self.bytes -= len;
+            self.samples.pop_front();
+        }
+    }
+}
+
+/// A handle to the rate measured by a `ThroughputMeterLink`. It can be cloned and read while the
+/// pipeline is running.
+#[derive(Clone)]
+pub struct Throughput {
+    state: Arc<Mutex<MeterState>>,
+}
+
+impl Throughput {
+    /// The rate over the last window, in bits per second.
+    pub fn bits_per_second(&self) -> f64 {
+        self.bytes_per_second() * 8.0
+    }
+
+    /// The rate over the last window, in bytes per second.
+    pub fn bytes_per_second(&self) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        let now = state.clock.now();
+        state.evict(now);
+        state.bytes as f64 / state.window.as_secs_f64()
+    }
+}
+
+/// `ThroughputMeterLink` measures the rate of the traffic passing through it, without holding up
+/// or modifying any packet. It counts the bytes of each packet, as measured by `packet_len`, over
+/// a sliding `window`, and the rate is read through the `Throughput` handle returned by
+/// `throughput`.
+pub struct ThroughputMeterLink<Packet> {
+    in_stream: Option<PacketStream<Packet>>,
+    packet_len: Option<PacketLen<Packet>>,
+    state: Arc<Mutex<MeterState>>,
+}
+
+impl<Packet> ThroughputMeterLink<Packet> {
+    pub fn new() -> Self {
+        ThroughputMeterLink {
+            in_stream: None,
+            packet_len: None,
+            state: Arc::new(Mutex::new(MeterState {
+                samples: VecDeque::new(),
+                bytes: 0,
+                window: Duration::from_secs(1),
+                clock: Box::new(SystemClock),
+            })),
+        }
+    }
+
+    /// Sets how many bytes a packet counts for.
+    pub fn packet_len(self, packet_len: PacketLen<Packet>) -> Self {
+        ThroughputMeterLink {
+            packet_len: Some(packet_len),
+            ..self
+        }
+    }
+
+    /// Changes window, the span of time the rate is averaged over, default value is 1 second.
+    pub fn window(self, window: Duration) -> Self {
+        assert!(
+            window > Duration::from_secs(0),
+            "window: {:?}, must be > 0",
+            window
+        );
+
+        self.state.lock().unwrap().window = window;
+        self
+    }
+
+    /// Changes the clock used to time packets, default is `SystemClock`.
+    pub fn clock<C: Clock + 'static>(self, clock: C) -> Self {
+        self.state.lock().unwrap().clock = Box::new(clock);
+        self
+    }
+
+    /// Returns a handle to the rate measured by this link, which remains valid after the link is
+    /// built.
+    pub fn throughput(&self) -> Throughput {
+        Throughput {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl<Packet> Default for ThroughputMeterLink<Packet> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Packet: Send + 'static> LinkBuilder<Packet, Packet> for ThroughputMeterLink<Packet> {
+    fn ingressors(self, mut in_streams: Vec<PacketStream<Packet>>) -> Self {
+        assert_eq!(
+            in_streams.len(),
+            1,
+            "ThroughputMeterLink may only take 1 input stream"
+        );
+
+        if self.in_stream.is_some() {
+            panic!("ThroughputMeterLink may only take 1 input stream")
+        }
+
+        ThroughputMeterLink {
+            in_stream: Some(in_streams.remove(0)),
+            ..self
+        }
+    }
+
+    fn ingressor(self, in_stream: PacketStream<Packet>) -> Self {
+        if self.in_stream.is_some() {
+            panic!("ThroughputMeterLink may only take 1 input stream")
+        }
+
+        ThroughputMeterLink {
+            in_stream: Some(in_stream),
+            ..self
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<Packet>, LinkBuildError> {
+        let in_stream = self.in_stream.ok_or(LinkBuildError::MissingIngressors)?;
+        let packet_len = self
+            .packet_len
+            .ok_or(LinkBuildError::Missing("packet_len"))?;
+
+        let egressor = ThroughputMeterEgressor {
+            in_stream,
+            packet_len,
+            state: self.state,
+        };
+        Ok((vec![], vec![Box::new(egressor)]))
+    }
+}
+
+/// The single egressor of ThroughputMeterLink
+struct ThroughputMeterEgressor<Packet> {
+    in_stream: PacketStream<Packet>,
+    packet_len: PacketLen<Packet>,
+    state: Arc<Mutex<MeterState>>,
+}
+
+impl<Packet> Unpin for ThroughputMeterEgressor<Packet> {}
+
+impl<Packet> Stream for ThroughputMeterEgressor<Packet> {
+    type Item = Packet;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let packet = ready!(Pin::new(&mut self.in_stream).poll_next(cx));
+        if let Some(packet) = &packet {
+            let len = (self.packet_len)(packet);
+            self.state.lock().unwrap().record(len);
+        }
+        Poll::Ready(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::clock::ManualClock;
+    use crate::utils::test::harness::{initialize_runtime, poll_once, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use futures::channel::mpsc;
+    use route_rs_packets::Ipv4Packet;
+
+    fn packet(payload_len: usize) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_payload(&vec![0; payload_len]);
+        packet
+    }
+
+    fn ipv4_len() -> PacketLen<Ipv4Packet> {
+        Box::new(|packet: &Ipv4Packet| packet.len())
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_when_built_without_packet_len() {
+        ThroughputMeterLink::<i32>::new()
+            .ingressor(immediate_stream(vec![]))
+            .build_link();
+    }
+
+    #[test]
+    fn measures_paced_traffic() {
+        let clock = ManualClock::new();
+        let link = ThroughputMeterLink::new()
+            .packet_len(ipv4_len())
+            .clock(clock.clone());
+        let throughput = link.throughput();
+
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (sender, receiver) = mpsc::unbounded::<Ipv4Packet>();
+            let (_, mut egressors) = link.ingressor(Box::new(receiver)).build_link();
+            let mut egressor = egressors.remove(0);
+
+            // 1250 bytes every 10ms is 1 megabit per second.
+            for _ in 0..200 {
+                sender.unbounded_send(packet(1230)).unwrap();
+                let released = poll_once(&mut egressor).await;
+                assert_eq!(
+                    released.map(|p| p.map(|p| p.total_len())),
+                    Poll::Ready(Some(1250))
+                );
+                clock.advance(Duration::from_millis(10));
+            }
+        });
+
+        let rate = throughput.bits_per_second();
+        assert!((rate - 1_000_000.0).abs() <= 10_000.0, "rate: {}", rate);
+
+        // Once traffic stops, the rate falls as the window slides past the last packets.
+        clock.advance(Duration::from_millis(500));
+        let rate = throughput.bits_per_second();
+        assert!((rate - 500_000.0).abs() <= 10_000.0, "rate: {}", rate);
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(throughput.bits_per_second(), 0.0);
+    }
+
+    #[test]
+    fn weights_by_packet_len() {
+        let clock = ManualClock::new();
+        let link = ThroughputMeterLink::new()
+            .packet_len(ipv4_len())
+            .window(Duration::from_secs(2))
+            .clock(clock);
+        let throughput = link.throughput();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = link
+                .ingressor(immediate_stream(vec![packet(80), packet(980), packet(0)]))
+                .build_link();
+
+            run_link(link).await
+        });
+
+        assert_eq!(results[0].len(), 3);
+        assert_eq!(
+            throughput.bytes_per_second(),
+            (100 + 1000 + 20) as f64 / 2.0
+        );
+    }
+}
@@ -0,0 +1,426 @@
+use crate::link::{Link, LinkBuildError, LinkBuilder, PacketStream};
+use crate::link_builder;
+use crate::processor::{classify, FlowKey};
+use futures::prelude::*;
+use futures::task::{Context, Poll};
+use log::trace;
+use route_rs_packets::Ipv4Packet;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Gives the weight of a flow, which must be > 0. A weight of 0 panics when a packet of the flow
+/// arrives.
+pub type FlowWeight = Box<dyn Fn(&FlowKey) -> u32 + Send + Sync + 'static>;
+
+/// Finish tags count bytes scaled by this, so that dividing by a weight keeps its precision.
+const WEIGHT_SCALE: u64 = 1 << 16;
+
+/// A handle to the number of packets a `WfqLink` dropped because their flow's queue was full. It
+/// can be cloned and read while the pipeline is running.
+#[derive(Clone, Debug, Default)]
+pub struct WfqDropCount {
+    count: Arc<AtomicU64>,
+}
+
+impl WfqDropCount {
+    pub fn new() -> Self {
+        WfqDropCount {
+            count: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Number of packets dropped so far for finding their flow's queue full.
+    pub fn get(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    fn increment(&self) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// `WfqLink` shares its output between flows with weighted fair queuing, so that no single flow
+/// can dominate an uplink. Packets are keyed on their 5-tuple, as in `FlowAccounting`, and queued
+/// per flow; while several flows are backlogged, each gets a share of the output bytes in
+/// proportion to its weight, given by the `weights` function. By default every flow weighs the
+/// same.
+///
+/// Each packet is stamped with the virtual time its flow would finish sending it, and packets are
+/// released in order of those finish tags. Virtual time advances to the tag of the packet last
+/// released (self-clocked fair queuing), so a flow that was idle starts level with the others
+/// rather than with credit saved up.
+///
+/// The link takes up to `queue_capacity` packets from its input every time a packet is asked of
+/// it, and releases packets as soon as it has one. A packet that arrives while its flow already
+/// has `queue_capacity` packets queued is dropped, so a flow sending faster than its share loses
+/// its own excess without crowding out the others.
+pub struct WfqLink {
+    in_stream: Option<PacketStream<Ipv4Packet>>,
+    queue_capacity: usize,
+    weights: FlowWeight,
+    dropped: WfqDropCount,
+}
+
+impl WfqLink {
+    pub fn new() -> Self {
+        WfqLink {
+            in_stream: None,
+            queue_capacity: 64,
+            weights: Box::new(|_| 1),
+            dropped: WfqDropCount::new(),
+        }
+    }
+
+    link_builder! {
+        /// Changes queue_capacity, the number of packets queued per flow, default value is 64.
+        queue_capacity: usize where queue_capacity > 0,
+        /// Changes the weight of each flow, default is 1 for every flow.
+        weights: FlowWeight,
+    }
+
+    /// Returns a handle to the number of packets this link dropped because their flow's queue
+    /// was full, which remains valid after the link is built.
+    pub fn dropped(&self) -> WfqDropCount {
+        self.dropped.clone()
+    }
+}
+
+impl Default for WfqLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LinkBuilder<Ipv4Packet, Ipv4Packet> for WfqLink {
+    fn ingressors(self, mut in_streams: Vec<PacketStream<Ipv4Packet>>) -> Self {
+        assert_eq!(in_streams.len(), 1, "WfqLink may only take 1 input stream");
+
+        if self.in_stream.is_some() {
+            panic!("WfqLink may only take 1 input stream")
+        }
+
+        WfqLink {
+            in_stream: Some(in_streams.remove(0)),
+            ..self
+        }
+    }
+
+    fn ingressor(self, in_stream: PacketStream<Ipv4Packet>) -> Self {
+        if self.in_stream.is_some() {
+            panic!("WfqLink may only take 1 input stream")
+        }
+
+        WfqLink {
+            in_stream: Some(in_stream),
+            ..self
+        }
+    }
+
+    fn ingressor_capacity(&self) -> Option<usize> {
+        Some(if self.in_stream.is_some() { 0 } else { 1 })
+    }
+
+    fn try_build_link(self) -> Result<Link<Ipv4Packet>, LinkBuildError> {
+        let in_stream = self.in_stream.ok_or(LinkBuildError::MissingIngressors)?;
+
+        let egressor = WfqEgressor {
+            in_stream,
+            finished: false,
+            queue_capacity: self.queue_capacity,
+            weights: self.weights,
+            dropped: self.dropped,
+            flows: HashMap::new(),
+            queued: BinaryHeap::new(),
+            virtual_time: 0,
+            arrivals: 0,
+        };
+        Ok((vec![], vec![Box::new(egressor)]))
+    }
+}
+
+/// What `WfqEgressor` remembers about a flow with packets queued.
+struct WfqFlow {
+    /// The finish tag of the flow's last queued packet.
+    last_finish: u64,
+    queued: usize,
+}
+
+/// A queued packet, ordered by finish tag, and then by arrival so that ties keep their order.
+struct Queued {
+    finish: u64,
+    arrival: u64,
+    key: FlowKey,
+    packet: Ipv4Packet,
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        (self.finish, self.arrival) == (other.finish, other.arrival)
+    }
+}
+
+impl Eq for Queued {}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.finish, self.arrival).cmp(&(other.finish, other.arrival))
+    }
+}
+
+/// The single egressor of WfqLink
+struct WfqEgressor {
+    in_stream: PacketStream<Ipv4Packet>,
+    finished: bool,
+    queue_capacity: usize,
+    weights: FlowWeight,
+    dropped: WfqDropCount,
+    flows: HashMap<FlowKey, WfqFlow>,
+    /// Every queued packet, of every flow. A flow's finish tags only grow, so taking the smallest
+    /// tag overall takes the head of one of the flows.
+    queued: BinaryHeap<Reverse<Queued>>,
+    virtual_time: u64,
+    arrivals: u64,
+}
+
+impl WfqEgressor {
+    fn enqueue(&mut self, packet: Ipv4Packet) {
+        let (key, _) = classify(&packet);
+        let weight = (self.weights)(&key);
+        assert!(weight > 0, "weight: {}, must be > 0", weight);
+        let weight = u64::from(weight);
+        let virtual_time = self.virtual_time;
+        let flow = self.flows.entry(key).or_insert(WfqFlow {
+            last_finish: virtual_time,
+            queued: 0,
+        });
+        if flow.queued >= self.queue_capacity {
+            trace!("dropped packet of flow {:?}, its queue is full", key);
+            self.dropped.increment();
+            return;
+        }
+
+        let size = u64::from(packet.total_len());
+        let finish = flow.last_finish.max(virtual_time) + size * WEIGHT_SCALE / weight;
+        flow.last_finish = finish;
+        flow.queued += 1;
+        self.queued.push(Reverse(Queued {
+            finish,
+            arrival: self.arrivals,
+            key,
+            packet,
+        }));
+        self.arrivals += 1;
+    }
+
+    fn dequeue(&mut self) -> Option<Ipv4Packet> {
+        let Reverse(queued) = self.queued.pop()?;
+        self.virtual_time = queued.finish;
+        let flow = self
+            .flows
+            .get_mut(&queued.key)
+            .expect("WfqEgressor: queued packet of an unknown flow");
+        flow.queued -= 1;
+        if flow.queued == 0 {
+            // The flow's finish tag is behind virtual time now, so it starts afresh either way.
+            self.flows.remove(&queued.key);
+        }
+        Some(queued.packet)
+    }
+}
+
+impl Unpin for WfqEgressor {}
+
+impl Stream for WfqEgressor {
+    type Item = Ipv4Packet;
+
+    /// Takes up to `queue_capacity` packets from the input, stopping early if it has none ready,
+    /// then releases the queued packet with the smallest finish tag. Bounding the packets taken
+    /// keeps a flood on the input from holding up the output.
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let egressor = &mut *self;
+        for _ in 0..egressor.queue_capacity {
+            if egressor.finished {
+                break;
+            }
+            match Pin::new(&mut egressor.in_stream).poll_next(cx) {
+                Poll::Ready(Some(packet)) => egressor.enqueue(packet),
+                Poll::Ready(None) => egressor.finished = true,
+                Poll::Pending => break,
+            }
+        }
+
+        match egressor.dequeue() {
+            Some(packet) => Poll::Ready(Some(packet)),
+            None if egressor.finished => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::harness::{initialize_runtime, poll_once, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use std::net::Ipv4Addr;
+
+    const UPLINK: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 1);
+    /// A queue capacity that takes in every packet of a test on the first poll, so that the flows
+    /// are backlogged from the start and nothing is dropped.
+    const BACKLOG: usize = 1000;
+
+    /// A UDP datagram of `flow`, told apart by its source port, with `data_len` bytes of data.
+    fn datagram(flow: u16, data_len: usize) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(Ipv4Addr::new(192, 168, 1, 10));
+        packet.set_dest_addr(UPLINK);
+        packet.set_protocol(17);
+        let mut udp = vec![0; 8 + data_len];
+        udp[0..2].copy_from_slice(&flow.to_be_bytes());
+        udp[2..4].copy_from_slice(&53u16.to_be_bytes());
+        packet.set_payload(&udp);
+        packet
+    }
+
+    fn flow_of(packet: &Ipv4Packet) -> u16 {
+        classify(packet).0.src_port
+    }
+
+    fn wfq(link: WfqLink, packets: Vec<Ipv4Packet>) -> Vec<Ipv4Packet> {
+        let mut runtime = initialize_runtime();
+        let mut results = runtime.block_on(async {
+            let link = link.ingressor(immediate_stream(packets)).build_link();
+            run_link(link).await
+        });
+        results.remove(0)
+    }
+
+    /// `fast` sends three packets for every one that `slow` sends.
+    fn unbalanced(count: usize, fast: u16, slow: u16) -> Vec<Ipv4Packet> {
+        (0..count)
+            .map(|n| datagram(if n % 4 == 3 { slow } else { fast }, 100))
+            .collect()
+    }
+
+    /// Number of the first `count` packets of the output from each of `flows`.
+    fn packets_per_flow(output: &[Ipv4Packet], count: usize, flows: &[u16]) -> Vec<usize> {
+        flows
+            .iter()
+            .map(|flow| {
+                output
+                    .iter()
+                    .take(count)
+                    .filter(|packet| flow_of(packet) == *flow)
+                    .count()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn equal_weights_share_equally() {
+        let output = wfq(
+            WfqLink::new().queue_capacity(BACKLOG),
+            unbalanced(400, 1, 2),
+        );
+
+        assert_eq!(output.len(), 400);
+        // The fast flow sends three times as fast as the slow one, but while both are backlogged
+        // they alternate.
+        assert_eq!(packets_per_flow(&output, 100, &[1, 2]), vec![50, 50]);
+    }
+
+    #[test]
+    fn shares_follow_weights() {
+        let link = WfqLink::new()
+            .queue_capacity(BACKLOG)
+            .weights(Box::new(|key| if key.src_port == 2 { 2 } else { 1 }));
+        let output = wfq(link, unbalanced(400, 1, 2));
+
+        assert_eq!(packets_per_flow(&output, 99, &[1, 2]), vec![33, 66]);
+    }
+
+    #[test]
+    #[should_panic(expected = "weight: 0, must be > 0")]
+    fn panics_on_zero_weight() {
+        let mut runtime = initialize_runtime();
+        runtime.block_on(async {
+            let (_, mut egressors) = WfqLink::new()
+                .weights(Box::new(|_| 0))
+                .ingressor(immediate_stream(vec![datagram(1, 100)]))
+                .build_link();
+            let _ = poll_once(&mut egressors[0]).await;
+        });
+    }
+
+    #[test]
+    fn shares_bytes_not_packets() {
+        let mut packets = vec![];
+        for _ in 0..40 {
+            packets.push(datagram(1, 972));
+            for _ in 0..10 {
+                packets.push(datagram(2, 72));
+            }
+        }
+
+        let output = wfq(WfqLink::new().queue_capacity(BACKLOG), packets);
+
+        // A 1000 byte packet of the first flow takes as long as ten 100 byte packets of the
+        // second.
+        assert_eq!(packets_per_flow(&output, 110, &[1, 2]), vec![10, 100]);
+    }
+
+    #[test]
+    fn keeps_order_within_a_flow() {
+        let packets: Vec<Ipv4Packet> = (0..60u8)
+            .map(|n| {
+                let mut packet = datagram(u16::from(n % 3), 100);
+                packet.set_ttl(n);
+                packet
+            })
+            .collect();
+
+        let output = wfq(WfqLink::new().queue_capacity(BACKLOG), packets);
+
+        for flow in 0..3u8 {
+            let ttls: Vec<u8> = output
+                .iter()
+                .filter(|packet| flow_of(packet) == u16::from(flow))
+                .map(|packet| packet.ttl())
+                .collect();
+            assert_eq!(ttls, (0..20).map(|n| n * 3 + flow).collect::<Vec<u8>>());
+        }
+    }
+
+    #[test]
+    fn drops_excess_of_a_full_flow() {
+        let link = WfqLink::new().queue_capacity(8);
+        let dropped = link.dropped();
+        // The slow flow sends one packet in sixteen.
+        let packets = (0..128)
+            .map(|n| datagram(if n % 16 == 15 { 2 } else { 1 }, 100))
+            .collect();
+
+        let output = wfq(link, packets);
+
+        // Each poll takes eight packets and sends one, so the fast flow's queue fills up, while
+        // the slow flow sends no faster than its share and loses nothing.
+        assert_eq!(output.len() as u64 + dropped.get(), 128);
+        assert!(dropped.get() > 0);
+        assert_eq!(packets_per_flow(&output, output.len(), &[2]), vec![8]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_zero_queue_capacity() {
+        WfqLink::new().queue_capacity(0);
+    }
+}
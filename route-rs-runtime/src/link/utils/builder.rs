@@ -0,0 +1,166 @@
+//! # What is it for?
+//!
+//! Link builders are immutable: every setter consumes the builder and returns a new one with a
+//! single field changed. Writing that out by hand means listing every field in every setter, and
+//! forgetting one silently resets it. `link_builder!` generates such setters from a list of
+//! fields instead, carrying every other field forward with struct update syntax.
+
+/// Generates a fluent setter for each listed field of the builder. Invoke it inside the builder's
+/// `impl` block. Each setter takes the new value, and returns the builder with only that field
+/// replaced. Fields declared as `Option<T>` get a setter that takes a `T` and stores `Some` of it.
+/// A field may be followed by `where` and a condition on its new value, which the setter asserts
+/// before storing it.
+///
+/// ```
+/// use route_rs_runtime::link_builder;
+///
+/// #[derive(Default)]
+/// pub struct ExampleLink {
+///     name: Option<String>,
+///     queue_capacity: usize,
+/// }
+///
+/// impl ExampleLink {
+///     link_builder! {
+///         /// Names the link.
+///         name: Option<String>,
+///         /// Changes queue_capacity, default value is 0.
+///         queue_capacity: usize where queue_capacity > 0,
+///     }
+/// }
+///
+/// let link = ExampleLink::default()
+///     .name(String::from("wan"))
+///     .queue_capacity(20);
+/// assert_eq!(link.name.as_deref(), Some("wan"));
+/// assert_eq!(link.queue_capacity, 20);
+/// ```
+#[macro_export]
+macro_rules! link_builder {
+    () => {};
+    ($(#[$meta:meta])* $field:ident: Option<$ty:ty> where $check:expr $(, $($rest:tt)*)?) => {
+        $(#[$meta])*
+        pub fn $field(self, $field: $ty) -> Self {
+            assert!(
+                $check,
+                "{}: {:?}, must satisfy `{}`",
+                stringify!($field),
+                $field,
+                stringify!($check)
+            );
+            Self {
+                $field: Some($field),
+                ..self
+            }
+        }
+
+        $crate::link_builder!($($($rest)*)?);
+    };
+    ($(#[$meta:meta])* $field:ident: Option<$ty:ty> $(, $($rest:tt)*)?) => {
+        $(#[$meta])*
+        pub fn $field(self, $field: $ty) -> Self {
+            Self {
+                $field: Some($field),
+                ..self
+            }
+        }
+
+        $crate::link_builder!($($($rest)*)?);
+    };
+    ($(#[$meta:meta])* $field:ident: $ty:ty where $check:expr $(, $($rest:tt)*)?) => {
+        $(#[$meta])*
+        pub fn $field(self, $field: $ty) -> Self {
+            assert!(
+                $check,
+                "{}: {:?}, must satisfy `{}`",
+                stringify!($field),
+                $field,
+                stringify!($check)
+            );
+            Self { $field, ..self }
+        }
+
+        $crate::link_builder!($($($rest)*)?);
+    };
+    ($(#[$meta:meta])* $field:ident: $ty:ty $(, $($rest:tt)*)?) => {
+        $(#[$meta])*
+        pub fn $field(self, $field: $ty) -> Self {
+            Self { $field, ..self }
+        }
+
+        $crate::link_builder!($($($rest)*)?);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    type Callback = Box<dyn Fn(usize) -> usize + Send + Sync + 'static>;
+
+    struct TestBuilder {
+        name: Option<String>,
+        callback: Option<Callback>,
+        queue_capacity: usize,
+        num_egressors: Option<usize>,
+    }
+
+    impl TestBuilder {
+        fn new() -> Self {
+            TestBuilder {
+                name: None,
+                callback: None,
+                queue_capacity: 10,
+                num_egressors: None,
+            }
+        }
+
+        link_builder! {
+            name: Option<String>,
+            /// A documented setter.
+            callback: Option<Callback>,
+            queue_capacity: usize where queue_capacity > 0,
+            num_egressors: Option<usize> where num_egressors > 0
+        }
+    }
+
+    #[test]
+    fn setters_preserve_other_fields() {
+        let builder = TestBuilder::new().name(String::from("fork"));
+        assert_eq!(builder.name.as_deref(), Some("fork"));
+        assert_eq!(builder.queue_capacity, 10);
+        assert!(builder.callback.is_none());
+        assert_eq!(builder.num_egressors, None);
+
+        let builder = builder.callback(Box::new(|x| x + 1));
+        assert_eq!(builder.name.as_deref(), Some("fork"));
+        assert_eq!((builder.callback.as_ref().unwrap())(1), 2);
+
+        let builder = builder.queue_capacity(20);
+        assert_eq!(builder.name.as_deref(), Some("fork"));
+        assert!(builder.callback.is_some());
+        assert_eq!(builder.queue_capacity, 20);
+
+        let builder = builder.num_egressors(3);
+        assert_eq!(builder.name.as_deref(), Some("fork"));
+        assert!(builder.callback.is_some());
+        assert_eq!(builder.queue_capacity, 20);
+        assert_eq!(builder.num_egressors, Some(3));
+    }
+
+    #[test]
+    fn later_calls_replace_earlier_ones() {
+        let builder = TestBuilder::new().num_egressors(2).num_egressors(5);
+        assert_eq!(builder.num_egressors, Some(5));
+    }
+
+    #[test]
+    #[should_panic(expected = "queue_capacity: 0, must satisfy `queue_capacity > 0`")]
+    fn setters_assert_their_condition() {
+        TestBuilder::new().queue_capacity(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "num_egressors: 0, must satisfy `num_egressors > 0`")]
+    fn optional_setters_assert_their_condition() {
+        TestBuilder::new().num_egressors(0);
+    }
+}
@@ -0,0 +1,31 @@
+//! # What is it for?
+//!
+//! Links that make decisions based on how long a packet has been waiting, such as `CoalesceLink`,
+//! read the current time through the `Clock` trait rather than calling `Instant::now()` directly.
+//! In production the `SystemClock` is used, while tests can substitute a `ManualClock` from
+//! `utils::test::clock` and advance time explicitly, so time based behavior can be tested without
+//! sleeping.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// Reads the time from the operating system.
+#[derive(Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Lets several links read the same clock, such as the links inside a composite.
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
@@ -1,2 +1,11 @@
 /// A cache for storing task handles.
 pub mod task_park;
+
+/// A source of the current time that links can use for time based decisions, and that tests can replace.
+pub mod clock;
+
+/// A handle reporting how many packets wait in the internal queues of a link.
+pub mod queue_depth;
+
+/// The `link_builder!` macro, which generates the fluent setters of link builders.
+pub mod builder;
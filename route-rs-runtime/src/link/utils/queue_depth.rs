@@ -0,0 +1,133 @@
+use crossbeam::crossbeam_channel::Receiver;
+use std::sync::{Arc, Mutex};
+
+type Depth = Box<dyn Fn() -> usize + Send + Sync>;
+
+/// A handle to the internal queues of a link, reporting how many packets are waiting in each. It
+/// is handed out by the builder, and the link registers its queues with it when it is built, so
+/// the depths can be read while the link runs and after it has finished. Once a link has run to
+/// completion every queue should be empty; a packet left behind means it was stuck.
+///
+/// The end of input is sent through the queues too, so a queue whose egressor has not yet seen the
+/// end counts it as a waiting packet.
+#[derive(Clone, Default)]
+pub struct QueueDepths {
+    queues: Arc<Mutex<Vec<Depth>>>,
+}
+
+impl QueueDepths {
+    pub fn new() -> Self {
+        QueueDepths {
+            queues: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// Registers `queue`, so that its depth is reported from now on.
+    pub(crate) fn watch<T: Send + 'static>(&self, queue: &Receiver<T>) {
+        let queue = queue.clone();
+        self.queues
+            .lock()
+            .unwrap()
+            .push(Box::new(move || queue.len()));
+    }
+
+    /// The number of packets waiting in each queue, in the order the link created them. Empty
+    /// until the link is built.
+    pub fn get(&self) -> Vec<usize> {
+        self.queues
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|len| len())
+            .collect()
+    }
+
+    /// Whether no packet is waiting in any queue.
+    pub fn all_empty(&self) -> bool {
+        self.get().iter().all(|depth| *depth == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classifier::Even;
+    use crate::link::primitive::{ClassifyLink, JoinLink, QueueLink};
+    use crate::link::{LinkBuilder, ProcessLinkBuilder};
+    use crate::processor::Identity;
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use futures::task::{noop_waker, Context, Poll};
+    use std::future::Future;
+    use std::pin::Pin;
+
+    #[test]
+    fn empty_before_build() {
+        let link = QueueLink::<Identity<i32>>::new();
+
+        assert_eq!(link.queue_depths().get(), vec![]);
+        assert!(link.queue_depths().all_empty());
+    }
+
+    #[test]
+    fn reports_stuck_packets() {
+        let link = QueueLink::new()
+            .ingressor(immediate_stream(0..20))
+            .processor(Identity::new())
+            .queue_capacity(5);
+        let depths = link.queue_depths();
+        let (mut runnables, egressors) = link.build_link();
+
+        // With nothing draining the egressor, the ingressor fills the queue and parks.
+        let waker = noop_waker();
+        let poll = Pin::new(&mut runnables[0]).poll(&mut Context::from_waker(&waker));
+        assert_eq!(poll, Poll::Pending);
+        assert_eq!(depths.get(), vec![5]);
+        assert!(!depths.all_empty());
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(run_link((runnables, egressors)));
+
+        assert_eq!(results[0], (0..20).collect::<Vec<i32>>());
+        assert_eq!(depths.get(), vec![0]);
+    }
+
+    #[test]
+    fn pipeline_drains_at_quiescence() {
+        let mut runtime = initialize_runtime();
+        let (results, depths) = runtime.block_on(async {
+            let classify = ClassifyLink::new()
+                .ingressor(immediate_stream(0..100))
+                .num_egressors(2)
+                .classifier(Even::new())
+                .dispatcher(Box::new(|is_even| if is_even { 0 } else { 1 }))
+                .queue_capacity(2);
+            let mut depths = vec![classify.queue_depths()];
+            let (mut runnables, classify_egressors) = classify.build_link();
+
+            let mut join = JoinLink::new().queue_capacity(2);
+            for egressor in classify_egressors {
+                let queue = QueueLink::new()
+                    .ingressor(egressor)
+                    .processor(Identity::new())
+                    .queue_capacity(3);
+                depths.push(queue.queue_depths());
+                let (mut queue_runnables, mut queue_egressors) = queue.build_link();
+                runnables.append(&mut queue_runnables);
+                join = join.ingressor(queue_egressors.remove(0));
+            }
+            depths.push(join.queue_depths());
+            let (mut join_runnables, join_egressors) = join.build_link();
+            runnables.append(&mut join_runnables);
+
+            (run_link((runnables, join_egressors)).await, depths)
+        });
+
+        let mut output = results[0].clone();
+        output.sort();
+        assert_eq!(output, (0..100).collect::<Vec<i32>>());
+
+        let queues: Vec<Vec<usize>> = depths.iter().map(|depths| depths.get()).collect();
+        assert_eq!(queues, vec![vec![0, 0], vec![0], vec![0], vec![0, 0]]);
+        assert!(depths.iter().all(|depths| depths.all_empty()));
+    }
+}
@@ -0,0 +1,273 @@
+//! # What is it for?
+//!
+//! A running router is otherwise a black box: its routes and counters live in shared handles
+//! inside the pipeline. `ManagementListener` serves those handles over a Unix socket, so they can
+//! be inspected without stopping anything. It only ever reads the handles, and runs as its own
+//! task, so it does not hold up forwarding.
+//!
+//! The protocol is line based. Each request is a single line, and each response is zero or more
+//! lines of text followed by an empty line. The requests are:
+//!
+//! - `show routes`: every route in the routing table, most specific first.
+//! - `show counters`: every counter, as its name and value.
+//! - `help`: the requests understood.
+
+use crate::routing::{Route, RoutingTable};
+use futures::prelude::*;
+use log::warn;
+use std::io;
+use std::os::unix::net::UnixListener as StdUnixListener;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+type Counter = Box<dyn Fn() -> u64 + Send + Sync>;
+
+const HELP: &str = "show routes\nshow counters\nhelp\n";
+
+/// How long to wait before accepting again after accepting a connection fails.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The state a `ManagementListener` reports on, shared by every connection.
+#[derive(Default)]
+struct ManagementState {
+    routes: Option<RoutingTable>,
+    counters: Vec<(String, Counter)>,
+}
+
+impl ManagementState {
+    /// The response to a single request line, without the empty line that ends it.
+    fn respond(&self, request: &str) -> String {
+        match request.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            ["show", "routes"] => match &self.routes {
+                Some(routes) => routes.routes().iter().map(format_route).collect(),
+                None => String::from("error: no routing table\n"),
+            },
+            ["show", "counters"] => self
+                .counters
+                .iter()
+                .map(|(name, read)| format!("{} {}\n", name, read()))
+                .collect(),
+            ["help"] => String::from(HELP),
+            _ => format!("error: unknown request {:?}, try help\n", request),
+        }
+    }
+}
+
+fn format_route(route: &Route) -> String {
+    let mut line = format!("{}/{}", route.prefix, route.prefix_len);
+    if route.is_blackhole() {
+        line.push_str(" blackhole");
+    }
+    for next_hop in route.next_hops.iter() {
+        match next_hop.gateway {
+            Some(gateway) => line.push_str(&format!(" via {}", gateway)),
+            None => line.push_str(" connected"),
+        }
+        line.push_str(&format!(" dev {}", next_hop.interface));
+    }
+    line.push('\n');
+    line
+}
+
+/// Serves the routes and counters of a running router over a Unix socket.
+///
+/// The socket is bound by `bind`, so that a bad path is reported at startup; requests are served
+/// once the future returned by `run` is spawned. It runs until the runtime shuts down, so spawn it
+/// alongside the pipeline rather than among the runnables the pipeline waits on.
+pub struct ManagementListener {
+    listener: StdUnixListener,
+    state: ManagementState,
+}
+
+impl ManagementListener {
+    /// Binds the socket at `path`, which must not already exist.
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let listener = StdUnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(ManagementListener {
+            listener,
+            state: ManagementState::default(),
+        })
+    }
+
+    /// Reports the routes of `routes` for `show routes`.
+    pub fn routes(self, routes: RoutingTable) -> Self {
+        ManagementListener {
+            state: ManagementState {
+                routes: Some(routes),
+                ..self.state
+            },
+            ..self
+        }
+    }
+
+    /// Adds a counter to `show counters`, read by calling `read` on every request. Counters are
+    /// reported in the order they are added.
+    pub fn counter(mut self, name: &str, read: Counter) -> Self {
+        self.state.counters.push((String::from(name), read));
+        self
+    }
+
+    /// Accepts connections, serving each in a task of its own. If accepting fails, for example
+    /// because the process is out of file descriptors, the error is logged and accepting resumes
+    /// after `ACCEPT_BACKOFF`, rather than retrying in a busy loop.
+    pub async fn run(self) {
+        let mut listener =
+            UnixListener::from_std(self.listener).expect("ManagementListener must run in Tokio");
+        let state = Arc::new(self.state);
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve(stream, Arc::clone(&state)));
+                }
+                Err(err) => {
+                    warn!("management socket failed to accept a connection: {}", err);
+                    tokio::time::delay_for(ACCEPT_BACKOFF).await;
+                }
+            }
+        }
+    }
+}
+
+/// Answers the requests of a single connection until it is closed.
+async fn serve(mut stream: UnixStream, state: Arc<ManagementState>) {
+    let (reader, mut writer) = stream.split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(Ok(request)) = lines.next().await {
+        let response = state.respond(&request) + "\n";
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::primitive::{DiscardLink, ProcessLink};
+    use crate::link::{LinkBuilder, ProcessLinkBuilder};
+    use crate::processor::Identity;
+    use crate::routing::NextHop;
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use std::net::Ipv4Addr;
+    use std::path::PathBuf;
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "route-rs-management-{}-{}.sock",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Sends each request in turn over a fresh connection, returning the response to each.
+    async fn request(path: &Path, requests: &[&str]) -> Vec<String> {
+        let mut stream = UnixStream::connect(path).await.unwrap();
+        let (reader, mut writer) = stream.split();
+        let mut lines = BufReader::new(reader).lines();
+
+        let mut responses = vec![];
+        for request in requests {
+            writer
+                .write_all(format!("{}\n", request).as_bytes())
+                .await
+                .unwrap();
+            let mut response = String::new();
+            while let Some(line) = lines.next().await {
+                let line = line.unwrap();
+                if line.is_empty() {
+                    break;
+                }
+                response.push_str(&line);
+                response.push('\n');
+            }
+            responses.push(response);
+        }
+        responses
+    }
+
+    #[test]
+    fn show_counters_reflects_traffic() {
+        let path = socket_path("counters");
+
+        let mut runtime = initialize_runtime();
+        let responses = runtime.block_on(async {
+            let (mut runnables, mut egressors) = ProcessLink::new()
+                .ingressor(immediate_stream(0..100))
+                .processor(Identity::new())
+                .build_link();
+            let discard = DiscardLink::new().ingressor(egressors.remove(0));
+            let discarded = discard.count();
+            let (mut discard_runnables, _) = discard.build_link();
+            runnables.append(&mut discard_runnables);
+
+            let listener = ManagementListener::bind(&path)
+                .unwrap()
+                .counter("discarded", Box::new(move || discarded.get()));
+            tokio::spawn(listener.run());
+
+            let before = request(&path, &["show counters"]).await;
+            run_link::<i32>((runnables, vec![])).await;
+            let after = request(&path, &["show counters"]).await;
+            vec![before[0].clone(), after[0].clone()]
+        });
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(responses, vec!["discarded 0\n", "discarded 100\n"]);
+    }
+
+    #[test]
+    fn show_routes() {
+        let path = socket_path("routes");
+        let routes = RoutingTable::new();
+        routes.insert(Route::new(
+            Ipv4Addr::new(0, 0, 0, 0),
+            0,
+            NextHop::via(Ipv4Addr::new(203, 0, 113, 1), 1),
+        ));
+        routes.insert(Route::new(
+            Ipv4Addr::new(192, 168, 1, 0),
+            24,
+            NextHop::connected(0),
+        ));
+        routes.insert(Route::blackhole(Ipv4Addr::new(10, 0, 0, 0), 8));
+
+        let mut runtime = initialize_runtime();
+        let responses = runtime.block_on(async {
+            let listener = ManagementListener::bind(&path)
+                .unwrap()
+                .routes(routes.clone());
+            tokio::spawn(listener.run());
+
+            request(&path, &["show routes", "show  conntrack", "help"]).await
+        });
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(
+            responses[0],
+            "192.168.1.0/24 connected dev 0\n\
+             10.0.0.0/8 blackhole\n\
+             0.0.0.0/0 via 203.0.113.1 dev 1\n"
+        );
+        assert_eq!(
+            responses[1],
+            "error: unknown request \"show  conntrack\", try help\n"
+        );
+        assert_eq!(responses[2], HELP);
+    }
+
+    #[test]
+    fn bind_fails_on_existing_path() {
+        let path = socket_path("existing");
+        let _first = ManagementListener::bind(&path).unwrap();
+
+        assert!(ManagementListener::bind(&path).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
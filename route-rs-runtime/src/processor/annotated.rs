@@ -0,0 +1,143 @@
+use crate::processor::Processor;
+use std::marker::PhantomData;
+
+/// Runs a `Processor` on packets that carry an annotation, such as the interfaces they arrived
+/// on and are bound for, without the processor having to know about it. Takes packets as
+/// `(annotation, packet)`, hands the packet to the inner processor, and pairs its output with the
+/// same annotation.
+///
+/// Since links are generic over their packet type, wrapping a processor this way lets composites
+/// like `MtransformNLink` run in an annotated pipeline: the annotation rides along through every
+/// link inside the composite.
+pub struct Annotated<P, A> {
+    processor: P,
+    phantom: PhantomData<A>,
+}
+
+impl<P: Processor, A: Send + Clone> Annotated<P, A> {
+    pub fn new(processor: P) -> Self {
+        Annotated {
+            processor,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The wrapped processor.
+    pub fn inner(&self) -> &P {
+        &self.processor
+    }
+}
+
+impl<P: Processor, A: Send + Clone> Processor for Annotated<P, A> {
+    type Input = (A, P::Input);
+    type Output = (A, P::Output);
+
+    fn process(&mut self, (annotation, packet): Self::Input) -> Option<Self::Output> {
+        let output = self.processor.process(packet)?;
+        Some((annotation, output))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::composite::MtransformNLink;
+    use crate::link::{LinkBuilder, PacketStream, ProcessLinkBuilder};
+    use crate::processor::{DecIpv4HopLimit, TransformFrom};
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use route_rs_packets::Ipv4Packet;
+    use std::net::Ipv4Addr;
+
+    /// The interfaces a packet arrived on and is bound for.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Interfaces {
+        inbound: usize,
+        outbound: usize,
+    }
+
+    const LAN_TO_WAN: Interfaces = Interfaces {
+        inbound: 0,
+        outbound: 1,
+    };
+    const WAN_TO_LAN: Interfaces = Interfaces {
+        inbound: 1,
+        outbound: 0,
+    };
+
+    /// Drops odd packets.
+    struct EvenOnly;
+
+    impl Processor for EvenOnly {
+        type Input = u32;
+        type Output = u32;
+
+        fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+            if packet % 2 == 0 {
+                Some(packet)
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_annotation() {
+        let mut elem = Annotated::new(EvenOnly);
+
+        assert_eq!(elem.process((LAN_TO_WAN, 4)), Some((LAN_TO_WAN, 4)));
+        assert_eq!(elem.process((WAN_TO_LAN, 5)), None);
+    }
+
+    #[test]
+    fn annotations_survive_composite() {
+        let lan = vec![(LAN_TO_WAN, 0x0A00_0001), (LAN_TO_WAN, 0x0A00_0002)];
+        let wan = vec![(WAN_TO_LAN, 0xC0A8_0001)];
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let input_streams: Vec<PacketStream<(Interfaces, u32)>> =
+                vec![immediate_stream(lan), immediate_stream(wan)];
+
+            let link = MtransformNLink::new()
+                .num_egressors(2)
+                .ingressors(input_streams)
+                .processor(Annotated::new(TransformFrom::<u32, Ipv4Addr>::new()))
+                .build_link();
+
+            run_link(link).await
+        });
+
+        let expected = vec![
+            (LAN_TO_WAN, Ipv4Addr::new(10, 0, 0, 1)),
+            (LAN_TO_WAN, Ipv4Addr::new(10, 0, 0, 2)),
+            (WAN_TO_LAN, Ipv4Addr::new(192, 168, 0, 1)),
+        ];
+        for egressor in results {
+            let mut egressor = egressor;
+            egressor.sort();
+            assert_eq!(egressor, expected);
+        }
+    }
+
+    #[test]
+    fn annotations_survive_packet_changes() {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_ttl(64);
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = MtransformNLink::new()
+                .num_egressors(1)
+                .ingressor(immediate_stream(vec![(WAN_TO_LAN, packet)]))
+                .processor(Annotated::new(DecIpv4HopLimit::new()))
+                .build_link();
+
+            run_link(link).await
+        });
+
+        let (interfaces, packet) = &results[0][0];
+        assert_eq!(*interfaces, WAN_TO_LAN);
+        assert_eq!(packet.ttl(), 63);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::processor::Processor;
+use route_rs_packets::Ipv4Packet;
+use std::net::Ipv4Addr;
+
+/// What a `BroadcastFilter` does with a kind of broadcast.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BroadcastPolicy {
+    /// Routes the packet like any other.
+    Forward,
+    /// Drops the packet.
+    Drop,
+    /// Hands the packet to the router itself, rather than routing it.
+    RedirectToHost,
+}
+
+/// Where a `BroadcastFilter` sent a packet.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BroadcastCheck {
+    /// The packet may continue to be routed.
+    Forward(Ipv4Packet),
+    /// The packet is for the router itself.
+    ToHost(Ipv4Packet),
+}
+
+/// Applies a `BroadcastPolicy` to IPv4 broadcasts, so that each deployment can choose how its
+/// router treats them. Limited broadcasts, to 255.255.255.255, are handled by the `limited`
+/// policy, which defaults to `RedirectToHost` since RFC 1812 forbids forwarding them. Directed
+/// broadcasts, to the broadcast address of one of the configured subnets, are handled by the
+/// `directed` policy, which defaults to `Drop` as RFC 2644 recommends, since forwarding them
+/// allows smurf amplification attacks. Every other packet is forwarded.
+///
+/// Subnets are given as a network address and prefix length; /31 and /32 subnets have no
+/// broadcast address (RFC 3021), so no packet is a directed broadcast to them.
+#[derive(Clone)]
+pub struct BroadcastFilter {
+    broadcast_addrs: Vec<Ipv4Addr>,
+    limited: BroadcastPolicy,
+    directed: BroadcastPolicy,
+}
+
+impl BroadcastFilter {
+    pub fn new(subnets: Vec<(Ipv4Addr, u8)>) -> Self {
+        let broadcast_addrs = subnets
+            .into_iter()
+            .filter(|(_, prefix_len)| *prefix_len < 31)
+            .map(|(network, prefix_len)| {
+                let host_mask = u32::MAX >> prefix_len;
+                Ipv4Addr::from(u32::from(network) | host_mask)
+            })
+            .collect();
+        BroadcastFilter {
+            broadcast_addrs,
+            limited: BroadcastPolicy::RedirectToHost,
+            directed: BroadcastPolicy::Drop,
+        }
+    }
+
+    /// Changes the policy for limited broadcasts, default is `RedirectToHost`.
+    pub fn limited(self, limited: BroadcastPolicy) -> Self {
+        BroadcastFilter { limited, ..self }
+    }
+
+    /// Changes the policy for directed broadcasts, default is `Drop`.
+    pub fn directed(self, directed: BroadcastPolicy) -> Self {
+        BroadcastFilter { directed, ..self }
+    }
+}
+
+impl Processor for BroadcastFilter {
+    type Input = Ipv4Packet;
+    type Output = BroadcastCheck;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let dest_addr = packet.dest_addr();
+        let policy = if dest_addr.is_broadcast() {
+            self.limited
+        } else if self.broadcast_addrs.contains(&dest_addr) {
+            self.directed
+        } else {
+            BroadcastPolicy::Forward
+        };
+
+        match policy {
+            BroadcastPolicy::Forward => Some(BroadcastCheck::Forward(packet)),
+            BroadcastPolicy::Drop => None,
+            BroadcastPolicy::RedirectToHost => Some(BroadcastCheck::ToHost(packet)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(dest_addr: Ipv4Addr) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(Ipv4Addr::new(203, 0, 113, 7));
+        packet.set_dest_addr(dest_addr);
+        packet.set_ttl(64);
+        packet.set_checksum();
+        packet
+    }
+
+    fn filter() -> BroadcastFilter {
+        BroadcastFilter::new(vec![
+            (Ipv4Addr::new(192, 168, 1, 0), 24),
+            (Ipv4Addr::new(10, 0, 0, 0), 8),
+            (Ipv4Addr::new(198, 51, 100, 0), 31),
+        ])
+    }
+
+    const DIRECTED: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 255);
+
+    #[test]
+    fn drops_directed_broadcast_by_default() {
+        assert_eq!(filter().process(packet(DIRECTED)), None);
+        assert_eq!(
+            filter().process(packet(Ipv4Addr::new(10, 255, 255, 255))),
+            None
+        );
+    }
+
+    #[test]
+    fn forwards_directed_broadcast() {
+        let mut elem = filter().directed(BroadcastPolicy::Forward);
+
+        assert_eq!(
+            elem.process(packet(DIRECTED)),
+            Some(BroadcastCheck::Forward(packet(DIRECTED)))
+        );
+    }
+
+    #[test]
+    fn redirects_directed_broadcast_to_host() {
+        let mut elem = filter().directed(BroadcastPolicy::RedirectToHost);
+
+        assert_eq!(
+            elem.process(packet(DIRECTED)),
+            Some(BroadcastCheck::ToHost(packet(DIRECTED)))
+        );
+    }
+
+    #[test]
+    fn limited_broadcast_follows_its_own_policy() {
+        let limited = Ipv4Addr::BROADCAST;
+
+        assert_eq!(
+            filter().process(packet(limited)),
+            Some(BroadcastCheck::ToHost(packet(limited)))
+        );
+        assert_eq!(
+            filter()
+                .limited(BroadcastPolicy::Drop)
+                .process(packet(limited)),
+            None
+        );
+    }
+
+    #[test]
+    fn forwards_unicast() {
+        for dest_addr in [
+            Ipv4Addr::new(192, 168, 1, 10),
+            Ipv4Addr::new(192, 168, 2, 255),
+            Ipv4Addr::new(198, 51, 100, 1),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                filter().process(packet(*dest_addr)),
+                Some(BroadcastCheck::Forward(packet(*dest_addr))),
+                "{}",
+                dest_addr
+            );
+        }
+    }
+}
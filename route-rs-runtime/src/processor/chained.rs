@@ -0,0 +1,159 @@
+use crate::processor::Processor;
+
+/// Runs two processors back to back as one, built by `Processor::and_then`. Packets go through
+/// `first`, and whatever it emits goes through `second`; a packet that `first` drops never reaches
+/// `second`. Fusing processors this way saves a `ProcessLink`, and the channel between them, for
+/// each step of a simple pipeline.
+pub struct Chained<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Chained<A, B>
+where
+    A: Processor,
+    B: Processor<Input = A::Output>,
+{
+    pub fn new(first: A, second: B) -> Self {
+        Chained { first, second }
+    }
+
+    /// The processor that runs first.
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    /// The processor that runs on the output of the first.
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+}
+
+impl<A, B> Processor for Chained<A, B>
+where
+    A: Processor,
+    B: Processor<Input = A::Output>,
+{
+    type Input = A::Input;
+    type Output = B::Output;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let packet = self.first.process(packet)?;
+        self.second.process(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::primitive::ProcessLink;
+    use crate::link::{LinkBuilder, ProcessLinkBuilder};
+    use crate::processor::{DecIpv4HopLimit, Identity, TransformFrom};
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use route_rs_packets::{EthernetFrame, Ipv4Packet};
+    use std::convert::TryFrom;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn packet(ttl: u8) -> Ipv4Packet {
+        let mac_data: Vec<u8> = vec![0xde, 0xad, 0xbe, 0xef, 0xff, 0xff, 1, 2, 3, 4, 5, 6, 0, 0];
+        let ip_data: Vec<u8> = vec![
+            0x45, 0, 0, 20, 0, 0, 0, 0, 64, 17, 0, 0, 192, 178, 128, 0, 10, 0, 0, 1,
+        ];
+        let mut frame = EthernetFrame::from_buffer(mac_data, 0).unwrap();
+        frame.set_payload(&ip_data);
+
+        let mut packet = Ipv4Packet::try_from(frame).unwrap();
+        packet.set_ttl(ttl);
+        packet.set_checksum();
+        packet
+    }
+
+    /// Decrements the TTL and refreshes the checksum, dropping packets whose TTL has run out.
+    struct ExpireTtl;
+
+    impl Processor for ExpireTtl {
+        type Input = Ipv4Packet;
+        type Output = Ipv4Packet;
+
+        fn process(&mut self, mut packet: Self::Input) -> Option<Self::Output> {
+            match packet.ttl() {
+                0 | 1 => None,
+                ttl => {
+                    packet.set_ttl(ttl - 1);
+                    packet.set_checksum();
+                    Some(packet)
+                }
+            }
+        }
+    }
+
+    /// Drops packets with a bad checksum, counting every packet it sees.
+    #[derive(Default)]
+    struct ValidateChecksum {
+        seen: Arc<AtomicUsize>,
+    }
+
+    impl Processor for ValidateChecksum {
+        type Input = Ipv4Packet;
+        type Output = Ipv4Packet;
+
+        fn process(&mut self, mut packet: Self::Input) -> Option<Self::Output> {
+            self.seen.fetch_add(1, Ordering::Relaxed);
+            if packet.validate_checksum() {
+                Some(packet)
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn runs_second_on_output_of_first() {
+        let mut elem = ExpireTtl.and_then(ValidateChecksum::default());
+
+        let packet = elem.process(packet(64)).unwrap();
+
+        assert_eq!(packet.ttl(), 63);
+        assert_eq!(elem.second().seen.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn none_from_first_skips_second() {
+        let mut elem = ExpireTtl.and_then(ValidateChecksum::default());
+
+        assert!(elem.process(packet(1)).is_none());
+        assert_eq!(elem.second().seen.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn none_from_second() {
+        // DecIpv4HopLimit leaves the checksum stale, so validation drops what it emits.
+        let mut elem = DecIpv4HopLimit::new().and_then(ValidateChecksum::default());
+
+        assert!(elem.process(packet(64)).is_none());
+        assert_eq!(elem.second().seen.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn in_process_link() {
+        let packets: Vec<u8> = vec![0, 1, 2, 3, 4];
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = ProcessLink::new()
+                .ingressor(immediate_stream(packets.clone()))
+                .processor(
+                    TransformFrom::<u8, u32>::new()
+                        .and_then(Identity::new())
+                        .and_then(TransformFrom::<u32, u64>::new()),
+                )
+                .build_link();
+
+            run_link(link).await
+        });
+
+        assert_eq!(results[0], vec![0u64, 1, 2, 3, 4]);
+    }
+}
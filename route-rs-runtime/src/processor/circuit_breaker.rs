@@ -0,0 +1,255 @@
+use crate::link::utils::clock::{Clock, SystemClock};
+use crate::processor::{FallibleProcessor, Processor};
+use std::time::{Duration, Instant};
+
+/// What a `CircuitBreaker` does with packets while it is open.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpenPolicy {
+    /// Forward packets untransformed, as if the stage were not there.
+    PassThrough,
+    /// Drop packets.
+    Drop,
+}
+
+/// The state of a `CircuitBreaker`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Packets go through the wrapped processor.
+    Closed,
+    /// The wrapped processor failed too many times in a row, so it is skipped until the cooldown
+    /// is over.
+    Open,
+    /// The cooldown is over, and the next packet will go through the wrapped processor as a trial.
+    HalfOpen,
+}
+
+/// Wraps a `FallibleProcessor` that may start failing, such as a NAT or crypto stage with bad
+/// state, so that it cannot take down forwarding. Packets go through the wrapped processor as
+/// usual, and a packet it fails on is dropped. After `threshold` failures in a row the breaker
+/// trips open: the processor is skipped for `cooldown`, and packets are handled according to the
+/// `OpenPolicy`. Once the cooldown is over, the next packet is a trial. If the processor handles
+/// it, the breaker closes again, and if it fails, the breaker opens for another cooldown.
+///
+/// Packets can only pass through untransformed if the processor does not change their type, so
+/// the input and output of the wrapped processor must be the same.
+pub struct CircuitBreaker<P> {
+    processor: P,
+    threshold: usize,
+    cooldown: Duration,
+    policy: OpenPolicy,
+    clock: Box<dyn Clock>,
+    failures: usize,
+    open_until: Option<Instant>,
+}
+
+impl<P: FallibleProcessor> CircuitBreaker<P> {
+    /// Trips after `threshold` failures in a row, and stays open for `cooldown`. Packets are
+    /// dropped while the breaker is open, since forwarding them untransformed is not safe for
+    /// every stage; use `when_open` to pass them through instead.
+    pub fn new(processor: P, threshold: usize, cooldown: Duration) -> Self {
+        assert!(threshold > 0, "threshold: {}, must be > 0", threshold);
+        CircuitBreaker {
+            processor,
+            threshold,
+            cooldown,
+            policy: OpenPolicy::Drop,
+            clock: Box::new(SystemClock),
+            failures: 0,
+            open_until: None,
+        }
+    }
+
+    /// Changes what is done with packets while the breaker is open, default is `OpenPolicy::Drop`.
+    pub fn when_open(self, policy: OpenPolicy) -> Self {
+        CircuitBreaker { policy, ..self }
+    }
+
+    /// Changes the clock used to time the cooldown, default is `SystemClock`.
+    pub fn clock<C: Clock + 'static>(self, clock: C) -> Self {
+        CircuitBreaker {
+            clock: Box::new(clock),
+            ..self
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        match self.open_until {
+            None => BreakerState::Closed,
+            Some(until) if self.clock.now() < until => BreakerState::Open,
+            Some(_) => BreakerState::HalfOpen,
+        }
+    }
+
+    /// The wrapped processor.
+    pub fn inner(&self) -> &P {
+        &self.processor
+    }
+}
+
+impl<P, T> Processor for CircuitBreaker<P>
+where
+    P: FallibleProcessor<Input = T, Output = T>,
+    T: Send + Clone,
+{
+    type Input = T;
+    type Output = T;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        if self.state() == BreakerState::Open {
+            return match self.policy {
+                OpenPolicy::PassThrough => Some(packet),
+                OpenPolicy::Drop => None,
+            };
+        }
+
+        match self.processor.try_process(packet) {
+            Ok(output) => {
+                self.failures = 0;
+                self.open_until = None;
+                output
+            }
+            Err(_) => {
+                self.failures += 1;
+                // A failed trial reopens the breaker straight away.
+                if self.failures >= self.threshold || self.open_until.is_some() {
+                    self.open_until = Some(self.clock.now() + self.cooldown);
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::clock::ManualClock;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Adds one to each packet, or fails while `broken` is set. Counts every call.
+    #[derive(Default)]
+    struct Flaky {
+        broken: Arc<AtomicBool>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl FallibleProcessor for Flaky {
+        type Input = i32;
+        type Output = i32;
+        type Error = &'static str;
+
+        fn try_process(
+            &mut self,
+            packet: Self::Input,
+        ) -> Result<Option<Self::Output>, Self::Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.broken.load(Ordering::SeqCst) {
+                Err("broken")
+            } else {
+                Ok(Some(packet + 1))
+            }
+        }
+    }
+
+    const COOLDOWN: Duration = Duration::from_secs(1);
+
+    fn breaker(clock: &ManualClock) -> (CircuitBreaker<Flaky>, Arc<AtomicBool>) {
+        let flaky = Flaky::default();
+        let broken = Arc::clone(&flaky.broken);
+        let breaker = CircuitBreaker::new(flaky, 3, COOLDOWN)
+            .when_open(OpenPolicy::PassThrough)
+            .clock(clock.clone());
+        (breaker, broken)
+    }
+
+    #[test]
+    fn closed_runs_processor() {
+        let clock = ManualClock::new();
+        let (mut elem, _) = breaker(&clock);
+
+        assert_eq!(elem.process(1), Some(2));
+        assert_eq!(elem.state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let clock = ManualClock::new();
+        let (mut elem, broken) = breaker(&clock);
+        broken.store(true, Ordering::SeqCst);
+
+        assert_eq!(elem.process(1), None);
+        assert_eq!(elem.process(2), None);
+        assert_eq!(elem.state(), BreakerState::Closed);
+        assert_eq!(elem.process(3), None);
+        assert_eq!(elem.state(), BreakerState::Open);
+
+        // While open, packets pass through untransformed without reaching the processor.
+        assert_eq!(elem.process(4), Some(4));
+        assert_eq!(elem.inner().calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let clock = ManualClock::new();
+        let (mut elem, broken) = breaker(&clock);
+
+        for _ in 0..3 {
+            broken.store(true, Ordering::SeqCst);
+            elem.process(1);
+            elem.process(1);
+            broken.store(false, Ordering::SeqCst);
+            assert_eq!(elem.process(1), Some(2));
+        }
+        assert_eq!(elem.state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn closes_after_cooldown() {
+        let clock = ManualClock::new();
+        let (mut elem, broken) = breaker(&clock);
+        broken.store(true, Ordering::SeqCst);
+        for packet in 0..3 {
+            elem.process(packet);
+        }
+        broken.store(false, Ordering::SeqCst);
+
+        clock.advance(COOLDOWN / 2);
+        assert_eq!(elem.state(), BreakerState::Open);
+        assert_eq!(elem.process(10), Some(10));
+
+        clock.advance(COOLDOWN / 2);
+        assert_eq!(elem.state(), BreakerState::HalfOpen);
+        assert_eq!(elem.process(10), Some(11));
+        assert_eq!(elem.state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn failed_trial_reopens() {
+        let clock = ManualClock::new();
+        let (mut elem, broken) = breaker(&clock);
+        broken.store(true, Ordering::SeqCst);
+        for packet in 0..3 {
+            elem.process(packet);
+        }
+
+        clock.advance(COOLDOWN);
+        assert_eq!(elem.state(), BreakerState::HalfOpen);
+        assert_eq!(elem.process(10), None);
+        assert_eq!(elem.state(), BreakerState::Open);
+        assert_eq!(elem.inner().calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn drops_while_open_by_default() {
+        let clock = ManualClock::new();
+        let flaky = Flaky::default();
+        flaky.broken.store(true, Ordering::SeqCst);
+        let mut elem = CircuitBreaker::new(flaky, 1, COOLDOWN).clock(clock.clone());
+
+        assert_eq!(elem.process(1), None);
+        assert_eq!(elem.state(), BreakerState::Open);
+        assert_eq!(elem.process(2), None);
+        assert_eq!(elem.inner().calls.load(Ordering::SeqCst), 1);
+    }
+}
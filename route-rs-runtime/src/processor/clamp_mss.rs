@@ -0,0 +1,276 @@
+use crate::interface::InterfaceConfig;
+use crate::processor::Processor;
+use route_rs_packets::{IpProtocol, Ipv4Packet};
+
+const TCP_FLAG_SYN: u8 = 0x02;
+const TCP_OPTION_END: u8 = 0;
+const TCP_OPTION_NOP: u8 = 1;
+const TCP_OPTION_MSS: u8 = 2;
+
+/// Clamps the maximum segment size that TCP peers announce to each other, so that their segments
+/// fit the MTU of a narrower path, such as a PPPoE or tunneled WAN. Hosts pick their MSS from the
+/// MTU of their own link, and if the path cannot carry segments that big, and ICMP fragmentation
+/// needed messages are filtered on the way back, the connection hangs once it sends full segments.
+///
+/// The MSS option of TCP SYN and SYN-ACK segments is lowered to the MTU less 40 bytes of IPv4 and
+/// TCP header, if it is larger, and the TCP checksum is updated to match. Every other packet, and
+/// every SYN whose MSS already fits, is passed through untouched.
+#[derive(Clone)]
+pub struct ClampMss {
+    mtu: Mtu,
+}
+
+/// Where `ClampMss` finds the MTU it clamps to.
+#[derive(Clone)]
+enum Mtu {
+    /// Given at construction.
+    Fixed(u16),
+    /// Read from the `InterfaceConfig` on every packet, so changes to the interface apply at once.
+    Interface {
+        config: InterfaceConfig,
+        interface: usize,
+    },
+}
+
+impl ClampMss {
+    /// Clamps the MSS to fit `mtu`.
+    pub fn new(mtu: u16) -> Self {
+        assert!(mtu >= 68, "mtu: {}, must be >= 68", mtu);
+        ClampMss {
+            mtu: Mtu::Fixed(mtu),
+        }
+    }
+
+    /// Clamps the MSS to fit the MTU of `interface` in `config`. The MTU is read for each packet,
+    /// so the clamp follows later changes to the interface. Packets are passed through untouched
+    /// while the interface is not configured.
+    pub fn for_interface(config: &InterfaceConfig, interface: usize) -> Self {
+        config
+            .mtu(interface)
+            .expect("Cannot clamp MSS! Interface is not configured");
+        ClampMss {
+            mtu: Mtu::Interface {
+                config: config.clone(),
+                interface,
+            },
+        }
+    }
+
+    /// The largest MSS that fits the MTU, if it is known.
+    fn mss(&self) -> Option<u16> {
+        let mtu = match &self.mtu {
+            Mtu::Fixed(mtu) => *mtu,
+            Mtu::Interface { config, interface } => config.mtu(*interface)?,
+        };
+        Some(mtu.max(68) - 40)
+    }
+}
+
+/// The offset into `packet.data` of the MSS value of a TCP SYN, if `packet` is one and has an MSS
+/// option.
+fn mss_offset(packet: &Ipv4Packet) -> Option<usize> {
+    if packet.protocol() != IpProtocol::TCP || packet.fragment_offset() != 0 {
+        return None;
+    }
+    let tcp = packet.payload_offset;
+    let header = packet.data.get(tcp..tcp + 20)?;
+    if header[13] & TCP_FLAG_SYN == 0 {
+        return None;
+    }
+    let options_end = (tcp + ((header[12] >> 4) as usize * 4)).min(packet.data.len());
+
+    let mut option = tcp + 20;
+    while option < options_end {
+        match packet.data[option] {
+            TCP_OPTION_END => return None,
+            TCP_OPTION_NOP => option += 1,
+            kind => {
+                let len = *packet.data.get(option + 1)? as usize;
+                if len < 2 || option + len > options_end {
+                    return None;
+                }
+                if kind == TCP_OPTION_MSS && len == 4 {
+                    return Some(option + 2);
+                }
+                option += len;
+            }
+        }
+    }
+    None
+}
+
+impl Processor for ClampMss {
+    type Input = Ipv4Packet;
+    type Output = Ipv4Packet;
+
+    fn process(&mut self, mut packet: Self::Input) -> Option<Self::Output> {
+        let (offset, clamped) = match (mss_offset(&packet), self.mss()) {
+            (Some(offset), Some(clamped)) => (offset, clamped),
+            _ => return Some(packet),
+        };
+        let mss = u16::from_be_bytes([packet.data[offset], packet.data[offset + 1]]);
+        if mss <= clamped {
+            return Some(packet);
+        }
+
+        packet.data[offset..offset + 2].copy_from_slice(&clamped.to_be_bytes());
+        // The checksum sums the segment in 16 bit words, so an MSS at an odd offset into the
+        // segment counts with its bytes swapped.
+        if (offset - packet.payload_offset) % 2 == 0 {
+            packet.update_transport_checksum(mss, clamped);
+        } else {
+            packet.update_transport_checksum(mss.swap_bytes(), clamped.swap_bytes());
+        }
+        Some(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::InterfaceSettings;
+    use route_rs_packets::MacAddr;
+    use std::net::Ipv4Addr;
+
+    const SYN: u8 = 0x02;
+    const SYN_ACK: u8 = 0x12;
+    const ACK: u8 = 0x10;
+
+    /// A TCP segment with `flags`, whose options are an MSS of `mss` after two NOPs, and a valid
+    /// checksum.
+    fn segment(flags: u8, mss: u16) -> Ipv4Packet {
+        let mut tcp = vec![0; 20];
+        tcp[0..2].copy_from_slice(&50000u16.to_be_bytes());
+        tcp[2..4].copy_from_slice(&443u16.to_be_bytes());
+        // 28 bytes of header, with the options.
+        tcp[12] = 0x70;
+        tcp[13] = flags;
+        tcp[14..16].copy_from_slice(&65535u16.to_be_bytes());
+        tcp.extend_from_slice(&[TCP_OPTION_NOP, TCP_OPTION_NOP, TCP_OPTION_MSS, 4]);
+        tcp.extend_from_slice(&mss.to_be_bytes());
+        // Pad the options out to a 32 bit boundary.
+        tcp.extend_from_slice(&[TCP_OPTION_END, TCP_OPTION_END]);
+
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(Ipv4Addr::new(192, 168, 1, 10));
+        packet.set_dest_addr(Ipv4Addr::new(93, 184, 216, 34));
+        packet.set_ttl(64);
+        packet.set_protocol(6);
+        packet.set_payload(&tcp);
+        packet.recompute_transport_checksum();
+        packet
+    }
+
+    fn mss(packet: &Ipv4Packet) -> u16 {
+        let offset = mss_offset(packet).unwrap();
+        u16::from_be_bytes([packet.data[offset], packet.data[offset + 1]])
+    }
+
+    #[test]
+    fn clamps_syn_to_path_mtu() {
+        let mut elem = ClampMss::new(1492);
+        assert!(segment(SYN, 1460).validate_transport_checksum());
+
+        let packet = elem.process(segment(SYN, 1460)).unwrap();
+
+        assert_eq!(mss(&packet), 1452);
+        assert!(packet.validate_transport_checksum());
+    }
+
+    #[test]
+    fn clamps_syn_ack() {
+        let mut elem = ClampMss::new(1492);
+
+        let packet = elem.process(segment(SYN_ACK, 1460)).unwrap();
+
+        assert_eq!(mss(&packet), 1452);
+        assert!(packet.validate_transport_checksum());
+    }
+
+    #[test]
+    fn clamps_mss_at_odd_offset() {
+        let mut elem = ClampMss::new(1492);
+        let mut syn = segment(SYN, 1460);
+        let options = syn.payload_offset + 20;
+        syn.data[options..options + 8].copy_from_slice(&[
+            TCP_OPTION_NOP,
+            TCP_OPTION_MSS,
+            4,
+            0x05,
+            0xB4,
+            TCP_OPTION_END,
+            TCP_OPTION_END,
+            TCP_OPTION_END,
+        ]);
+        syn.recompute_transport_checksum();
+
+        let packet = elem.process(syn).unwrap();
+
+        assert_eq!(mss(&packet), 1452);
+        assert!(packet.validate_transport_checksum());
+    }
+
+    #[test]
+    fn leaves_smaller_mss_alone() {
+        let mut elem = ClampMss::new(1492);
+
+        let packet = elem.process(segment(SYN, 1400)).unwrap();
+
+        assert_eq!(packet, segment(SYN, 1400));
+    }
+
+    #[test]
+    fn leaves_non_syn_alone() {
+        let mut elem = ClampMss::new(1492);
+
+        let packet = elem.process(segment(ACK, 1460)).unwrap();
+
+        assert_eq!(packet, segment(ACK, 1460));
+    }
+
+    #[test]
+    fn leaves_non_tcp_alone() {
+        let mut elem = ClampMss::new(1492);
+        let mut udp = segment(SYN, 1460);
+        udp.set_protocol(17);
+        udp.set_checksum();
+
+        let packet = elem.process(udp.clone()).unwrap();
+
+        assert_eq!(packet, udp);
+    }
+
+    #[test]
+    fn reads_mtu_of_interface() {
+        let config = InterfaceConfig::new();
+        config.set(
+            0,
+            InterfaceSettings::new(
+                1400,
+                MacAddr::new([0x02, 0, 0, 0, 0, 1]),
+                vec![Ipv4Addr::new(203, 0, 113, 7)],
+            ),
+        );
+        let mut elem = ClampMss::for_interface(&config, 0);
+
+        let packet = elem.process(segment(SYN, 1460)).unwrap();
+
+        assert_eq!(mss(&packet), 1360);
+    }
+
+    #[test]
+    fn follows_changes_to_interface() {
+        let mac = MacAddr::new([0x02, 0, 0, 0, 0, 1]);
+        let config = InterfaceConfig::new();
+        config.set(0, InterfaceSettings::new(1500, mac, vec![]));
+        let mut elem = ClampMss::for_interface(&config, 0);
+
+        assert_eq!(mss(&elem.process(segment(SYN, 1460)).unwrap()), 1460);
+
+        config.set(0, InterfaceSettings::new(1492, mac, vec![]));
+        let packet = elem.process(segment(SYN, 1460)).unwrap();
+
+        assert_eq!(mss(&packet), 1452);
+        assert!(packet.validate_transport_checksum());
+    }
+}
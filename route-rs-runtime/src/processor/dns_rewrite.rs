@@ -0,0 +1,365 @@
+use crate::processor::Processor;
+use route_rs_packets::{IpProtocol, Ipv4Packet};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::net::Ipv4Addr;
+
+const DNS_PORT: u16 = 53;
+const DNS_HEADER_LEN: usize = 12;
+const DNS_TYPE_A: u16 = 1;
+const DNS_CLASS_IN: u16 = 1;
+const DNS_TYPE_OPT: u16 = 41;
+/// The UDP payload size advertised in the OPT record of responses to EDNS queries.
+const EDNS_UDP_PAYLOAD_SIZE: u16 = 1232;
+
+/// The outcome of passing a packet through `DnsRewrite`.
+#[derive(Clone, Debug)]
+pub enum DnsRewriteOutput {
+    /// The packet was not a query for a configured name, and continues unchanged.
+    PassThrough(Ipv4Packet),
+    /// The packet was a query for a configured name. It was dropped, and this is the synthesized
+    /// response, addressed back to the client.
+    Response(Ipv4Packet),
+}
+
+/// Answers DNS A queries for configured names locally, for split-horizon setups such as resolving
+/// `router.lan` to the LAN address of the router. Only well formed UDP queries to port 53 with a
+/// single question are considered; TCP, other record types, unknown names, and anything that does
+/// not parse pass through untouched. Queries may carry an EDNS version 0 OPT record (RFC 6891), in
+/// which case the response carries an OPT record of its own, without any options.
+#[derive(Clone, Default)]
+pub struct DnsRewrite {
+    names: HashMap<String, Ipv4Addr>,
+    ttl: u32,
+}
+
+/// The parts of a query needed to build a response.
+struct DnsQuery {
+    /// Offset of the DNS message in the IPv4 payload.
+    offset: usize,
+    /// Length of the DNS message up to the end of the question.
+    len: usize,
+    name: String,
+    /// Whether the query carried an EDNS OPT record.
+    edns: bool,
+}
+
+impl DnsRewrite {
+    pub fn new() -> Self {
+        DnsRewrite {
+            names: HashMap::new(),
+            ttl: 60,
+        }
+    }
+
+    /// Answers A queries for `name` with `addr`. Names are matched case insensitively.
+    pub fn name(mut self, name: &str, addr: Ipv4Addr) -> Self {
+        self.names
+            .insert(name.trim_end_matches('.').to_ascii_lowercase(), addr);
+        self
+    }
+
+    /// Changes the TTL of synthesized answers, default is 60 seconds.
+    pub fn ttl(self, ttl: u32) -> Self {
+        DnsRewrite {
+            names: self.names,
+            ttl,
+        }
+    }
+
+    /// Parses `payload` as a UDP datagram holding a standard DNS query with one A/IN question, and
+    /// at most an EDNS OPT record besides.
+    fn parse_query(payload: &[u8]) -> Option<DnsQuery> {
+        let udp = payload.get(..8)?;
+        let dest_port = u16::from_be_bytes(udp[2..4].try_into().unwrap());
+        let udp_len = u16::from_be_bytes(udp[4..6].try_into().unwrap()) as usize;
+        if dest_port != DNS_PORT || udp_len < 8 + DNS_HEADER_LEN || udp_len > payload.len() {
+            return None;
+        }
+
+        let dns = &payload[8..udp_len];
+        let flags = u16::from_be_bytes(dns[2..4].try_into().unwrap());
+        let counts: Vec<u16> = dns[4..12]
+            .chunks_exact(2)
+            .map(|count| u16::from_be_bytes([count[0], count[1]]))
+            .collect();
+        // QR must be clear, and the opcode must be a standard query.
+        if flags & 0xF800 != 0 || counts[..3] != [1, 0, 0] || counts[3] > 1 {
+            return None;
+        }
+
+        let mut labels = vec![];
+        let mut position = DNS_HEADER_LEN;
+        loop {
+            let len = *dns.get(position)? as usize;
+            position += 1;
+            if len == 0 {
+                break;
+            }
+            // Compression pointers and extended labels have no place in a query's question.
+            if len > 63 {
+                return None;
+            }
+            let label = dns.get(position..position + len)?;
+            labels.push(String::from_utf8(label.to_vec()).ok()?.to_ascii_lowercase());
+            position += len;
+        }
+
+        let question = dns.get(position..position + 4)?;
+        let qtype = u16::from_be_bytes(question[0..2].try_into().unwrap());
+        let qclass = u16::from_be_bytes(question[2..4].try_into().unwrap());
+        if qtype != DNS_TYPE_A || qclass != DNS_CLASS_IN {
+            return None;
+        }
+
+        let edns = counts[3] == 1;
+        if edns {
+            // The OPT record has the root as its name, and its TTL holds the EDNS version.
+            let opt = dns.get(position + 4..position + 15)?;
+            let rtype = u16::from_be_bytes(opt[1..3].try_into().unwrap());
+            let rdlen = u16::from_be_bytes(opt[9..11].try_into().unwrap()) as usize;
+            if opt[0] != 0 || rtype != DNS_TYPE_OPT || opt[6] != 0 {
+                return None;
+            }
+            dns.get(position + 15..position + 15 + rdlen)?;
+        }
+
+        Some(DnsQuery {
+            offset: 8,
+            len: position + 4,
+            name: labels.join("."),
+            edns,
+        })
+    }
+
+    fn respond(&self, query: &Ipv4Packet, parsed: &DnsQuery, addr: Ipv4Addr) -> Ipv4Packet {
+        let payload = query.payload();
+        let request = &payload[parsed.offset..parsed.offset + parsed.len];
+
+        let mut dns = Vec::with_capacity(parsed.len + 16);
+        dns.extend_from_slice(&request[0..2]);
+        // QR, AA and RA set, RD copied from the query, NOERROR.
+        let recursion_desired = request[2] & 0x01;
+        dns.extend_from_slice(&[0x84 | recursion_desired, 0x80]);
+        dns.extend_from_slice(&[0, 1, 0, 1, 0, 0, 0, parsed.edns as u8]);
+        dns.extend_from_slice(&request[DNS_HEADER_LEN..]);
+        // The answer names the question by pointing at it.
+        dns.extend_from_slice(&[0xC0, DNS_HEADER_LEN as u8]);
+        dns.extend_from_slice(&DNS_TYPE_A.to_be_bytes());
+        dns.extend_from_slice(&DNS_CLASS_IN.to_be_bytes());
+        dns.extend_from_slice(&self.ttl.to_be_bytes());
+        dns.extend_from_slice(&4u16.to_be_bytes());
+        dns.extend_from_slice(&addr.octets());
+        if parsed.edns {
+            dns.push(0);
+            dns.extend_from_slice(&DNS_TYPE_OPT.to_be_bytes());
+            dns.extend_from_slice(&EDNS_UDP_PAYLOAD_SIZE.to_be_bytes());
+            // No extended RCODE, version 0, no flags, and no options.
+            dns.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        }
+
+        let mut udp = Vec::with_capacity(8 + dns.len());
+        udp.extend_from_slice(&payload[2..4]);
+        udp.extend_from_slice(&payload[0..2]);
+        udp.extend_from_slice(&((8 + dns.len()) as u16).to_be_bytes());
+        // A zero checksum means no checksum over IPv4.
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend_from_slice(&dns);
+
+        let mut response = Ipv4Packet::empty();
+        response.set_payload(&udp);
+        response.set_protocol(17);
+        response.set_ttl(64);
+        response.set_src_addr(query.dest_addr());
+        response.set_dest_addr(query.src_addr());
+        response.set_checksum();
+        response
+    }
+}
+
+impl Processor for DnsRewrite {
+    type Input = Ipv4Packet;
+    type Output = DnsRewriteOutput;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        if packet.protocol() != IpProtocol::UDP {
+            return Some(DnsRewriteOutput::PassThrough(packet));
+        }
+
+        let response = DnsRewrite::parse_query(&packet.payload()).and_then(|parsed| {
+            self.names
+                .get(&parsed.name)
+                .map(|addr| self.respond(&packet, &parsed, *addr))
+        });
+        match response {
+            Some(response) => Some(DnsRewriteOutput::Response(response)),
+            None => Some(DnsRewriteOutput::PassThrough(packet)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 10);
+    const RESOLVER: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 1);
+    const ROUTER_LAN: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 1);
+
+    fn dns_query(name: &str, qtype: u16) -> Vec<u8> {
+        let mut dns = vec![0xBE, 0xEF, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in name.split('.') {
+            dns.push(label.len() as u8);
+            dns.extend_from_slice(label.as_bytes());
+        }
+        dns.push(0);
+        dns.extend_from_slice(&qtype.to_be_bytes());
+        dns.extend_from_slice(&DNS_CLASS_IN.to_be_bytes());
+        dns
+    }
+
+    fn query_packet(protocol: u8, dns: &[u8]) -> Ipv4Packet {
+        let mut udp = vec![0xC3, 0x50, 0, 53];
+        udp.extend_from_slice(&((8 + dns.len()) as u16).to_be_bytes());
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend_from_slice(dns);
+
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(CLIENT);
+        packet.set_dest_addr(RESOLVER);
+        packet.set_ttl(64);
+        packet.set_protocol(protocol);
+        packet.set_payload(&udp);
+        packet.set_checksum();
+        packet
+    }
+
+    fn rewrite() -> DnsRewrite {
+        DnsRewrite::new().name("router.lan", ROUTER_LAN).ttl(300)
+    }
+
+    fn passes_through(query: Ipv4Packet) {
+        match rewrite().process(query.clone()).unwrap() {
+            DnsRewriteOutput::PassThrough(packet) => assert_eq!(packet, query),
+            other => panic!("Expected PassThrough, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn answers_configured_name() {
+        let query = query_packet(17, &dns_query("Router.LAN", DNS_TYPE_A));
+
+        let mut response = match rewrite().process(query).unwrap() {
+            DnsRewriteOutput::Response(response) => response,
+            other => panic!("Expected Response, got {:?}", other),
+        };
+
+        assert_eq!(response.src_addr(), RESOLVER);
+        assert_eq!(response.dest_addr(), CLIENT);
+        assert!(response.validate_checksum());
+
+        let udp = response.payload();
+        assert_eq!(udp[0..4], [0, 53, 0xC3, 0x50]);
+        assert_eq!(u16::from_be_bytes([udp[4], udp[5]]) as usize, udp.len());
+
+        let dns = &udp[8..];
+        let question = dns_query("Router.LAN", DNS_TYPE_A);
+        // Same ID, a response with AA, RD and RA set, one question and one answer.
+        assert_eq!(dns[0..12], [0xBE, 0xEF, 0x85, 0x80, 0, 1, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(dns[12..question.len()], question[12..]);
+
+        let answer = &dns[question.len()..];
+        assert_eq!(answer[0..2], [0xC0, 12]);
+        assert_eq!(answer[2..6], [0, 1, 0, 1]);
+        assert_eq!(u32::from_be_bytes(answer[6..10].try_into().unwrap()), 300);
+        assert_eq!(answer[10..12], [0, 4]);
+        assert_eq!(answer[12..16], ROUTER_LAN.octets());
+        assert_eq!(answer.len(), 16);
+    }
+
+    /// `dns_query`, with an OPT record of EDNS `version` carrying a cookie option.
+    fn edns_query(name: &str, version: u8) -> Vec<u8> {
+        let mut dns = dns_query(name, DNS_TYPE_A);
+        dns[11] = 1;
+        dns.push(0);
+        dns.extend_from_slice(&DNS_TYPE_OPT.to_be_bytes());
+        dns.extend_from_slice(&4096u16.to_be_bytes());
+        dns.extend_from_slice(&[0, version, 0x80, 0]);
+        dns.extend_from_slice(&12u16.to_be_bytes());
+        dns.extend_from_slice(&[0, 10, 0, 8, 1, 2, 3, 4, 5, 6, 7, 8]);
+        dns
+    }
+
+    #[test]
+    fn answers_edns_query() {
+        let query = query_packet(17, &edns_query("router.lan", 0));
+
+        let response = match rewrite().process(query).unwrap() {
+            DnsRewriteOutput::Response(response) => response,
+            other => panic!("Expected Response, got {:?}", other),
+        };
+
+        let udp = response.payload();
+        assert_eq!(u16::from_be_bytes([udp[4], udp[5]]) as usize, udp.len());
+        let dns = &udp[8..];
+        // One question, one answer, and an OPT record.
+        assert_eq!(dns[4..12], [0, 1, 0, 1, 0, 0, 0, 1]);
+
+        let answer = &dns[dns_query("router.lan", DNS_TYPE_A).len()..];
+        assert_eq!(answer[12..16], ROUTER_LAN.octets());
+        let opt = &answer[16..];
+        assert_eq!(opt[0..3], [0, 0, 41]);
+        assert_eq!(u16::from_be_bytes([opt[3], opt[4]]), EDNS_UDP_PAYLOAD_SIZE);
+        assert_eq!(opt[5..11], [0, 0, 0, 0, 0, 0]);
+        assert_eq!(opt.len(), 11);
+    }
+
+    #[test]
+    fn passes_unsupported_edns() {
+        passes_through(query_packet(17, &edns_query("router.lan", 1)));
+
+        let mut truncated = edns_query("router.lan", 0);
+        truncated.truncate(truncated.len() - 4);
+        passes_through(query_packet(17, &truncated));
+
+        let mut not_opt = edns_query("router.lan", 0);
+        not_opt[dns_query("router.lan", DNS_TYPE_A).len() + 2] = 1;
+        passes_through(query_packet(17, &not_opt));
+    }
+
+    #[test]
+    fn passes_unconfigured_name() {
+        passes_through(query_packet(17, &dns_query("example.com", DNS_TYPE_A)));
+    }
+
+    #[test]
+    fn passes_other_record_types() {
+        passes_through(query_packet(17, &dns_query("router.lan", 28)));
+    }
+
+    #[test]
+    fn passes_tcp() {
+        passes_through(query_packet(6, &dns_query("router.lan", DNS_TYPE_A)));
+    }
+
+    #[test]
+    fn passes_malformed() {
+        let mut truncated = dns_query("router.lan", DNS_TYPE_A);
+        truncated.truncate(20);
+        passes_through(query_packet(17, &truncated));
+
+        let mut response = dns_query("router.lan", DNS_TYPE_A);
+        response[2] |= 0x80;
+        passes_through(query_packet(17, &response));
+
+        let mut compressed = dns_query("router.lan", DNS_TYPE_A);
+        compressed[12] = 0xC0;
+        passes_through(query_packet(17, &compressed));
+
+        let mut not_dns = query_packet(17, &dns_query("router.lan", DNS_TYPE_A));
+        let mut udp = not_dns.payload().to_vec();
+        udp[3] = 54;
+        not_dns.set_payload(&udp);
+        passes_through(not_dns);
+    }
+}
@@ -5,7 +5,10 @@ use rand::SeedableRng;
 use std::marker::PhantomData;
 
 /// DropProcessor
-/// Drops packets with weighted randomness.
+///
+/// Drops packets with weighted randomness. With a `drop_chance` below 1.0 it emulates a lossy
+/// link, passing the surviving packets through unchanged; set a `seed` to make the losses
+/// reproducible.
 pub struct Drop<A: Send + Clone> {
     phantom: PhantomData<A>,
     bernoulli: Bernoulli,
@@ -61,3 +64,52 @@ impl<A: Send + Clone> Default for Drop<A> {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn survivors(drop: &mut Drop<u32>, count: u32) -> Vec<u32> {
+        (0..count)
+            .filter_map(|packet| drop.process(packet))
+            .collect()
+    }
+
+    #[test]
+    fn drops_at_configured_rate() {
+        let mut drop = Drop::new().drop_chance(0.1).seed(42);
+
+        // 10% loss of 100,000 packets has a standard deviation of about 95 survivors, so this
+        // tolerance is over 10 of them.
+        let survived = survivors(&mut drop, 100_000).len();
+        assert!(
+            (89_000..=91_000).contains(&survived),
+            "survived: {}",
+            survived
+        );
+    }
+
+    #[test]
+    fn passes_survivors_unchanged_and_in_order() {
+        let mut drop = Drop::new().drop_chance(0.5).seed(3);
+
+        let survived = survivors(&mut drop, 1000);
+        assert!(survived.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn seed_reproduces_drop_pattern() {
+        let first = survivors(&mut Drop::new().drop_chance(0.1).seed(0), 1000);
+        let second = survivors(&mut Drop::new().drop_chance(0.1).seed(0), 1000);
+        assert_eq!(first, second);
+
+        let other_seed = survivors(&mut Drop::new().drop_chance(0.1).seed(1), 1000);
+        assert_ne!(first, other_seed);
+    }
+
+    #[test]
+    fn extremes_drop_nothing_or_everything() {
+        assert_eq!(survivors(&mut Drop::new().drop_chance(0.0), 100).len(), 100);
+        assert!(survivors(&mut Drop::new(), 100).is_empty());
+    }
+}
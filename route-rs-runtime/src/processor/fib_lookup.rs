@@ -0,0 +1,218 @@
+use crate::processor::{FlowKey, Processor};
+use crate::routing::{NextHop, RoutingTable};
+use route_rs_packets::Ipv4Packet;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Looks up the destination of each packet in a `RoutingTable`, and tags the packet with the next
+/// hop of the most specific matching route. Packets with no matching route, or whose route is a
+/// blackhole, are dropped; the table counts the drops of each blackhole.
+///
+/// When the route has several equal cost next hops, one is picked by hashing the 5-tuple of the
+/// packet, so every packet of a flow takes the same path and is not reordered, while different
+/// flows spread across all of them.
+///
+/// The table is shared rather than owned, so routes updated through any other handle to it apply
+/// starting with the next packet.
+#[derive(Clone)]
+pub struct FibLookup {
+    table: RoutingTable,
+}
+
+impl FibLookup {
+    pub fn new(table: RoutingTable) -> Self {
+        FibLookup { table }
+    }
+}
+
+/// Hashes the `FlowKey` of `packet`. All fragments of a datagram share a key, and so stay
+/// together.
+pub(crate) fn flow_hash(packet: &Ipv4Packet) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    FlowKey::of(packet).hash(&mut hasher);
+    hasher.finish()
+}
+
+impl Processor for FibLookup {
+    type Input = Ipv4Packet;
+    type Output = (NextHop, Ipv4Packet);
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let next_hop = self.table.lookup(packet.dest_addr(), flow_hash(&packet))?;
+        Some((next_hop, packet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::primitive::ProcessLink;
+    use crate::link::{LinkBuilder, ProcessLinkBuilder};
+    use crate::routing::Route;
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use std::net::Ipv4Addr;
+
+    const LAN: usize = 0;
+    const WAN: usize = 1;
+    const BACKUP: usize = 2;
+
+    fn packet(dest_addr: Ipv4Addr) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(Ipv4Addr::new(192, 168, 1, 10));
+        packet.set_dest_addr(dest_addr);
+        packet.set_ttl(64);
+        packet
+    }
+
+    fn table() -> RoutingTable {
+        let table = RoutingTable::new();
+        table.insert(Route::new(
+            Ipv4Addr::new(192, 168, 1, 0),
+            24,
+            NextHop::connected(LAN),
+        ));
+        table.insert(Route::new(
+            Ipv4Addr::new(0, 0, 0, 0),
+            0,
+            NextHop::via(Ipv4Addr::new(203, 0, 113, 1), WAN),
+        ));
+        table
+    }
+
+    #[test]
+    fn tags_with_next_hop() {
+        let mut elem = FibLookup::new(table());
+
+        let (next_hop, _) = elem.process(packet(Ipv4Addr::new(8, 8, 8, 8))).unwrap();
+        assert_eq!(next_hop, NextHop::via(Ipv4Addr::new(203, 0, 113, 1), WAN));
+
+        let (next_hop, _) = elem
+            .process(packet(Ipv4Addr::new(192, 168, 1, 20)))
+            .unwrap();
+        assert_eq!(next_hop, NextHop::connected(LAN));
+    }
+
+    #[test]
+    fn drops_unroutable() {
+        let table = table();
+        table.remove(Ipv4Addr::new(0, 0, 0, 0), 0);
+        let mut elem = FibLookup::new(table);
+
+        assert!(elem.process(packet(Ipv4Addr::new(8, 8, 8, 8))).is_none());
+    }
+
+    #[test]
+    fn drops_blackholed() {
+        let table = table();
+        let victim = Ipv4Addr::new(192, 168, 1, 66);
+        table.insert(Route::blackhole(victim, 32));
+        let mut elem = FibLookup::new(table.clone());
+
+        assert!(elem.process(packet(victim)).is_none());
+        assert!(elem.process(packet(victim)).is_none());
+        assert!(elem
+            .process(packet(Ipv4Addr::new(192, 168, 1, 65)))
+            .is_some());
+        assert!(elem
+            .process(packet(Ipv4Addr::new(192, 168, 1, 67)))
+            .is_some());
+        assert_eq!(table.drops(victim, 32), Some(2));
+    }
+
+    #[test]
+    fn follows_updated_route() {
+        let table = table();
+        let mut elem = FibLookup::new(table.clone());
+
+        let (next_hop, _) = elem.process(packet(Ipv4Addr::new(8, 8, 8, 8))).unwrap();
+        assert_eq!(next_hop.interface, WAN);
+
+        table.insert(Route::new(
+            Ipv4Addr::new(0, 0, 0, 0),
+            0,
+            NextHop::via(Ipv4Addr::new(198, 51, 100, 1), BACKUP),
+        ));
+
+        let (next_hop, _) = elem.process(packet(Ipv4Addr::new(8, 8, 8, 8))).unwrap();
+        assert_eq!(
+            next_hop,
+            NextHop::via(Ipv4Addr::new(198, 51, 100, 1), BACKUP)
+        );
+    }
+
+    fn flow(src_port: u16) -> Ipv4Packet {
+        let mut packet = packet(Ipv4Addr::new(8, 8, 8, 8));
+        packet.set_protocol(6);
+        let mut tcp = vec![0; 20];
+        tcp[0..2].copy_from_slice(&src_port.to_be_bytes());
+        tcp[2..4].copy_from_slice(&443u16.to_be_bytes());
+        packet.set_payload(&tcp);
+        packet
+    }
+
+    #[test]
+    fn balances_flows_across_equal_cost_next_hops() {
+        let table = RoutingTable::new();
+        let next_hops = vec![
+            NextHop::via(Ipv4Addr::new(203, 0, 113, 1), WAN),
+            NextHop::via(Ipv4Addr::new(198, 51, 100, 1), BACKUP),
+        ];
+        table.insert(Route::multipath(
+            Ipv4Addr::new(0, 0, 0, 0),
+            0,
+            next_hops.clone(),
+        ));
+        let mut elem = FibLookup::new(table);
+
+        let mut chosen = vec![];
+        for src_port in 40000..40016 {
+            let (next_hop, _) = elem.process(flow(src_port)).unwrap();
+            // Every packet of a flow takes the same next hop.
+            for _ in 0..4 {
+                assert_eq!(elem.process(flow(src_port)).unwrap().0, next_hop);
+            }
+            chosen.push(next_hop);
+        }
+
+        assert!(chosen.contains(&next_hops[0]));
+        assert!(chosen.contains(&next_hops[1]));
+    }
+
+    #[test]
+    fn fragments_hash_without_ports() {
+        let mut first = flow(40000);
+        first.set_flags(false, true);
+        let mut later = packet(Ipv4Addr::new(8, 8, 8, 8));
+        later.set_protocol(6);
+        later.set_fragment_offset(185);
+        later.set_payload(&[0xAA; 20]);
+
+        assert_eq!(flow_hash(&first), flow_hash(&later));
+        assert_ne!(flow_hash(&flow(40000)), flow_hash(&first));
+    }
+
+    #[test]
+    fn running_link_sees_updates() {
+        let table = table();
+        let handle = table.clone();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = ProcessLink::new()
+                .ingressor(immediate_stream(vec![packet(Ipv4Addr::new(8, 8, 8, 8))]))
+                .processor(FibLookup::new(table))
+                .build_link();
+            handle.replace(&[Route::new(
+                Ipv4Addr::new(0, 0, 0, 0),
+                0,
+                NextHop::connected(BACKUP),
+            )]);
+
+            run_link(link).await
+        });
+
+        assert_eq!(results[0].len(), 1);
+        assert_eq!(results[0][0].0, NextHop::connected(BACKUP));
+    }
+}
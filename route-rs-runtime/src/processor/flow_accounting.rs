@@ -0,0 +1,366 @@
+use crate::link::utils::clock::{Clock, SystemClock};
+use crate::processor::Processor;
+use crossbeam::Sender;
+use route_rs_packets::{IpProtocol, Ipv4Packet};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+const TCP_FIN: u8 = 0x01;
+const TCP_RST: u8 = 0x04;
+
+/// How often idle flows are looked for while packets are flowing.
+pub(crate) const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The 5-tuple that identifies a flow. Ports are 0 for protocols other than TCP and UDP, and for
+/// fragments.
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
+pub struct FlowKey {
+    pub src_addr: Ipv4Addr,
+    pub dest_addr: Ipv4Addr,
+    pub src_port: u16,
+    pub dest_port: u16,
+    pub protocol: u8,
+}
+
+impl FlowKey {
+    /// The flow `packet` belongs to. Fragments other than the first carry no transport header, so
+    /// every fragment is keyed without ports, and all fragments of a datagram share a key.
+    pub fn of(packet: &Ipv4Packet) -> Self {
+        let mut key = FlowKey {
+            src_addr: packet.src_addr(),
+            dest_addr: packet.dest_addr(),
+            src_port: 0,
+            dest_port: 0,
+            protocol: packet.data[packet.layer3_offset + 9],
+        };
+        if let Some(ports) = transport_payload(packet)
+            .as_deref()
+            .and_then(|payload| payload.get(0..4))
+        {
+            key.src_port = u16::from_be_bytes([ports[0], ports[1]]);
+            key.dest_port = u16::from_be_bytes([ports[2], ports[3]]);
+        }
+        key
+    }
+}
+
+/// The payload of `packet`, starting with its TCP or UDP header, unless it carries some other
+/// protocol or is a fragment.
+fn transport_payload(packet: &Ipv4Packet) -> Option<Cow<'_, [u8]>> {
+    let protocol = packet.protocol();
+    let (_, more_fragments) = packet.flags();
+    let fragmented = more_fragments || packet.fragment_offset() != 0;
+    if (protocol == IpProtocol::TCP || protocol == IpProtocol::UDP) && !fragmented {
+        Some(packet.payload())
+    } else {
+        None
+    }
+}
+
+/// The statistics of a finished flow, modeled on a NetFlow v5 flow record.
+#[derive(Clone, Debug)]
+pub struct FlowRecord {
+    pub key: FlowKey,
+    /// Number of packets in the flow.
+    pub packets: u32,
+    /// Number of layer 3 bytes in the flow.
+    pub bytes: u32,
+    /// When the first packet of the flow was seen.
+    pub first: Instant,
+    /// When the last packet of the flow was seen.
+    pub last: Instant,
+    /// The TCP flags of every packet in the flow, ORed together.
+    pub tcp_flags: u8,
+}
+
+/// Tallies the packets and bytes of each flow passing through it, and passes the packets on
+/// unchanged. Finished flows are exported as `FlowRecord`s on the channel given to `new`; a flow
+/// is finished when it has been idle for `idle_timeout`, or when one of its TCP packets carries a
+/// FIN or RST. Records that do not fit in the channel are dropped, so accounting never holds
+/// packets up.
+///
+/// Idle flows are swept while packets are flowing; call `expire` to sweep them when there may be
+/// no traffic.
+pub struct FlowAccounting {
+    flows: HashMap<FlowKey, FlowRecord>,
+    export: Sender<FlowRecord>,
+    idle_timeout: Duration,
+    clock: Box<dyn Clock>,
+    last_sweep: Option<Instant>,
+}
+
+impl FlowAccounting {
+    pub fn new(export: Sender<FlowRecord>) -> Self {
+        FlowAccounting {
+            flows: HashMap::new(),
+            export,
+            idle_timeout: Duration::from_secs(15),
+            clock: Box::new(SystemClock),
+            last_sweep: None,
+        }
+    }
+
+    /// Changes idle_timeout, default value is 15 seconds.
+    pub fn idle_timeout(self, idle_timeout: Duration) -> Self {
+        FlowAccounting {
+            idle_timeout,
+            ..self
+        }
+    }
+
+    /// Changes the clock used to time out flows, default is `SystemClock`.
+    pub fn clock<C: Clock + 'static>(self, clock: C) -> Self {
+        FlowAccounting {
+            clock: Box::new(clock),
+            ..self
+        }
+    }
+
+    /// The number of flows currently being tallied.
+    pub fn active_flows(&self) -> usize {
+        self.flows.len()
+    }
+
+    /// Exports and forgets every flow that has been idle for `idle_timeout`.
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        self.last_sweep = Some(now);
+
+        let idle_timeout = self.idle_timeout;
+        let idle: Vec<FlowKey> = self
+            .flows
+            .iter()
+            .filter(|(_, record)| now.saturating_duration_since(record.last) >= idle_timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in idle {
+            self.export(key);
+        }
+    }
+
+    fn export(&mut self, key: FlowKey) {
+        if let Some(record) = self.flows.remove(&key) {
+            let _ = self.export.try_send(record);
+        }
+    }
+
+    /// Tallies a packet of `total_len` bytes to the flow `key`.
+    fn account(&mut self, key: FlowKey, tcp_flags: u8, total_len: u16) {
+        let now = self.clock.now();
+        let due = self
+            .last_sweep
+            .is_none_or(|last_sweep| now.saturating_duration_since(last_sweep) >= SWEEP_INTERVAL);
+        if due {
+            self.expire();
+        }
+
+        let record = self.flows.entry(key).or_insert(FlowRecord {
+            key,
+            packets: 0,
+            bytes: 0,
+            first: now,
+            last: now,
+            tcp_flags: 0,
+        });
+        record.packets = record.packets.saturating_add(1);
+        record.bytes = record.bytes.saturating_add(u32::from(total_len));
+        record.last = now;
+        record.tcp_flags |= tcp_flags;
+
+        if tcp_flags & (TCP_FIN | TCP_RST) != 0 {
+            self.export(key);
+        }
+    }
+}
+
+/// The flow `packet` belongs to, as `FlowKey::of` keys it, and its TCP flags.
+pub(crate) fn classify(packet: &Ipv4Packet) -> (FlowKey, u8) {
+    let tcp_flags = match transport_payload(packet) {
+        Some(payload) if packet.protocol() == IpProtocol::TCP => {
+            payload.get(13).copied().unwrap_or(0)
+        }
+        _ => 0,
+    };
+    (FlowKey::of(packet), tcp_flags)
+}
+
+impl Processor for FlowAccounting {
+    type Input = Ipv4Packet;
+    type Output = Ipv4Packet;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let (key, tcp_flags) = classify(&packet);
+        self.account(key, tcp_flags, packet.total_len());
+        Some(packet)
+    }
+}
+
+/// Runs a `FlowAccounting` on packets annotated with the flow they belonged to before NAT, as
+/// `PreserveOriginalTuple` annotates them, and tallies each packet to its annotated flow rather
+/// than the translated one it now carries. Records then name the real client behind the NAT.
+/// Packets and their annotations pass on unchanged.
+pub struct OriginalTupleAccounting {
+    accounting: FlowAccounting,
+}
+
+impl OriginalTupleAccounting {
+    pub fn new(accounting: FlowAccounting) -> Self {
+        OriginalTupleAccounting { accounting }
+    }
+
+    /// The wrapped accounting, to call `expire` on or count its flows.
+    pub fn inner(&mut self) -> &mut FlowAccounting {
+        &mut self.accounting
+    }
+}
+
+impl Processor for OriginalTupleAccounting {
+    type Input = (FlowKey, Ipv4Packet);
+    type Output = (FlowKey, Ipv4Packet);
+
+    fn process(&mut self, (original, packet): Self::Input) -> Option<Self::Output> {
+        let (_, tcp_flags) = classify(&packet);
+        self.accounting
+            .account(original, tcp_flags, packet.total_len());
+        Some((original, packet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::clock::ManualClock;
+    use crossbeam::crossbeam_channel;
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 10);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 34);
+
+    fn segment(src_port: u16, flags: u8, data_len: usize) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(CLIENT);
+        packet.set_dest_addr(SERVER);
+        packet.set_protocol(6);
+        let mut tcp = vec![0; 20 + data_len];
+        tcp[0..2].copy_from_slice(&src_port.to_be_bytes());
+        tcp[2..4].copy_from_slice(&443u16.to_be_bytes());
+        tcp[13] = flags;
+        packet.set_payload(&tcp);
+        packet
+    }
+
+    fn datagram(dest_port: u16) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(CLIENT);
+        packet.set_dest_addr(SERVER);
+        packet.set_protocol(17);
+        let mut udp = vec![0; 8 + 100];
+        udp[0..2].copy_from_slice(&5353u16.to_be_bytes());
+        udp[2..4].copy_from_slice(&dest_port.to_be_bytes());
+        packet.set_payload(&udp);
+        packet
+    }
+
+    #[test]
+    fn passes_packets_unchanged() {
+        let (export, _records) = crossbeam_channel::unbounded();
+        let mut elem = FlowAccounting::new(export);
+
+        let packet = segment(40000, 0x10, 10);
+        let output = elem.process(packet.clone()).unwrap();
+        assert_eq!(output.data, packet.data);
+        assert_eq!(elem.active_flows(), 1);
+    }
+
+    #[test]
+    fn exports_idle_flow() {
+        let clock = ManualClock::new();
+        let (export, records) = crossbeam_channel::unbounded();
+        let mut elem = FlowAccounting::new(export)
+            .idle_timeout(Duration::from_secs(10))
+            .clock(clock.clone());
+
+        let packets = vec![datagram(53), datagram(53), datagram(53)];
+        let bytes: u32 = packets.iter().map(|p| u32::from(p.total_len())).sum();
+        let first = clock.now();
+        for packet in packets {
+            elem.process(packet).unwrap();
+            clock.advance(Duration::from_secs(1));
+        }
+        let last = first + Duration::from_secs(2);
+
+        clock.advance(Duration::from_secs(8));
+        elem.expire();
+        assert!(records.try_recv().is_err());
+
+        clock.advance(Duration::from_secs(1));
+        elem.expire();
+        let record = records.try_recv().unwrap();
+        assert_eq!(record.key.protocol, 17);
+        assert_eq!(record.key.src_port, 5353);
+        assert_eq!(record.key.dest_port, 53);
+        assert_eq!(record.packets, 3);
+        assert_eq!(record.bytes, bytes);
+        assert_eq!(record.first, first);
+        assert_eq!(record.last, last);
+        assert_eq!(elem.active_flows(), 0);
+    }
+
+    #[test]
+    fn exports_on_fin_and_rst() {
+        let (export, records) = crossbeam_channel::unbounded();
+        let mut elem = FlowAccounting::new(export);
+
+        let flow = vec![
+            segment(40000, 0x02, 0),
+            segment(40000, 0x10, 100),
+            segment(40000, 0x11, 0),
+        ];
+        let bytes: u32 = flow.iter().map(|p| u32::from(p.total_len())).sum();
+        for packet in flow {
+            elem.process(packet).unwrap();
+        }
+        elem.process(segment(40001, 0x04, 0)).unwrap();
+
+        let record = records.try_recv().unwrap();
+        assert_eq!(record.key.src_port, 40000);
+        assert_eq!(record.packets, 3);
+        assert_eq!(record.bytes, bytes);
+        assert_eq!(record.tcp_flags, 0x13);
+
+        let record = records.try_recv().unwrap();
+        assert_eq!(record.key.src_port, 40001);
+        assert_eq!(record.packets, 1);
+        assert_eq!(elem.active_flows(), 0);
+    }
+
+    #[test]
+    fn sweeps_idle_flows_while_traffic_flows() {
+        let clock = ManualClock::new();
+        let (export, records) = crossbeam_channel::unbounded();
+        let mut elem = FlowAccounting::new(export)
+            .idle_timeout(Duration::from_secs(5))
+            .clock(clock.clone());
+
+        elem.process(datagram(53)).unwrap();
+        clock.advance(Duration::from_secs(6));
+        elem.process(datagram(123)).unwrap();
+
+        let record = records.try_recv().unwrap();
+        assert_eq!(record.key.dest_port, 53);
+        assert_eq!(elem.active_flows(), 1);
+    }
+
+    #[test]
+    fn full_export_channel_does_not_block() {
+        let (export, records) = crossbeam_channel::bounded(1);
+        let mut elem = FlowAccounting::new(export);
+
+        elem.process(segment(40000, 0x04, 0)).unwrap();
+        elem.process(segment(40001, 0x04, 0)).unwrap();
+
+        assert_eq!(records.try_recv().unwrap().key.src_port, 40000);
+        assert!(records.try_recv().is_err());
+    }
+}
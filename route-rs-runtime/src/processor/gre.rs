@@ -0,0 +1,226 @@
+use crate::processor::Processor;
+use route_rs_packets::{IpProtocol, Ipv4Packet};
+use std::convert::TryInto;
+use std::net::Ipv4Addr;
+
+const GRE_PROTOCOL: u8 = 47;
+const GRE_CHECKSUM_PRESENT: u16 = 0x8000;
+const GRE_KEY_PRESENT: u16 = 0x2000;
+const GRE_SEQUENCE_PRESENT: u16 = 0x1000;
+const GRE_VERSION_MASK: u16 = 0x0007;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+
+/// Wraps each IPv4 packet in a GRE header (RFC 2890) and an outer IPv4 header from `src_addr` to
+/// the tunnel endpoint `dest_addr`. If a key is set, it is carried in the GRE key field.
+#[derive(Clone)]
+pub struct GreEncap {
+    src_addr: Ipv4Addr,
+    dest_addr: Ipv4Addr,
+    key: Option<u32>,
+    ttl: u8,
+}
+
+impl GreEncap {
+    pub fn new(src_addr: Ipv4Addr, dest_addr: Ipv4Addr) -> GreEncap {
+        GreEncap {
+            src_addr,
+            dest_addr,
+            key: None,
+            ttl: 64,
+        }
+    }
+
+    /// Sets the GRE key, by default no key is sent.
+    pub fn key(self, key: u32) -> GreEncap {
+        GreEncap {
+            key: Some(key),
+            ..self
+        }
+    }
+
+    /// Changes the TTL of the outer header, default is 64.
+    pub fn ttl(self, ttl: u8) -> GreEncap {
+        GreEncap { ttl, ..self }
+    }
+}
+
+impl Processor for GreEncap {
+    type Input = Ipv4Packet;
+    type Output = Ipv4Packet;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let inner = &packet.data[packet.layer3_offset..];
+
+        let flags = if self.key.is_some() {
+            GRE_KEY_PRESENT
+        } else {
+            0
+        };
+        let mut payload = Vec::with_capacity(8 + inner.len());
+        payload.extend_from_slice(&flags.to_be_bytes());
+        payload.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        if let Some(key) = self.key {
+            payload.extend_from_slice(&key.to_be_bytes());
+        }
+        payload.extend_from_slice(inner);
+
+        let mut outer = Ipv4Packet::empty();
+        outer.set_payload(&payload);
+        outer.set_protocol(GRE_PROTOCOL);
+        outer.set_src_addr(self.src_addr);
+        outer.set_dest_addr(self.dest_addr);
+        outer.set_ttl(self.ttl);
+        outer.set_checksum();
+        Some(outer)
+    }
+}
+
+/// Strips the outer IPv4 and GRE headers from tunneled packets and recovers the inner IPv4
+/// packet. Packets that are not GRE, carry an unsupported GRE version or payload type, do not
+/// match the configured key, or whose inner packet does not parse are dropped.
+#[derive(Clone, Default)]
+pub struct GreDecap {
+    key: Option<u32>,
+}
+
+impl GreDecap {
+    pub fn new() -> GreDecap {
+        GreDecap { key: None }
+    }
+
+    /// Only accept packets carrying this GRE key. By default, only packets without a key are
+    /// accepted.
+    pub fn key(self, key: u32) -> GreDecap {
+        GreDecap { key: Some(key) }
+    }
+}
+
+impl Processor for GreDecap {
+    type Input = Ipv4Packet;
+    type Output = Ipv4Packet;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        if packet.protocol() != IpProtocol::GREs {
+            return None;
+        }
+
+        let payload = packet.payload();
+        if payload.len() < 4 {
+            return None;
+        }
+        let flags = u16::from_be_bytes(payload[0..2].try_into().unwrap());
+        let protocol_type = u16::from_be_bytes(payload[2..4].try_into().unwrap());
+        if flags & GRE_VERSION_MASK != 0 || protocol_type != ETHERTYPE_IPV4 {
+            return None;
+        }
+
+        let mut offset = 4;
+        if flags & GRE_CHECKSUM_PRESENT != 0 {
+            offset += 4;
+        }
+        let key = if flags & GRE_KEY_PRESENT != 0 {
+            let key = payload
+                .get(offset..offset + 4)
+                .map(|key| u32::from_be_bytes(key.try_into().unwrap()));
+            offset += 4;
+            Some(key?)
+        } else {
+            None
+        };
+        if flags & GRE_SEQUENCE_PRESENT != 0 {
+            offset += 4;
+        }
+        if key != self.key || payload.len() < offset {
+            return None;
+        }
+
+        Ipv4Packet::try_from_bytes(&payload[offset..]).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inner() -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(Ipv4Addr::new(192, 168, 1, 10));
+        packet.set_dest_addr(Ipv4Addr::new(192, 168, 2, 20));
+        packet.set_ttl(64);
+        packet.set_protocol(17);
+        packet.set_payload(&[0, 53, 0, 53, 0, 12, 0, 0, 1, 2, 3, 4]);
+        packet.set_checksum();
+        packet
+    }
+
+    fn encap() -> GreEncap {
+        GreEncap::new(
+            Ipv4Addr::new(203, 0, 113, 1),
+            Ipv4Addr::new(198, 51, 100, 1),
+        )
+    }
+
+    #[test]
+    fn encap_builds_outer_header() {
+        let mut outer = encap().key(42).process(inner()).unwrap();
+
+        assert_eq!(outer.protocol(), IpProtocol::GREs);
+        assert_eq!(outer.src_addr(), Ipv4Addr::new(203, 0, 113, 1));
+        assert_eq!(outer.dest_addr(), Ipv4Addr::new(198, 51, 100, 1));
+        assert!(outer.validate_checksum());
+        assert_eq!(outer.payload()[..8], [0x20, 0, 0x08, 0, 0, 0, 0, 42]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let outer = encap().process(inner()).unwrap();
+        let recovered = GreDecap::new().process(outer).unwrap();
+        assert_eq!(recovered.data, inner().data);
+    }
+
+    #[test]
+    fn round_trip_with_key() {
+        let outer = encap().key(0xDEAD_BEEF).process(inner()).unwrap();
+        let recovered = GreDecap::new().key(0xDEAD_BEEF).process(outer).unwrap();
+        assert_eq!(recovered.data, inner().data);
+    }
+
+    #[test]
+    fn drops_mismatched_key() {
+        let outer = encap().key(1).process(inner()).unwrap();
+        assert!(GreDecap::new().key(2).process(outer.clone()).is_none());
+        assert!(GreDecap::new().process(outer).is_none());
+    }
+
+    #[test]
+    fn drops_malformed() {
+        let mut decap = GreDecap::new();
+
+        // Not GRE at all.
+        assert!(decap.process(inner()).is_none());
+
+        // Truncated GRE header.
+        let mut outer = Ipv4Packet::empty();
+        outer.set_protocol(GRE_PROTOCOL);
+        outer.set_payload(&[0, 0]);
+        assert!(decap.process(outer).is_none());
+
+        // Key bit set, but no room for the key.
+        let mut outer = Ipv4Packet::empty();
+        outer.set_protocol(GRE_PROTOCOL);
+        outer.set_payload(&[0x20, 0, 0x08, 0, 0, 0]);
+        assert!(decap.key(0).process(outer).is_none());
+
+        // Unsupported GRE version.
+        let mut outer = encap().process(inner()).unwrap();
+        outer.data[21] |= 0x01;
+        assert!(GreDecap::new().process(outer).is_none());
+
+        // Inner packet is truncated.
+        let mut outer = encap().process(inner()).unwrap();
+        let len = outer.payload().len();
+        let truncated = outer.payload()[..len - 8].to_vec();
+        outer.set_payload(&truncated);
+        assert!(GreDecap::new().process(outer).is_none());
+    }
+}
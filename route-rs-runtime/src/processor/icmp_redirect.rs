@@ -0,0 +1,238 @@
+use crate::interface::InterfaceConfig;
+use crate::processor::InterfaceAwareProcessor;
+use crate::routing::{NextHop, RoutingTable};
+use route_rs_packets::{Ipv4Packet, ICMP_CODE_REDIRECT_HOST, ICMP_REDIRECT};
+
+/// A routed packet, and the ICMP redirect to send back to its source if it took a detour
+/// through the router.
+#[derive(Clone, Debug)]
+pub struct RedirectCheck {
+    pub next_hop: NextHop,
+    /// The packet, unchanged. It is forwarded whether or not a redirect is sent.
+    pub packet: Ipv4Packet,
+    /// The ICMP redirect addressed to the source of `packet`, if one is due.
+    pub redirect: Option<Ipv4Packet>,
+}
+
+/// Generates ICMP redirects (RFC 792, RFC 1812 section 5.2.7.2) for packets the router forwards
+/// back out of the interface they arrived on. When the source of such a packet is directly
+/// connected to that interface, it could have reached the next hop without the router, so it is
+/// sent a host redirect naming the next hop as the better gateway. If the destination is itself
+/// directly connected, the destination is the better gateway.
+///
+/// It runs after `FibLookup`, on packets tagged with their inbound interface, so wrap it in
+/// `InterfaceAware`. Redirects are sent from the primary address of the inbound interface in
+/// `InterfaceConfig`; no redirect is sent on an interface without one. Whether a source is
+/// directly connected is looked up in the same `RoutingTable` as the next hop.
+#[derive(Clone)]
+pub struct IcmpRedirect {
+    table: RoutingTable,
+    config: InterfaceConfig,
+}
+
+impl IcmpRedirect {
+    pub fn new(table: RoutingTable, config: InterfaceConfig) -> Self {
+        IcmpRedirect { table, config }
+    }
+
+    /// The redirect for `packet`, if it leaves through `next_hop` on the interface it arrived on.
+    fn redirect(
+        &self,
+        next_hop: NextHop,
+        packet: &Ipv4Packet,
+        inbound: usize,
+    ) -> Option<Ipv4Packet> {
+        if next_hop.interface != inbound {
+            return None;
+        }
+        let source_route = self.table.route_to(packet.src_addr())?;
+        if !source_route
+            .next_hops
+            .contains(&NextHop::connected(inbound))
+        {
+            return None;
+        }
+        let src_addr = self.config.get(inbound)?.primary_ipv4_addr()?;
+        let gateway = next_hop.gateway.unwrap_or_else(|| packet.dest_addr());
+
+        Some(Ipv4Packet::icmp_error(
+            packet,
+            src_addr,
+            ICMP_REDIRECT,
+            ICMP_CODE_REDIRECT_HOST,
+            gateway.octets(),
+        ))
+    }
+}
+
+impl InterfaceAwareProcessor for IcmpRedirect {
+    type Input = (NextHop, Ipv4Packet);
+    type Output = RedirectCheck;
+
+    fn process(&mut self, (next_hop, packet): Self::Input, inbound: usize) -> Option<Self::Output> {
+        let redirect = self.redirect(next_hop, &packet, inbound);
+        Some(RedirectCheck {
+            next_hop,
+            packet,
+            redirect,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::InterfaceSettings;
+    use crate::routing::Route;
+    use route_rs_packets::{IpProtocol, MacAddr};
+    use std::net::Ipv4Addr;
+
+    const LAN: usize = 0;
+    const WAN: usize = 1;
+
+    const ROUTER: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 1);
+    const HOST: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 10);
+    /// Another router on the LAN, the better gateway to the lab network.
+    const LAB_GATEWAY: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 2);
+    const LAB_SERVER: Ipv4Addr = Ipv4Addr::new(10, 20, 0, 5);
+
+    fn packet(src_addr: Ipv4Addr, dest_addr: Ipv4Addr) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(src_addr);
+        packet.set_dest_addr(dest_addr);
+        packet.set_ttl(64);
+        packet.set_protocol(17);
+        packet.set_payload(&[0x55; 16]);
+        packet.set_checksum();
+        packet
+    }
+
+    fn redirect() -> IcmpRedirect {
+        let table = RoutingTable::new();
+        table.insert(Route::new(
+            Ipv4Addr::new(192, 168, 1, 0),
+            24,
+            NextHop::connected(LAN),
+        ));
+        table.insert(Route::new(
+            Ipv4Addr::new(10, 20, 0, 0),
+            16,
+            NextHop::via(LAB_GATEWAY, LAN),
+        ));
+        table.insert(Route::new(
+            Ipv4Addr::new(0, 0, 0, 0),
+            0,
+            NextHop::via(Ipv4Addr::new(203, 0, 113, 1), WAN),
+        ));
+
+        let config = InterfaceConfig::new();
+        config.set(
+            LAN,
+            InterfaceSettings::new(1500, MacAddr::new([0x02, 0, 0, 0, 0, 1]), vec![ROUTER]),
+        );
+        IcmpRedirect::new(table, config)
+    }
+
+    #[test]
+    fn redirects_to_better_gateway() {
+        let mut elem = redirect();
+        let original = packet(HOST, LAB_SERVER);
+
+        let checked = elem
+            .process((NextHop::via(LAB_GATEWAY, LAN), original.clone()), LAN)
+            .unwrap();
+
+        assert_eq!(checked.next_hop, NextHop::via(LAB_GATEWAY, LAN));
+        assert_eq!(checked.packet, original);
+
+        let mut icmp = checked.redirect.unwrap();
+        assert_eq!(icmp.protocol(), IpProtocol::ICMP);
+        assert_eq!(icmp.src_addr(), ROUTER);
+        assert_eq!(icmp.dest_addr(), HOST);
+        assert!(icmp.validate_checksum());
+
+        let message = icmp.payload();
+        assert_eq!(message[0], ICMP_REDIRECT);
+        assert_eq!(message[1], ICMP_CODE_REDIRECT_HOST);
+        assert_eq!(message[4..8], LAB_GATEWAY.octets());
+        // The quoted header identifies the redirected packet.
+        assert_eq!(message[8..28], original.data[..20]);
+    }
+
+    #[test]
+    fn redirects_to_connected_destination() {
+        let mut elem = redirect();
+        let neighbour = Ipv4Addr::new(192, 168, 1, 20);
+
+        let checked = elem
+            .process((NextHop::connected(LAN), packet(HOST, neighbour)), LAN)
+            .unwrap();
+
+        assert_eq!(
+            checked.redirect.unwrap().payload()[4..8],
+            neighbour.octets()
+        );
+    }
+
+    #[test]
+    fn no_redirect_to_other_interface() {
+        let mut elem = redirect();
+        let next_hop = NextHop::via(Ipv4Addr::new(203, 0, 113, 1), WAN);
+
+        let checked = elem
+            .process((next_hop, packet(HOST, Ipv4Addr::new(8, 8, 8, 8))), LAN)
+            .unwrap();
+
+        assert!(checked.redirect.is_none());
+    }
+
+    #[test]
+    fn no_redirect_for_remote_source() {
+        let mut elem = redirect();
+
+        // Packets from beyond the better gateway could not have used it directly.
+        let checked = elem
+            .process(
+                (
+                    NextHop::via(LAB_GATEWAY, LAN),
+                    packet(Ipv4Addr::new(10, 30, 0, 1), LAB_SERVER),
+                ),
+                LAN,
+            )
+            .unwrap();
+
+        assert!(checked.redirect.is_none());
+    }
+
+    #[test]
+    fn no_redirect_without_interface_address() {
+        let mut elem = IcmpRedirect::new(redirect().table, InterfaceConfig::new());
+
+        let checked = elem
+            .process(
+                (NextHop::via(LAB_GATEWAY, LAN), packet(HOST, LAB_SERVER)),
+                LAN,
+            )
+            .unwrap();
+
+        assert!(checked.redirect.is_none());
+        assert_eq!(checked.packet, packet(HOST, LAB_SERVER));
+    }
+
+    #[test]
+    fn source_check_counts_no_drops() {
+        let mut elem = redirect();
+        let blocked = Ipv4Addr::new(192, 168, 1, 66);
+        elem.table.insert(Route::blackhole(blocked, 32));
+
+        let checked = elem
+            .process(
+                (NextHop::via(LAB_GATEWAY, LAN), packet(blocked, LAB_SERVER)),
+                LAN,
+            )
+            .unwrap();
+
+        assert!(checked.redirect.is_none());
+        assert_eq!(elem.table.drops(blocked, 32), Some(0));
+    }
+}
@@ -0,0 +1,342 @@
+use crate::processor::Processor;
+use route_rs_packets::{
+    IpProtocol, Ipv6Packet, ICMPV6_ECHO_REPLY, ICMPV6_ECHO_REQUEST, ICMPV6_PACKET_TOO_BIG,
+    ICMPV6_TIME_EXCEEDED, IPV6_MIN_MTU,
+};
+use std::net::Ipv6Addr;
+
+/// Whether an ICMPv6 error of `icmp_type` may be sent about `packet` (RFC 4443 section 2.4). No
+/// error is sent about an ICMPv6 error message, nor about a packet whose source is unspecified or
+/// multicast, since it names no single node to send the error to. No error is sent about a packet
+/// to a multicast destination either, except for Packet Too Big, which Path MTU Discovery for
+/// multicast relies on.
+fn may_send_error(packet: &Ipv6Packet, icmp_type: u8) -> bool {
+    let is_icmpv6_error = packet.next_header() == IpProtocol::IPv6_ICMP
+        && matches!(packet.payload().first(), Some(icmp_type) if *icmp_type < 128);
+    let src_addr = packet.src_addr();
+    let to_multicast = packet.dest_addr().is_multicast() && icmp_type != ICMPV6_PACKET_TOO_BIG;
+    !(is_icmpv6_error || src_addr.is_unspecified() || src_addr.is_multicast() || to_multicast)
+}
+
+/// Answers ICMPv6 echo requests (RFC 4443 section 4) addressed to the router, so that its IPv6
+/// addresses can be pinged. A request to one of `addresses` with a valid checksum is answered
+/// with an echo reply from that address, carrying the identifier, sequence number and data of
+/// the request. Every other packet is dropped, so classify packets addressed to the router into
+/// this processor.
+#[derive(Clone)]
+pub struct Icmpv6EchoResponder {
+    addresses: Vec<Ipv6Addr>,
+}
+
+impl Icmpv6EchoResponder {
+    pub fn new(addresses: Vec<Ipv6Addr>) -> Self {
+        Icmpv6EchoResponder { addresses }
+    }
+}
+
+impl Processor for Icmpv6EchoResponder {
+    type Input = Ipv6Packet;
+    type Output = Ipv6Packet;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let dest_addr = packet.dest_addr();
+        if packet.next_header() != IpProtocol::IPv6_ICMP
+            || !self.addresses.contains(&dest_addr)
+            || !packet.validate_icmpv6_checksum()
+        {
+            return None;
+        }
+        let message = packet.payload();
+        if message.len() < 8 || message[0] != ICMPV6_ECHO_REQUEST || message[1] != 0 {
+            return None;
+        }
+
+        let mut reply = Ipv6Packet::icmpv6(
+            dest_addr,
+            packet.src_addr(),
+            ICMPV6_ECHO_REPLY,
+            0,
+            &message[4..],
+        );
+        reply.set_hop_limit(64);
+        Some(reply)
+    }
+}
+
+/// The outcome of checking the hop limit of a packet the router forwards.
+#[derive(Clone, Debug)]
+pub enum HopLimitCheck {
+    /// The packet may continue, with its hop limit decremented.
+    Forward(Ipv6Packet),
+    /// The hop limit ran out, so the packet was dropped. Holds the ICMPv6 time exceeded message to
+    /// send back to its source, unless RFC 4443 forbids an error about the packet.
+    Exceeded(Option<Ipv6Packet>),
+}
+
+/// Decrements the hop limit of every packet the router forwards, like `DecIpv6HopLimit`, but
+/// drops packets whose hop limit runs out rather than forwarding them with a hop limit of zero.
+/// Such packets are replaced with an ICMPv6 time exceeded, hop limit exceeded in transit message
+/// (RFC 4443 section 3.3) addressed to the packet's source, which is what makes traceroute work.
+#[derive(Clone)]
+pub struct Ipv6HopLimitEnforce {
+    src_addr: Ipv6Addr,
+}
+
+impl Ipv6HopLimitEnforce {
+    /// Sends ICMPv6 errors from the router address `src_addr`.
+    pub fn new(src_addr: Ipv6Addr) -> Self {
+        Ipv6HopLimitEnforce { src_addr }
+    }
+}
+
+impl Processor for Ipv6HopLimitEnforce {
+    type Input = Ipv6Packet;
+    type Output = HopLimitCheck;
+
+    fn process(&mut self, mut packet: Self::Input) -> Option<Self::Output> {
+        let hop_limit = packet.hop_limit();
+        if hop_limit > 1 {
+            packet.set_hop_limit(hop_limit - 1);
+            return Some(HopLimitCheck::Forward(packet));
+        }
+
+        if !may_send_error(&packet, ICMPV6_TIME_EXCEEDED) {
+            return Some(HopLimitCheck::Exceeded(None));
+        }
+        let icmp =
+            Ipv6Packet::icmpv6_error(&packet, self.src_addr, ICMPV6_TIME_EXCEEDED, 0, [0; 4]);
+        Some(HopLimitCheck::Exceeded(Some(icmp)))
+    }
+}
+
+/// The outcome of checking an IPv6 packet against the egress MTU.
+#[derive(Clone, Debug)]
+pub enum Ipv6MtuCheck {
+    /// The packet may continue towards the egress interface.
+    Forward(Ipv6Packet),
+    /// The packet was too big, so it was dropped. Holds the ICMPv6 Packet Too Big message to send
+    /// back to its source, unless RFC 4443 forbids an error about the packet.
+    PacketTooBig(Option<Ipv6Packet>),
+}
+
+/// Enforces the MTU of an egress interface for IPv6 Path MTU Discovery (RFC 8201). Routers never
+/// fragment IPv6 packets, so every packet larger than the MTU is dropped, and replaced with an
+/// ICMPv6 Packet Too Big message (RFC 4443 section 3.2) that carries the MTU and is addressed to
+/// the packet's source. The IPv6 counterpart of `MtuEnforce`.
+#[derive(Clone)]
+pub struct Ipv6MtuEnforce {
+    mtu: u32,
+    src_addr: Ipv6Addr,
+}
+
+impl Ipv6MtuEnforce {
+    /// Enforces `mtu`, which must be at least the IPv6 minimum of 1280, sending ICMPv6 errors
+    /// from the router address `src_addr`.
+    pub fn new(mtu: u32, src_addr: Ipv6Addr) -> Self {
+        assert!(
+            mtu as usize >= IPV6_MIN_MTU,
+            "mtu: {}, must be >= {}",
+            mtu,
+            IPV6_MIN_MTU
+        );
+        Ipv6MtuEnforce { mtu, src_addr }
+    }
+}
+
+impl Processor for Ipv6MtuEnforce {
+    type Input = Ipv6Packet;
+    type Output = Ipv6MtuCheck;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let len = packet.data.len() - packet.layer3_offset;
+        if len <= self.mtu as usize {
+            return Some(Ipv6MtuCheck::Forward(packet));
+        }
+
+        if !may_send_error(&packet, ICMPV6_PACKET_TOO_BIG) {
+            return Some(Ipv6MtuCheck::PacketTooBig(None));
+        }
+        let icmp = Ipv6Packet::icmpv6_error(
+            &packet,
+            self.src_addr,
+            ICMPV6_PACKET_TOO_BIG,
+            0,
+            self.mtu.to_be_bytes(),
+        );
+        Some(Ipv6MtuCheck::PacketTooBig(Some(icmp)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> Ipv6Addr {
+        s.parse().unwrap()
+    }
+
+    fn router() -> Ipv6Addr {
+        addr("2001:db8::1")
+    }
+
+    fn host() -> Ipv6Addr {
+        addr("2001:db8:1::10")
+    }
+
+    fn echo_request(dest_addr: Ipv6Addr) -> Ipv6Packet {
+        // Identifier 0x1234, sequence number 7, then the data.
+        let body = [0x12, 0x34, 0, 7, b'p', b'i', b'n', b'g'];
+        Ipv6Packet::icmpv6(host(), dest_addr, ICMPV6_ECHO_REQUEST, 0, &body)
+    }
+
+    fn packet(len: usize, hop_limit: u8) -> Ipv6Packet {
+        let mut packet = Ipv6Packet::empty();
+        packet.set_src_addr(host());
+        packet.set_dest_addr(addr("2001:db8:2::20"));
+        packet.set_next_header(17);
+        packet.set_hop_limit(hop_limit);
+        packet.set_payload(&vec![0x55; len - 40]);
+        packet
+    }
+
+    #[test]
+    fn ping_router() {
+        let mut elem = Icmpv6EchoResponder::new(vec![addr("fe80::1"), router()]);
+
+        let reply = elem.process(echo_request(router())).unwrap();
+
+        assert_eq!(reply.src_addr(), router());
+        assert_eq!(reply.dest_addr(), host());
+        assert_eq!(reply.next_header(), IpProtocol::IPv6_ICMP);
+        assert!(reply.validate_icmpv6_checksum());
+        let message = reply.payload();
+        assert_eq!(message[0], ICMPV6_ECHO_REPLY);
+        assert_eq!(message[1], 0);
+        assert_eq!(message[4..], [0x12, 0x34, 0, 7, b'p', b'i', b'n', b'g']);
+    }
+
+    #[test]
+    fn ignores_ping_to_others() {
+        let mut elem = Icmpv6EchoResponder::new(vec![router()]);
+
+        assert!(elem.process(echo_request(addr("2001:db8::2"))).is_none());
+
+        let mut corrupt = echo_request(router());
+        corrupt.data[corrupt.payload_offset + 8] ^= 0xFF;
+        assert!(elem.process(corrupt).is_none());
+
+        let reply = Ipv6Packet::icmpv6(host(), router(), ICMPV6_ECHO_REPLY, 0, &[0; 4]);
+        assert!(elem.process(reply).is_none());
+    }
+
+    #[test]
+    fn forwards_with_decremented_hop_limit() {
+        let mut elem = Ipv6HopLimitEnforce::new(router());
+
+        match elem.process(packet(100, 64)).unwrap() {
+            HopLimitCheck::Forward(forwarded) => assert_eq!(forwarded.hop_limit(), 63),
+            other => panic!("Expected Forward, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn expired_packet_gets_time_exceeded() {
+        let mut elem = Ipv6HopLimitEnforce::new(router());
+        let expired = packet(100, 1);
+
+        let icmp = match elem.process(expired.clone()).unwrap() {
+            HopLimitCheck::Exceeded(Some(icmp)) => icmp,
+            other => panic!("Expected Exceeded, got {:?}", other),
+        };
+
+        assert_eq!(icmp.src_addr(), router());
+        assert_eq!(icmp.dest_addr(), host());
+        assert!(icmp.validate_icmpv6_checksum());
+        let message = icmp.payload();
+        assert_eq!(message[0], ICMPV6_TIME_EXCEEDED);
+        assert_eq!(message[1], 0);
+        assert_eq!(message[8..], expired.data[..]);
+    }
+
+    #[test]
+    fn no_error_about_errors() {
+        let mut elem = Ipv6HopLimitEnforce::new(router());
+        let mut error = Ipv6Packet::icmpv6_error(&packet(100, 1), router(), 3, 0, [0; 4]);
+        error.set_hop_limit(1);
+
+        match elem.process(error).unwrap() {
+            HopLimitCheck::Exceeded(None) => {}
+            other => panic!("Expected Exceeded without a message, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn no_time_exceeded_about_multicast_or_unspecified_source() {
+        let mut elem = Ipv6HopLimitEnforce::new(router());
+        let mut to_multicast = packet(100, 1);
+        to_multicast.set_dest_addr(addr("ff0e::101"));
+        let mut from_unspecified = packet(100, 1);
+        from_unspecified.set_src_addr(Ipv6Addr::UNSPECIFIED);
+
+        for expired in [to_multicast, from_unspecified].iter().cloned() {
+            match elem.process(expired).unwrap() {
+                HopLimitCheck::Exceeded(None) => {}
+                other => panic!("Expected Exceeded without a message, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_mtu_below_minimum() {
+        Ipv6MtuEnforce::new(1279, router());
+    }
+
+    #[test]
+    fn forwards_packets_that_fit() {
+        let mut elem = Ipv6MtuEnforce::new(1480, router());
+
+        match elem.process(packet(1480, 64)).unwrap() {
+            Ipv6MtuCheck::Forward(forwarded) => assert_eq!(forwarded.data.len(), 1480),
+            other => panic!("Expected Forward, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn oversized_packet_gets_packet_too_big() {
+        let mut elem = Ipv6MtuEnforce::new(1480, router());
+
+        let icmp = match elem.process(packet(1500, 64)).unwrap() {
+            Ipv6MtuCheck::PacketTooBig(Some(icmp)) => icmp,
+            other => panic!("Expected PacketTooBig, got {:?}", other),
+        };
+
+        assert_eq!(icmp.src_addr(), router());
+        assert_eq!(icmp.dest_addr(), host());
+        assert_eq!(icmp.hop_limit(), 64);
+        assert!(icmp.data.len() <= IPV6_MIN_MTU);
+        assert!(icmp.validate_icmpv6_checksum());
+        let message = icmp.payload();
+        assert_eq!(message[0], ICMPV6_PACKET_TOO_BIG);
+        assert_eq!(message[1], 0);
+        assert_eq!(message[4..8], 1480u32.to_be_bytes());
+    }
+
+    #[test]
+    fn packet_too_big_about_multicast_but_not_unspecified_source() {
+        let mut elem = Ipv6MtuEnforce::new(1480, router());
+        let mut to_multicast = packet(1500, 64);
+        to_multicast.set_dest_addr(addr("ff0e::101"));
+        let mut from_unspecified = packet(1500, 64);
+        from_unspecified.set_src_addr(Ipv6Addr::UNSPECIFIED);
+
+        match elem.process(to_multicast).unwrap() {
+            Ipv6MtuCheck::PacketTooBig(Some(icmp)) => assert_eq!(icmp.dest_addr(), host()),
+            other => panic!("Expected PacketTooBig, got {:?}", other),
+        }
+        match elem.process(from_unspecified).unwrap() {
+            Ipv6MtuCheck::PacketTooBig(None) => {}
+            other => panic!("Expected PacketTooBig without a message, got {:?}", other),
+        }
+    }
+}
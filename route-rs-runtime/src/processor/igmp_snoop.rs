@@ -0,0 +1,408 @@
+use crate::processor::{Processor, StatefulProcessor};
+use route_rs_packets::{IpProtocol, Ipv4Packet};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex};
+
+const IGMP_V1_MEMBERSHIP_REPORT: u8 = 0x12;
+const IGMP_V2_MEMBERSHIP_REPORT: u8 = 0x16;
+const IGMP_V2_LEAVE_GROUP: u8 = 0x17;
+const IGMP_V3_MEMBERSHIP_REPORT: u8 = 0x22;
+
+// IGMPv3 group record types, from RFC 3376.
+const MODE_IS_INCLUDE: u8 = 1;
+const CHANGE_TO_INCLUDE_MODE: u8 = 3;
+
+/// The interfaces subscribed to each multicast group, as learned by `IgmpSnoop`. Clones share the
+/// same table, so one handle can be given to the snooper and another to `MulticastForward`.
+#[derive(Clone, Default)]
+pub struct GroupMembership {
+    groups: Arc<Mutex<HashMap<Ipv4Addr, HashSet<usize>>>>,
+}
+
+impl GroupMembership {
+    pub fn new() -> Self {
+        GroupMembership {
+            groups: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The interfaces subscribed to `group`, in ascending order.
+    pub fn subscribers(&self, group: Ipv4Addr) -> Vec<usize> {
+        let mut subscribers: Vec<usize> = self
+            .groups
+            .lock()
+            .unwrap()
+            .get(&group)
+            .map(|interfaces| interfaces.iter().copied().collect())
+            .unwrap_or_default();
+        subscribers.sort();
+        subscribers
+    }
+
+    fn join(&self, group: Ipv4Addr, interface: usize) {
+        self.groups
+            .lock()
+            .unwrap()
+            .entry(group)
+            .or_default()
+            .insert(interface);
+    }
+
+    fn leave(&self, group: Ipv4Addr, interface: usize) {
+        let mut groups = self.groups.lock().unwrap();
+        if let Some(interfaces) = groups.get_mut(&group) {
+            interfaces.remove(&interface);
+            if interfaces.is_empty() {
+                groups.remove(&group);
+            }
+        }
+    }
+}
+
+/// Watches IGMP membership reports and leaves, tagged with the interface they arrived on, and
+/// records which interfaces are subscribed to which groups. Understands IGMPv1, v2 and v3
+/// reports. Every packet is passed through unchanged, so the snooper can sit inline.
+pub struct IgmpSnoop {
+    membership: GroupMembership,
+}
+
+impl IgmpSnoop {
+    pub fn new(membership: GroupMembership) -> Self {
+        IgmpSnoop { membership }
+    }
+
+    fn snoop(&self, interface: usize, igmp: &[u8]) {
+        match igmp.first() {
+            Some(&IGMP_V1_MEMBERSHIP_REPORT) | Some(&IGMP_V2_MEMBERSHIP_REPORT) => {
+                if let Some(group) = read_addr(igmp, 4) {
+                    self.membership.join(group, interface);
+                }
+            }
+            Some(&IGMP_V2_LEAVE_GROUP) => {
+                if let Some(group) = read_addr(igmp, 4) {
+                    self.membership.leave(group, interface);
+                }
+            }
+            Some(&IGMP_V3_MEMBERSHIP_REPORT) => self.snoop_v3(interface, igmp),
+            _ => {}
+        }
+    }
+
+    /// An IGMPv3 report holds a list of group records. An INCLUDE record with no sources is a
+    /// leave; any other record means the interface wants at least some of the group's traffic.
+    fn snoop_v3(&self, interface: usize, igmp: &[u8]) {
+        let num_records = match igmp.get(6..8) {
+            Some(num) => u16::from_be_bytes(num.try_into().unwrap()),
+            None => return,
+        };
+
+        let mut offset = 8;
+        for _ in 0..num_records {
+            let record = match igmp.get(offset..offset + 8) {
+                Some(record) => record,
+                None => return,
+            };
+            let record_type = record[0];
+            let aux_len = record[1] as usize * 4;
+            let num_sources = u16::from_be_bytes(record[2..4].try_into().unwrap()) as usize;
+            let group = read_addr(record, 4).unwrap();
+
+            let is_leave = num_sources == 0
+                && (record_type == MODE_IS_INCLUDE || record_type == CHANGE_TO_INCLUDE_MODE);
+            if is_leave {
+                self.membership.leave(group, interface);
+            } else {
+                self.membership.join(group, interface);
+            }
+
+            offset += 8 + num_sources * 4 + aux_len;
+        }
+    }
+}
+
+fn read_addr(bytes: &[u8], offset: usize) -> Option<Ipv4Addr> {
+    let octets: [u8; 4] = bytes.get(offset..offset + 4)?.try_into().unwrap();
+    Some(Ipv4Addr::from(octets))
+}
+
+impl Processor for IgmpSnoop {
+    type Input = (usize, Ipv4Packet);
+    type Output = (usize, Ipv4Packet);
+
+    fn process(&mut self, (interface, packet): Self::Input) -> Option<Self::Output> {
+        if packet.protocol() == IpProtocol::IGMP {
+            self.snoop(interface, &packet.payload());
+        }
+        Some((interface, packet))
+    }
+}
+
+/// Each group is its address, then the number of subscribed interfaces as a u16, then each
+/// interface as a u32, all big endian.
+impl StatefulProcessor for IgmpSnoop {
+    fn snapshot(&self) -> Vec<u8> {
+        let groups = self.membership.groups.lock().unwrap();
+        let mut snapshot = vec![];
+        for (group, interfaces) in groups.iter() {
+            snapshot.extend_from_slice(&group.octets());
+            snapshot.extend_from_slice(&(interfaces.len() as u16).to_be_bytes());
+            for interface in interfaces.iter() {
+                snapshot.extend_from_slice(&(*interface as u32).to_be_bytes());
+            }
+        }
+        snapshot
+    }
+
+    fn restore(&mut self, snapshot: &[u8]) -> Result<(), &'static str> {
+        let mut restored = HashMap::new();
+        let mut offset = 0;
+        while offset < snapshot.len() {
+            let header = snapshot
+                .get(offset..offset + 6)
+                .ok_or("IgmpSnoop snapshot has a partial group")?;
+            let group = read_addr(header, 0).unwrap();
+            let count = u16::from_be_bytes(header[4..6].try_into().unwrap()) as usize;
+            offset += 6;
+
+            let interfaces = snapshot
+                .get(offset..offset + count * 4)
+                .ok_or("IgmpSnoop snapshot has a partial group")?
+                .chunks_exact(4)
+                .map(|interface| u32::from_be_bytes(interface.try_into().unwrap()) as usize)
+                .collect::<HashSet<usize>>();
+            offset += count * 4;
+            restored.insert(group, interfaces);
+        }
+
+        *self.membership.groups.lock().unwrap() = restored;
+        Ok(())
+    }
+}
+
+/// Tags multicast packets with the interfaces subscribed to their group, as learned by
+/// `IgmpSnoop`, rather than flooding them. Packets to groups in 224.0.0.0/24 are link-local
+/// control traffic that hosts do not report, so they are still flooded to `interfaces`. The
+/// ingress interface is never included, and packets with nowhere to go are dropped.
+pub struct MulticastForward {
+    membership: GroupMembership,
+    interfaces: Vec<usize>,
+}
+
+impl MulticastForward {
+    /// Forwards among `interfaces`, using the subscriptions in `membership`.
+    pub fn new(membership: GroupMembership, interfaces: Vec<usize>) -> Self {
+        MulticastForward {
+            membership,
+            interfaces,
+        }
+    }
+}
+
+impl Processor for MulticastForward {
+    type Input = (usize, Ipv4Packet);
+    type Output = (Vec<usize>, Ipv4Packet);
+
+    fn process(&mut self, (ingress, packet): Self::Input) -> Option<Self::Output> {
+        let group = packet.dest_addr();
+        if !group.is_multicast() {
+            return None;
+        }
+
+        let candidates = if group.octets()[..3] == [224, 0, 0] {
+            self.interfaces.clone()
+        } else {
+            self.membership.subscribers(group)
+        };
+        let egress: Vec<usize> = candidates
+            .into_iter()
+            .filter(|interface| *interface != ingress)
+            .collect();
+
+        if egress.is_empty() {
+            None
+        } else {
+            Some((egress, packet))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPSTREAM: usize = 0;
+    const LAN1: usize = 1;
+    const LAN2: usize = 2;
+    const LAN3: usize = 3;
+
+    fn group() -> Ipv4Addr {
+        Ipv4Addr::new(239, 1, 2, 3)
+    }
+
+    fn igmp(igmp: &[u8]) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_protocol(2);
+        packet.set_dest_addr(Ipv4Addr::new(224, 0, 0, 22));
+        packet.set_payload(igmp);
+        packet
+    }
+
+    fn v2(message_type: u8, group: Ipv4Addr) -> Ipv4Packet {
+        let mut message = vec![message_type, 0, 0, 0];
+        message.extend_from_slice(&group.octets());
+        igmp(&message)
+    }
+
+    fn multicast(group: Ipv4Addr) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_protocol(17);
+        packet.set_dest_addr(group);
+        packet
+    }
+
+    fn elements() -> (IgmpSnoop, MulticastForward) {
+        let membership = GroupMembership::new();
+        (
+            IgmpSnoop::new(membership.clone()),
+            MulticastForward::new(membership, vec![UPSTREAM, LAN1, LAN2, LAN3]),
+        )
+    }
+
+    #[test]
+    fn forwards_only_to_subscribers() {
+        let (mut snoop, mut forward) = elements();
+
+        let (interface, _) = snoop
+            .process((LAN2, v2(IGMP_V2_MEMBERSHIP_REPORT, group())))
+            .unwrap();
+        assert_eq!(interface, LAN2);
+
+        let (egress, _) = forward.process((UPSTREAM, multicast(group()))).unwrap();
+        assert_eq!(egress, vec![LAN2]);
+    }
+
+    #[test]
+    fn drops_unsubscribed_groups() {
+        let (mut snoop, mut forward) = elements();
+
+        snoop
+            .process((LAN2, v2(IGMP_V1_MEMBERSHIP_REPORT, group())))
+            .unwrap();
+
+        assert!(forward
+            .process((UPSTREAM, multicast(Ipv4Addr::new(239, 9, 9, 9))))
+            .is_none());
+        assert!(forward.process((LAN2, multicast(group()))).is_none());
+        assert!(forward
+            .process((UPSTREAM, multicast(Ipv4Addr::new(10, 0, 0, 1))))
+            .is_none());
+    }
+
+    #[test]
+    fn floods_link_local_groups() {
+        let (_, mut forward) = elements();
+
+        let (egress, _) = forward
+            .process((LAN1, multicast(Ipv4Addr::new(224, 0, 0, 251))))
+            .unwrap();
+        assert_eq!(egress, vec![UPSTREAM, LAN2, LAN3]);
+    }
+
+    #[test]
+    fn leave_unsubscribes() {
+        let (mut snoop, mut forward) = elements();
+
+        snoop
+            .process((LAN1, v2(IGMP_V2_MEMBERSHIP_REPORT, group())))
+            .unwrap();
+        snoop
+            .process((LAN3, v2(IGMP_V2_MEMBERSHIP_REPORT, group())))
+            .unwrap();
+        let (egress, _) = forward.process((UPSTREAM, multicast(group()))).unwrap();
+        assert_eq!(egress, vec![LAN1, LAN3]);
+
+        snoop
+            .process((LAN1, v2(IGMP_V2_LEAVE_GROUP, group())))
+            .unwrap();
+        let (egress, _) = forward.process((UPSTREAM, multicast(group()))).unwrap();
+        assert_eq!(egress, vec![LAN3]);
+    }
+
+    #[test]
+    fn understands_v3_reports() {
+        let (mut snoop, _) = elements();
+        let membership = snoop.membership.clone();
+        let other_group = Ipv4Addr::new(239, 4, 5, 6);
+
+        // Two records: EXCLUDE {} for group(), then ALLOW one source for other_group.
+        let mut report = vec![IGMP_V3_MEMBERSHIP_REPORT, 0, 0, 0, 0, 0, 0, 2];
+        report.extend_from_slice(&[2, 0, 0, 0]);
+        report.extend_from_slice(&group().octets());
+        report.extend_from_slice(&[5, 0, 0, 1]);
+        report.extend_from_slice(&other_group.octets());
+        report.extend_from_slice(&[10, 0, 0, 1]);
+        snoop.process((LAN1, igmp(&report))).unwrap();
+
+        assert_eq!(membership.subscribers(group()), vec![LAN1]);
+        assert_eq!(membership.subscribers(other_group), vec![LAN1]);
+
+        // TO_IN {} is a leave.
+        let mut report = vec![IGMP_V3_MEMBERSHIP_REPORT, 0, 0, 0, 0, 0, 0, 1];
+        report.extend_from_slice(&[3, 0, 0, 0]);
+        report.extend_from_slice(&group().octets());
+        snoop.process((LAN1, igmp(&report))).unwrap();
+
+        assert!(membership.subscribers(group()).is_empty());
+    }
+
+    #[test]
+    fn ignores_truncated_reports() {
+        let (mut snoop, _) = elements();
+        let membership = snoop.membership.clone();
+
+        snoop
+            .process((LAN1, igmp(&[IGMP_V2_MEMBERSHIP_REPORT, 0, 0, 0, 239])))
+            .unwrap();
+        snoop
+            .process((
+                LAN1,
+                igmp(&[IGMP_V3_MEMBERSHIP_REPORT, 0, 0, 0, 0, 0, 0, 3]),
+            ))
+            .unwrap();
+
+        assert!(membership.groups.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn snapshot_restores_into_fresh_snooper() {
+        let (mut snoop, _) = elements();
+        snoop
+            .process((LAN1, v2(IGMP_V2_MEMBERSHIP_REPORT, group())))
+            .unwrap();
+        snoop
+            .process((LAN3, v2(IGMP_V2_MEMBERSHIP_REPORT, group())))
+            .unwrap();
+        let snapshot = snoop.snapshot();
+
+        let (mut restored, mut forward) = elements();
+        restored.restore(&snapshot).unwrap();
+
+        let (egress, _) = forward.process((UPSTREAM, multicast(group()))).unwrap();
+        assert_eq!(egress, vec![LAN1, LAN3]);
+    }
+
+    #[test]
+    fn restore_rejects_malformed_snapshot() {
+        let (mut snoop, _) = elements();
+        snoop
+            .process((LAN1, v2(IGMP_V2_MEMBERSHIP_REPORT, group())))
+            .unwrap();
+        let mut snapshot = snoop.snapshot();
+        snapshot.pop();
+
+        assert!(snoop.restore(&snapshot).is_err());
+        assert_eq!(snoop.membership.subscribers(group()), vec![LAN1]);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::processor::{InterfaceAwareProcessor, Processor};
+
+/// Runs an `InterfaceAwareProcessor` as a `Processor`. Takes packets tagged with the interface they
+/// arrived on, hands the packet and the interface to the inner processor, and tags its output with
+/// the same interface.
+pub struct InterfaceAware<P> {
+    processor: P,
+}
+
+impl<P: InterfaceAwareProcessor> InterfaceAware<P> {
+    pub fn new(processor: P) -> Self {
+        InterfaceAware { processor }
+    }
+
+    /// The wrapped processor.
+    pub fn inner(&self) -> &P {
+        &self.processor
+    }
+}
+
+impl<P: InterfaceAwareProcessor> Processor for InterfaceAware<P> {
+    type Input = (usize, P::Input);
+    type Output = (usize, P::Output);
+
+    fn process(&mut self, (inbound, packet): Self::Input) -> Option<Self::Output> {
+        let output = self.processor.process(packet, inbound)?;
+        Some((inbound, output))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::primitive::ProcessLink;
+    use crate::link::{LinkBuilder, ProcessLinkBuilder};
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+
+    const LAN: usize = 0;
+    const WAN: usize = 1;
+
+    /// Drops packets from the WAN, and records where everything else came from.
+    struct LanOnly;
+
+    impl InterfaceAwareProcessor for LanOnly {
+        type Input = i32;
+        type Output = (i32, usize);
+
+        fn process(&mut self, packet: Self::Input, inbound: usize) -> Option<Self::Output> {
+            if inbound == WAN {
+                None
+            } else {
+                Some((packet, inbound))
+            }
+        }
+    }
+
+    #[test]
+    fn delivers_inbound_interface() {
+        let mut elem = InterfaceAware::new(LanOnly);
+
+        assert_eq!(elem.process((LAN, 7)), Some((LAN, (7, LAN))));
+        assert_eq!(elem.process((3, 8)), Some((3, (8, 3))));
+        assert_eq!(elem.process((WAN, 9)), None);
+    }
+
+    #[test]
+    fn runs_in_process_link() {
+        let packets = vec![(LAN, 1), (WAN, 2), (LAN, 3), (2, 4), (WAN, 5)];
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = ProcessLink::new()
+                .ingressor(immediate_stream(packets))
+                .processor(InterfaceAware::new(LanOnly))
+                .build_link();
+
+            run_link(link).await
+        });
+        assert_eq!(
+            results[0],
+            vec![(LAN, (1, LAN)), (LAN, (3, LAN)), (2, (4, 2))]
+        );
+    }
+}
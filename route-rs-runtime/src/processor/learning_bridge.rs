@@ -0,0 +1,278 @@
+use crate::link::utils::clock::{Clock, SystemClock};
+use crate::processor::{Processor, StatefulProcessor};
+use route_rs_packets::{EthernetFrame, MacAddr};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::time::{Duration, Instant};
+
+/// Where a `LearningBridge` decided to send a frame.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum BridgeForward {
+    /// Send the frame out of this port only.
+    Port(usize),
+    /// Send the frame out of every port except the one it arrived on.
+    Flood { ingress: usize },
+}
+
+impl BridgeForward {
+    /// Whether a frame with this decision should leave through `port`.
+    pub fn includes(&self, port: usize) -> bool {
+        match *self {
+            BridgeForward::Port(egress) => egress == port,
+            BridgeForward::Flood { ingress } => ingress != port,
+        }
+    }
+}
+
+/// An L2 learning switch. Takes each frame tagged with the port it arrived on, learns that the
+/// source MAC lives behind that port, and tags the frame with where it should be forwarded.
+/// Frames to a learned MAC go out of the learned port only; broadcast, multicast, and unknown
+/// destinations are flooded. Frames whose destination lives behind the port they arrived on are
+/// dropped, since the destination has already seen them.
+///
+/// Entries that have not been refreshed for `max_age` are forgotten, so hosts that move between
+/// ports are relearned.
+pub struct LearningBridge {
+    table: HashMap<MacAddr, (usize, Instant)>,
+    max_age: Duration,
+    clock: Box<dyn Clock>,
+}
+
+impl LearningBridge {
+    pub fn new() -> Self {
+        LearningBridge {
+            table: HashMap::new(),
+            max_age: Duration::from_secs(300),
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Changes max_age, default value is 300 seconds.
+    pub fn max_age(self, max_age: Duration) -> Self {
+        LearningBridge {
+            table: self.table,
+            max_age,
+            clock: self.clock,
+        }
+    }
+
+    /// Changes the clock used to age out entries, default is `SystemClock`.
+    pub fn clock<C: Clock + 'static>(self, clock: C) -> Self {
+        LearningBridge {
+            table: self.table,
+            max_age: self.max_age,
+            clock: Box::new(clock),
+        }
+    }
+
+    /// The port `mac` was last seen on, if it has not aged out.
+    pub fn lookup(&self, mac: &MacAddr) -> Option<usize> {
+        let now = self.clock.now();
+        self.table
+            .get(mac)
+            .filter(|(_, seen)| now.saturating_duration_since(*seen) < self.max_age)
+            .map(|(port, _)| *port)
+    }
+
+    /// Forgets all entries that have aged out.
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        let max_age = self.max_age;
+        self.table
+            .retain(|_, (_, seen)| now.saturating_duration_since(*seen) < max_age);
+    }
+}
+
+impl Default for LearningBridge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The group bit is the least significant bit of the first octet; it is set for both multicast
+/// and broadcast addresses.
+fn is_group(mac: &MacAddr) -> bool {
+    mac.bytes[0] & 0x01 != 0
+}
+
+impl Processor for LearningBridge {
+    type Input = (usize, EthernetFrame);
+    type Output = (BridgeForward, EthernetFrame);
+
+    fn process(&mut self, (ingress, frame): Self::Input) -> Option<Self::Output> {
+        let src_mac = frame.src_mac();
+        if !is_group(&src_mac) {
+            self.table.insert(src_mac, (ingress, self.clock.now()));
+        }
+
+        let dest_mac = frame.dest_mac();
+        if is_group(&dest_mac) {
+            return Some((BridgeForward::Flood { ingress }, frame));
+        }
+        match self.lookup(&dest_mac) {
+            Some(port) if port == ingress => None,
+            Some(port) => Some((BridgeForward::Port(port), frame)),
+            None => Some((BridgeForward::Flood { ingress }, frame)),
+        }
+    }
+}
+
+/// Each entry is the MAC, the port as a u32, and the age of the entry in milliseconds as a u64,
+/// all big endian. Storing ages rather than instants lets entries keep aging after a restore.
+const SNAPSHOT_ENTRY_LEN: usize = 6 + 4 + 8;
+
+impl StatefulProcessor for LearningBridge {
+    fn snapshot(&self) -> Vec<u8> {
+        let now = self.clock.now();
+        let mut snapshot = Vec::with_capacity(self.table.len() * SNAPSHOT_ENTRY_LEN);
+        for (mac, (port, seen)) in self.table.iter() {
+            let age = now.saturating_duration_since(*seen);
+            if age >= self.max_age {
+                continue;
+            }
+            snapshot.extend_from_slice(&mac.bytes);
+            snapshot.extend_from_slice(&(*port as u32).to_be_bytes());
+            snapshot.extend_from_slice(&(age.as_millis() as u64).to_be_bytes());
+        }
+        snapshot
+    }
+
+    fn restore(&mut self, snapshot: &[u8]) -> Result<(), &'static str> {
+        if !snapshot.len().is_multiple_of(SNAPSHOT_ENTRY_LEN) {
+            return Err("LearningBridge snapshot has a partial entry");
+        }
+
+        let now = self.clock.now();
+        let mut table = HashMap::with_capacity(snapshot.len() / SNAPSHOT_ENTRY_LEN);
+        for entry in snapshot.chunks_exact(SNAPSHOT_ENTRY_LEN) {
+            let mac = MacAddr::new(entry[0..6].try_into().unwrap());
+            let port = u32::from_be_bytes(entry[6..10].try_into().unwrap()) as usize;
+            let age = Duration::from_millis(u64::from_be_bytes(entry[10..18].try_into().unwrap()));
+            let seen = now.checked_sub(age).unwrap_or(now);
+            table.insert(mac, (port, seen));
+        }
+        self.table = table;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::clock::ManualClock;
+
+    const HOST_A: MacAddr = MacAddr {
+        bytes: [0x02, 0, 0, 0, 0, 0x0A],
+    };
+    const HOST_B: MacAddr = MacAddr {
+        bytes: [0x02, 0, 0, 0, 0, 0x0B],
+    };
+    const BROADCAST: MacAddr = MacAddr { bytes: [0xFF; 6] };
+
+    fn frame(src_mac: MacAddr, dest_mac: MacAddr) -> EthernetFrame {
+        let mut frame = EthernetFrame::empty();
+        frame.set_src_mac(src_mac);
+        frame.set_dest_mac(dest_mac);
+        frame
+    }
+
+    #[test]
+    fn floods_unknown_destination() {
+        let mut bridge = LearningBridge::new();
+
+        let (forward, _) = bridge.process((1, frame(HOST_A, HOST_B))).unwrap();
+
+        assert_eq!(forward, BridgeForward::Flood { ingress: 1 });
+        assert!(!forward.includes(1));
+        assert!(forward.includes(0));
+        assert!(forward.includes(2));
+    }
+
+    #[test]
+    fn forwards_to_learned_port_only() {
+        let mut bridge = LearningBridge::new();
+
+        bridge.process((1, frame(HOST_A, HOST_B))).unwrap();
+        let (forward, _) = bridge.process((3, frame(HOST_B, HOST_A))).unwrap();
+
+        assert_eq!(forward, BridgeForward::Port(1));
+        assert!(forward.includes(1));
+        assert!(!forward.includes(0));
+        assert!(!forward.includes(2));
+        assert_eq!(bridge.lookup(&HOST_B), Some(3));
+    }
+
+    #[test]
+    fn floods_broadcast_even_when_learned() {
+        let mut bridge = LearningBridge::new();
+
+        bridge.process((1, frame(HOST_A, BROADCAST))).unwrap();
+        let (forward, _) = bridge.process((2, frame(HOST_B, BROADCAST))).unwrap();
+
+        assert_eq!(forward, BridgeForward::Flood { ingress: 2 });
+        assert_eq!(bridge.lookup(&BROADCAST), None);
+    }
+
+    #[test]
+    fn drops_frames_back_to_ingress_port() {
+        let mut bridge = LearningBridge::new();
+
+        bridge.process((1, frame(HOST_A, HOST_B))).unwrap();
+        assert!(bridge.process((1, frame(HOST_B, HOST_A))).is_none());
+    }
+
+    #[test]
+    fn relearns_after_aging() {
+        let clock = ManualClock::new();
+        let mut bridge = LearningBridge::new()
+            .max_age(Duration::from_secs(10))
+            .clock(clock.clone());
+
+        bridge.process((1, frame(HOST_A, HOST_B))).unwrap();
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(bridge.lookup(&HOST_A), Some(1));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(bridge.lookup(&HOST_A), None);
+        let (forward, _) = bridge.process((2, frame(HOST_B, HOST_A))).unwrap();
+        assert_eq!(forward, BridgeForward::Flood { ingress: 2 });
+
+        bridge.expire();
+        bridge.process((4, frame(HOST_A, HOST_B))).unwrap();
+        assert_eq!(bridge.lookup(&HOST_A), Some(4));
+    }
+
+    #[test]
+    fn snapshot_restores_into_fresh_bridge() {
+        let clock = ManualClock::new();
+        let mut bridge = LearningBridge::new()
+            .max_age(Duration::from_secs(10))
+            .clock(clock.clone());
+        bridge.process((1, frame(HOST_A, BROADCAST))).unwrap();
+        clock.advance(Duration::from_secs(4));
+        bridge.process((3, frame(HOST_B, BROADCAST))).unwrap();
+
+        let snapshot = bridge.snapshot();
+        let mut restored = LearningBridge::new()
+            .max_age(Duration::from_secs(10))
+            .clock(clock.clone());
+        restored.restore(&snapshot).unwrap();
+
+        let (forward, _) = restored.process((3, frame(HOST_B, HOST_A))).unwrap();
+        assert_eq!(forward, BridgeForward::Port(1));
+        assert_eq!(restored.lookup(&HOST_B), Some(3));
+
+        // Entries keep the age they had when the snapshot was taken.
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(restored.lookup(&HOST_A), None);
+    }
+
+    #[test]
+    fn restore_rejects_malformed_snapshot() {
+        let mut bridge = LearningBridge::new();
+        bridge.process((1, frame(HOST_A, BROADCAST))).unwrap();
+
+        assert!(bridge.restore(&[0; 5]).is_err());
+        assert_eq!(bridge.lookup(&HOST_A), Some(1));
+    }
+}
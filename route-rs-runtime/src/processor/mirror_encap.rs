@@ -0,0 +1,156 @@
+use crate::processor::Processor;
+use route_rs_packets::{Ipv4Packet, UdpSegment};
+use std::net::Ipv4Addr;
+
+/// Wraps each IPv4 packet in an outer IPv4/UDP header addressed to a remote collector, for
+/// mirroring traffic to an analyzer. The whole inner packet, starting at its IPv4 header, becomes
+/// the UDP payload.
+///
+/// `MirrorEncap` consumes the packet it is handed, so it should sit behind a `ForkLink` egressor
+/// that acts as a tap; the packets on the other egressors are forwarded untouched.
+#[derive(Clone)]
+pub struct MirrorEncap {
+    src_addr: Ipv4Addr,
+    src_port: u16,
+    collector_addr: Ipv4Addr,
+    collector_port: u16,
+    ttl: u8,
+}
+
+impl MirrorEncap {
+    pub fn new(src_addr: Ipv4Addr, collector_addr: Ipv4Addr, collector_port: u16) -> MirrorEncap {
+        MirrorEncap {
+            src_addr,
+            src_port: collector_port,
+            collector_addr,
+            collector_port,
+            ttl: 64,
+        }
+    }
+
+    /// Changes the UDP source port of the tunnel, default is the collector port.
+    pub fn src_port(self, src_port: u16) -> MirrorEncap {
+        MirrorEncap { src_port, ..self }
+    }
+
+    /// Changes the TTL of the outer header, default is 64.
+    pub fn ttl(self, ttl: u8) -> MirrorEncap {
+        MirrorEncap { ttl, ..self }
+    }
+}
+
+impl Processor for MirrorEncap {
+    type Input = Ipv4Packet;
+    type Output = Ipv4Packet;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let inner = &packet.data[packet.layer3_offset..];
+
+        let mut udp = UdpSegment::empty();
+        udp.set_src_port(self.src_port);
+        udp.set_dest_port(self.collector_port);
+        udp.set_payload(inner);
+        // UdpSegment::set_payload leaves the length field alone. The checksum stays 0, which
+        // means no checksum over IPv4.
+        let udp_len = (udp.data.len() - udp.layer4_offset) as u16;
+        udp.data[udp.layer4_offset + 4..=udp.layer4_offset + 5]
+            .copy_from_slice(&udp_len.to_be_bytes());
+
+        let mut outer = Ipv4Packet::encap_udp(udp);
+        outer.set_src_addr(self.src_addr);
+        outer.set_dest_addr(self.collector_addr);
+        outer.set_ttl(self.ttl);
+        outer.set_checksum();
+        Some(outer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::primitive::{ForkLink, ProcessLink};
+    use crate::link::{LinkBuilder, ProcessLinkBuilder};
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use route_rs_packets::IpProtocol;
+    use std::convert::TryFrom;
+
+    fn original() -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(Ipv4Addr::new(192, 168, 1, 10));
+        packet.set_dest_addr(Ipv4Addr::new(8, 8, 8, 8));
+        packet.set_ttl(64);
+        packet.set_protocol(17);
+        packet.set_payload(&[0, 53, 0, 53, 0, 12, 0, 0, 1, 2, 3, 4]);
+        packet.set_checksum();
+        packet
+    }
+
+    #[test]
+    fn outer_headers_target_collector() {
+        let collector = Ipv4Addr::new(10, 9, 9, 9);
+        let mut elem = MirrorEncap::new(Ipv4Addr::new(10, 0, 0, 1), collector, 4789).ttl(32);
+
+        let mut mirrored = elem.process(original()).unwrap();
+
+        assert_eq!(mirrored.src_addr(), Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(mirrored.dest_addr(), collector);
+        assert_eq!(mirrored.ttl(), 32);
+        assert_eq!(mirrored.protocol(), IpProtocol::UDP);
+        assert!(mirrored.validate_checksum());
+
+        let udp = UdpSegment::try_from(mirrored.clone()).unwrap();
+        assert_eq!(udp.src_port(), 4789);
+        assert_eq!(udp.dest_port(), 4789);
+        assert_eq!(udp.length() as usize, 8 + original().data.len());
+    }
+
+    #[test]
+    fn inner_bytes_equal_original() {
+        let mut elem =
+            MirrorEncap::new(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 9, 9, 9), 4789)
+                .src_port(50000);
+
+        let mirrored = elem.process(original()).unwrap();
+        let udp = UdpSegment::try_from(mirrored).unwrap();
+
+        assert_eq!(udp.src_port(), 50000);
+        assert_eq!(udp.payload().to_vec(), original().data);
+        assert_eq!(
+            Ipv4Packet::try_from_bytes(&udp.payload()).unwrap(),
+            original()
+        );
+    }
+
+    #[test]
+    fn forwarded_original_is_untouched() {
+        let packets = vec![original(), original()];
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let (mut fork_runnables, mut fork_egressors) = ForkLink::new()
+                .ingressor(immediate_stream(packets.clone()))
+                .num_egressors(2)
+                .build_link();
+            let tap = fork_egressors.pop().unwrap();
+
+            let (mut mirror_runnables, mirror_egressors) = ProcessLink::new()
+                .ingressor(tap)
+                .processor(MirrorEncap::new(
+                    Ipv4Addr::new(10, 0, 0, 1),
+                    Ipv4Addr::new(10, 9, 9, 9),
+                    4789,
+                ))
+                .build_link();
+
+            fork_runnables.append(&mut mirror_runnables);
+            fork_egressors.extend(mirror_egressors);
+            run_link((fork_runnables, fork_egressors)).await
+        });
+
+        assert_eq!(results[0], packets);
+        for mirrored in results[1].iter() {
+            assert_eq!(mirrored.dest_addr(), Ipv4Addr::new(10, 9, 9, 9));
+        }
+    }
+}
@@ -19,9 +19,168 @@ pub use self::drop::*;
 mod dec_ip_hop;
 pub use self::dec_ip_hop::*;
 
+mod set_ttl;
+pub use self::set_ttl::*;
+
+mod set_dscp;
+pub use self::set_dscp::*;
+
+mod mirror_encap;
+pub use self::mirror_encap::*;
+
+mod gre;
+pub use self::gre::*;
+
+mod crypto;
+pub use self::crypto::*;
+
+mod learning_bridge;
+pub use self::learning_bridge::*;
+
+mod multicast_reflector;
+pub use self::multicast_reflector::*;
+
+mod igmp_snoop;
+pub use self::igmp_snoop::*;
+
+mod mtu_enforce;
+pub use self::mtu_enforce::*;
+
+mod validate_flags;
+pub use self::validate_flags::*;
+
+mod clamp_mss;
+pub use self::clamp_mss::*;
+
+mod icmp_redirect;
+pub use self::icmp_redirect::*;
+
+mod ndp_responder;
+pub use self::ndp_responder::*;
+
+mod icmpv6;
+pub use self::icmpv6::*;
+
+mod broadcast_filter;
+pub use self::broadcast_filter::*;
+
+mod rewrite_l2;
+pub use self::rewrite_l2::*;
+
+mod pppoe;
+pub use self::pppoe::*;
+
+mod dns_rewrite;
+pub use self::dns_rewrite::*;
+
+mod fib_lookup;
+pub use self::fib_lookup::*;
+
+mod policy_router;
+pub use self::policy_router::*;
+
+mod set_mark;
+pub use self::set_mark::*;
+
+mod wan_failover;
+pub use self::wan_failover::*;
+
+mod rip_listener;
+pub use self::rip_listener::*;
+
+mod flow_accounting;
+pub use self::flow_accounting::*;
+
+mod preserve_original_tuple;
+pub use self::preserve_original_tuple::*;
+
+mod per_flow_rate_limiter;
+pub use self::per_flow_rate_limiter::*;
+
+mod interface_aware;
+pub use self::interface_aware::*;
+
+mod annotated;
+pub use self::annotated::*;
+
+mod sequence_tagger;
+pub use self::sequence_tagger::*;
+
+mod chained;
+pub use self::chained::*;
+
+mod circuit_breaker;
+pub use self::circuit_breaker::*;
+
+mod registry;
+pub use self::registry::*;
+
 pub trait Processor {
     type Input: Send + Clone;
     type Output: Send + Clone;
 
     fn process(&mut self, packet: Self::Input) -> Option<Self::Output>;
+
+    /// Fuses this processor with `next` into one processor, which runs `next` on whatever this
+    /// processor emits. If this processor drops a packet, `next` never sees it.
+    fn and_then<P>(self, next: P) -> Chained<Self, P>
+    where
+        Self: Sized,
+        P: Processor<Input = Self::Output>,
+    {
+        Chained::new(self, next)
+    }
+}
+
+/// A boxed processor is a processor, so that processors chosen at runtime, such as those built by a
+/// `ProcessorRegistry`, can be handed to links.
+impl<P: Processor + ?Sized> Processor for Box<P> {
+    type Input = P::Input;
+    type Output = P::Output;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        (**self).process(packet)
+    }
+}
+
+/// A `Processor` that works on many packets at once. `BatchProcessLink` collects packets into a
+/// batch before handing them over, which amortizes per-packet overhead for work that is cheaper
+/// to do in bulk, such as recomputing checksums.
+pub trait BatchProcessor {
+    type Input: Send + Clone;
+    type Output: Send + Clone;
+
+    fn process_batch(&mut self, packets: Vec<Self::Input>) -> Vec<Self::Output>;
+}
+
+/// A `Processor` whose processing can fail, such as a stage that depends on state that may go
+/// bad. Returning an error rather than `None` lets a wrapper tell a failure apart from a packet
+/// that was meant to be dropped. Wrap it in `CircuitBreaker` to run it as a `Processor`.
+pub trait FallibleProcessor {
+    type Input: Send + Clone;
+    type Output: Send + Clone;
+    type Error;
+
+    fn try_process(&mut self, packet: Self::Input) -> Result<Option<Self::Output>, Self::Error>;
+}
+
+/// A `Processor` that needs to know which interface each packet arrived on, such as reverse path
+/// filtering or a stateful firewall. Wrap it in `InterfaceAware` to run it on packets tagged with
+/// their inbound interface.
+pub trait InterfaceAwareProcessor {
+    type Input: Send + Clone;
+    type Output: Send + Clone;
+
+    fn process(&mut self, packet: Self::Input, inbound: usize) -> Option<Self::Output>;
+}
+
+/// A processor whose state can be exported and imported, so that it can be checkpointed across
+/// restarts, or handed over to a fresh instance.
+pub trait StatefulProcessor {
+    /// Serializes the current state.
+    fn snapshot(&self) -> Vec<u8>;
+
+    /// Replaces the current state with one produced by `snapshot`. Returns an error, and leaves
+    /// the state untouched, if the snapshot is malformed.
+    fn restore(&mut self, snapshot: &[u8]) -> Result<(), &'static str>;
 }
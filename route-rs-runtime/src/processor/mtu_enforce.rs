@@ -0,0 +1,239 @@
+use crate::interface::InterfaceConfig;
+use crate::processor::Processor;
+use route_rs_packets::{Ipv4Packet, ICMP_CODE_FRAGMENTATION_NEEDED, ICMP_DEST_UNREACHABLE};
+use std::net::Ipv4Addr;
+
+/// The outcome of checking a packet against the egress MTU.
+#[derive(Clone, Debug)]
+pub enum MtuCheck {
+    /// The packet may continue towards the egress interface.
+    Forward(Ipv4Packet),
+    /// The packet was too big and could not be fragmented, so it was dropped. Holds the ICMP
+    /// "fragmentation needed" message to send back to its source.
+    FragmentationNeeded(Ipv4Packet),
+}
+
+/// Enforces the MTU of an egress interface for Path MTU Discovery (RFC 1191). Packets that are
+/// larger than the MTU and have the Don't Fragment flag set are dropped, and replaced with an ICMP
+/// destination unreachable, fragmentation needed message that carries the MTU and is addressed
+/// to the packet's source. All other packets are forwarded; packets that are too big but may be
+/// fragmented are left to a fragmenter downstream.
+#[derive(Clone)]
+pub struct MtuEnforce {
+    egress: Egress,
+}
+
+/// Where `MtuEnforce` finds the MTU it enforces, and the address it sends ICMP errors from.
+#[derive(Clone)]
+enum Egress {
+    /// Given at construction.
+    Fixed { mtu: u16, src_addr: Ipv4Addr },
+    /// Read from the `InterfaceConfig` on every packet, so changes to the interface apply at once.
+    Interface {
+        config: InterfaceConfig,
+        interface: usize,
+    },
+}
+
+impl MtuEnforce {
+    /// Enforces `mtu`, sending ICMP errors from the router address `src_addr`.
+    pub fn new(mtu: u16, src_addr: Ipv4Addr) -> Self {
+        assert!(mtu >= 68, "mtu: {}, must be >= 68", mtu);
+        MtuEnforce {
+            egress: Egress::Fixed { mtu, src_addr },
+        }
+    }
+
+    /// Enforces the MTU of `interface` in `config`, sending ICMP errors from its primary address.
+    /// Both are read for each packet, so the enforcer follows later changes to the interface.
+    /// Packets are dropped while the interface is not configured, and packets that need an ICMP
+    /// error are dropped without one while it has no IPv4 address.
+    pub fn for_interface(config: &InterfaceConfig, interface: usize) -> Self {
+        config
+            .get(interface)
+            .expect("Cannot enforce MTU! Interface is not configured");
+        MtuEnforce {
+            egress: Egress::Interface {
+                config: config.clone(),
+                interface,
+            },
+        }
+    }
+
+    fn mtu(&self) -> Option<u16> {
+        match &self.egress {
+            Egress::Fixed { mtu, .. } => Some(*mtu),
+            Egress::Interface { config, interface } => config.mtu(*interface),
+        }
+    }
+
+    fn src_addr(&self) -> Option<Ipv4Addr> {
+        match &self.egress {
+            Egress::Fixed { src_addr, .. } => Some(*src_addr),
+            Egress::Interface { config, interface } => config
+                .get(*interface)
+                .and_then(|settings| settings.primary_ipv4_addr()),
+        }
+    }
+}
+
+impl Processor for MtuEnforce {
+    type Input = Ipv4Packet;
+    type Output = MtuCheck;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let mtu = self.mtu()?;
+        let (dont_fragment, _) = packet.flags();
+        if packet.total_len() <= mtu || !dont_fragment {
+            return Some(MtuCheck::Forward(packet));
+        }
+
+        let mtu = mtu.to_be_bytes();
+        let icmp = Ipv4Packet::icmp_error(
+            &packet,
+            self.src_addr()?,
+            ICMP_DEST_UNREACHABLE,
+            ICMP_CODE_FRAGMENTATION_NEEDED,
+            [0, 0, mtu[0], mtu[1]],
+        );
+        Some(MtuCheck::FragmentationNeeded(icmp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::InterfaceSettings;
+    use route_rs_packets::{IpProtocol, MacAddr};
+
+    const ROUTER: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 1);
+    const HOST: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 10);
+
+    fn packet(total_len: usize, dont_fragment: bool) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(HOST);
+        packet.set_dest_addr(Ipv4Addr::new(8, 8, 8, 8));
+        packet.set_ttl(64);
+        packet.set_protocol(6);
+        packet.set_flags(dont_fragment, false);
+        packet.set_payload(&vec![0x55; total_len - 20]);
+        packet.set_checksum();
+        packet
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_tiny_mtu() {
+        MtuEnforce::new(20, ROUTER);
+    }
+
+    #[test]
+    fn forwards_packets_that_fit() {
+        let mut elem = MtuEnforce::new(1492, ROUTER);
+
+        match elem.process(packet(1492, true)).unwrap() {
+            MtuCheck::Forward(forwarded) => assert_eq!(forwarded, packet(1492, true)),
+            other => panic!("Expected Forward, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn forwards_oversized_packets_that_may_fragment() {
+        let mut elem = MtuEnforce::new(1492, ROUTER);
+
+        match elem.process(packet(1500, false)).unwrap() {
+            MtuCheck::Forward(forwarded) => assert_eq!(forwarded.total_len(), 1500),
+            other => panic!("Expected Forward, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn oversized_df_packet_gets_fragmentation_needed() {
+        let mut elem = MtuEnforce::new(1492, ROUTER);
+
+        let mut icmp = match elem.process(packet(1500, true)).unwrap() {
+            MtuCheck::FragmentationNeeded(icmp) => icmp,
+            other => panic!("Expected FragmentationNeeded, got {:?}", other),
+        };
+
+        assert_eq!(icmp.protocol(), IpProtocol::ICMP);
+        assert_eq!(icmp.src_addr(), ROUTER);
+        assert_eq!(icmp.dest_addr(), HOST);
+        assert!(icmp.validate_checksum());
+
+        let message = icmp.payload();
+        assert_eq!(message[0], ICMP_DEST_UNREACHABLE);
+        assert_eq!(message[1], ICMP_CODE_FRAGMENTATION_NEEDED);
+        assert_eq!(u16::from_be_bytes([message[6], message[7]]), 1492);
+        // The quoted header identifies the dropped packet.
+        assert_eq!(message[8..28], packet(1500, true).data[..20]);
+    }
+
+    #[test]
+    fn reads_mtu_of_interface() {
+        const LAN: usize = 0;
+        const WAN: usize = 1;
+        let config = InterfaceConfig::new();
+        config.set(
+            LAN,
+            InterfaceSettings::new(1500, MacAddr::new([0x02, 0, 0, 0, 0, 1]), vec![ROUTER]),
+        );
+        config.set(
+            WAN,
+            InterfaceSettings::new(
+                1492,
+                MacAddr::new([0x02, 0, 0, 0, 0, 2]),
+                vec![Ipv4Addr::new(203, 0, 113, 7)],
+            ),
+        );
+
+        let mut lan = MtuEnforce::for_interface(&config, LAN);
+        let mut wan = MtuEnforce::for_interface(&config, WAN);
+
+        match lan.process(packet(1500, true)).unwrap() {
+            MtuCheck::Forward(_) => {}
+            other => panic!("Expected Forward, got {:?}", other),
+        }
+        match wan.process(packet(1500, true)).unwrap() {
+            MtuCheck::FragmentationNeeded(icmp) => {
+                assert_eq!(icmp.src_addr(), Ipv4Addr::new(203, 0, 113, 7));
+                assert_eq!(icmp.payload()[6..8], 1492u16.to_be_bytes());
+            }
+            other => panic!("Expected FragmentationNeeded, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn follows_changes_to_interface() {
+        const WAN: usize = 1;
+        let mac = MacAddr::new([0x02, 0, 0, 0, 0, 2]);
+        let config = InterfaceConfig::new();
+        config.set(WAN, InterfaceSettings::new(1500, mac, vec![ROUTER]));
+        let mut elem = MtuEnforce::for_interface(&config, WAN);
+
+        match elem.process(packet(1500, true)).unwrap() {
+            MtuCheck::Forward(_) => {}
+            other => panic!("Expected Forward, got {:?}", other),
+        }
+
+        let wan_addr = Ipv4Addr::new(203, 0, 113, 7);
+        config.set(WAN, InterfaceSettings::new(1492, mac, vec![wan_addr]));
+        match elem.process(packet(1500, true)).unwrap() {
+            MtuCheck::FragmentationNeeded(icmp) => {
+                assert_eq!(icmp.src_addr(), wan_addr);
+                assert_eq!(icmp.payload()[6..8], 1492u16.to_be_bytes());
+            }
+            other => panic!("Expected FragmentationNeeded, got {:?}", other),
+        }
+
+        config.set(WAN, InterfaceSettings::new(1492, mac, vec![]));
+        assert!(elem.process(packet(1500, true)).is_none());
+        assert!(elem.process(packet(1492, true)).is_some());
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_unconfigured_interface() {
+        MtuEnforce::for_interface(&InterfaceConfig::new(), 0);
+    }
+}
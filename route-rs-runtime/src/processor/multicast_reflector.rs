@@ -0,0 +1,152 @@
+use crate::processor::Processor;
+use route_rs_packets::{IpProtocol, Ipv4Packet};
+use std::convert::TryInto;
+use std::net::Ipv4Addr;
+
+/// The mDNS group and port.
+pub const MDNS_GROUP: (Ipv4Addr, u16) = (Ipv4Addr::new(224, 0, 0, 251), 5353);
+/// The SSDP group and port.
+pub const SSDP_GROUP: (Ipv4Addr, u16) = (Ipv4Addr::new(239, 255, 255, 250), 1900);
+
+/// Reflects link-local service discovery traffic, such as mDNS and SSDP, between interfaces that
+/// would otherwise not see each other's multicast. Takes each packet tagged with the interface it
+/// arrived on, and if it is UDP to one of the configured groups, tags it with every other
+/// configured interface it should be copied to. Everything else is dropped, so the reflector
+/// should sit on a tap beside the regular forwarding path.
+#[derive(Clone)]
+pub struct MulticastReflector {
+    interfaces: Vec<usize>,
+    groups: Vec<(Ipv4Addr, u16)>,
+}
+
+impl MulticastReflector {
+    /// Reflects between `interfaces`, by default for mDNS and SSDP.
+    pub fn new(interfaces: Vec<usize>) -> Self {
+        MulticastReflector {
+            interfaces,
+            groups: vec![MDNS_GROUP, SSDP_GROUP],
+        }
+    }
+
+    /// Replaces the groups to reflect, as pairs of group address and UDP port.
+    pub fn groups(self, groups: Vec<(Ipv4Addr, u16)>) -> Self {
+        MulticastReflector {
+            interfaces: self.interfaces,
+            groups,
+        }
+    }
+
+    fn matches(&self, packet: &Ipv4Packet) -> bool {
+        if packet.protocol() != IpProtocol::UDP {
+            return false;
+        }
+        let payload = packet.payload();
+        let dest_port = match payload.get(2..4) {
+            Some(port) => u16::from_be_bytes(port.try_into().unwrap()),
+            None => return false,
+        };
+        self.groups.contains(&(packet.dest_addr(), dest_port))
+    }
+}
+
+impl Processor for MulticastReflector {
+    type Input = (usize, Ipv4Packet);
+    type Output = (Vec<usize>, Ipv4Packet);
+
+    fn process(&mut self, (ingress, packet): Self::Input) -> Option<Self::Output> {
+        if !self.interfaces.contains(&ingress) || !self.matches(&packet) {
+            return None;
+        }
+
+        let egress: Vec<usize> = self
+            .interfaces
+            .iter()
+            .copied()
+            .filter(|interface| *interface != ingress)
+            .collect();
+        if egress.is_empty() {
+            None
+        } else {
+            Some((egress, packet))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAN1: usize = 1;
+    const LAN2: usize = 2;
+    const GUEST: usize = 3;
+    const WAN: usize = 0;
+
+    fn udp_packet((dest_addr, dest_port): (Ipv4Addr, u16)) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(Ipv4Addr::new(192, 168, 1, 10));
+        packet.set_dest_addr(dest_addr);
+        packet.set_ttl(255);
+        packet.set_protocol(17);
+        let port = dest_port.to_be_bytes();
+        packet.set_payload(&[port[0], port[1], port[0], port[1], 0, 8, 0, 0]);
+        packet
+    }
+
+    #[test]
+    fn reflects_mdns_to_other_interfaces_only() {
+        let mut reflector = MulticastReflector::new(vec![LAN1, LAN2]);
+
+        let (egress, _) = reflector.process((LAN1, udp_packet(MDNS_GROUP))).unwrap();
+
+        assert_eq!(egress, vec![LAN2]);
+    }
+
+    #[test]
+    fn reflects_ssdp_to_all_other_interfaces() {
+        let mut reflector = MulticastReflector::new(vec![LAN1, LAN2, GUEST]);
+
+        let (egress, _) = reflector.process((LAN2, udp_packet(SSDP_GROUP))).unwrap();
+
+        assert_eq!(egress, vec![LAN1, GUEST]);
+    }
+
+    #[test]
+    fn ignores_other_traffic() {
+        let mut reflector = MulticastReflector::new(vec![LAN1, LAN2]);
+
+        let unicast = udp_packet((Ipv4Addr::new(192, 168, 1, 1), 5353));
+        assert!(reflector.process((LAN1, unicast)).is_none());
+
+        let wrong_port = udp_packet((MDNS_GROUP.0, 53));
+        assert!(reflector.process((LAN1, wrong_port)).is_none());
+
+        let mut not_udp = udp_packet(MDNS_GROUP);
+        not_udp.set_protocol(6);
+        assert!(reflector.process((LAN1, not_udp)).is_none());
+
+        let truncated = {
+            let mut packet = udp_packet(MDNS_GROUP);
+            packet.set_payload(&[0x14]);
+            packet
+        };
+        assert!(reflector.process((LAN1, truncated)).is_none());
+    }
+
+    #[test]
+    fn ignores_unconfigured_interfaces() {
+        let mut reflector = MulticastReflector::new(vec![LAN1, LAN2]);
+        assert!(reflector.process((WAN, udp_packet(MDNS_GROUP))).is_none());
+
+        let mut lonely = MulticastReflector::new(vec![LAN1]);
+        assert!(lonely.process((LAN1, udp_packet(MDNS_GROUP))).is_none());
+    }
+
+    #[test]
+    fn custom_groups() {
+        let group = (Ipv4Addr::new(239, 1, 2, 3), 4000);
+        let mut reflector = MulticastReflector::new(vec![LAN1, LAN2]).groups(vec![group]);
+
+        assert!(reflector.process((LAN1, udp_packet(MDNS_GROUP))).is_none());
+        assert!(reflector.process((LAN1, udp_packet(group))).is_some());
+    }
+}
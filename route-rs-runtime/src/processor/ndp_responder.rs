@@ -0,0 +1,342 @@
+use crate::processor::Processor;
+use route_rs_packets::{
+    IpProtocol, Ipv6Packet, MacAddr, ICMPV6_NEIGHBOR_ADVERTISEMENT, ICMPV6_NEIGHBOR_SOLICITATION,
+};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::net::Ipv6Addr;
+use std::sync::{Arc, Mutex};
+
+/// Option carrying the link-layer address of the sender of a solicitation.
+const OPTION_SOURCE_LINK_LAYER_ADDR: u8 = 1;
+/// Option carrying the link-layer address of the target of an advertisement.
+const OPTION_TARGET_LINK_LAYER_ADDR: u8 = 2;
+
+/// Advertisement flags: sent by a router, in response to a solicitation, overriding any cached
+/// link-layer address.
+const FLAG_ROUTER: u8 = 0x80;
+const FLAG_SOLICITED: u8 = 0x40;
+const FLAG_OVERRIDE: u8 = 0x20;
+
+/// A handle to the link-layer addresses an `NdpResponder` has learned for its neighbors. It can
+/// be cloned and read while the pipeline is running, for example to address frames to the next
+/// hop.
+#[derive(Clone, Default)]
+pub struct NeighborCache {
+    entries: Arc<Mutex<HashMap<Ipv6Addr, MacAddr>>>,
+}
+
+impl NeighborCache {
+    pub fn new() -> Self {
+        NeighborCache {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The link-layer address of `addr`, if it has been learned.
+    pub fn lookup(&self, addr: &Ipv6Addr) -> Option<MacAddr> {
+        self.entries.lock().unwrap().get(addr).copied()
+    }
+
+    /// Records that `addr` is reachable at `mac`, replacing what was learned for it before.
+    pub fn insert(&self, addr: Ipv6Addr, mac: MacAddr) {
+        self.entries.lock().unwrap().insert(addr, mac);
+    }
+
+    /// Number of neighbors learned.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Answers IPv6 Neighbor Solicitations (RFC 4861) for the addresses of the router, the IPv6
+/// counterpart of replying to ARP requests. A solicitation whose target is one of `addresses` is
+/// answered with a Neighbor Advertisement carrying `mac` as the target link-layer address. Every
+/// other packet is dropped, so classify Neighbor Discovery messages into this processor.
+///
+/// Along the way, it learns the link-layer addresses of neighbors from the solicitations and
+/// advertisements they send, into the `NeighborCache` returned by `neighbors`. Messages that fail
+/// the validation of RFC 4861 section 7.1, such as those with a hop limit below 255, which must
+/// have come from off-link, are dropped without being learned from.
+pub struct NdpResponder {
+    mac: MacAddr,
+    addresses: Vec<Ipv6Addr>,
+    neighbors: NeighborCache,
+}
+
+impl NdpResponder {
+    pub fn new(mac: MacAddr, addresses: Vec<Ipv6Addr>) -> Self {
+        NdpResponder {
+            mac,
+            addresses,
+            neighbors: NeighborCache::new(),
+        }
+    }
+
+    /// Returns a handle to the neighbor cache, which remains valid after the link is built.
+    pub fn neighbors(&self) -> NeighborCache {
+        self.neighbors.clone()
+    }
+
+    /// The advertisement answering a solicitation from `src_addr` for `target`.
+    fn advertisement(&self, src_addr: Ipv6Addr, target: Ipv6Addr) -> Ipv6Packet {
+        // A node checking whether its tentative address is a duplicate solicits from the
+        // unspecified address, so the answer goes to all nodes, unsolicited.
+        let (dest_addr, flags) = if src_addr.is_unspecified() {
+            (
+                Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1),
+                FLAG_ROUTER | FLAG_OVERRIDE,
+            )
+        } else {
+            (src_addr, FLAG_ROUTER | FLAG_SOLICITED | FLAG_OVERRIDE)
+        };
+
+        let mut body = vec![flags, 0, 0, 0];
+        body.extend_from_slice(&target.octets());
+        body.extend_from_slice(&[OPTION_TARGET_LINK_LAYER_ADDR, 1]);
+        body.extend_from_slice(&self.mac.bytes);
+
+        Ipv6Packet::icmpv6(target, dest_addr, ICMPV6_NEIGHBOR_ADVERTISEMENT, 0, &body)
+    }
+}
+
+/// The link-layer address in the option of `option_type` among `options`, or an error if the
+/// options are malformed.
+fn link_layer_option(options: &[u8], option_type: u8) -> Result<Option<MacAddr>, ()> {
+    let mut found = None;
+    let mut rest = options;
+    while !rest.is_empty() {
+        if rest.len() < 2 {
+            return Err(());
+        }
+        let len = usize::from(rest[1]) * 8;
+        if len == 0 || len > rest.len() {
+            return Err(());
+        }
+        if rest[0] == option_type && len >= 8 {
+            found = Some(MacAddr::new(rest[2..8].try_into().unwrap()));
+        }
+        rest = &rest[len..];
+    }
+    Ok(found)
+}
+
+impl Processor for NdpResponder {
+    type Input = Ipv6Packet;
+    type Output = Ipv6Packet;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        if packet.next_header() != IpProtocol::IPv6_ICMP
+            || packet.hop_limit() != 255
+            || !packet.validate_icmpv6_checksum()
+        {
+            return None;
+        }
+        let message = packet.payload();
+        // Both messages carry 4 bytes of flags and a 16 byte target after the ICMPv6 header.
+        if message.len() < 24 || message[1] != 0 {
+            return None;
+        }
+        let target: [u8; 16] = message[8..24].try_into().unwrap();
+        let target = Ipv6Addr::from(target);
+        if target.is_multicast() {
+            return None;
+        }
+        let src_addr = packet.src_addr();
+
+        match message[0] {
+            ICMPV6_NEIGHBOR_SOLICITATION => {
+                let source_mac =
+                    link_layer_option(&message[24..], OPTION_SOURCE_LINK_LAYER_ADDR).ok()?;
+                if let Some(mac) = source_mac {
+                    if src_addr.is_unspecified() {
+                        return None;
+                    }
+                    self.neighbors.insert(src_addr, mac);
+                }
+                if self.addresses.contains(&target) {
+                    Some(self.advertisement(src_addr, target))
+                } else {
+                    None
+                }
+            }
+            ICMPV6_NEIGHBOR_ADVERTISEMENT => {
+                let target_mac =
+                    link_layer_option(&message[24..], OPTION_TARGET_LINK_LAYER_ADDR).ok()?;
+                if let Some(mac) = target_mac {
+                    self.neighbors.insert(target, mac);
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTER_MAC: MacAddr = MacAddr {
+        bytes: [0x02, 0, 0, 0, 0, 0x01],
+    };
+    const HOST_MAC: MacAddr = MacAddr {
+        bytes: [0x02, 0, 0, 0, 0, 0x02],
+    };
+
+    fn addr(s: &str) -> Ipv6Addr {
+        s.parse().unwrap()
+    }
+
+    fn responder() -> NdpResponder {
+        NdpResponder::new(ROUTER_MAC, vec![addr("fe80::1"), addr("2001:db8::1")])
+    }
+
+    fn solicited_node(target: Ipv6Addr) -> Ipv6Addr {
+        let octets = target.octets();
+        Ipv6Addr::new(
+            0xff02,
+            0,
+            0,
+            0,
+            0,
+            1,
+            0xff00 | u16::from(octets[13]),
+            u16::from_be_bytes([octets[14], octets[15]]),
+        )
+    }
+
+    fn solicitation(
+        src_addr: Ipv6Addr,
+        target: Ipv6Addr,
+        source_mac: Option<MacAddr>,
+    ) -> Ipv6Packet {
+        let mut body = vec![0; 4];
+        body.extend_from_slice(&target.octets());
+        if let Some(mac) = source_mac {
+            body.extend_from_slice(&[OPTION_SOURCE_LINK_LAYER_ADDR, 1]);
+            body.extend_from_slice(&mac.bytes);
+        }
+        Ipv6Packet::icmpv6(
+            src_addr,
+            solicited_node(target),
+            ICMPV6_NEIGHBOR_SOLICITATION,
+            0,
+            &body,
+        )
+    }
+
+    #[test]
+    fn answers_solicitation_for_link_local() {
+        let mut responder = responder();
+        let neighbors = responder.neighbors();
+
+        let advertisement = responder
+            .process(solicitation(
+                addr("fe80::2"),
+                addr("fe80::1"),
+                Some(HOST_MAC),
+            ))
+            .unwrap();
+
+        assert_eq!(advertisement.src_addr(), addr("fe80::1"));
+        assert_eq!(advertisement.dest_addr(), addr("fe80::2"));
+        assert_eq!(advertisement.hop_limit(), 255);
+        assert_eq!(advertisement.next_header(), IpProtocol::IPv6_ICMP);
+        assert!(advertisement.validate_icmpv6_checksum());
+
+        let message = advertisement.payload();
+        assert_eq!(message.len(), 32);
+        assert_eq!(message[0], ICMPV6_NEIGHBOR_ADVERTISEMENT);
+        assert_eq!(message[1], 0);
+        assert_eq!(message[4], FLAG_ROUTER | FLAG_SOLICITED | FLAG_OVERRIDE);
+        assert_eq!(message[8..24], addr("fe80::1").octets());
+        assert_eq!(message[24..26], [OPTION_TARGET_LINK_LAYER_ADDR, 1]);
+        assert_eq!(message[26..32], ROUTER_MAC.bytes);
+
+        assert_eq!(neighbors.lookup(&addr("fe80::2")), Some(HOST_MAC));
+    }
+
+    #[test]
+    fn answers_duplicate_address_detection_to_all_nodes() {
+        let mut responder = responder();
+
+        let advertisement = responder
+            .process(solicitation(
+                Ipv6Addr::UNSPECIFIED,
+                addr("2001:db8::1"),
+                None,
+            ))
+            .unwrap();
+
+        assert_eq!(advertisement.dest_addr(), addr("ff02::1"));
+        assert_eq!(advertisement.payload()[4], FLAG_ROUTER | FLAG_OVERRIDE);
+        assert!(responder.neighbors().is_empty());
+    }
+
+    #[test]
+    fn ignores_solicitation_for_other_target_but_learns_sender() {
+        let mut responder = responder();
+
+        let reply = responder.process(solicitation(
+            addr("fe80::2"),
+            addr("fe80::3"),
+            Some(HOST_MAC),
+        ));
+
+        assert!(reply.is_none());
+        assert_eq!(
+            responder.neighbors().lookup(&addr("fe80::2")),
+            Some(HOST_MAC)
+        );
+    }
+
+    #[test]
+    fn learns_from_advertisement() {
+        let mut responder = responder();
+        let mut body = vec![FLAG_SOLICITED, 0, 0, 0];
+        body.extend_from_slice(&addr("fe80::2").octets());
+        body.extend_from_slice(&[OPTION_TARGET_LINK_LAYER_ADDR, 1]);
+        body.extend_from_slice(&HOST_MAC.bytes);
+        let advertisement = Ipv6Packet::icmpv6(
+            addr("fe80::2"),
+            addr("fe80::1"),
+            ICMPV6_NEIGHBOR_ADVERTISEMENT,
+            0,
+            &body,
+        );
+
+        assert!(responder.process(advertisement).is_none());
+        assert_eq!(
+            responder.neighbors().lookup(&addr("fe80::2")),
+            Some(HOST_MAC)
+        );
+    }
+
+    #[test]
+    fn drops_invalid_solicitations() {
+        let mut responder = responder();
+
+        let mut off_link = solicitation(addr("fe80::2"), addr("fe80::1"), Some(HOST_MAC));
+        off_link.set_hop_limit(254);
+        assert!(responder.process(off_link).is_none());
+
+        let mut corrupt = solicitation(addr("fe80::2"), addr("fe80::1"), Some(HOST_MAC));
+        corrupt.data[corrupt.payload_offset + 2] ^= 0xFF;
+        assert!(responder.process(corrupt).is_none());
+
+        let mut bad_option = solicitation(addr("fe80::2"), addr("fe80::1"), Some(HOST_MAC));
+        bad_option.data[bad_option.payload_offset + 25] = 0;
+        bad_option.set_icmpv6_checksum();
+        assert!(responder.process(bad_option).is_none());
+
+        let dad_with_source = solicitation(Ipv6Addr::UNSPECIFIED, addr("fe80::1"), Some(HOST_MAC));
+        assert!(responder.process(dad_with_source).is_none());
+
+        assert!(responder.neighbors().is_empty());
+    }
+}
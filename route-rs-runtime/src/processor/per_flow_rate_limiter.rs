@@ -0,0 +1,184 @@
+use super::flow_accounting::{classify, SWEEP_INTERVAL};
+use crate::link::utils::clock::{Clock, SystemClock};
+use crate::processor::{FlowKey, Processor};
+use route_rs_packets::Ipv4Packet;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+struct FlowBucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// Limits every flow to `rate` packets per second, so that a single client cannot hog the uplink.
+/// Each flow, keyed on its 5-tuple as in `FlowAccounting`, gets its own token bucket holding up
+/// to `burst` packets, and packets that find their bucket empty are dropped. Flows are not limited
+/// by one another: a flow that is under its budget passes untouched however busy the others are.
+///
+/// A new flow starts with a full bucket, so short flows are never held back. Flows that have been
+/// idle for `idle_timeout` are forgotten; as long as that is at least the time a bucket takes to
+/// fill, a forgotten flow would have come back to a full bucket anyway. Idle flows are swept while
+/// packets are flowing; call `expire` to sweep them when there may be no traffic.
+pub struct PerFlowRateLimiter {
+    rate: f64,
+    burst: f64,
+    flows: HashMap<FlowKey, FlowBucket>,
+    idle_timeout: Duration,
+    clock: Box<dyn Clock>,
+    last_sweep: Option<Instant>,
+}
+
+impl PerFlowRateLimiter {
+    /// Limits each flow to `rate` packets per second, with bursts of up to `burst` packets.
+    pub fn new(rate: u64, burst: u64) -> Self {
+        assert!(rate > 0, "rate: {}, must be > 0", rate);
+        assert!(burst > 0, "burst: {}, must be > 0", burst);
+        PerFlowRateLimiter {
+            rate: rate as f64,
+            burst: burst as f64,
+            flows: HashMap::new(),
+            idle_timeout: Duration::from_secs(15),
+            clock: Box::new(SystemClock),
+            last_sweep: None,
+        }
+    }
+
+    /// Changes idle_timeout, default value is 15 seconds.
+    pub fn idle_timeout(self, idle_timeout: Duration) -> Self {
+        PerFlowRateLimiter {
+            idle_timeout,
+            ..self
+        }
+    }
+
+    /// Changes the clock used to refill buckets and time out flows, default is `SystemClock`.
+    pub fn clock<C: Clock + 'static>(self, clock: C) -> Self {
+        PerFlowRateLimiter {
+            clock: Box::new(clock),
+            ..self
+        }
+    }
+
+    /// The number of flows currently being limited.
+    pub fn active_flows(&self) -> usize {
+        self.flows.len()
+    }
+
+    /// Forgets every flow that has been idle for `idle_timeout`.
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        self.last_sweep = Some(now);
+
+        let idle_timeout = self.idle_timeout;
+        self.flows
+            .retain(|_, bucket| now.saturating_duration_since(bucket.last) < idle_timeout);
+    }
+}
+
+impl Processor for PerFlowRateLimiter {
+    type Input = Ipv4Packet;
+    type Output = Ipv4Packet;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let now = self.clock.now();
+        let due = self
+            .last_sweep
+            .is_none_or(|last_sweep| now.saturating_duration_since(last_sweep) >= SWEEP_INTERVAL);
+        if due {
+            self.expire();
+        }
+
+        let (key, _) = classify(&packet);
+        let (rate, burst) = (self.rate, self.burst);
+        let bucket = self.flows.entry(key).or_insert(FlowBucket {
+            tokens: burst,
+            last: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.last);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * rate).min(burst);
+        bucket.last = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Some(packet)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::clock::ManualClock;
+    use std::net::Ipv4Addr;
+
+    fn datagram(src_port: u16) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(Ipv4Addr::new(192, 168, 1, 10));
+        packet.set_dest_addr(Ipv4Addr::new(93, 184, 216, 34));
+        packet.set_protocol(17);
+        let mut udp = vec![0; 8 + 100];
+        udp[0..2].copy_from_slice(&src_port.to_be_bytes());
+        udp[2..4].copy_from_slice(&443u16.to_be_bytes());
+        packet.set_payload(&udp);
+        packet
+    }
+
+    /// Sends `count` packets of the flow from `src_port`, returning how many were passed.
+    fn send(elem: &mut PerFlowRateLimiter, src_port: u16, count: usize) -> usize {
+        (0..count)
+            .filter_map(|_| elem.process(datagram(src_port)))
+            .count()
+    }
+
+    #[test]
+    fn saturated_flow_does_not_starve_others() {
+        let clock = ManualClock::new();
+        let mut elem = PerFlowRateLimiter::new(100, 10).clock(clock.clone());
+
+        // Each 10ms a bulk flow sends 20 packets and an interactive flow sends 1.
+        let (mut bulk, mut interactive) = (0, 0);
+        for _ in 0..100 {
+            bulk += send(&mut elem, 40000, 20);
+            interactive += send(&mut elem, 40001, 1);
+            clock.advance(Duration::from_millis(10));
+        }
+
+        // The bulk flow gets its burst, then one packet per tick.
+        assert_eq!(bulk, 10 + 99);
+        assert_eq!(interactive, 100);
+        assert_eq!(elem.active_flows(), 2);
+    }
+
+    #[test]
+    fn refills_at_rate() {
+        let clock = ManualClock::new();
+        let mut elem = PerFlowRateLimiter::new(10, 5).clock(clock.clone());
+
+        assert_eq!(send(&mut elem, 40000, 8), 5);
+        clock.advance(Duration::from_millis(300));
+        assert_eq!(send(&mut elem, 40000, 8), 3);
+
+        // Buckets hold no more than the burst, however long the flow is quiet.
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(send(&mut elem, 40000, 8), 5);
+    }
+
+    #[test]
+    fn evicts_idle_flows() {
+        let clock = ManualClock::new();
+        let mut elem = PerFlowRateLimiter::new(10, 5)
+            .idle_timeout(Duration::from_secs(10))
+            .clock(clock.clone());
+
+        send(&mut elem, 40000, 1);
+        clock.advance(Duration::from_secs(9));
+        elem.expire();
+        assert_eq!(elem.active_flows(), 1);
+
+        clock.advance(Duration::from_secs(1));
+        send(&mut elem, 40001, 1);
+        assert_eq!(elem.active_flows(), 1);
+    }
+}
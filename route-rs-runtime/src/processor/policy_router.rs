@@ -0,0 +1,351 @@
+use crate::processor::{flow_hash, Mark, Processor};
+use crate::routing::{mask, NextHop, RoutingTable};
+use route_rs_packets::Ipv4Packet;
+use std::marker::PhantomData;
+use std::net::Ipv4Addr;
+
+/// A packet a `PolicyRouter` can route: a bare `Ipv4Packet`, which counts as carrying mark 0, or
+/// one marked by `SetMark`, which keeps its mark once routed.
+pub trait PolicyPacket: Send + Clone {
+    /// The packet tagged with its next hop.
+    type Routed: Send + Clone;
+
+    fn mark(&self) -> Mark;
+
+    fn packet(&self) -> &Ipv4Packet;
+
+    fn routed(self, next_hop: NextHop) -> Self::Routed;
+}
+
+impl PolicyPacket for Ipv4Packet {
+    type Routed = (NextHop, Ipv4Packet);
+
+    fn mark(&self) -> Mark {
+        0
+    }
+
+    fn packet(&self) -> &Ipv4Packet {
+        self
+    }
+
+    fn routed(self, next_hop: NextHop) -> Self::Routed {
+        (next_hop, self)
+    }
+}
+
+impl PolicyPacket for (Mark, Ipv4Packet) {
+    type Routed = (Mark, (NextHop, Ipv4Packet));
+
+    fn mark(&self) -> Mark {
+        self.0
+    }
+
+    fn packet(&self) -> &Ipv4Packet {
+        &self.1
+    }
+
+    fn routed(self, next_hop: NextHop) -> Self::Routed {
+        (self.0, (next_hop, self.1))
+    }
+}
+
+/// What a `PolicyRouter` rule matches packets on. A packet matches if it matches every field
+/// that is set; a `PolicyMatch` with no fields set matches every packet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PolicyMatch {
+    src: Option<(Ipv4Addr, u8)>,
+    dscp: Option<u8>,
+    mark: Option<Mark>,
+}
+
+impl PolicyMatch {
+    pub fn new() -> Self {
+        PolicyMatch {
+            src: None,
+            dscp: None,
+            mark: None,
+        }
+    }
+
+    /// Changes src, the prefix the source address must be in, default is any source.
+    pub fn src(self, prefix: Ipv4Addr, prefix_len: u8) -> Self {
+        assert!(
+            prefix_len <= 32,
+            "prefix_len: {}, must be <= 32",
+            prefix_len
+        );
+        PolicyMatch {
+            src: Some((prefix, prefix_len)),
+            ..self
+        }
+    }
+
+    /// Changes dscp, the DSCP the packet must carry, default is any DSCP.
+    pub fn dscp(self, dscp: u8) -> Self {
+        PolicyMatch {
+            dscp: Some(dscp),
+            ..self
+        }
+    }
+
+    /// Changes mark, the mark `SetMark` must have tagged the packet with, default is any mark.
+    pub fn mark(self, mark: Mark) -> Self {
+        PolicyMatch {
+            mark: Some(mark),
+            ..self
+        }
+    }
+
+    pub fn matches<P: PolicyPacket>(&self, packet: &P) -> bool {
+        let mark_matches = self.mark.is_none_or(|mark| packet.mark() == mark);
+        let packet = packet.packet();
+        let src_matches = self.src.is_none_or(|(prefix, prefix_len)| {
+            let mask = mask(prefix_len);
+            u32::from(packet.src_addr()) & mask == u32::from(prefix) & mask
+        });
+        let dscp_matches = self.dscp.is_none_or(|dscp| packet.dscp() == dscp);
+        src_matches && dscp_matches && mark_matches
+    }
+}
+
+/// Routes packets by policy before falling back to their destination. Each packet is checked
+/// against an ordered list of rules, and tagged with the next hop of the first rule it matches,
+/// whatever its destination. Packets that match no rule are looked up in the `RoutingTable`, as
+/// `FibLookup` does, and dropped if it has no route for them.
+///
+/// This lets, say, a guest network leave through a different uplink than the rest of the LAN,
+/// without a second routing table.
+///
+/// A `PolicyRouter<(Mark, Ipv4Packet)>` routes packets marked by `SetMark`, so rules can match on
+/// the mark with `PolicyMatch::mark`, and tags each with its next hop as `(Mark, (NextHop,
+/// Ipv4Packet))`.
+pub struct PolicyRouter<P = Ipv4Packet> {
+    rules: Vec<(PolicyMatch, NextHop)>,
+    table: RoutingTable,
+    packet: PhantomData<P>,
+}
+
+impl<P: PolicyPacket> PolicyRouter<P> {
+    pub fn new(table: RoutingTable) -> Self {
+        PolicyRouter {
+            rules: vec![],
+            table,
+            packet: PhantomData,
+        }
+    }
+
+    /// Adds a rule sending packets that match `matcher` to `next_hop`. Rules are checked in the
+    /// order they were added.
+    pub fn rule(mut self, matcher: PolicyMatch, next_hop: NextHop) -> Self {
+        self.rules.push((matcher, next_hop));
+        self
+    }
+}
+
+impl<P: PolicyPacket> Processor for PolicyRouter<P> {
+    type Input = P;
+    type Output = P::Routed;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let next_hop = match self
+            .rules
+            .iter()
+            .find(|(matcher, _)| matcher.matches(&packet))
+        {
+            Some((_, next_hop)) => *next_hop,
+            None => {
+                let ipv4 = packet.packet();
+                self.table.lookup(ipv4.dest_addr(), flow_hash(ipv4))?
+            }
+        };
+        Some(packet.routed(next_hop))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::primitive::ProcessLink;
+    use crate::link::Pipeline;
+    use crate::link::{LinkBuilder, ProcessLinkBuilder};
+    use crate::processor::{FibLookup, SetMark};
+    use crate::routing::Route;
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+
+    const LAN: usize = 0;
+    const WAN: usize = 1;
+    const GUEST_WAN: usize = 2;
+
+    const LAN_HOST: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 10);
+    const GUEST_HOST: Ipv4Addr = Ipv4Addr::new(192, 168, 2, 10);
+    const INTERNET: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 34);
+
+    fn packet(src_addr: Ipv4Addr, dest_addr: Ipv4Addr) -> Ipv4Packet {
+        Ipv4Packet::from_parts(src_addr, dest_addr, 17, 64, &[0; 8])
+    }
+
+    fn wan() -> NextHop {
+        NextHop::via(Ipv4Addr::new(203, 0, 113, 1), WAN)
+    }
+
+    fn guest_wan() -> NextHop {
+        NextHop::via(Ipv4Addr::new(198, 51, 100, 1), GUEST_WAN)
+    }
+
+    fn table() -> RoutingTable {
+        let table = RoutingTable::new();
+        table.insert(Route::new(
+            Ipv4Addr::new(192, 168, 0, 0),
+            16,
+            NextHop::connected(LAN),
+        ));
+        table.insert(Route::new(Ipv4Addr::new(0, 0, 0, 0), 0, wan()));
+        table
+    }
+
+    fn guest_policy() -> PolicyRouter {
+        PolicyRouter::new(table()).rule(
+            PolicyMatch::new().src(Ipv4Addr::new(192, 168, 2, 0), 24),
+            guest_wan(),
+        )
+    }
+
+    #[test]
+    fn routes_guest_subnet_out_its_own_uplink() {
+        let packets = vec![packet(GUEST_HOST, INTERNET), packet(LAN_HOST, INTERNET)];
+
+        let mut fib = FibLookup::new(table());
+        assert_eq!(fib.process(packets[0].clone()).unwrap().0, wan());
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = ProcessLink::new()
+                .ingressor(immediate_stream(packets))
+                .processor(guest_policy())
+                .build_link();
+
+            run_link(link).await
+        });
+
+        let next_hops: Vec<NextHop> = results[0].iter().map(|(next_hop, _)| *next_hop).collect();
+        assert_eq!(next_hops, vec![guest_wan(), wan()]);
+    }
+
+    #[test]
+    fn falls_back_to_table() {
+        let mut elem = guest_policy();
+
+        let (next_hop, _) = elem.process(packet(LAN_HOST, GUEST_HOST)).unwrap();
+        assert_eq!(next_hop, NextHop::connected(LAN));
+
+        let table = table();
+        table.remove(Ipv4Addr::new(0, 0, 0, 0), 0);
+        let mut elem = PolicyRouter::new(table);
+        assert!(elem.process(packet(LAN_HOST, INTERNET)).is_none());
+    }
+
+    #[test]
+    fn matches_on_dscp() {
+        let mut elem = PolicyRouter::new(table()).rule(PolicyMatch::new().dscp(46), guest_wan());
+
+        let mut voice = packet(LAN_HOST, INTERNET);
+        voice.set_dscp(46);
+        assert_eq!(elem.process(voice).unwrap().0, guest_wan());
+        assert_eq!(elem.process(packet(LAN_HOST, INTERNET)).unwrap().0, wan());
+    }
+
+    #[test]
+    fn every_field_must_match() {
+        let matcher = PolicyMatch::new()
+            .src(Ipv4Addr::new(192, 168, 2, 0), 24)
+            .dscp(46);
+
+        let mut guest_voice = packet(GUEST_HOST, INTERNET);
+        guest_voice.set_dscp(46);
+        let mut lan_voice = packet(LAN_HOST, INTERNET);
+        lan_voice.set_dscp(46);
+
+        assert!(matcher.matches(&guest_voice));
+        assert!(!matcher.matches(&lan_voice));
+        assert!(!matcher.matches(&packet(GUEST_HOST, INTERNET)));
+        assert!(PolicyMatch::new().matches(&lan_voice));
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let mut elem = PolicyRouter::new(table())
+            .rule(
+                PolicyMatch::new().src(Ipv4Addr::new(192, 168, 2, 10), 32),
+                NextHop::connected(LAN),
+            )
+            .rule(
+                PolicyMatch::new().src(Ipv4Addr::new(192, 168, 2, 0), 24),
+                guest_wan(),
+            );
+
+        assert_eq!(
+            elem.process(packet(GUEST_HOST, INTERNET)).unwrap().0,
+            NextHop::connected(LAN)
+        );
+        assert_eq!(
+            elem.process(packet(Ipv4Addr::new(192, 168, 2, 11), INTERNET))
+                .unwrap()
+                .0,
+            guest_wan()
+        );
+    }
+
+    #[test]
+    fn matches_on_mark() {
+        const GUEST: Mark = 1;
+        let matcher = PolicyMatch::new().mark(GUEST);
+
+        assert!(matcher.matches(&(GUEST, packet(LAN_HOST, INTERNET))));
+        assert!(!matcher.matches(&(2, packet(LAN_HOST, INTERNET))));
+        assert!(!matcher.matches(&packet(LAN_HOST, INTERNET)));
+        assert!(PolicyMatch::new()
+            .mark(0)
+            .matches(&packet(LAN_HOST, INTERNET)));
+    }
+
+    #[test]
+    fn routes_by_mark() {
+        const GUEST: Mark = 1;
+        let packets = vec![
+            packet(GUEST_HOST, INTERNET),
+            packet(LAN_HOST, INTERNET),
+            packet(GUEST_HOST, LAN_HOST),
+        ];
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let guests = PolicyMatch::new().src(Ipv4Addr::new(192, 168, 2, 0), 24);
+            let link = Pipeline::new(immediate_stream(packets))
+                .then(
+                    ProcessLink::new().processor(
+                        SetMark::new().rule(move |packet| guests.matches(packet), GUEST),
+                    ),
+                )
+                .then(ProcessLink::new().processor(
+                    PolicyRouter::new(table()).rule(PolicyMatch::new().mark(GUEST), guest_wan()),
+                ))
+                .build();
+
+            run_link(link).await
+        });
+
+        let routed: Vec<(Mark, NextHop, Ipv4Addr)> = results[0]
+            .iter()
+            .map(|(mark, (next_hop, packet))| (*mark, *next_hop, packet.src_addr()))
+            .collect();
+        assert_eq!(
+            routed,
+            vec![
+                (GUEST, guest_wan(), GUEST_HOST),
+                (0, wan(), LAN_HOST),
+                (GUEST, guest_wan(), GUEST_HOST),
+            ]
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use crate::processor::Processor;
+use route_rs_packets::{EthernetFrame, Ipv4Packet, MacAddr};
+use std::convert::{TryFrom, TryInto};
+
+/// EtherType of PPPoE session stage frames.
+pub const ETHERTYPE_PPPOE_SESSION: u16 = 0x8864;
+/// Bytes PPPoE adds in front of each IP packet: the 6 byte PPPoE header and the 2 byte PPP
+/// protocol field.
+pub const PPPOE_OVERHEAD: u16 = 8;
+
+const PPPOE_VERSION_TYPE: u8 = 0x11;
+const PPPOE_CODE_SESSION: u8 = 0x00;
+const PPP_PROTOCOL_IPV4: u16 = 0x0021;
+
+/// The MTU left for IP packets on a PPPoE session carried over a link with `link_mtu`. For
+/// standard Ethernet, this is 1492. The link must leave room for the 68 byte minimum IPv4 MTU.
+pub fn pppoe_mtu(link_mtu: u16) -> u16 {
+    assert!(
+        link_mtu >= 68 + PPPOE_OVERHEAD,
+        "link_mtu: {}, must be >= {}",
+        link_mtu,
+        68 + PPPOE_OVERHEAD
+    );
+    link_mtu - PPPOE_OVERHEAD
+}
+
+/// Wraps IPv4 packets in PPP and PPPoE session headers (RFC 2516) for the session `session_id`,
+/// and in an Ethernet header addressed to the access concentrator. The session ID and the MAC of
+/// the access concentrator come from PPPoE discovery, which is not handled here.
+#[derive(Clone)]
+pub struct PppoeEncap {
+    session_id: u16,
+    src_mac: MacAddr,
+    ac_mac: MacAddr,
+}
+
+impl PppoeEncap {
+    pub fn new(session_id: u16, src_mac: MacAddr, ac_mac: MacAddr) -> Self {
+        PppoeEncap {
+            session_id,
+            src_mac,
+            ac_mac,
+        }
+    }
+}
+
+impl Processor for PppoeEncap {
+    type Input = Ipv4Packet;
+    type Output = EthernetFrame;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let ip = &packet.data[packet.layer3_offset..];
+        // The PPPoE length covers the PPP protocol field and the IP packet. A packet too long for
+        // it is dropped.
+        let length = u16::try_from(ip.len() + 2).ok()?;
+
+        let mut payload = Vec::with_capacity(PPPOE_OVERHEAD as usize + ip.len());
+        payload.extend_from_slice(&[PPPOE_VERSION_TYPE, PPPOE_CODE_SESSION]);
+        payload.extend_from_slice(&self.session_id.to_be_bytes());
+        payload.extend_from_slice(&length.to_be_bytes());
+        payload.extend_from_slice(&PPP_PROTOCOL_IPV4.to_be_bytes());
+        payload.extend_from_slice(ip);
+
+        let mut frame = EthernetFrame::empty();
+        frame.set_dest_mac(self.ac_mac);
+        frame.set_src_mac(self.src_mac);
+        frame.set_ether_type(ETHERTYPE_PPPOE_SESSION);
+        frame.set_payload(&payload);
+        Some(frame)
+    }
+}
+
+/// Unwraps IPv4 packets from PPPoE session frames for the session `session_id`. Frames for other
+/// sessions, PPP control traffic such as LCP, and malformed frames are dropped.
+#[derive(Clone)]
+pub struct PppoeDecap {
+    session_id: u16,
+}
+
+impl PppoeDecap {
+    pub fn new(session_id: u16) -> Self {
+        PppoeDecap { session_id }
+    }
+}
+
+impl Processor for PppoeDecap {
+    type Input = EthernetFrame;
+    type Output = Ipv4Packet;
+
+    fn process(&mut self, frame: Self::Input) -> Option<Self::Output> {
+        if frame.ether_type() != ETHERTYPE_PPPOE_SESSION {
+            return None;
+        }
+
+        let payload = frame.payload();
+        let header = payload.get(..PPPOE_OVERHEAD as usize)?;
+        let session_id = u16::from_be_bytes(header[2..4].try_into().unwrap());
+        let length = u16::from_be_bytes(header[4..6].try_into().unwrap()) as usize;
+        let protocol = u16::from_be_bytes(header[6..8].try_into().unwrap());
+        if header[0] != PPPOE_VERSION_TYPE
+            || header[1] != PPPOE_CODE_SESSION
+            || session_id != self.session_id
+            || protocol != PPP_PROTOCOL_IPV4
+            || length < 2
+        {
+            return None;
+        }
+
+        // Anything past the PPPoE length is Ethernet padding.
+        let ip = payload.get(PPPOE_OVERHEAD as usize..6 + length)?;
+        Ipv4Packet::try_from_bytes(ip).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const SESSION: u16 = 0x1234;
+    const ROUTER_MAC: MacAddr = MacAddr {
+        bytes: [0x02, 0, 0, 0, 0, 0x01],
+    };
+    const AC_MAC: MacAddr = MacAddr {
+        bytes: [0x02, 0, 0, 0, 0, 0xAC],
+    };
+
+    fn packet() -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(Ipv4Addr::new(203, 0, 113, 7));
+        packet.set_dest_addr(Ipv4Addr::new(8, 8, 8, 8));
+        packet.set_ttl(64);
+        packet.set_protocol(17);
+        packet.set_payload(&[0, 53, 0, 53, 0, 12, 0, 0, 1, 2, 3, 4]);
+        packet.set_checksum();
+        packet
+    }
+
+    fn encap() -> PppoeEncap {
+        PppoeEncap::new(SESSION, ROUTER_MAC, AC_MAC)
+    }
+
+    #[test]
+    fn mtu_accounts_for_overhead() {
+        assert_eq!(pppoe_mtu(1500), 1492);
+    }
+
+    #[test]
+    #[should_panic(expected = "link_mtu: 75, must be >= 76")]
+    fn mtu_must_leave_room_for_ipv4() {
+        pppoe_mtu(75);
+    }
+
+    #[test]
+    fn encap_sets_session_and_length() {
+        let frame = encap().process(packet()).unwrap();
+
+        assert_eq!(frame.ether_type(), ETHERTYPE_PPPOE_SESSION);
+        assert_eq!(frame.dest_mac(), AC_MAC);
+        assert_eq!(frame.src_mac(), ROUTER_MAC);
+
+        let payload = frame.payload();
+        assert_eq!(payload[..2], [0x11, 0x00]);
+        assert_eq!(u16::from_be_bytes([payload[2], payload[3]]), SESSION);
+        assert_eq!(
+            u16::from_be_bytes([payload[4], payload[5]]) as usize,
+            packet().data.len() + 2
+        );
+        assert_eq!(payload[6..8], [0x00, 0x21]);
+        assert_eq!(payload.len(), packet().data.len() + 8);
+    }
+
+    #[test]
+    fn encap_drops_packets_too_long_for_length() {
+        let mut longest = packet();
+        longest.set_payload(&vec![0; 65533 - 20]);
+        let frame = encap().process(longest).unwrap();
+        assert_eq!(frame.payload()[4..6], [0xFF, 0xFF]);
+
+        let mut too_long = packet();
+        too_long.set_payload(&vec![0; 65534 - 20]);
+        assert!(encap().process(too_long).is_none());
+    }
+
+    #[test]
+    fn round_trip() {
+        let frame = encap().process(packet()).unwrap();
+        let recovered = PppoeDecap::new(SESSION).process(frame).unwrap();
+        assert_eq!(recovered.data, packet().data);
+    }
+
+    #[test]
+    fn ignores_padding() {
+        let mut frame = encap().process(packet()).unwrap();
+        frame.data.extend_from_slice(&[0; 16]);
+        let recovered = PppoeDecap::new(SESSION).process(frame).unwrap();
+        assert_eq!(recovered.data, packet().data);
+    }
+
+    #[test]
+    fn drops_other_sessions_and_malformed() {
+        let mut decap = PppoeDecap::new(SESSION);
+
+        let other_session = PppoeEncap::new(SESSION + 1, ROUTER_MAC, AC_MAC)
+            .process(packet())
+            .unwrap();
+        assert!(decap.process(other_session).is_none());
+
+        let ipv4 = EthernetFrame::encap_ipv4(packet());
+        assert!(decap.process(ipv4).is_none());
+
+        let mut lcp = encap().process(packet()).unwrap();
+        lcp.data[20..22].copy_from_slice(&[0xC0, 0x21]);
+        assert!(decap.process(lcp).is_none());
+
+        let mut truncated = encap().process(packet()).unwrap();
+        truncated.data.truncate(30);
+        assert!(decap.process(truncated).is_none());
+
+        let mut short_header = encap().process(packet()).unwrap();
+        short_header.data.truncate(18);
+        assert!(decap.process(short_header).is_none());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::processor::{classify, FlowKey, Processor};
+use route_rs_packets::Ipv4Packet;
+
+/// Annotates each packet with the 5-tuple of its flow, as `FlowAccounting` keys it, so that the
+/// tuple survives the elements that rewrite the packet after it. Placed before NAT, with the NAT
+/// wrapped in `Annotated`, it keeps the address of the real client behind the NAT for
+/// `OriginalTupleAccounting`, or anything else downstream that reports on flows.
+#[derive(Default)]
+pub struct PreserveOriginalTuple;
+
+impl PreserveOriginalTuple {
+    pub fn new() -> Self {
+        PreserveOriginalTuple
+    }
+}
+
+impl Processor for PreserveOriginalTuple {
+    type Input = Ipv4Packet;
+    type Output = (FlowKey, Ipv4Packet);
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let (key, _) = classify(&packet);
+        Some((key, packet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::primitive::ProcessLink;
+    use crate::link::{Pipeline, ProcessLinkBuilder};
+    use crate::processor::{Annotated, FlowAccounting, OriginalTupleAccounting};
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use crossbeam::crossbeam_channel;
+    use std::net::Ipv4Addr;
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 10);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(93, 184, 216, 34);
+    const WAN: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 1);
+
+    const TCP_ACK: u8 = 0x10;
+    const TCP_FIN: u8 = 0x01;
+
+    fn segment(flags: u8) -> Ipv4Packet {
+        let mut tcp = vec![0; 20];
+        tcp[0..2].copy_from_slice(&40000u16.to_be_bytes());
+        tcp[2..4].copy_from_slice(&443u16.to_be_bytes());
+        tcp[13] = flags;
+        Ipv4Packet::from_parts(CLIENT, SERVER, 6, 64, &tcp)
+    }
+
+    /// Translates the source of every packet to the WAN address and port 61000.
+    struct SourceNat;
+
+    impl Processor for SourceNat {
+        type Input = Ipv4Packet;
+        type Output = Ipv4Packet;
+
+        fn process(&mut self, mut packet: Self::Input) -> Option<Self::Output> {
+            let mut payload = packet.payload().into_owned();
+            payload[0..2].copy_from_slice(&61000u16.to_be_bytes());
+            packet.set_payload(&payload);
+            packet.set_src_addr(WAN);
+            packet.set_checksum();
+            Some(packet)
+        }
+    }
+
+    #[test]
+    fn annotates_flow() {
+        let mut elem = PreserveOriginalTuple::new();
+
+        let (key, packet) = elem.process(segment(TCP_ACK)).unwrap();
+        assert_eq!(packet, segment(TCP_ACK));
+        assert_eq!(
+            key,
+            FlowKey {
+                src_addr: CLIENT,
+                dest_addr: SERVER,
+                src_port: 40000,
+                dest_port: 443,
+                protocol: 6,
+            }
+        );
+    }
+
+    #[test]
+    fn accounting_reports_client_behind_nat() {
+        let (export, records) = crossbeam_channel::unbounded();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = Pipeline::new(immediate_stream(vec![segment(TCP_ACK), segment(TCP_FIN)]))
+                .then(ProcessLink::new().processor(PreserveOriginalTuple::new()))
+                .then(ProcessLink::new().processor(Annotated::new(SourceNat)))
+                .then(
+                    ProcessLink::new()
+                        .processor(OriginalTupleAccounting::new(FlowAccounting::new(export))),
+                )
+                .build();
+
+            run_link(link).await
+        });
+
+        // The packets leave translated...
+        for (_, packet) in &results[0] {
+            assert_eq!(packet.src_addr(), WAN);
+        }
+        // ...but are accounted to the client.
+        let record = records.try_recv().unwrap();
+        assert_eq!(record.key.src_addr, CLIENT);
+        assert_eq!(record.key.src_port, 40000);
+        assert_eq!(record.packets, 2);
+        assert_eq!(record.tcp_flags, TCP_ACK | TCP_FIN);
+        assert!(records.try_recv().is_err());
+    }
+
+    #[test]
+    fn accounting_without_annotation_sees_translation() {
+        let (export, records) = crossbeam_channel::unbounded();
+        let mut nat = SourceNat;
+        let mut accounting = FlowAccounting::new(export);
+
+        for flags in &[TCP_ACK, TCP_FIN] {
+            let packet = nat.process(segment(*flags)).unwrap();
+            accounting.process(packet).unwrap();
+        }
+
+        let record = records.try_recv().unwrap();
+        assert_eq!(record.key.src_addr, WAN);
+        assert_eq!(record.key.src_port, 61000);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::processor::Processor;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// The parameters of a processor, by name, as read from a configuration.
+pub type Params = HashMap<String, String>;
+
+/// A processor chosen at runtime. It is a `Processor` itself, so it can be handed to any link.
+pub type BoxedProcessor<Input, Output> = Box<dyn Processor<Input = Input, Output = Output> + Send>;
+
+/// Why a processor could not be constructed through a `ProcessorRegistry`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessorRegistryError {
+    /// No constructor is registered under this name.
+    UnknownProcessor(String),
+    /// The constructor requires a parameter that was not given.
+    MissingParam(String),
+    /// A parameter was given a value the constructor cannot use.
+    InvalidParam { param: String, value: String },
+}
+
+impl fmt::Display for ProcessorRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProcessorRegistryError::UnknownProcessor(name) => {
+                write!(f, "No processor registered as {:?}", name)
+            }
+            ProcessorRegistryError::MissingParam(param) => {
+                write!(f, "Missing parameter {:?}", param)
+            }
+            ProcessorRegistryError::InvalidParam { param, value } => {
+                write!(f, "Invalid value {:?} for parameter {:?}", value, param)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProcessorRegistryError {}
+
+/// Parses the parameter `name` out of `params`, for use in the constructors of a
+/// `ProcessorRegistry`.
+pub fn param<T: FromStr>(params: &Params, name: &str) -> Result<T, ProcessorRegistryError> {
+    let value = params
+        .get(name)
+        .ok_or_else(|| ProcessorRegistryError::MissingParam(name.to_string()))?;
+    value
+        .parse()
+        .map_err(|_| ProcessorRegistryError::InvalidParam {
+            param: name.to_string(),
+            value: value.to_string(),
+        })
+}
+
+/// Parses the parameter `name` out of `params`, falling back to `default` if it was not given.
+pub fn param_or<T: FromStr>(
+    params: &Params,
+    name: &str,
+    default: T,
+) -> Result<T, ProcessorRegistryError> {
+    match params.get(name) {
+        Some(_) => param(params, name),
+        None => Ok(default),
+    }
+}
+
+type Constructor<Input, Output> = Box<
+    dyn Fn(&Params) -> Result<BoxedProcessor<Input, Output>, ProcessorRegistryError> + Send + Sync,
+>;
+
+/// Constructors of processors that take `Input` and emit `Output`, by name, for a control plane
+/// to build the processors of a pipeline from a configuration at runtime. Each constructor parses
+/// the parameters it needs, with `param` and `param_or`, and returns the processor. Paired with a
+/// `LinkRegistry`, this lets a pipeline be assembled without knowing its processors at compile
+/// time.
+pub struct ProcessorRegistry<Input, Output> {
+    constructors: HashMap<String, Constructor<Input, Output>>,
+}
+
+impl<Input, Output> Default for ProcessorRegistry<Input, Output> {
+    fn default() -> Self {
+        ProcessorRegistry::new()
+    }
+}
+
+impl<Input, Output> ProcessorRegistry<Input, Output> {
+    pub fn new() -> Self {
+        ProcessorRegistry {
+            constructors: HashMap::new(),
+        }
+    }
+
+    /// Registers `constructor` as `name`, replacing any constructor already registered as it.
+    pub fn register<P, F>(&mut self, name: &str, constructor: F)
+    where
+        P: Processor<Input = Input, Output = Output> + Send + 'static,
+        F: Fn(&Params) -> Result<P, ProcessorRegistryError> + Send + Sync + 'static,
+    {
+        let constructor = move |params: &Params| {
+            let processor: BoxedProcessor<Input, Output> = Box::new(constructor(params)?);
+            Ok(processor)
+        };
+        self.constructors
+            .insert(name.to_string(), Box::new(constructor));
+    }
+
+    /// Whether a constructor is registered as `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.constructors.contains_key(name)
+    }
+
+    /// Constructs the processor registered as `name` from `params`.
+    pub fn build(
+        &self,
+        name: &str,
+        params: &Params,
+    ) -> Result<BoxedProcessor<Input, Output>, ProcessorRegistryError> {
+        match self.constructors.get(name) {
+            Some(constructor) => constructor(params),
+            None => Err(ProcessorRegistryError::UnknownProcessor(name.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::primitive::ProcessLink;
+    use crate::link::{LinkBuilder, ProcessLinkBuilder};
+    use crate::processor::{DecIpv4HopLimit, SetDscp};
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use route_rs_packets::Ipv4Packet;
+
+    fn registry() -> ProcessorRegistry<Ipv4Packet, Ipv4Packet> {
+        let mut registry = ProcessorRegistry::new();
+        registry.register("DecrementTtl", |_| Ok(DecIpv4HopLimit::new()));
+        registry.register("SetDscp", |params| {
+            let dscp: u8 = param(params, "dscp")?;
+            if dscp >= 64 {
+                return Err(ProcessorRegistryError::InvalidParam {
+                    param: String::from("dscp"),
+                    value: dscp.to_string(),
+                });
+            }
+            Ok(SetDscp::new(dscp))
+        });
+        registry
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> Params {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn packet(ttl: u8) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_ttl(ttl);
+        packet.set_checksum();
+        packet
+    }
+
+    #[test]
+    fn builds_processors_by_name() {
+        let registry = registry();
+        let mut decrement = registry.build("DecrementTtl", &Params::new()).unwrap();
+        let mut mark = registry
+            .build("SetDscp", &params(&[("dscp", "46")]))
+            .unwrap();
+
+        let packet = mark
+            .process(decrement.process(packet(64)).unwrap())
+            .unwrap();
+
+        assert_eq!(packet.ttl(), 63);
+        assert_eq!(packet.dscp(), 46);
+    }
+
+    #[test]
+    fn built_processor_runs_in_link() {
+        let registry = registry();
+        let processor = registry
+            .build("DecrementTtl", &Params::new())
+            .unwrap()
+            .and_then(
+                registry
+                    .build("SetDscp", &params(&[("dscp", "10")]))
+                    .unwrap(),
+            );
+
+        let link = ProcessLink::new()
+            .ingressor(immediate_stream(vec![packet(64), packet(2)]))
+            .processor(processor)
+            .build_link();
+        let results = initialize_runtime().block_on(run_link(link));
+
+        let ttls: Vec<u8> = results[0].iter().map(|packet| packet.ttl()).collect();
+        assert_eq!(ttls, vec![63, 1]);
+        assert!(results[0].iter().all(|packet| packet.dscp() == 10));
+    }
+
+    #[test]
+    fn rejects_bad_params() {
+        let registry = registry();
+
+        assert_eq!(
+            registry.build("SetDscp", &Params::new()).err().unwrap(),
+            ProcessorRegistryError::MissingParam(String::from("dscp"))
+        );
+        assert_eq!(
+            registry
+                .build("SetDscp", &params(&[("dscp", "EF")]))
+                .err()
+                .unwrap(),
+            ProcessorRegistryError::InvalidParam {
+                param: String::from("dscp"),
+                value: String::from("EF"),
+            }
+        );
+        assert!(registry
+            .build("SetDscp", &params(&[("dscp", "64")]))
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_processor() {
+        let registry = registry();
+
+        assert!(registry.contains("SetDscp"));
+        assert!(!registry.contains("SetTtl"));
+        assert_eq!(
+            registry.build("SetTtl", &Params::new()).err().unwrap(),
+            ProcessorRegistryError::UnknownProcessor(String::from("SetTtl"))
+        );
+    }
+
+    #[test]
+    fn param_or_falls_back() {
+        let params = params(&[("ttl", "32")]);
+
+        assert_eq!(param_or(&params, "ttl", 64u8), Ok(32));
+        assert_eq!(param_or(&params, "dscp", 0u8), Ok(0));
+    }
+}
@@ -0,0 +1,277 @@
+use crate::interface::InterfaceConfig;
+use crate::processor::Processor;
+use crate::routing::NextHop;
+use route_rs_packets::{EthernetFrame, MacAddr};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex};
+use std::task::Waker;
+
+/// A handle to the link-layer addresses of IPv4 neighbors, the IPv4 counterpart of
+/// `NeighborCache`. It can be cloned, so that whatever speaks ARP fills in entries while
+/// `RewriteL2` reads them. Addresses `RewriteL2` needed but found no entry for are kept as pending,
+/// until an entry is inserted for them or resolving them is given up on, so the ARP speaker knows
+/// which to send requests for.
+#[derive(Clone, Default)]
+pub struct ArpCache {
+    entries: Arc<Mutex<HashMap<Ipv4Addr, MacAddr>>>,
+    pending: Arc<Mutex<HashSet<Ipv4Addr>>>,
+    /// Tasks to wake when an entry is inserted, such as those of `PendingArpQueue`s.
+    watchers: Arc<Mutex<Vec<Waker>>>,
+}
+
+impl ArpCache {
+    pub fn new() -> Self {
+        ArpCache {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            pending: Arc::new(Mutex::new(HashSet::new())),
+            watchers: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// The link-layer address of `addr`, if it has been learned.
+    pub fn lookup(&self, addr: &Ipv4Addr) -> Option<MacAddr> {
+        self.entries.lock().unwrap().get(addr).copied()
+    }
+
+    /// Records that `addr` is reachable at `mac`, replacing what was learned for it before.
+    pub fn insert(&self, addr: Ipv4Addr, mac: MacAddr) {
+        self.entries.lock().unwrap().insert(addr, mac);
+        self.pending.lock().unwrap().remove(&addr);
+        for waker in self.watchers.lock().unwrap().drain(..) {
+            waker.wake();
+        }
+    }
+
+    /// Wakes the task of `waker` the next time an entry is inserted.
+    pub(crate) fn watch(&self, waker: &Waker) {
+        let mut watchers = self.watchers.lock().unwrap();
+        if !watchers.iter().any(|watcher| watcher.will_wake(waker)) {
+            watchers.push(waker.clone());
+        }
+    }
+
+    /// Marks `addr` as needing to be resolved, unless it already is.
+    pub fn request(&self, addr: Ipv4Addr) {
+        if self.lookup(&addr).is_none() {
+            self.pending.lock().unwrap().insert(addr);
+        }
+    }
+
+    /// Stops resolving `addr`, if it is pending, such as when it did not answer in time.
+    pub fn cancel(&self, addr: &Ipv4Addr) {
+        self.pending.lock().unwrap().remove(addr);
+    }
+
+    /// The addresses waiting to be resolved, in ascending order.
+    pub fn pending(&self) -> Vec<Ipv4Addr> {
+        let mut pending: Vec<Ipv4Addr> = self.pending.lock().unwrap().iter().copied().collect();
+        pending.sort();
+        pending
+    }
+
+    /// Number of neighbors learned.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Addresses each IPv4 frame the router forwards for the hop it is about to take. The source MAC
+/// becomes the MAC of the egress interface, and the destination MAC the one `ArpCache` has for the
+/// next hop: the gateway of the route, or for a directly connected destination, the destination
+/// of the packet itself. Outputs the frame tagged with its egress interface, ready to be
+/// classified to that interface's egressor.
+///
+/// Frames whose next hop is not in the cache yet are dropped, and the next hop marked as pending
+/// in the cache, so that it gets resolved for the frames that follow. Frames for interfaces
+/// missing from the `InterfaceConfig`, and frames that do not carry IPv4, are dropped.
+#[derive(Clone)]
+pub struct RewriteL2 {
+    interfaces: InterfaceConfig,
+    arp_cache: ArpCache,
+}
+
+impl RewriteL2 {
+    pub fn new(interfaces: InterfaceConfig, arp_cache: ArpCache) -> Self {
+        RewriteL2 {
+            interfaces,
+            arp_cache,
+        }
+    }
+}
+
+/// The destination address of the IPv4 packet in `frame`, if it carries one.
+fn ipv4_dest_addr(frame: &EthernetFrame) -> Option<Ipv4Addr> {
+    if frame.ether_type() != 0x0800 {
+        return None;
+    }
+    let octets: [u8; 4] = frame.payload().get(16..20)?.try_into().unwrap();
+    Some(Ipv4Addr::from(octets))
+}
+
+/// What `RewriteL2::resolve` made of a frame.
+pub(crate) enum Resolution {
+    /// The frame, addressed for its next hop and tagged with its egress interface.
+    Resolved(usize, EthernetFrame),
+    /// The link-layer address of the next hop is not known yet. Holds the next hop and the frame,
+    /// untouched.
+    Unresolved(Ipv4Addr, EthernetFrame),
+    /// The frame cannot be delivered, whatever the cache learns.
+    Undeliverable,
+}
+
+impl RewriteL2 {
+    /// Addresses `frame` for `next_hop`, without marking unresolved next hops as pending.
+    pub(crate) fn resolve(&self, next_hop: NextHop, mut frame: EthernetFrame) -> Resolution {
+        let dest_addr = match ipv4_dest_addr(&frame) {
+            Some(dest_addr) => dest_addr,
+            None => return Resolution::Undeliverable,
+        };
+        let src_mac = match self.interfaces.mac(next_hop.interface) {
+            Some(mac) => mac,
+            None => return Resolution::Undeliverable,
+        };
+        let neighbor = next_hop.gateway.unwrap_or(dest_addr);
+        match self.arp_cache.lookup(&neighbor) {
+            Some(dest_mac) => {
+                frame.set_src_mac(src_mac);
+                frame.set_dest_mac(dest_mac);
+                Resolution::Resolved(next_hop.interface, frame)
+            }
+            None => Resolution::Unresolved(neighbor, frame),
+        }
+    }
+}
+
+impl Processor for RewriteL2 {
+    type Input = (NextHop, EthernetFrame);
+    type Output = (usize, EthernetFrame);
+
+    fn process(&mut self, (next_hop, frame): Self::Input) -> Option<Self::Output> {
+        match self.resolve(next_hop, frame) {
+            Resolution::Resolved(interface, frame) => Some((interface, frame)),
+            Resolution::Unresolved(neighbor, _) => {
+                self.arp_cache.request(neighbor);
+                None
+            }
+            Resolution::Undeliverable => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::InterfaceSettings;
+    use route_rs_packets::Ipv4Packet;
+
+    const LAN: usize = 0;
+    const WAN: usize = 1;
+
+    const LAN_MAC: MacAddr = MacAddr {
+        bytes: [0x02, 0, 0, 0, 0, 0x01],
+    };
+    const WAN_MAC: MacAddr = MacAddr {
+        bytes: [0x02, 0, 0, 0, 0, 0x02],
+    };
+    const HOST_MAC: MacAddr = MacAddr {
+        bytes: [0x02, 0, 0, 0, 0, 0x10],
+    };
+    const GATEWAY_MAC: MacAddr = MacAddr {
+        bytes: [0x02, 0, 0, 0, 0, 0x20],
+    };
+
+    const HOST: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 10);
+    const GATEWAY: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 1);
+
+    fn interfaces() -> InterfaceConfig {
+        let interfaces = InterfaceConfig::new();
+        interfaces.set(
+            LAN,
+            InterfaceSettings::new(1500, LAN_MAC, vec![Ipv4Addr::new(192, 168, 1, 1)]),
+        );
+        interfaces.set(
+            WAN,
+            InterfaceSettings::new(1500, WAN_MAC, vec![Ipv4Addr::new(203, 0, 113, 2)]),
+        );
+        interfaces
+    }
+
+    fn frame(dest_addr: Ipv4Addr) -> EthernetFrame {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(Ipv4Addr::new(198, 51, 100, 7));
+        packet.set_dest_addr(dest_addr);
+        packet.set_ttl(64);
+        let mut frame = EthernetFrame::encap_ipv4(packet);
+        frame.set_src_mac(MacAddr::new([0x02, 0, 0, 0, 0, 0xAA]));
+        frame.set_dest_mac(WAN_MAC);
+        frame
+    }
+
+    #[test]
+    fn rewrites_for_connected_destination() {
+        let arp_cache = ArpCache::new();
+        arp_cache.insert(HOST, HOST_MAC);
+        let mut elem = RewriteL2::new(interfaces(), arp_cache);
+
+        let (interface, rewritten) = elem
+            .process((NextHop::connected(LAN), frame(HOST)))
+            .unwrap();
+
+        assert_eq!(interface, LAN);
+        assert_eq!(rewritten.src_mac(), LAN_MAC);
+        assert_eq!(rewritten.dest_mac(), HOST_MAC);
+        assert_eq!(rewritten.payload(), frame(HOST).payload());
+    }
+
+    #[test]
+    fn rewrites_for_gateway() {
+        let arp_cache = ArpCache::new();
+        arp_cache.insert(GATEWAY, GATEWAY_MAC);
+        let mut elem = RewriteL2::new(interfaces(), arp_cache);
+        let dest_addr = Ipv4Addr::new(8, 8, 8, 8);
+
+        let (interface, rewritten) = elem
+            .process((NextHop::via(GATEWAY, WAN), frame(dest_addr)))
+            .unwrap();
+
+        assert_eq!(interface, WAN);
+        assert_eq!(rewritten.src_mac(), WAN_MAC);
+        assert_eq!(rewritten.dest_mac(), GATEWAY_MAC);
+    }
+
+    #[test]
+    fn unresolved_next_hop_is_dropped_and_requested() {
+        let arp_cache = ArpCache::new();
+        let mut elem = RewriteL2::new(interfaces(), arp_cache.clone());
+
+        assert!(elem
+            .process((NextHop::connected(LAN), frame(HOST)))
+            .is_none());
+        assert_eq!(arp_cache.pending(), vec![HOST]);
+
+        arp_cache.insert(HOST, HOST_MAC);
+        assert!(arp_cache.pending().is_empty());
+        assert!(elem
+            .process((NextHop::connected(LAN), frame(HOST)))
+            .is_some());
+    }
+
+    #[test]
+    fn drops_unknown_interface_and_non_ipv4() {
+        let arp_cache = ArpCache::new();
+        arp_cache.insert(HOST, HOST_MAC);
+        let mut elem = RewriteL2::new(interfaces(), arp_cache);
+
+        assert!(elem.process((NextHop::connected(7), frame(HOST))).is_none());
+
+        let mut arp = frame(HOST);
+        arp.set_ether_type(0x0806);
+        assert!(elem.process((NextHop::connected(LAN), arp)).is_none());
+    }
+}
@@ -0,0 +1,458 @@
+use crate::link::utils::clock::{Clock, SystemClock};
+use crate::processor::Processor;
+use crate::routing::{NextHop, Route, RoutingTable};
+use route_rs_packets::{IpProtocol, Ipv4Packet};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+/// The UDP port RIP speaks on.
+pub const RIP_PORT: u16 = 520;
+/// The metric RIP uses for unreachable destinations.
+pub const RIP_INFINITY: u32 = 16;
+
+const RIP_COMMAND_RESPONSE: u8 = 2;
+const RIP_VERSION_2: u8 = 2;
+const RIP_AFI_IPV4: u16 = 2;
+const RIP_ENTRY_LEN: usize = 20;
+
+/// A route learned from a neighbor.
+struct LearnedRoute {
+    next_hop: NextHop,
+    metric: u32,
+    seen: Instant,
+}
+
+/// Learns routes from RIPv2 responses (RFC 2453) sent by neighboring routers, and installs them
+/// in a shared `RoutingTable`. Takes each packet tagged with the interface it arrived on; RIP
+/// responses are consumed, and everything else passes through untouched, so the listener can sit
+/// on the path of traffic addressed to the router.
+///
+/// Each advertised metric is increased by one for the hop to the neighbor. A learned route is
+/// replaced only by a better one, or updated by the neighbor it was learned from, which can also
+/// withdraw it by advertising it as unreachable. Routes that are not refreshed within `timeout`
+/// are removed. Routes in the table that were not learned over RIP, such as connected and static
+/// routes, are never touched.
+pub struct RipListener {
+    table: RoutingTable,
+    learned: HashMap<(Ipv4Addr, u8), LearnedRoute>,
+    timeout: Duration,
+    clock: Box<dyn Clock>,
+}
+
+impl RipListener {
+    pub fn new(table: RoutingTable) -> Self {
+        RipListener {
+            table,
+            learned: HashMap::new(),
+            timeout: Duration::from_secs(180),
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Changes how long learned routes live without being refreshed, default value is 180
+    /// seconds.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        RipListener { timeout, ..self }
+    }
+
+    /// Changes the clock used to age out routes, default is `SystemClock`.
+    pub fn clock<C: Clock + 'static>(self, clock: C) -> Self {
+        RipListener {
+            clock: Box::new(clock),
+            ..self
+        }
+    }
+
+    /// The metric of the learned route to `prefix`/`prefix_len`, if there is one.
+    pub fn metric(&self, prefix: Ipv4Addr, prefix_len: u8) -> Option<u32> {
+        let route = Route::new(prefix, prefix_len, NextHop::connected(0));
+        self.learned
+            .get(&(route.prefix, prefix_len))
+            .map(|learned| learned.metric)
+    }
+
+    /// The learned routes to advertise out of `interface`, with their metrics. Split horizon
+    /// with poisoned reverse applies: routes learned over `interface` are advertised back as
+    /// unreachable, so the neighbors they came from never route through this router to reach
+    /// them.
+    pub fn advertisements(&self, interface: usize) -> Vec<(Route, u32)> {
+        let mut advertisements: Vec<(Route, u32)> = self
+            .learned
+            .iter()
+            .map(|((prefix, prefix_len), learned)| {
+                let metric = if learned.next_hop.interface == interface {
+                    RIP_INFINITY
+                } else {
+                    learned.metric
+                };
+                (Route::new(*prefix, *prefix_len, learned.next_hop), metric)
+            })
+            .collect();
+        advertisements.sort_by_key(|(route, _)| (route.prefix, route.prefix_len));
+        advertisements
+    }
+
+    /// Removes the routes that have not been refreshed within the timeout.
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        let timeout = self.timeout;
+        let table = &self.table;
+        self.learned.retain(|(prefix, prefix_len), learned| {
+            let alive = now.saturating_duration_since(learned.seen) < timeout;
+            if !alive {
+                table.remove(*prefix, *prefix_len);
+            }
+            alive
+        });
+    }
+
+    /// Applies one advertised route, whose next hop is the neighbor that advertised it.
+    fn update(&mut self, route: Route, advertised_metric: u32) {
+        let metric = (advertised_metric + 1).min(RIP_INFINITY);
+        let key = (route.prefix, route.prefix_len);
+        let next_hop = route.next_hops[0];
+        let now = self.clock.now();
+
+        match self.learned.get_mut(&key) {
+            Some(learned) if learned.next_hop == next_hop => {
+                if metric == RIP_INFINITY {
+                    self.learned.remove(&key);
+                    self.table.remove(route.prefix, route.prefix_len);
+                    return;
+                }
+                learned.metric = metric;
+                learned.seen = now;
+            }
+            Some(learned) if metric < learned.metric => {
+                learned.next_hop = next_hop;
+                learned.metric = metric;
+                learned.seen = now;
+                self.table.insert(route);
+            }
+            Some(_) => {}
+            None => {
+                let unlearned = self.table.get(route.prefix, route.prefix_len).is_some();
+                if metric == RIP_INFINITY || unlearned {
+                    return;
+                }
+                self.learned.insert(
+                    key,
+                    LearnedRoute {
+                        next_hop,
+                        metric,
+                        seen: now,
+                    },
+                );
+                self.table.insert(route);
+            }
+        }
+    }
+
+    /// Parses `payload` as a UDP datagram holding a RIPv2 response, returning its route entries
+    /// as routes and advertised metrics. Next hops of 0.0.0.0 mean the neighbor itself.
+    fn parse_response(
+        payload: &[u8],
+        neighbor: Ipv4Addr,
+        interface: usize,
+    ) -> Option<Vec<(Route, u32)>> {
+        let udp = payload.get(..8)?;
+        let src_port = u16::from_be_bytes(udp[0..2].try_into().unwrap());
+        let dest_port = u16::from_be_bytes(udp[2..4].try_into().unwrap());
+        let udp_len = u16::from_be_bytes(udp[4..6].try_into().unwrap()) as usize;
+        if src_port != RIP_PORT || dest_port != RIP_PORT || udp_len > payload.len() {
+            return None;
+        }
+
+        let rip = payload.get(8..udp_len)?;
+        let header = rip.get(..4)?;
+        if header[0] != RIP_COMMAND_RESPONSE || header[1] != RIP_VERSION_2 {
+            return None;
+        }
+
+        let mut routes = vec![];
+        for entry in rip[4..].chunks_exact(RIP_ENTRY_LEN) {
+            let afi = u16::from_be_bytes(entry[0..2].try_into().unwrap());
+            let prefix = Ipv4Addr::from(u32::from_be_bytes(entry[4..8].try_into().unwrap()));
+            let mask = u32::from_be_bytes(entry[8..12].try_into().unwrap());
+            let gateway = Ipv4Addr::from(u32::from_be_bytes(entry[12..16].try_into().unwrap()));
+            let metric = u32::from_be_bytes(entry[16..20].try_into().unwrap());
+            // Authentication entries, other address families, and non contiguous masks are not
+            // routes we can install.
+            if afi != RIP_AFI_IPV4 || mask.leading_ones() + mask.trailing_zeros() != 32 {
+                continue;
+            }
+            if metric == 0 || metric > RIP_INFINITY {
+                continue;
+            }
+
+            let gateway = if gateway.is_unspecified() {
+                neighbor
+            } else {
+                gateway
+            };
+            let next_hop = NextHop::via(gateway, interface);
+            routes.push((
+                Route::new(prefix, mask.leading_ones() as u8, next_hop),
+                metric,
+            ));
+        }
+        Some(routes)
+    }
+}
+
+impl Processor for RipListener {
+    type Input = (usize, Ipv4Packet);
+    type Output = (usize, Ipv4Packet);
+
+    fn process(&mut self, (ingress, packet): Self::Input) -> Option<Self::Output> {
+        self.expire();
+
+        if packet.protocol() != IpProtocol::UDP {
+            return Some((ingress, packet));
+        }
+        let routes =
+            match RipListener::parse_response(&packet.payload(), packet.src_addr(), ingress) {
+                Some(routes) => routes,
+                None => return Some((ingress, packet)),
+            };
+        for (route, metric) in routes {
+            self.update(route, metric);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::clock::ManualClock;
+
+    const LAN: usize = 0;
+    const WAN: usize = 1;
+    const NEIGHBOR: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+    const OTHER_NEIGHBOR: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 3);
+
+    fn entry(prefix: Ipv4Addr, prefix_len: u8, next_hop: Ipv4Addr, metric: u32) -> Vec<u8> {
+        let mut entry = vec![];
+        entry.extend_from_slice(&RIP_AFI_IPV4.to_be_bytes());
+        entry.extend_from_slice(&[0, 0]);
+        entry.extend_from_slice(&prefix.octets());
+        let mask = if prefix_len == 0 {
+            0
+        } else {
+            u32::MAX << (32 - prefix_len)
+        };
+        entry.extend_from_slice(&mask.to_be_bytes());
+        entry.extend_from_slice(&next_hop.octets());
+        entry.extend_from_slice(&metric.to_be_bytes());
+        entry
+    }
+
+    fn response(src_addr: Ipv4Addr, entries: &[Vec<u8>]) -> Ipv4Packet {
+        let mut rip = vec![RIP_COMMAND_RESPONSE, RIP_VERSION_2, 0, 0];
+        for entry in entries {
+            rip.extend_from_slice(entry);
+        }
+        let mut udp = vec![];
+        udp.extend_from_slice(&RIP_PORT.to_be_bytes());
+        udp.extend_from_slice(&RIP_PORT.to_be_bytes());
+        udp.extend_from_slice(&((8 + rip.len()) as u16).to_be_bytes());
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend_from_slice(&rip);
+
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(src_addr);
+        packet.set_dest_addr(Ipv4Addr::new(224, 0, 0, 9));
+        packet.set_ttl(1);
+        packet.set_protocol(17);
+        packet.set_payload(&udp);
+        packet.set_checksum();
+        packet
+    }
+
+    fn canned_update() -> Ipv4Packet {
+        response(
+            NEIGHBOR,
+            &[
+                entry(Ipv4Addr::new(172, 16, 0, 0), 12, Ipv4Addr::UNSPECIFIED, 1),
+                entry(
+                    Ipv4Addr::new(198, 51, 100, 0),
+                    24,
+                    Ipv4Addr::new(10, 0, 0, 9),
+                    3,
+                ),
+                entry(Ipv4Addr::new(203, 0, 113, 0), 24, Ipv4Addr::UNSPECIFIED, 16),
+            ],
+        )
+    }
+
+    #[test]
+    fn installs_advertised_routes() {
+        let table = RoutingTable::new();
+        let mut rip = RipListener::new(table.clone());
+
+        assert!(rip.process((WAN, canned_update())).is_none());
+
+        assert_eq!(
+            table.routes(),
+            vec![
+                Route::new(
+                    Ipv4Addr::new(198, 51, 100, 0),
+                    24,
+                    NextHop::via(Ipv4Addr::new(10, 0, 0, 9), WAN)
+                ),
+                Route::new(
+                    Ipv4Addr::new(172, 16, 0, 0),
+                    12,
+                    NextHop::via(NEIGHBOR, WAN)
+                ),
+            ]
+        );
+        assert_eq!(rip.metric(Ipv4Addr::new(172, 16, 0, 0), 12), Some(2));
+        assert_eq!(rip.metric(Ipv4Addr::new(198, 51, 100, 0), 24), Some(4));
+        assert_eq!(rip.metric(Ipv4Addr::new(203, 0, 113, 0), 24), None);
+    }
+
+    #[test]
+    fn passes_other_traffic() {
+        let mut rip = RipListener::new(RoutingTable::new());
+
+        let mut packet = Ipv4Packet::empty();
+        packet.set_protocol(6);
+        assert!(rip.process((LAN, packet)).is_some());
+
+        let mut not_rip = canned_update();
+        let mut udp = not_rip.payload().to_vec();
+        udp[1] = 53;
+        not_rip.set_payload(&udp);
+        assert!(rip.process((LAN, not_rip)).is_some());
+    }
+
+    #[test]
+    fn keeps_better_route_and_withdraws() {
+        let table = RoutingTable::new();
+        let mut rip = RipListener::new(table.clone());
+        let prefix = Ipv4Addr::new(172, 16, 0, 0);
+
+        rip.process((
+            WAN,
+            response(NEIGHBOR, &[entry(prefix, 12, Ipv4Addr::UNSPECIFIED, 5)]),
+        ));
+        rip.process((
+            LAN,
+            response(
+                OTHER_NEIGHBOR,
+                &[entry(prefix, 12, Ipv4Addr::UNSPECIFIED, 7)],
+            ),
+        ));
+        assert_eq!(
+            table.get(prefix, 12),
+            Some(vec![NextHop::via(NEIGHBOR, WAN)])
+        );
+
+        rip.process((
+            LAN,
+            response(
+                OTHER_NEIGHBOR,
+                &[entry(prefix, 12, Ipv4Addr::UNSPECIFIED, 2)],
+            ),
+        ));
+        assert_eq!(
+            table.get(prefix, 12),
+            Some(vec![NextHop::via(OTHER_NEIGHBOR, LAN)])
+        );
+        assert_eq!(rip.metric(prefix, 12), Some(3));
+
+        // Only the neighbor the route was learned from can withdraw it.
+        rip.process((
+            WAN,
+            response(NEIGHBOR, &[entry(prefix, 12, Ipv4Addr::UNSPECIFIED, 16)]),
+        ));
+        assert!(table.get(prefix, 12).is_some());
+        rip.process((
+            LAN,
+            response(
+                OTHER_NEIGHBOR,
+                &[entry(prefix, 12, Ipv4Addr::UNSPECIFIED, 16)],
+            ),
+        ));
+        assert_eq!(table.get(prefix, 12), None);
+    }
+
+    #[test]
+    fn leaves_static_routes_alone() {
+        let table = RoutingTable::new();
+        let connected = Route::new(Ipv4Addr::new(172, 16, 0, 0), 12, NextHop::connected(LAN));
+        table.insert(connected);
+        let mut rip = RipListener::new(table.clone());
+
+        rip.process((WAN, canned_update()));
+
+        assert_eq!(
+            table.get(Ipv4Addr::new(172, 16, 0, 0), 12),
+            Some(vec![NextHop::connected(LAN)])
+        );
+    }
+
+    #[test]
+    fn ages_out_routes() {
+        let clock = ManualClock::new();
+        let table = RoutingTable::new();
+        let mut rip = RipListener::new(table.clone())
+            .timeout(Duration::from_secs(180))
+            .clock(clock.clone());
+
+        rip.process((WAN, canned_update()));
+        clock.advance(Duration::from_secs(120));
+        rip.process((
+            WAN,
+            response(
+                NEIGHBOR,
+                &[entry(
+                    Ipv4Addr::new(172, 16, 0, 0),
+                    12,
+                    Ipv4Addr::UNSPECIFIED,
+                    1,
+                )],
+            ),
+        ));
+        clock.advance(Duration::from_secs(60));
+        rip.expire();
+
+        assert_eq!(
+            table.routes(),
+            vec![Route::new(
+                Ipv4Addr::new(172, 16, 0, 0),
+                12,
+                NextHop::via(NEIGHBOR, WAN)
+            )]
+        );
+    }
+
+    #[test]
+    fn poisons_reverse() {
+        let mut rip = RipListener::new(RoutingTable::new());
+        rip.process((
+            WAN,
+            response(
+                NEIGHBOR,
+                &[entry(
+                    Ipv4Addr::new(172, 16, 0, 0),
+                    12,
+                    Ipv4Addr::UNSPECIFIED,
+                    1,
+                )],
+            ),
+        ));
+
+        let route = Route::new(
+            Ipv4Addr::new(172, 16, 0, 0),
+            12,
+            NextHop::via(NEIGHBOR, WAN),
+        );
+        assert_eq!(rip.advertisements(LAN), vec![(route.clone(), 2)]);
+        assert_eq!(rip.advertisements(WAN), vec![(route, RIP_INFINITY)]);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::processor::Processor;
+use std::marker::PhantomData;
+
+/// Annotates each packet with its sequence number, counting up from `first_sequence`. The
+/// annotation is what `ReorderBufferLink` restores order by, and what lets a test tell whether the
+/// links after the tagger reordered or lost any packets.
+pub struct SequenceTagger<A: Send + Clone> {
+    next: u64,
+    phantom: PhantomData<A>,
+}
+
+impl<A: Send + Clone> SequenceTagger<A> {
+    pub fn new() -> Self {
+        SequenceTagger {
+            next: 0,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Changes first_sequence, the sequence number of the first packet, default value is 0.
+    pub fn first_sequence(self, first_sequence: u64) -> Self {
+        SequenceTagger {
+            next: first_sequence,
+            ..self
+        }
+    }
+}
+
+impl<A: Send + Clone> Default for SequenceTagger<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Send + Clone> Processor for SequenceTagger<A> {
+    type Input = A;
+    type Output = (u64, A);
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let sequence = self.next;
+        self.next = self.next.wrapping_add(1);
+        Some((sequence, packet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::primitive::{ForkLink, ProcessLink, ReorderBufferLink};
+    use crate::link::{LinkBuilder, ProcessLinkBuilder};
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use crate::utils::test::sequence_checker::SequenceChecker;
+
+    #[test]
+    fn counts_up_from_first_sequence() {
+        let mut tagger = SequenceTagger::new().first_sequence(7);
+
+        assert_eq!(tagger.process('a'), Some((7, 'a')));
+        assert_eq!(tagger.process('b'), Some((8, 'b')));
+        assert_eq!(tagger.process('c'), Some((9, 'c')));
+    }
+
+    #[test]
+    fn wraps_around() {
+        let mut tagger = SequenceTagger::new().first_sequence(u64::MAX);
+
+        assert_eq!(tagger.process(()), Some((u64::MAX, ())));
+        assert_eq!(tagger.process(()), Some((0, ())));
+    }
+
+    #[test]
+    fn fork_keeps_each_egressor_contiguous() {
+        let packets: Vec<u32> = (0..100).collect();
+        let checkers: Vec<SequenceChecker<u32>> = (0..3).map(|_| SequenceChecker::new()).collect();
+        let reports: Vec<_> = checkers.iter().map(|checker| checker.report()).collect();
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let (_, mut tagged) = ProcessLink::new()
+                .ingressor(immediate_stream(packets.clone()))
+                .processor(SequenceTagger::new())
+                .build_link();
+
+            let (runnables, forked) = ForkLink::new()
+                .ingressor(tagged.remove(0))
+                .num_egressors(3)
+                .build_link();
+
+            let egressors = forked
+                .into_iter()
+                .zip(checkers)
+                .map(|(egressor, checker)| {
+                    let (_, mut checked) = ProcessLink::new()
+                        .ingressor(egressor)
+                        .processor(checker)
+                        .build_link();
+                    checked.remove(0)
+                })
+                .collect();
+
+            run_link((runnables, egressors)).await
+        });
+
+        for (egressor, report) in results.iter().zip(reports) {
+            report.assert_contiguous();
+            assert_eq!(report.checked(), 100);
+            let untagged: Vec<u32> = egressor.iter().map(|(_, packet)| *packet).collect();
+            assert_eq!(untagged, packets);
+        }
+    }
+
+    #[test]
+    fn reorder_buffer_strips_tags() {
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let (_, mut tagged) = ProcessLink::new()
+                .ingressor(immediate_stream(vec!['a', 'b', 'c']))
+                .processor(SequenceTagger::new())
+                .build_link();
+
+            let link = ReorderBufferLink::new()
+                .ingressor(tagged.remove(0))
+                .build_link();
+
+            run_link(link).await
+        });
+
+        assert_eq!(results[0], vec!['a', 'b', 'c']);
+    }
+}
@@ -0,0 +1,52 @@
+use crate::processor::Processor;
+use route_rs_packets::Ipv4Packet;
+
+/// Marks every IPv4 packet with a fixed DSCP, such as `46` (Expedited Forwarding) for voice
+/// traffic, so that routers downstream queue it accordingly. The ECN bits are left alone.
+#[derive(Clone)]
+pub struct SetDscp {
+    dscp: u8,
+}
+
+impl SetDscp {
+    /// Panics if `dscp` does not fit in the 6 bits of the field.
+    pub fn new(dscp: u8) -> Self {
+        assert!(dscp < 64, "DSCP {} does not fit in 6 bits", dscp);
+        SetDscp { dscp }
+    }
+}
+
+impl Processor for SetDscp {
+    type Input = Ipv4Packet;
+    type Output = Ipv4Packet;
+
+    fn process(&mut self, mut packet: Self::Input) -> Option<Self::Output> {
+        packet.set_dscp(self.dscp);
+        packet.set_checksum();
+        Some(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_packet() {
+        let mut packet = Ipv4Packet::empty();
+        packet.data[packet.layer3_offset + 1] = 0x01;
+        packet.set_checksum();
+
+        let mut packet = SetDscp::new(46).process(packet).unwrap();
+
+        assert_eq!(packet.dscp(), 46);
+        assert_eq!(packet.ecn(), 0x01);
+        assert!(packet.validate_checksum());
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_wide_dscp() {
+        SetDscp::new(64);
+    }
+}
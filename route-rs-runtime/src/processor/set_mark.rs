@@ -0,0 +1,182 @@
+use crate::processor::Processor;
+
+/// A number tagged onto a packet by one stage for later stages to act on, like a Linux fwmark.
+/// Marked packets travel as `(Mark, packet)`, so stages that do not care about the mark can run
+/// on them wrapped in `Annotated`. Packets no rule marks get mark 0.
+pub type Mark = u32;
+
+type Predicate<P> = Box<dyn Fn(&P) -> bool + Send>;
+
+/// Marks each packet with the mark of the first rule it matches, or 0 if it matches none, and
+/// passes it on unchanged. Marking decouples classifying a packet from acting on it: `SetMark`
+/// decides once, early, what kind of traffic a packet is, and later stages such as a `ClassifyLink`
+/// with a `ByMark` classifier branch on the mark alone.
+pub struct SetMark<P> {
+    rules: Vec<(Predicate<P>, Mark)>,
+}
+
+impl<P> SetMark<P> {
+    pub fn new() -> Self {
+        SetMark { rules: vec![] }
+    }
+
+    /// Adds a rule marking packets for which `predicate` holds with `mark`. Rules are checked in
+    /// the order they were added.
+    pub fn rule<F>(mut self, predicate: F, mark: Mark) -> Self
+    where
+        F: Fn(&P) -> bool + Send + 'static,
+    {
+        self.rules.push((Box::new(predicate), mark));
+        self
+    }
+}
+
+impl<P> Default for SetMark<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Send + Clone> Processor for SetMark<P> {
+    type Input = P;
+    type Output = (Mark, P);
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let mark = self
+            .rules
+            .iter()
+            .find(|(predicate, _)| predicate(&packet))
+            .map_or(0, |(_, mark)| *mark);
+        Some((mark, packet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::ByMark;
+    use crate::link::primitive::{ClassifyLink, ProcessLink};
+    use crate::link::{LinkBuilder, Pipeline, ProcessLinkBuilder};
+    use crate::processor::{Annotated, FibLookup, PolicyMatch};
+    use crate::routing::{NextHop, Route, RoutingTable};
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use route_rs_packets::Ipv4Packet;
+    use std::net::Ipv4Addr;
+
+    const GUEST: Mark = 1;
+    const VOICE: Mark = 2;
+
+    const WAN: usize = 1;
+    const GUEST_WAN: usize = 2;
+
+    fn packet(src_addr: Ipv4Addr, dscp: u8) -> Ipv4Packet {
+        let mut packet =
+            Ipv4Packet::from_parts(src_addr, Ipv4Addr::new(93, 184, 216, 34), 17, 64, &[0; 8]);
+        packet.set_dscp(dscp);
+        packet
+    }
+
+    fn set_mark() -> SetMark<Ipv4Packet> {
+        let guests = PolicyMatch::new().src(Ipv4Addr::new(192, 168, 2, 0), 24);
+        let voice = PolicyMatch::new().dscp(46);
+        SetMark::new()
+            .rule(move |packet| guests.matches(packet), GUEST)
+            .rule(move |packet| voice.matches(packet), VOICE)
+    }
+
+    fn default_route(next_hop: NextHop) -> RoutingTable {
+        let table = RoutingTable::new();
+        table.insert(Route::new(Ipv4Addr::new(0, 0, 0, 0), 0, next_hop));
+        table
+    }
+
+    #[test]
+    fn marks_with_first_matching_rule() {
+        let mut elem = set_mark();
+
+        let guest = packet(Ipv4Addr::new(192, 168, 2, 10), 0);
+        assert_eq!(elem.process(guest.clone()), Some((GUEST, guest)));
+        let guest_voice = packet(Ipv4Addr::new(192, 168, 2, 10), 46);
+        assert_eq!(elem.process(guest_voice).unwrap().0, GUEST);
+        let voice = packet(Ipv4Addr::new(192, 168, 1, 10), 46);
+        assert_eq!(elem.process(voice).unwrap().0, VOICE);
+    }
+
+    #[test]
+    fn unmatched_packets_get_mark_zero() {
+        let mut elem = set_mark();
+        assert_eq!(
+            elem.process(packet(Ipv4Addr::new(192, 168, 1, 10), 0))
+                .unwrap()
+                .0,
+            0
+        );
+
+        let mut unruled = SetMark::new();
+        assert_eq!(unruled.process('a'), Some((0, 'a')));
+    }
+
+    #[test]
+    fn routes_by_mark_set_earlier() {
+        let wan = NextHop::via(Ipv4Addr::new(203, 0, 113, 1), WAN);
+        let guest_wan = NextHop::via(Ipv4Addr::new(198, 51, 100, 1), GUEST_WAN);
+        let packets = vec![
+            packet(Ipv4Addr::new(192, 168, 1, 10), 0),
+            packet(Ipv4Addr::new(192, 168, 2, 10), 0),
+            packet(Ipv4Addr::new(192, 168, 1, 11), 46),
+            packet(Ipv4Addr::new(192, 168, 2, 11), 0),
+        ];
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let (mut runnables, mut by_mark) = Pipeline::new(immediate_stream(packets))
+                .then(ProcessLink::new().processor(set_mark()))
+                .then(
+                    ClassifyLink::new()
+                        .num_egressors(2)
+                        .classifier(ByMark::new())
+                        .dispatcher(Box::new(|mark| if mark == GUEST { 1 } else { 0 })),
+                )
+                .build();
+
+            let (_, mut guest_routed) = ProcessLink::new()
+                .ingressor(by_mark.remove(1))
+                .processor(Annotated::new(FibLookup::new(default_route(guest_wan))))
+                .build_link();
+            let (mut main_runnables, mut main_routed) = Pipeline::new(by_mark.remove(0))
+                .then(
+                    ProcessLink::new()
+                        .processor(Annotated::new(FibLookup::new(default_route(wan)))),
+                )
+                .build();
+            runnables.append(&mut main_runnables);
+
+            let egressors = vec![main_routed.remove(0), guest_routed.remove(0)];
+            run_link((runnables, egressors)).await
+        });
+
+        let routed: Vec<Vec<(Mark, NextHop, Ipv4Addr)>> = results
+            .iter()
+            .map(|egressor| {
+                egressor
+                    .iter()
+                    .map(|(mark, (next_hop, packet))| (*mark, *next_hop, packet.src_addr()))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            routed,
+            vec![
+                vec![
+                    (0, wan, Ipv4Addr::new(192, 168, 1, 10)),
+                    (VOICE, wan, Ipv4Addr::new(192, 168, 1, 11)),
+                ],
+                vec![
+                    (GUEST, guest_wan, Ipv4Addr::new(192, 168, 2, 10)),
+                    (GUEST, guest_wan, Ipv4Addr::new(192, 168, 2, 11)),
+                ],
+            ]
+        );
+    }
+}
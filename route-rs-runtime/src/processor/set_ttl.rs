@@ -0,0 +1,133 @@
+use crate::processor::Processor;
+use route_rs_packets::{incremental_checksum, Ipv4Packet};
+
+/// Overwrites the TTL of every IPv4 packet with a fixed value, so that hosts beyond the router
+/// cannot tell from the TTL how many hops a packet has already taken. Unlike `DecIpv4HopLimit`,
+/// the TTL a packet arrives with has no bearing on the TTL it leaves with.
+///
+/// The header checksum is updated incrementally (RFC 1624) rather than recomputed over the whole
+/// header, so a packet that arrives with a valid checksum leaves with one.
+#[derive(Clone)]
+pub struct SetTtl {
+    ttl: u8,
+}
+
+impl SetTtl {
+    pub fn new(ttl: u8) -> Self {
+        SetTtl { ttl }
+    }
+}
+
+impl Processor for SetTtl {
+    type Input = Ipv4Packet;
+    type Output = Ipv4Packet;
+
+    fn process(&mut self, mut packet: Self::Input) -> Option<Self::Output> {
+        let offset = packet.layer3_offset;
+        // The TTL shares its 16 bit word of the header with the protocol.
+        let protocol = packet.data[offset + 9];
+        let old_word = u16::from_be_bytes([packet.ttl(), protocol]);
+        let new_word = u16::from_be_bytes([self.ttl, protocol]);
+        let checksum = incremental_checksum(packet.checksum(), old_word, new_word);
+
+        packet.set_ttl(self.ttl);
+        packet.data[offset + 10..=offset + 11].copy_from_slice(&checksum.to_be_bytes());
+        Some(packet)
+    }
+}
+
+/// Sets the TTL of IPv4 packets that have none, a TTL of 0, to a default, and leaves packets with
+/// an explicit TTL alone. Meant for the packets the router's own host stack originates, which
+/// should leave with a consistent TTL even when the stack did not set one; packets being forwarded
+/// belong to `DecIpv4HopLimit` or `SetTtl` instead.
+///
+/// The header checksum is updated incrementally, as `SetTtl` does.
+#[derive(Clone)]
+pub struct SetDefaultTtl {
+    set_ttl: SetTtl,
+}
+
+impl SetDefaultTtl {
+    pub fn new(default_ttl: u8) -> Self {
+        SetDefaultTtl {
+            set_ttl: SetTtl::new(default_ttl),
+        }
+    }
+}
+
+impl Processor for SetDefaultTtl {
+    type Input = Ipv4Packet;
+    type Output = Ipv4Packet;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        if packet.ttl() == 0 {
+            self.set_ttl.process(packet)
+        } else {
+            Some(packet)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn packet(ttl: u8) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(Ipv4Addr::new(192, 168, 1, 10));
+        packet.set_dest_addr(Ipv4Addr::new(8, 8, 8, 8));
+        packet.set_ttl(ttl);
+        packet.set_protocol(17);
+        packet.set_payload(&[0x55; 12]);
+        packet.set_checksum();
+        packet
+    }
+
+    #[test]
+    fn sets_ttl_regardless_of_input() {
+        let mut elem = SetTtl::new(64);
+
+        for ttl in [0, 1, 63, 64, 65, 128, 255].iter() {
+            let mut packet = elem.process(packet(*ttl)).unwrap();
+
+            assert_eq!(packet.ttl(), 64, "input ttl {}", ttl);
+            assert!(packet.validate_checksum(), "input ttl {}", ttl);
+            assert_eq!(packet.checksum(), packet.caclulate_checksum());
+        }
+    }
+
+    #[test]
+    fn leaves_rest_of_packet_alone() {
+        let mut elem = SetTtl::new(255);
+        let original = packet(3);
+
+        let packet = elem.process(original.clone()).unwrap();
+
+        assert_eq!(packet.src_addr(), original.src_addr());
+        assert_eq!(packet.dest_addr(), original.dest_addr());
+        assert_eq!(packet.payload(), original.payload());
+    }
+
+    #[test]
+    fn sets_default_ttl_when_unset() {
+        let mut elem = SetDefaultTtl::new(64);
+
+        let mut packet = elem.process(packet(0)).unwrap();
+
+        assert_eq!(packet.ttl(), 64);
+        assert!(packet.validate_checksum());
+    }
+
+    #[test]
+    fn keeps_explicit_ttl() {
+        let mut elem = SetDefaultTtl::new(64);
+
+        for ttl in [1, 63, 65, 255].iter() {
+            let original = packet(*ttl);
+            let packet = elem.process(original.clone()).unwrap();
+
+            assert_eq!(packet, original, "input ttl {}", ttl);
+        }
+    }
+}
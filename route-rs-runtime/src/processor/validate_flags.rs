@@ -0,0 +1,244 @@
+use crate::processor::Processor;
+use log::{trace, warn};
+use route_rs_packets::Ipv4Packet;
+
+/// A combination of IPv4 flags and fragment offset that no conforming host sends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlagViolation {
+    /// The reserved flag, the evil bit of RFC 3514, is set.
+    ReservedFlag,
+    /// Both Don't Fragment and More Fragments are set, yet a packet that may not be fragmented
+    /// can not be one of several fragments.
+    DontFragmentWithMoreFragments,
+    /// Don't Fragment is set on a packet with a fragment offset, so it is a fragment of a packet
+    /// that may not be fragmented.
+    DontFragmentWithOffset,
+}
+
+/// What a `ValidateFlags` does with a packet whose flags are malformed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlagDisposition {
+    /// Drops the packet.
+    Drop,
+    /// Passes the packet on. The first packet with each kind of violation is logged at warn level,
+    /// and the rest at trace level, so that a flood of them does not flood the log.
+    Log,
+}
+
+/// Checks the flags of IPv4 packets against RFC 791, and applies a `FlagDisposition` to packets
+/// with a `FlagViolation`, by default dropping them. Packets with valid flags pass unchanged.
+/// Whatever the disposition, the packets with malformed flags are counted.
+#[derive(Clone)]
+pub struct ValidateFlags {
+    disposition: FlagDisposition,
+    violations: u64,
+    /// The kinds of violation that have been logged at warn level.
+    warned: Vec<FlagViolation>,
+}
+
+impl ValidateFlags {
+    pub fn new() -> Self {
+        ValidateFlags {
+            disposition: FlagDisposition::Drop,
+            violations: 0,
+            warned: vec![],
+        }
+    }
+
+    /// Changes disposition, what is done with packets with malformed flags, default is `Drop`.
+    pub fn disposition(self, disposition: FlagDisposition) -> Self {
+        ValidateFlags {
+            disposition,
+            ..self
+        }
+    }
+
+    /// How many packets with malformed flags have been seen.
+    pub fn violations(&self) -> u64 {
+        self.violations
+    }
+
+    /// The first violation of `packet`, in the order `FlagViolation` lists them, if any.
+    pub fn check(packet: &Ipv4Packet) -> Option<FlagViolation> {
+        let (dont_fragment, more_fragments) = packet.flags();
+        if packet.reserved_flag() {
+            Some(FlagViolation::ReservedFlag)
+        } else if dont_fragment && more_fragments {
+            Some(FlagViolation::DontFragmentWithMoreFragments)
+        } else if dont_fragment && packet.fragment_offset() != 0 {
+            Some(FlagViolation::DontFragmentWithOffset)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for ValidateFlags {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Processor for ValidateFlags {
+    type Input = Ipv4Packet;
+    type Output = Ipv4Packet;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let violation = match ValidateFlags::check(&packet) {
+            Some(violation) => violation,
+            None => return Some(packet),
+        };
+        self.violations += 1;
+
+        match self.disposition {
+            FlagDisposition::Drop => {
+                trace!(
+                    "dropped packet from {} to {}: {:?}",
+                    packet.src_addr(),
+                    packet.dest_addr(),
+                    violation
+                );
+                None
+            }
+            FlagDisposition::Log if !self.warned.contains(&violation) => {
+                self.warned.push(violation);
+                warn!(
+                    "packet from {} to {} has malformed flags: {:?}",
+                    packet.src_addr(),
+                    packet.dest_addr(),
+                    violation
+                );
+                Some(packet)
+            }
+            FlagDisposition::Log => {
+                trace!(
+                    "packet from {} to {} has malformed flags: {:?}",
+                    packet.src_addr(),
+                    packet.dest_addr(),
+                    violation
+                );
+                Some(packet)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::logger::capture_logs;
+    use std::net::Ipv4Addr;
+
+    fn packet(
+        reserved: bool,
+        dont_fragment: bool,
+        more_fragments: bool,
+        offset: u16,
+    ) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::from_parts(
+            Ipv4Addr::new(198, 51, 100, 7),
+            Ipv4Addr::new(192, 168, 1, 10),
+            17,
+            64,
+            &[0; 8],
+        );
+        packet.set_flags(dont_fragment, more_fragments);
+        packet.set_reserved_flag(reserved);
+        packet.set_fragment_offset(offset);
+        packet.set_checksum();
+        packet
+    }
+
+    fn malformed() -> Vec<(Ipv4Packet, FlagViolation)> {
+        vec![
+            (packet(true, false, false, 0), FlagViolation::ReservedFlag),
+            (
+                packet(false, true, true, 0),
+                FlagViolation::DontFragmentWithMoreFragments,
+            ),
+            (
+                packet(false, true, false, 185),
+                FlagViolation::DontFragmentWithOffset,
+            ),
+        ]
+    }
+
+    #[test]
+    fn passes_valid_flags() {
+        let valid = vec![
+            packet(false, false, false, 0),
+            packet(false, true, false, 0),
+            packet(false, false, true, 0),
+            packet(false, false, true, 185),
+            packet(false, false, false, 185),
+        ];
+
+        let mut validate = ValidateFlags::new();
+        for packet in valid {
+            assert_eq!(ValidateFlags::check(&packet), None);
+            assert_eq!(validate.process(packet.clone()), Some(packet));
+        }
+    }
+
+    #[test]
+    fn finds_each_violation() {
+        for (packet, violation) in malformed() {
+            assert_eq!(ValidateFlags::check(&packet), Some(violation));
+        }
+    }
+
+    #[test]
+    fn drops_malformed_flags_by_default() {
+        let mut validate = ValidateFlags::new();
+        for (packet, _) in malformed() {
+            assert_eq!(validate.process(packet), None);
+        }
+        assert_eq!(validate.violations(), 3);
+    }
+
+    #[test]
+    fn logs_and_passes_malformed_flags() {
+        let mut validate = ValidateFlags::new().disposition(FlagDisposition::Log);
+
+        let (passed, records) = capture_logs(|| {
+            malformed()
+                .into_iter()
+                .map(|(packet, _)| validate.process(packet.clone()) == Some(packet))
+                .collect::<Vec<bool>>()
+        });
+
+        assert_eq!(passed, vec![true, true, true]);
+        let warnings: Vec<&str> = records
+            .iter()
+            .filter(|record| record.level == log::Level::Warn)
+            .map(|record| record.message.as_str())
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "packet from 198.51.100.7 to 192.168.1.10 has malformed flags: ReservedFlag",
+                "packet from 198.51.100.7 to 192.168.1.10 has malformed flags: DontFragmentWithMoreFragments",
+                "packet from 198.51.100.7 to 192.168.1.10 has malformed flags: DontFragmentWithOffset",
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_once_per_violation() {
+        let mut validate = ValidateFlags::new().disposition(FlagDisposition::Log);
+        let evil = packet(true, false, false, 0);
+
+        let (_, records) = capture_logs(|| {
+            for _ in 0..100 {
+                validate.process(evil.clone());
+            }
+        });
+
+        let warnings = records
+            .iter()
+            .filter(|record| record.level == log::Level::Warn)
+            .count();
+        assert_eq!(warnings, 1);
+        assert_eq!(validate.violations(), 100);
+    }
+}
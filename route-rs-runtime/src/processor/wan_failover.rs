@@ -0,0 +1,228 @@
+use crate::processor::Processor;
+use crate::routing::NextHop;
+use log::trace;
+use route_rs_packets::Ipv4Packet;
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// One of the two uplinks of a `WanFailover`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WanRole {
+    Primary,
+    Backup,
+}
+
+/// An uplink: where outbound packets go to leave through it, and the address NAT should translate
+/// their source to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Wan {
+    pub next_hop: NextHop,
+    pub nat_source: Ipv4Addr,
+}
+
+impl Wan {
+    pub fn new(next_hop: NextHop, nat_source: Ipv4Addr) -> Self {
+        Wan {
+            next_hop,
+            nat_source,
+        }
+    }
+}
+
+/// A handle to the health of the uplinks of a `WanFailover`, for whatever runs the health checks
+/// to report through. Clones share the same flags, so a change is seen by the failover starting
+/// with the next packet. Both uplinks start out up.
+#[derive(Clone)]
+pub struct WanHealth {
+    primary: Arc<AtomicBool>,
+    backup: Arc<AtomicBool>,
+}
+
+impl WanHealth {
+    fn new() -> Self {
+        WanHealth {
+            primary: Arc::new(AtomicBool::new(true)),
+            backup: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    fn flag(&self, wan: WanRole) -> &AtomicBool {
+        match wan {
+            WanRole::Primary => &self.primary,
+            WanRole::Backup => &self.backup,
+        }
+    }
+
+    /// Reports whether `wan` passed its last health check.
+    pub fn set_up(&self, wan: WanRole, up: bool) {
+        self.flag(wan).store(up, Ordering::Relaxed);
+    }
+
+    pub fn is_up(&self, wan: WanRole) -> bool {
+        self.flag(wan).load(Ordering::Relaxed)
+    }
+
+    /// The uplink outbound traffic should take: the primary while it is up, and the backup while
+    /// only the backup is. If neither is up the primary is used, since the health checks can not
+    /// tell which will recover first.
+    pub fn active(&self) -> WanRole {
+        if !self.is_up(WanRole::Primary) && self.is_up(WanRole::Backup) {
+            WanRole::Backup
+        } else {
+            WanRole::Primary
+        }
+    }
+}
+
+/// Sends outbound traffic over a primary uplink, and fails over to a backup uplink while the
+/// primary is down. Each packet is tagged with the `Wan` it should leave through, for the links
+/// after it to route it by `next_hop` and translate its source to `nat_source`. Traffic returns
+/// to the primary as soon as it is reported up again.
+///
+/// The failover does no health checking of its own: the health of each uplink is reported through
+/// the `WanHealth` handle returned by `health`.
+pub struct WanFailover {
+    primary: Wan,
+    backup: Wan,
+    health: WanHealth,
+    active: WanRole,
+}
+
+impl WanFailover {
+    pub fn new(primary: Wan, backup: Wan) -> Self {
+        WanFailover {
+            primary,
+            backup,
+            health: WanHealth::new(),
+            active: WanRole::Primary,
+        }
+    }
+
+    /// Returns a handle to report the health of the uplinks through, which remains valid after the
+    /// failover is handed to a link.
+    pub fn health(&self) -> WanHealth {
+        self.health.clone()
+    }
+
+    /// The uplink that packets are currently sent over.
+    pub fn active(&self) -> WanRole {
+        self.health.active()
+    }
+}
+
+impl Processor for WanFailover {
+    type Input = Ipv4Packet;
+    type Output = (Wan, Ipv4Packet);
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let active = self.health.active();
+        if active != self.active {
+            trace!("failed over from {:?} WAN to {:?} WAN", self.active, active);
+            self.active = active;
+        }
+
+        let wan = match active {
+            WanRole::Primary => self.primary,
+            WanRole::Backup => self.backup,
+        };
+        Some((wan, packet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::primitive::ProcessLink;
+    use crate::link::{LinkBuilder, ProcessLinkBuilder};
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+
+    const PRIMARY: Wan = Wan {
+        next_hop: NextHop {
+            gateway: Some(Ipv4Addr::new(198, 51, 100, 1)),
+            interface: 1,
+        },
+        nat_source: Ipv4Addr::new(198, 51, 100, 20),
+    };
+    const BACKUP: Wan = Wan {
+        next_hop: NextHop {
+            gateway: Some(Ipv4Addr::new(203, 0, 113, 1)),
+            interface: 2,
+        },
+        nat_source: Ipv4Addr::new(203, 0, 113, 20),
+    };
+
+    fn outbound() -> Ipv4Packet {
+        Ipv4Packet::from_parts(
+            Ipv4Addr::new(192, 168, 1, 10),
+            Ipv4Addr::new(93, 184, 216, 34),
+            17,
+            64,
+            &[0; 8],
+        )
+    }
+
+    fn wan_of(elem: &mut WanFailover) -> Wan {
+        let (wan, packet) = elem.process(outbound()).unwrap();
+        assert_eq!(packet, outbound());
+        wan
+    }
+
+    #[test]
+    fn uses_primary_while_up() {
+        let mut elem = WanFailover::new(PRIMARY, BACKUP);
+
+        assert_eq!(wan_of(&mut elem), PRIMARY);
+        assert_eq!(elem.active(), WanRole::Primary);
+    }
+
+    #[test]
+    fn fails_over_to_backup_and_back() {
+        let mut elem = WanFailover::new(PRIMARY, BACKUP);
+        let health = elem.health();
+
+        health.set_up(WanRole::Primary, false);
+        let wan = wan_of(&mut elem);
+        assert_eq!(wan, BACKUP);
+        assert_eq!(wan.nat_source, Ipv4Addr::new(203, 0, 113, 20));
+        assert_eq!(elem.active(), WanRole::Backup);
+
+        health.set_up(WanRole::Primary, true);
+        assert_eq!(wan_of(&mut elem), PRIMARY);
+    }
+
+    #[test]
+    fn stays_on_primary_when_both_down() {
+        let mut elem = WanFailover::new(PRIMARY, BACKUP);
+        let health = elem.health();
+
+        health.set_up(WanRole::Backup, false);
+        assert_eq!(wan_of(&mut elem), PRIMARY);
+
+        health.set_up(WanRole::Primary, false);
+        assert_eq!(wan_of(&mut elem), PRIMARY);
+    }
+
+    #[test]
+    fn health_reported_while_running() {
+        let elem = WanFailover::new(PRIMARY, BACKUP);
+        let health = elem.health();
+        health.set_up(WanRole::Primary, false);
+
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let link = ProcessLink::new()
+                .ingressor(immediate_stream(vec![outbound(), outbound()]))
+                .processor(elem)
+                .build_link();
+
+            run_link(link).await
+        });
+
+        for (wan, _) in &results[0] {
+            assert_eq!(*wan, BACKUP);
+        }
+        assert_eq!(results[0].len(), 2);
+    }
+}
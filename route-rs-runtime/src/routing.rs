@@ -0,0 +1,394 @@
+//! # What is it for?
+//!
+//! The routing table, or FIB, maps destination prefixes to where packets for them should go next.
+//! A router builds one `RoutingTable` and hands clones of it both to the processors that look
+//! routes up, such as `FibLookup`, and to whatever maintains the routes, such as a routing daemon.
+//! Since clones share the same table, routes can be changed while the pipeline runs, without
+//! rebuilding any links.
+
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Where packets matching a route are sent next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NextHop {
+    /// The router to forward to, or `None` if the destination is directly connected.
+    pub gateway: Option<Ipv4Addr>,
+    /// The egress interface, identified by the same index used to tag packets.
+    pub interface: usize,
+}
+
+impl NextHop {
+    /// A route through the router `gateway`, reached over `interface`.
+    pub fn via(gateway: Ipv4Addr, interface: usize) -> Self {
+        NextHop {
+            gateway: Some(gateway),
+            interface,
+        }
+    }
+
+    /// A route to hosts directly connected to `interface`.
+    pub fn connected(interface: usize) -> Self {
+        NextHop {
+            gateway: None,
+            interface,
+        }
+    }
+}
+
+/// A route, as a prefix and where packets matching it go next. A route with several next hops
+/// is an equal cost multipath route: each flow is sent through one of them. A route with no next
+/// hops is a blackhole: packets matching it are dropped.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Route {
+    pub prefix: Ipv4Addr,
+    pub prefix_len: u8,
+    pub next_hops: Vec<NextHop>,
+}
+
+impl Route {
+    pub fn new(prefix: Ipv4Addr, prefix_len: u8, next_hop: NextHop) -> Self {
+        Route::multipath(prefix, prefix_len, vec![next_hop])
+    }
+
+    /// A null route, that drops every packet to the prefix. Since the most specific route wins,
+    /// a blackhole can cut a single host or subnet out of a wider route.
+    pub fn blackhole(prefix: Ipv4Addr, prefix_len: u8) -> Self {
+        assert!(
+            prefix_len <= 32,
+            "prefix_len: {}, must be <= 32",
+            prefix_len
+        );
+        Route {
+            prefix: Ipv4Addr::from(u32::from(prefix) & mask(prefix_len)),
+            prefix_len,
+            next_hops: vec![],
+        }
+    }
+
+    /// A route that balances flows across the equal cost `next_hops`.
+    pub fn multipath(prefix: Ipv4Addr, prefix_len: u8, next_hops: Vec<NextHop>) -> Self {
+        assert!(
+            !next_hops.is_empty(),
+            "Route must have at least one next hop, use Route::blackhole to drop"
+        );
+        Route {
+            next_hops,
+            ..Route::blackhole(prefix, prefix_len)
+        }
+    }
+
+    pub fn is_blackhole(&self) -> bool {
+        self.next_hops.is_empty()
+    }
+}
+
+pub(crate) fn mask(prefix_len: u8) -> u32 {
+    match prefix_len {
+        0 => 0,
+        len => u32::MAX << (32 - len),
+    }
+}
+
+/// The next hops of a route in the table, and how many packets it has dropped if it is a
+/// blackhole.
+struct Entry {
+    next_hops: Vec<NextHop>,
+    drops: AtomicU64,
+}
+
+impl Entry {
+    fn new(next_hops: Vec<NextHop>) -> Self {
+        Entry {
+            next_hops,
+            drops: AtomicU64::new(0),
+        }
+    }
+}
+
+/// One map per prefix length, from masked prefix to route.
+type Prefixes = Vec<HashMap<u32, Entry>>;
+
+/// The most specific route in `prefixes` that covers `addr`, as its prefix length and entry.
+fn longest_match(prefixes: &Prefixes, addr: Ipv4Addr) -> Option<(u8, &Entry)> {
+    let addr = u32::from(addr);
+    (0..=32u8).rev().find_map(|len| {
+        prefixes[len as usize]
+            .get(&(addr & mask(len)))
+            .map(|entry| (len, entry))
+    })
+}
+
+fn prefixes(routes: &[Route]) -> Prefixes {
+    let mut prefixes: Prefixes = (0..=32).map(|_| HashMap::new()).collect();
+    for route in routes {
+        prefixes[route.prefix_len as usize]
+            .insert(u32::from(route.prefix), Entry::new(route.next_hops.clone()));
+    }
+    prefixes
+}
+
+/// A longest prefix match table of IPv4 routes. Clones share the same table, so routes changed
+/// through one handle are seen by every processor holding another, starting with the next lookup.
+#[derive(Clone)]
+pub struct RoutingTable {
+    prefixes: Arc<RwLock<Prefixes>>,
+}
+
+impl RoutingTable {
+    pub fn new() -> Self {
+        RoutingTable {
+            prefixes: Arc::new(RwLock::new(prefixes(&[]))),
+        }
+    }
+
+    /// Adds `route`, replacing any route to the same prefix.
+    pub fn insert(&self, route: Route) {
+        self.prefixes.write().unwrap()[route.prefix_len as usize]
+            .insert(u32::from(route.prefix), Entry::new(route.next_hops));
+    }
+
+    /// The next hops of the route to exactly `prefix`/`prefix_len`, if there is one. Blackhole
+    /// routes have no next hops.
+    pub fn get(&self, prefix: Ipv4Addr, prefix_len: u8) -> Option<Vec<NextHop>> {
+        let route = Route::blackhole(prefix, prefix_len);
+        self.prefixes.read().unwrap()[prefix_len as usize]
+            .get(&u32::from(route.prefix))
+            .map(|entry| entry.next_hops.clone())
+    }
+
+    /// Removes the route to `prefix`/`prefix_len`, returning its next hops if there was one.
+    pub fn remove(&self, prefix: Ipv4Addr, prefix_len: u8) -> Option<Vec<NextHop>> {
+        let route = Route::blackhole(prefix, prefix_len);
+        self.prefixes.write().unwrap()[prefix_len as usize]
+            .remove(&u32::from(route.prefix))
+            .map(|entry| entry.next_hops)
+    }
+
+    /// How many lookups the blackhole route to exactly `prefix`/`prefix_len` has dropped, if
+    /// there is such a route.
+    pub fn drops(&self, prefix: Ipv4Addr, prefix_len: u8) -> Option<u64> {
+        let route = Route::blackhole(prefix, prefix_len);
+        self.prefixes.read().unwrap()[prefix_len as usize]
+            .get(&u32::from(route.prefix))
+            .filter(|entry| entry.next_hops.is_empty())
+            .map(|entry| entry.drops.load(Ordering::Relaxed))
+    }
+
+    /// Replaces every route in the table at once. Lookups see either the old routes or the new
+    /// ones, never a mix of both.
+    pub fn replace(&self, routes: &[Route]) {
+        let replacement = prefixes(routes);
+        *self.prefixes.write().unwrap() = replacement;
+    }
+
+    /// The next hop of the most specific route that covers `addr`. If the route has several
+    /// next hops, `flow_hash` picks one, so packets with the same hash always take the same path.
+    /// If the route is a blackhole, a drop is counted against it and `None` is returned, just as
+    /// when there is no route at all.
+    pub fn lookup(&self, addr: Ipv4Addr, flow_hash: u64) -> Option<NextHop> {
+        let prefixes = self.prefixes.read().unwrap();
+        let (_, entry) = longest_match(&prefixes, addr)?;
+        if entry.next_hops.is_empty() {
+            entry.drops.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        let next_hops = &entry.next_hops;
+        Some(next_hops[(flow_hash % next_hops.len() as u64) as usize])
+    }
+
+    /// The most specific route that covers `addr`, if there is one. Unlike `lookup`, it is only a
+    /// query: no drop is counted against a blackhole route, and no next hop is picked.
+    pub fn route_to(&self, addr: Ipv4Addr) -> Option<Route> {
+        let prefixes = self.prefixes.read().unwrap();
+        let (prefix_len, entry) = longest_match(&prefixes, addr)?;
+        Some(Route {
+            prefix: Ipv4Addr::from(u32::from(addr) & mask(prefix_len)),
+            prefix_len,
+            next_hops: entry.next_hops.clone(),
+        })
+    }
+
+    /// Every route in the table, most specific first, then by prefix.
+    pub fn routes(&self) -> Vec<Route> {
+        let prefixes = self.prefixes.read().unwrap();
+        let mut routes: Vec<Route> = prefixes
+            .iter()
+            .enumerate()
+            .flat_map(|(len, routes)| {
+                routes.iter().map(move |(prefix, entry)| Route {
+                    prefix: Ipv4Addr::from(*prefix),
+                    prefix_len: len as u8,
+                    next_hops: entry.next_hops.clone(),
+                })
+            })
+            .collect();
+        routes.sort_by_key(|route| (std::cmp::Reverse(route.prefix_len), route.prefix));
+        routes
+    }
+}
+
+impl Default for RoutingTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAN: usize = 0;
+    const WAN: usize = 1;
+    const ISP: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 1);
+
+    fn home_router() -> RoutingTable {
+        let table = RoutingTable::new();
+        table.insert(Route::new(
+            Ipv4Addr::new(0, 0, 0, 0),
+            0,
+            NextHop::via(ISP, WAN),
+        ));
+        table.insert(Route::new(
+            Ipv4Addr::new(192, 168, 1, 0),
+            24,
+            NextHop::connected(LAN),
+        ));
+        table
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let table = home_router();
+        table.insert(Route::new(
+            Ipv4Addr::new(192, 168, 1, 128),
+            25,
+            NextHop::via(Ipv4Addr::new(192, 168, 1, 2), LAN),
+        ));
+
+        assert_eq!(
+            table.lookup(Ipv4Addr::new(192, 168, 1, 10), 0),
+            Some(NextHop::connected(LAN))
+        );
+        assert_eq!(
+            table.lookup(Ipv4Addr::new(192, 168, 1, 200), 0),
+            Some(NextHop::via(Ipv4Addr::new(192, 168, 1, 2), LAN))
+        );
+        assert_eq!(
+            table.lookup(Ipv4Addr::new(8, 8, 8, 8), 0),
+            Some(NextHop::via(ISP, WAN))
+        );
+    }
+
+    #[test]
+    fn no_route_without_default() {
+        let table = home_router();
+        assert_eq!(
+            table.remove(Ipv4Addr::new(0, 0, 0, 0), 0),
+            Some(vec![NextHop::via(ISP, WAN)])
+        );
+
+        assert_eq!(table.lookup(Ipv4Addr::new(8, 8, 8, 8), 0), None);
+        assert_eq!(table.remove(Ipv4Addr::new(0, 0, 0, 0), 0), None);
+    }
+
+    #[test]
+    fn masks_host_bits() {
+        let route = Route::new(Ipv4Addr::new(10, 1, 2, 3), 8, NextHop::connected(LAN));
+        assert_eq!(route.prefix, Ipv4Addr::new(10, 0, 0, 0));
+
+        let table = RoutingTable::new();
+        table.insert(route);
+        assert!(table.remove(Ipv4Addr::new(10, 9, 9, 9), 8).is_some());
+    }
+
+    #[test]
+    fn replace_swaps_all_routes() {
+        let table = home_router();
+        let handle = table.clone();
+
+        handle.replace(&[Route::new(
+            Ipv4Addr::new(10, 0, 0, 0),
+            8,
+            NextHop::connected(WAN),
+        )]);
+
+        assert_eq!(table.lookup(Ipv4Addr::new(192, 168, 1, 10), 0), None);
+        assert_eq!(
+            table.routes(),
+            vec![Route::new(
+                Ipv4Addr::new(10, 0, 0, 0),
+                8,
+                NextHop::connected(WAN)
+            )]
+        );
+    }
+
+    #[test]
+    fn flow_hash_picks_among_equal_cost_next_hops() {
+        let table = RoutingTable::new();
+        let next_hops = vec![
+            NextHop::via(ISP, WAN),
+            NextHop::via(Ipv4Addr::new(198, 51, 100, 1), LAN),
+        ];
+        table.insert(Route::multipath(
+            Ipv4Addr::new(0, 0, 0, 0),
+            0,
+            next_hops.clone(),
+        ));
+
+        let addr = Ipv4Addr::new(8, 8, 8, 8);
+        assert_eq!(table.lookup(addr, 0), Some(next_hops[0]));
+        assert_eq!(table.lookup(addr, 1), Some(next_hops[1]));
+        assert_eq!(table.lookup(addr, 7), Some(next_hops[1]));
+    }
+
+    #[test]
+    fn blackhole_drops_and_counts() {
+        let table = home_router();
+        let victim = Ipv4Addr::new(192, 168, 1, 66);
+        table.insert(Route::blackhole(victim, 32));
+
+        assert_eq!(table.lookup(victim, 0), None);
+        assert_eq!(table.lookup(victim, 1), None);
+        assert_eq!(table.drops(victim, 32), Some(2));
+        assert_eq!(
+            table.lookup(Ipv4Addr::new(192, 168, 1, 67), 0),
+            Some(NextHop::connected(LAN))
+        );
+        assert_eq!(table.drops(Ipv4Addr::new(192, 168, 1, 0), 24), None);
+        assert_eq!(table.get(victim, 32), Some(vec![]));
+    }
+
+    #[test]
+    fn route_to_does_not_count_drops() {
+        let table = home_router();
+        let victim = Ipv4Addr::new(192, 168, 1, 66);
+        table.insert(Route::blackhole(victim, 32));
+
+        assert_eq!(table.route_to(victim), Some(Route::blackhole(victim, 32)));
+        assert_eq!(table.drops(victim, 32), Some(0));
+        assert_eq!(
+            table.route_to(Ipv4Addr::new(192, 168, 1, 67)),
+            Some(Route::new(
+                Ipv4Addr::new(192, 168, 1, 0),
+                24,
+                NextHop::connected(LAN)
+            ))
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_without_next_hops() {
+        Route::multipath(Ipv4Addr::new(10, 0, 0, 0), 8, vec![]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_on_long_prefix() {
+        Route::new(Ipv4Addr::new(10, 0, 0, 0), 33, NextHop::connected(LAN));
+    }
+}
@@ -2,3 +2,6 @@
 pub mod test;
 
 pub mod runner;
+
+#[cfg(feature = "sync-runner")]
+pub mod sync_runner;
@@ -0,0 +1,289 @@
+use crate::link::{Link, TokioRunnable};
+use crate::utils::test::packet_collectors::ExhaustiveCollector;
+use crossbeam::crossbeam_channel;
+use futures::prelude::*;
+use futures::task::{waker, ArcWake, Context, Poll, Waker};
+use std::fmt::Debug;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, Thread};
+
+/// Marks its task to be polled again when woken, and unparks the thread running the tasks.
+struct WakeFlag {
+    woken: AtomicBool,
+    runner: Arc<Mutex<Thread>>,
+}
+
+impl ArcWake for WakeFlag {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.woken.store(true, Ordering::SeqCst);
+        arc_self.runner.lock().unwrap().unpark();
+    }
+}
+
+struct Task {
+    runnable: TokioRunnable,
+    flag: Arc<WakeFlag>,
+    waker: Waker,
+}
+
+/// `SyncRunner` drives the runnables of a router on the calling thread, without a Tokio runtime,
+/// for targets where a single threaded poll loop is all there is. It does not remove the
+/// dependency on the `tokio` crate, which the rest of route-rs-runtime builds on. It is a cooperative
+/// scheduler: each call to `poll_once` polls every runnable that has been woken since it was last
+/// polled, in the order the runnables were given, and a runnable runs until it returns
+/// `Pending`.
+///
+/// Only links built from futures and channels can run this way, which covers the links that move
+/// packets, such as `ProcessLink`, `QueueLink`, `ClassifyLink`, `JoinLink` and `ForkLink`. Links
+/// that wait on Tokio's timer or sockets, such as `DelayLink` or `RawSocketLink`, need a Tokio
+/// runtime.
+///
+/// Available with the `sync-runner` feature.
+pub struct SyncRunner {
+    tasks: Vec<Task>,
+    /// The thread that `run` parks, shared with the wakers of the tasks.
+    runner: Arc<Mutex<Thread>>,
+}
+
+impl SyncRunner {
+    pub fn new(runnables: Vec<TokioRunnable>) -> Self {
+        let runner = Arc::new(Mutex::new(thread::current()));
+        let tasks = runnables
+            .into_iter()
+            .map(|runnable| {
+                // Every runnable is polled once to begin with.
+                let flag = Arc::new(WakeFlag {
+                    woken: AtomicBool::new(true),
+                    runner: Arc::clone(&runner),
+                });
+                Task {
+                    runnable,
+                    waker: waker(Arc::clone(&flag)),
+                    flag,
+                }
+            })
+            .collect();
+        SyncRunner { tasks, runner }
+    }
+
+    /// Polls every runnable that has been woken once, and forgets the runnables that complete.
+    /// Returns whether any runnable was polled; if none was, every runnable left is waiting on
+    /// something outside the runner, such as a channel fed by another thread.
+    pub fn poll_once(&mut self) -> bool {
+        let mut polled = false;
+        self.tasks.retain_mut(|task| {
+            if !task.flag.woken.swap(false, Ordering::SeqCst) {
+                return true;
+            }
+            polled = true;
+            let mut cx = Context::from_waker(&task.waker);
+            Pin::new(&mut task.runnable).poll(&mut cx) == Poll::Pending
+        });
+        polled
+    }
+
+    /// Whether every runnable has completed.
+    pub fn is_finished(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Polls the runnables until they have all completed, parking the thread whenever none of
+    /// them is ready to make progress, until one is woken.
+    pub fn run(&mut self) {
+        *self.runner.lock().unwrap() = thread::current();
+        while !self.is_finished() {
+            if !self.poll_once() {
+                thread::park();
+            }
+        }
+    }
+}
+
+/// Like `runner`, but runs `link` to completion on the calling thread with a `SyncRunner`, rather
+/// than on a Tokio runtime. The packets that come out of each egressor of the link are returned.
+pub fn run_link_sync<OutputPacket: Debug + Send + Clone + 'static>(
+    link: Link<OutputPacket>,
+) -> Vec<Vec<OutputPacket>> {
+    let (mut runnables, egressors) = link;
+
+    let (mut consumers, receivers): (
+        Vec<TokioRunnable>,
+        Vec<crossbeam_channel::Receiver<OutputPacket>>,
+    ) = egressors
+        .into_iter()
+        .map(|egressor| {
+            let (s, r) = crossbeam_channel::unbounded::<OutputPacket>();
+            let consumer: TokioRunnable = Box::new(ExhaustiveCollector::new(0, egressor, s));
+            (consumer, r)
+        })
+        .unzip();
+
+    runnables.append(&mut consumers);
+    SyncRunner::new(runnables).run();
+
+    receivers
+        .into_iter()
+        .map(|receiver| receiver.try_iter().collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::Classifier;
+    use crate::link::primitive::{ClassifyLink, JoinLink, ProcessLink, QueueLink};
+    use crate::link::{LinkBuilder, Pipeline, ProcessLinkBuilder};
+    use crate::processor::{DecIpv4HopLimit, ValidateFlags};
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use route_rs_packets::Ipv4Packet;
+    use std::net::Ipv4Addr;
+
+    const LAN: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 0);
+
+    /// Whether a packet is bound for the LAN, or elsewhere.
+    struct ToLan;
+
+    impl Classifier for ToLan {
+        type Packet = Ipv4Packet;
+        type Class = bool;
+
+        fn classify(&self, packet: &Self::Packet) -> Self::Class {
+            packet.dest_addr().octets()[0..3] == LAN.octets()[0..3]
+        }
+    }
+
+    fn packets() -> Vec<Ipv4Packet> {
+        (0..200u8)
+            .map(|n| {
+                let dest_addr = if n % 3 == 0 {
+                    Ipv4Addr::new(192, 168, 1, n)
+                } else {
+                    Ipv4Addr::new(8, 8, n, 8)
+                };
+                let mut packet =
+                    Ipv4Packet::from_parts(Ipv4Addr::new(10, 0, 0, 1), dest_addr, 17, n, &[n; 8]);
+                // Every seventh packet has the evil bit set.
+                if n % 7 == 0 {
+                    packet.set_reserved_flag(true);
+                    packet.set_checksum();
+                }
+                packet
+            })
+            .collect()
+    }
+
+    /// Validates, decrements the TTL of, and routes IPv4 packets to the LAN or the WAN, through
+    /// several queues, and joins the WAN packets from two sources.
+    fn handle_ipv4(packets: Vec<Ipv4Packet>) -> Link<Ipv4Packet> {
+        let (mut runnables, mut routed) = Pipeline::new(immediate_stream(packets))
+            .then(ProcessLink::new().processor(ValidateFlags::new()))
+            .then(QueueLink::new().processor(DecIpv4HopLimit::new()))
+            .then(
+                ClassifyLink::new()
+                    .num_egressors(2)
+                    .classifier(ToLan)
+                    .dispatcher(Box::new(|to_lan| if to_lan { 0 } else { 1 })),
+            )
+            .build();
+
+        let wan = routed.remove(1);
+        let lan = routed.remove(0);
+        let (mut join_runnables, wan) = JoinLink::new()
+            .ingressor(wan)
+            .ingressor(immediate_stream(vec![]))
+            .build_link();
+        runnables.append(&mut join_runnables);
+        let (mut queue_runnables, mut lan) = QueueLink::new()
+            .ingressor(lan)
+            .processor(DecIpv4HopLimit::new())
+            .build_link();
+        runnables.append(&mut queue_runnables);
+
+        (
+            runnables,
+            vec![lan.remove(0), wan.into_iter().next().unwrap()],
+        )
+    }
+
+    #[test]
+    fn matches_tokio() {
+        let mut runtime = initialize_runtime();
+        let expected = runtime.block_on(async { run_link(handle_ipv4(packets())).await });
+
+        let results = run_link_sync(handle_ipv4(packets()));
+
+        assert_eq!(results, expected);
+        assert_eq!(results[0].len() + results[1].len(), 200 - 29);
+    }
+
+    #[test]
+    fn runs_without_runtime() {
+        // There is no Tokio runtime on this thread, so anything needing one would panic.
+        let results = run_link_sync(
+            QueueLink::new()
+                .ingressor(immediate_stream(0..1000))
+                .processor(crate::processor::Identity::new())
+                .queue_capacity(1)
+                .build_link(),
+        );
+
+        assert_eq!(results[0], (0..1000).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn poll_once_only_polls_woken_runnables() {
+        let (sender, receiver) = futures::channel::mpsc::unbounded::<u32>();
+        let (runnables, mut egressors) = QueueLink::new()
+            .ingressor(Box::new(receiver))
+            .processor(crate::processor::Identity::new())
+            .build_link();
+        let mut runner = SyncRunner::new(runnables);
+
+        assert!(runner.poll_once());
+        // The queue is waiting on its input, so nothing is woken.
+        assert!(!runner.poll_once());
+        assert!(!runner.is_finished());
+
+        sender.unbounded_send(7).unwrap();
+        assert!(runner.poll_once());
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        assert_eq!(
+            Pin::new(&mut egressors[0]).poll_next(&mut cx),
+            Poll::Ready(Some(7))
+        );
+
+        drop(sender);
+        assert!(runner.poll_once());
+        assert!(runner.is_finished());
+    }
+
+    #[test]
+    fn run_is_woken_from_another_thread() {
+        let (sender, receiver) = futures::channel::mpsc::unbounded::<u32>();
+        let (mut runnables, egressors) = QueueLink::new()
+            .ingressor(Box::new(receiver))
+            .processor(crate::processor::Identity::new())
+            .build_link();
+        let (collected, received) = crossbeam_channel::unbounded();
+        runnables.push(Box::new(ExhaustiveCollector::new(
+            0,
+            egressors.into_iter().next().unwrap(),
+            collected,
+        )));
+        let mut runner = SyncRunner::new(runnables);
+
+        let feeder = thread::spawn(move || {
+            for n in 0..3 {
+                thread::sleep(std::time::Duration::from_millis(10));
+                sender.unbounded_send(n).unwrap();
+            }
+        });
+        runner.run();
+        feeder.join().unwrap();
+
+        assert_eq!(received.try_iter().collect::<Vec<u32>>(), vec![0, 1, 2]);
+    }
+}
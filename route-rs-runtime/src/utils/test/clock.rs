@@ -0,0 +1,34 @@
+use crate::link::utils::clock::Clock;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A `Clock` that only moves forward when told to. Clones share the same time, so a test can keep
+/// one handle to advance the clock while the link under test holds another.
+#[derive(Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
@@ -1,7 +1,12 @@
-use crate::link::{Link, TokioRunnable};
+use crate::link::{Link, PacketStream, TokioRunnable};
 use crate::utils::test::packet_collectors::ExhaustiveCollector;
 use crossbeam::crossbeam_channel;
+use futures::future::poll_fn;
+use futures::prelude::*;
+use futures::task::Poll;
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::time::Duration;
 use tokio::runtime;
 
 /// The utils::test::harness module should be able to help Link authors abstract away the
@@ -31,6 +36,18 @@ pub fn initialize_runtime() -> runtime::Runtime {
         .unwrap()
 }
 
+/// Like `initialize_runtime`, but every task runs on the calling thread. Runnables are polled in
+/// the order they were spawned, and a task that is woken goes to the back of a single run queue,
+/// so two runs of the same Link interleave their runnables identically. Use this for tests whose
+/// expected output depends on how runnables are scheduled relative to each other.
+pub fn initialize_runtime_deterministic() -> runtime::Runtime {
+    runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
 pub async fn run_link<OutputPacket: Debug + Send + Clone + 'static>(
     link: Link<OutputPacket>,
 ) -> Vec<Vec<OutputPacket>> {
@@ -63,6 +80,50 @@ pub async fn run_link<OutputPacket: Debug + Send + Clone + 'static>(
         .collect()
 }
 
+/// Like `run_link`, but the output of each egressor is keyed on a label rather than on its
+/// position, so tests can refer to egressors by name. `labels[i]` names the i-th egressor of the
+/// Link, and there must be exactly one label per egressor.
+pub async fn run_link_named<OutputPacket: Debug + Send + Clone + 'static>(
+    link: Link<OutputPacket>,
+    labels: &[&str],
+) -> HashMap<String, Vec<OutputPacket>> {
+    assert_eq!(
+        link.1.len(),
+        labels.len(),
+        "Link has {} egressors, but {} labels were provided",
+        link.1.len(),
+        labels.len()
+    );
+
+    labels
+        .iter()
+        .map(|label| label.to_string())
+        .zip(run_link(link).await)
+        .collect()
+}
+
+/// Like `run_link`, but fails the test if the Link's runnables and egressors have not all
+/// completed within `timeout`, rather than hanging forever. Use this to guard against deadlocks.
+pub async fn run_link_with_timeout<OutputPacket: Debug + Send + Clone + 'static>(
+    link: Link<OutputPacket>,
+    timeout: Duration,
+) -> Vec<Vec<OutputPacket>> {
+    match tokio::time::timeout(timeout, run_link(link)).await {
+        Ok(results) => results,
+        Err(_) => panic!(
+            "Link did not complete within {:?}, it may be deadlocked or waiting on input that never ends",
+            timeout
+        ),
+    }
+}
+
+/// Polls `egressor` once, returning whatever it yields, `Poll::Pending` included. Tests of links
+/// that hold packets back, driven by a `ManualClock`, use this to check what a link releases at
+/// each step rather than running it to completion.
+pub async fn poll_once<T>(egressor: &mut PacketStream<T>) -> Poll<Option<T>> {
+    poll_fn(|cx| Poll::Ready(egressor.poll_next_unpin(cx))).await
+}
+
 async fn spawn_runnables(runnables: Vec<TokioRunnable>) {
     let mut handles = vec![];
     for runnable in runnables {
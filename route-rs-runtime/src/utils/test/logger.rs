@@ -0,0 +1,60 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::cell::RefCell;
+use std::sync::Once;
+
+/// A log record, as `capture_logs` saw it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapturedRecord {
+    pub level: Level,
+    /// The module the record was logged from, such as `route_rs_runtime::link::primitive::classify_link`.
+    pub target: String,
+    pub message: String,
+}
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Vec<CapturedRecord>>> = const { RefCell::new(None) };
+}
+
+/// Saves records logged on threads that are capturing, and ignores everything else.
+struct CapturingLogger;
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        CAPTURED.with(|captured| {
+            if let Some(records) = captured.borrow_mut().as_mut() {
+                records.push(CapturedRecord {
+                    level: record.level(),
+                    target: record.target().to_string(),
+                    message: record.args().to_string(),
+                });
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger;
+static INSTALL: Once = Once::new();
+
+/// Runs `f`, and returns what it returned along with every record it logged, at any level.
+///
+/// Records are captured per thread, so that tests running in parallel do not see each other's
+/// records, which means links must run on the calling thread: build the runtime with
+/// `initialize_runtime_deterministic`. The first call installs the capturing logger as the global
+/// logger, so it can not be combined with any other logger.
+pub fn capture_logs<R>(f: impl FnOnce() -> R) -> (R, Vec<CapturedRecord>) {
+    INSTALL.call_once(|| {
+        log::set_logger(&LOGGER).expect("capture_logs: another logger is already installed");
+        log::set_max_level(LevelFilter::Trace);
+    });
+
+    CAPTURED.with(|captured| *captured.borrow_mut() = Some(vec![]));
+    let result = f();
+    let records = CAPTURED.with(|captured| captured.borrow_mut().take().unwrap());
+    (result, records)
+}
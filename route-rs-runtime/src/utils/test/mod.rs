@@ -1,3 +1,7 @@
+pub mod clock;
 pub mod harness;
+pub mod logger;
 pub mod packet_collectors;
 pub mod packet_generators;
+pub mod sequence_checker;
+pub mod trace;
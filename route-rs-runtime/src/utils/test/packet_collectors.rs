@@ -4,6 +4,8 @@ use futures::prelude::*;
 use futures::task::{Context, Poll};
 use std::fmt::Debug;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 /// A structure that may be handed an input stream that it will exhaustively drain from until it
 /// recieves a None. Useful for testing purposes.
@@ -73,3 +75,108 @@ impl<T: Debug> Future for ExhaustiveCollector<T> {
         }
     }
 }
+
+/// A handle to the tally of a `CountingCollector`, which can be read once the collector has run.
+#[derive(Clone, Debug, Default)]
+pub struct MatchCount {
+    count: Arc<AtomicUsize>,
+}
+
+impl MatchCount {
+    /// Number of packets that matched so far.
+    pub fn get(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+}
+
+/// Counting Collector drains its input stream like Exhaustive Drain, but tallies the packets that
+/// satisfy a predicate, so a test can assert on how many packets of a kind came out of an egressor
+/// without holding on to all of them. The tally is read through the `MatchCount` handle returned
+/// by `count`.
+pub struct CountingCollector<T> {
+    stream: PacketStream<T>,
+    predicate: Box<dyn Fn(&T) -> bool + Send>,
+    count: MatchCount,
+}
+
+impl<T> Unpin for CountingCollector<T> {}
+
+impl<T> CountingCollector<T> {
+    /// Counts the packets of `stream` for which `predicate` returns true.
+    pub fn matching<F>(stream: PacketStream<T>, predicate: F) -> Self
+    where
+        F: Fn(&T) -> bool + Send + 'static,
+    {
+        CountingCollector {
+            stream,
+            predicate: Box::new(predicate),
+            count: MatchCount::default(),
+        }
+    }
+
+    /// Returns a handle to the tally, which remains valid after the collector has been spawned.
+    pub fn count(&self) -> MatchCount {
+        self.count.clone()
+    }
+}
+
+impl<T> Future for CountingCollector<T> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let collector = Pin::into_inner(self);
+        loop {
+            match ready!(Pin::new(&mut collector.stream).poll_next(cx)) {
+                Some(value) => {
+                    if (collector.predicate)(&value) {
+                        collector.count.count.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+                None => return Poll::Ready(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::primitive::ProcessLink;
+    use crate::link::{LinkBuilder, ProcessLinkBuilder};
+    use crate::processor::Identity;
+    use crate::utils::test::harness::initialize_runtime;
+    use crate::utils::test::packet_generators::immediate_stream;
+
+    #[test]
+    fn counts_matching_packets() {
+        const LAN: usize = 0;
+        const WAN: usize = 1;
+        // Every third packet is bound for the WAN.
+        let packets: Vec<(usize, i32)> = (0..99)
+            .map(|n| (if n % 3 == 0 { WAN } else { LAN }, n))
+            .collect();
+
+        let mut runtime = initialize_runtime();
+        let (wan, all) = runtime.block_on(async {
+            let (_, mut egressors) = ProcessLink::new()
+                .ingressor(immediate_stream(packets.clone()))
+                .processor(Identity::new())
+                .build_link();
+
+            let collector = CountingCollector::matching(egressors.remove(0), |(interface, _)| {
+                *interface == WAN
+            });
+            let wan = collector.count();
+            tokio::spawn(collector).await.unwrap();
+
+            let collector = CountingCollector::matching(immediate_stream(packets), |_| true);
+            let all = collector.count();
+            tokio::spawn(collector).await.unwrap();
+
+            (wan.get(), all.get())
+        });
+
+        assert_eq!(wan, 33);
+        assert_eq!(all, 99);
+    }
+}
@@ -0,0 +1,166 @@
+use crate::processor::Processor;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+/// A break in the order of the sequence numbers a `SequenceChecker` saw.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SequenceError {
+    /// The sequence numbers from `expected` up to, but not including, `got` never arrived.
+    Gap { expected: u64, got: u64 },
+    /// `got` arrived after a later sequence number, when `expected` was due. A duplicate looks
+    /// the same.
+    Reordered { expected: u64, got: u64 },
+}
+
+#[derive(Debug, Default)]
+struct Tally {
+    checked: usize,
+    errors: Vec<SequenceError>,
+}
+
+/// A handle to what a `SequenceChecker` found, which can be read once the checker has run.
+#[derive(Clone, Debug, Default)]
+pub struct SequenceReport {
+    tally: Arc<Mutex<Tally>>,
+}
+
+impl SequenceReport {
+    /// Number of packets checked so far.
+    pub fn checked(&self) -> usize {
+        self.tally.lock().unwrap().checked
+    }
+
+    /// Every gap and reordering found so far, in the order they were found.
+    pub fn errors(&self) -> Vec<SequenceError> {
+        self.tally.lock().unwrap().errors.clone()
+    }
+
+    /// Fails the test if any gap or reordering was found.
+    pub fn assert_contiguous(&self) {
+        let errors = self.errors();
+        assert!(
+            errors.is_empty(),
+            "sequence was not contiguous: {:?}",
+            errors
+        );
+    }
+}
+
+/// Passes packets annotated by a `SequenceTagger` through unchanged, checking that their sequence
+/// numbers count up by one from `first_sequence`. Place it at the egress of the links under test,
+/// and read what it found through the `SequenceReport` returned by `report`.
+///
+/// After a gap, the checker expects the sequence number following the one that arrived, so a
+/// single lost packet is reported once rather than throwing off every packet after it.
+pub struct SequenceChecker<A> {
+    next: u64,
+    report: SequenceReport,
+    phantom: PhantomData<A>,
+}
+
+impl<A> SequenceChecker<A> {
+    pub fn new() -> Self {
+        SequenceChecker {
+            next: 0,
+            report: SequenceReport::default(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Changes first_sequence, the sequence number expected first, default value is 0.
+    pub fn first_sequence(self, first_sequence: u64) -> Self {
+        SequenceChecker {
+            next: first_sequence,
+            ..self
+        }
+    }
+
+    /// Returns a handle to what the checker found, which remains valid after the checker has been
+    /// handed to a link.
+    pub fn report(&self) -> SequenceReport {
+        self.report.clone()
+    }
+}
+
+impl<A> Default for SequenceChecker<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Send + Clone> Processor for SequenceChecker<A> {
+    type Input = (u64, A);
+    type Output = (u64, A);
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let (got, _) = packet;
+        let expected = self.next;
+        let mut tally = self.report.tally.lock().unwrap();
+        tally.checked += 1;
+        if got < expected {
+            tally
+                .errors
+                .push(SequenceError::Reordered { expected, got });
+        } else {
+            if got > expected {
+                tally.errors.push(SequenceError::Gap { expected, got });
+            }
+            self.next = got.wrapping_add(1);
+        }
+        Some(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(sequences: &[u64]) -> SequenceReport {
+        let mut checker = SequenceChecker::new();
+        for &sequence in sequences {
+            assert_eq!(checker.process((sequence, ())), Some((sequence, ())));
+        }
+        checker.report()
+    }
+
+    #[test]
+    fn contiguous_sequence_has_no_errors() {
+        let report = check(&[0, 1, 2, 3]);
+
+        report.assert_contiguous();
+        assert_eq!(report.checked(), 4);
+    }
+
+    #[test]
+    fn reports_gaps_and_reorderings() {
+        let report = check(&[0, 2, 1, 3, 6, 6]);
+
+        assert_eq!(
+            report.errors(),
+            vec![
+                SequenceError::Gap {
+                    expected: 1,
+                    got: 2
+                },
+                SequenceError::Reordered {
+                    expected: 3,
+                    got: 1
+                },
+                SequenceError::Gap {
+                    expected: 4,
+                    got: 6
+                },
+                SequenceError::Reordered {
+                    expected: 7,
+                    got: 6
+                },
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "sequence was not contiguous")]
+    fn assert_contiguous_fails_on_gap() {
+        check(&[0, 2]).assert_contiguous();
+    }
+}
@@ -0,0 +1,309 @@
+use crate::classifier::Classifier;
+use crate::processor::Processor;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+/// What a link did with a packet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Sent the packet to the egressor at this index.
+    Forwarded(usize),
+    /// Dropped the packet, for this reason.
+    Dropped(String),
+}
+
+/// One packet passing through one link.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// The name the link was traced under.
+    pub link: String,
+    /// The packet as it entered the link, formatted with `Debug`.
+    pub packet: String,
+    pub action: Action,
+}
+
+/// Records what every traced link of a pipeline does with each packet, to find out where a
+/// packet went astray when a test of a large pipeline fails. Wrap the processors, classifiers and
+/// dispatchers handed to links with the methods of a `Trace`, naming each link, and run the
+/// pipeline as usual; every clone of the `Trace` shares the same record.
+///
+/// Links run concurrently, so events of different packets may interleave. Send one packet at a
+/// time through the pipeline to read off its path with `steps`.
+#[derive(Clone, Default)]
+pub struct Trace {
+    events: Arc<Mutex<Vec<TraceEvent>>>,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Trace {
+            events: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    fn record(&self, link: &str, packet: String, action: Action) {
+        self.events.lock().unwrap().push(TraceEvent {
+            link: link.to_string(),
+            packet,
+            action,
+        });
+    }
+
+    /// Traces `processor` as the link named `link`. A packet it passes on is forwarded to egressor
+    /// 0, and a packet it returns `None` for is dropped.
+    pub fn processor<P>(&self, link: &str, processor: P) -> TracedProcessor<P>
+    where
+        P: Processor,
+        P::Input: Debug,
+    {
+        TracedProcessor {
+            trace: self.clone(),
+            link: link.to_string(),
+            processor,
+        }
+    }
+
+    /// Traces `classifier` as the link named `link`. The classes it returns carry the packet
+    /// along, so the ClassifyLink must be given a dispatcher traced with `dispatcher` or
+    /// `multi_dispatcher`, which records where each packet was sent.
+    pub fn classifier<C>(&self, link: &str, classifier: C) -> TracedClassifier<C>
+    where
+        C: Classifier,
+        C::Packet: Debug,
+    {
+        TracedClassifier {
+            link: link.to_string(),
+            classifier,
+        }
+    }
+
+    /// Traces `dispatcher`, for a ClassifyLink whose classifier is traced with `classifier`.
+    pub fn dispatcher<Class: 'static>(
+        &self,
+        dispatcher: Box<dyn Fn(Class) -> usize + Send + Sync>,
+    ) -> Box<dyn Fn(TracedClass<Class>) -> usize + Send + Sync> {
+        let trace = self.clone();
+        Box::new(move |traced: TracedClass<Class>| {
+            let port = dispatcher(traced.class);
+            trace.record(&traced.link, traced.packet, Action::Forwarded(port));
+            port
+        })
+    }
+
+    /// Traces `dispatcher`, for a ClassifyLink whose classifier is traced with `classifier`. A
+    /// packet sent to several egressors gets an event for each, and a packet sent to none is
+    /// dropped.
+    pub fn multi_dispatcher<Class: Debug + 'static>(
+        &self,
+        dispatcher: Box<dyn Fn(Class) -> Vec<usize> + Send + Sync>,
+    ) -> Box<dyn Fn(TracedClass<Class>) -> Vec<usize> + Send + Sync> {
+        let trace = self.clone();
+        Box::new(move |traced: TracedClass<Class>| {
+            let reason = format!("no egressor for class {:?}", traced.class);
+            let ports = dispatcher(traced.class);
+            if ports.is_empty() {
+                trace.record(&traced.link, traced.packet, Action::Dropped(reason));
+            } else {
+                for port in ports.iter() {
+                    let packet = traced.packet.clone();
+                    trace.record(&traced.link, packet, Action::Forwarded(*port));
+                }
+            }
+            ports
+        })
+    }
+
+    /// Every event recorded so far, in the order they happened.
+    pub fn events(&self) -> Vec<TraceEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// The link and action of every event recorded so far, in the order they happened, leaving
+    /// out the packets.
+    pub fn steps(&self) -> Vec<(String, Action)> {
+        self.events()
+            .into_iter()
+            .map(|event| (event.link, event.action))
+            .collect()
+    }
+}
+
+/// A processor traced by `Trace::processor`.
+pub struct TracedProcessor<P> {
+    trace: Trace,
+    link: String,
+    processor: P,
+}
+
+impl<P> Processor for TracedProcessor<P>
+where
+    P: Processor,
+    P::Input: Debug,
+{
+    type Input = P::Input;
+    type Output = P::Output;
+
+    fn process(&mut self, packet: Self::Input) -> Option<Self::Output> {
+        let description = format!("{:?}", packet);
+        let output = self.processor.process(packet);
+        let action = match output {
+            Some(_) => Action::Forwarded(0),
+            None => Action::Dropped(String::from("dropped by processor")),
+        };
+        self.trace.record(&self.link, description, action);
+        output
+    }
+}
+
+/// The class of a packet classified by a `TracedClassifier`, along with what the dispatcher needs
+/// to record where the packet went.
+pub struct TracedClass<Class> {
+    link: String,
+    packet: String,
+    class: Class,
+}
+
+/// A classifier traced by `Trace::classifier`.
+pub struct TracedClassifier<C> {
+    link: String,
+    classifier: C,
+}
+
+impl<C> Classifier for TracedClassifier<C>
+where
+    C: Classifier,
+    C::Packet: Debug,
+{
+    type Packet = C::Packet;
+    type Class = TracedClass<C::Class>;
+
+    fn classify(&self, packet: &Self::Packet) -> Self::Class {
+        TracedClass {
+            link: self.link.clone(),
+            packet: format!("{:?}", packet),
+            class: self.classifier.classify(packet),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classifier::ByField;
+    use crate::link::primitive::{ClassifyLink, ProcessLink};
+    use crate::link::{LinkBuilder, ProcessLinkBuilder};
+    use crate::utils::test::harness::{initialize_runtime, run_link};
+    use crate::utils::test::packet_generators::immediate_stream;
+    use route_rs_packets::Ipv4Packet;
+    use std::net::Ipv4Addr;
+
+    /// Decrements the TTL, dropping packets whose TTL runs out.
+    struct ExpireTtl;
+
+    impl Processor for ExpireTtl {
+        type Input = Ipv4Packet;
+        type Output = Ipv4Packet;
+
+        fn process(&mut self, mut packet: Self::Input) -> Option<Self::Output> {
+            if packet.ttl() <= 1 {
+                return None;
+            }
+            packet.set_ttl(packet.ttl() - 1);
+            Some(packet)
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Side {
+        Lan,
+        Wan,
+    }
+
+    fn packet(dest_addr: Ipv4Addr, ttl: u8) -> Ipv4Packet {
+        let mut packet = Ipv4Packet::empty();
+        packet.set_src_addr(Ipv4Addr::new(192, 168, 1, 10));
+        packet.set_dest_addr(dest_addr);
+        packet.set_ttl(ttl);
+        packet.set_checksum();
+        packet
+    }
+
+    /// Runs `packet` through a hop limit check, then a classifier that sends traffic bound for
+    /// the WAN to its only egressor and drops traffic that stays on the LAN, tracing both links.
+    fn trace(packet: Ipv4Packet) -> (Vec<Vec<Ipv4Packet>>, Trace) {
+        let trace = Trace::new();
+        let mut runtime = initialize_runtime();
+        let results = runtime.block_on(async {
+            let (mut runnables, mut egressors) = ProcessLink::new()
+                .ingressor(immediate_stream(vec![packet]))
+                .processor(trace.processor("hop_limit", ExpireTtl))
+                .build_link();
+
+            let classifier = ByField::new(
+                |packet: &Ipv4Packet| packet.dest_addr().octets()[..3] == [192, 168, 1],
+                vec![(true, Side::Lan)].into_iter().collect(),
+                Side::Wan,
+            );
+            let (mut classify_runnables, classify_egressors) = ClassifyLink::new()
+                .ingressor(egressors.remove(0))
+                .classifier(trace.classifier("lan_or_wan", classifier))
+                .multi_dispatcher(trace.multi_dispatcher(Box::new(|side| match side {
+                    Side::Lan => vec![],
+                    Side::Wan => vec![0],
+                })))
+                .num_egressors(1)
+                .build_link();
+            runnables.append(&mut classify_runnables);
+
+            run_link((runnables, classify_egressors)).await
+        });
+        (results, trace)
+    }
+
+    #[test]
+    fn lan_to_lan_dropped_at_classifier() {
+        let (results, trace) = trace(packet(Ipv4Addr::new(192, 168, 1, 20), 64));
+
+        assert!(results[0].is_empty());
+        assert_eq!(
+            trace.steps(),
+            vec![
+                (String::from("hop_limit"), Action::Forwarded(0)),
+                (
+                    String::from("lan_or_wan"),
+                    Action::Dropped(String::from("no egressor for class Lan"))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn lan_to_wan_forwarded() {
+        let (results, trace) = trace(packet(Ipv4Addr::new(93, 184, 216, 34), 64));
+
+        assert_eq!(results[0].len(), 1);
+        assert_eq!(
+            trace.steps(),
+            vec![
+                (String::from("hop_limit"), Action::Forwarded(0)),
+                (String::from("lan_or_wan"), Action::Forwarded(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn expired_dropped_at_processor() {
+        let expired = packet(Ipv4Addr::new(93, 184, 216, 34), 1);
+        let (results, trace) = trace(expired.clone());
+
+        assert!(results[0].is_empty());
+        assert_eq!(
+            trace.events(),
+            vec![TraceEvent {
+                link: String::from("hop_limit"),
+                packet: format!("{:?}", expired),
+                action: Action::Dropped(String::from("dropped by processor")),
+            }]
+        );
+    }
+}